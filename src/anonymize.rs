@@ -0,0 +1,92 @@
+use csv::{ReaderBuilder, Writer};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Maps email addresses to stable pseudonyms such as `user_0001`, numbered in
+/// the order the addresses are first seen.
+///
+/// The mapping can be saved to and loaded from a CSV file, so the same
+/// address keeps its pseudonym across runs and datasets and results can be
+/// de-anonymized later.
+#[derive(Debug, Clone, Default)]
+pub struct AddressMapper {
+    preserve_domains: bool, // Keep the real domain, as in `user_0001@example.com`
+    pseudonyms: HashMap<String, String>, // Address -> pseudonym
+    addresses: Vec<String>, // Addresses in first-seen order
+}
+
+impl AddressMapper {
+    /// Creates an empty mapper.
+    pub fn new(preserve_domains: bool) -> Self {
+        AddressMapper {
+            preserve_domains,
+            ..AddressMapper::default()
+        }
+    }
+
+    /// Returns the pseudonym of `address`, assigning the next free one if the
+    /// address hasn't been seen before.
+    pub fn pseudonym(&mut self, address: &str) -> String {
+        if let Some(pseudonym) = self.pseudonyms.get(address) {
+            return pseudonym.clone();
+        }
+
+        let mut pseudonym = format!("user_{:04}", self.addresses.len() + 1);
+        if self.preserve_domains {
+            if let Some((_, domain)) = address.rsplit_once('@') {
+                pseudonym = format!("{}@{}", pseudonym, domain);
+            }
+        }
+        self.pseudonyms.insert(address.to_string(), pseudonym.clone());
+        self.addresses.push(address.to_string());
+        pseudonym
+    }
+
+    /// Looks up the real address behind a pseudonym.
+    pub fn original(&self, pseudonym: &str) -> Option<&str> {
+        self.addresses
+            .iter()
+            .find(|address| self.pseudonyms[*address] == pseudonym)
+            .map(String::as_str)
+    }
+
+    /// Returns the number of addresses mapped so far.
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// Returns true if no address has been mapped yet.
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Writes the mapping as `address,pseudonym` rows in first-seen order.
+    pub fn save_csv(&self, path: &Path) -> io::Result<()> {
+        let mut writer = Writer::from_path(path)?;
+        writer.write_record(["address", "pseudonym"])?;
+        for address in &self.addresses {
+            writer.write_record([address, &self.pseudonyms[address]])?;
+        }
+        writer.flush()
+    }
+
+    /// Reads a mapping written by `save_csv`. New addresses are numbered
+    /// after the ones already in the file.
+    pub fn load_csv(path: &Path, preserve_domains: bool) -> io::Result<Self> {
+        let mut mapper = AddressMapper::new(preserve_domains);
+        let mut reader = ReaderBuilder::new().has_headers(true).from_path(path)?;
+        for record in reader.records() {
+            let record = record?;
+            let (Some(address), Some(pseudonym)) = (record.get(0), record.get(1)) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected address and pseudonym", record.position().map_or(0, |pos| pos.line())),
+                ));
+            };
+            mapper.pseudonyms.insert(address.to_string(), pseudonym.to_string());
+            mapper.addresses.push(address.to_string());
+        }
+        Ok(mapper)
+    }
+}
@@ -0,0 +1,871 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use crate::config::{CommunityMethod, Config, InputFormat, PagerankVariant};
+use crate::email::{parse_date, Delimiter};
+use crate::filter::TextPattern;
+use crate::graph::{MatchMode, RemovalStrategy};
+use crate::logger::Verbosity;
+use crate::spread::SpreadModel;
+
+/// Command-line interface of the email graph analysis tool.
+/// Flags given here take precedence over values from `--config`.
+/// Without a subcommand the full analysis report is printed.
+#[derive(Debug, Parser)]
+#[command(about = "Build and analyze a communication graph from email data")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to the email CSV file or mail directory; a file name with * or ? reads every match, and - reads standard input
+    #[arg(short, long, global = true)]
+    pub input: Option<String>,
+
+    /// Stop when one of the files matched by the input pattern can't be read, instead of skipping it
+    #[arg(long, global = true)]
+    pub strict: bool,
+
+    /// CSV file of address,key,value rows to attach to people as attributes (e.g. department)
+    #[arg(long, global = true)]
+    pub node_tags: Option<PathBuf>,
+
+    /// Layout of the input: a CSV file or a mail directory
+    #[arg(long, global = true, value_enum)]
+    pub format: Option<InputFormat>,
+
+    /// Field separator of the CSV input: , \t ; or | (detected from the header if omitted)
+    #[arg(long, global = true)]
+    pub delimiter: Option<Delimiter>,
+
+    /// TOML file with analysis settings
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Only analyze emails sent at or after this date (e.g. 2001-07-01)
+    #[arg(long, global = true, value_parser = parse_cli_date)]
+    pub after: Option<DateTime<Utc>>,
+
+    /// Only analyze emails sent before this date
+    #[arg(long, global = true, value_parser = parse_cli_date)]
+    pub before: Option<DateTime<Utc>>,
+
+    /// Keep emails with a missing or unparseable date when --after/--before is set
+    #[arg(long, global = true)]
+    pub include_undated: bool,
+
+    /// Keep the senders of emails without any recipient address as people without edges
+    #[arg(long, global = true)]
+    pub keep_senders_without_recipients: bool,
+
+    /// Drop recipient addresses longer than this many bytes (default 254)
+    #[arg(long, global = true)]
+    pub max_address_length: Option<usize>,
+
+    /// Only analyze emails whose subject matches this regex (case-insensitive)
+    #[arg(long, global = true)]
+    pub subject_regex: Option<TextPattern>,
+
+    /// Only analyze emails whose body matches this regex (case-insensitive)
+    #[arg(long, global = true)]
+    pub body_regex: Option<TextPattern>,
+
+    /// File of addresses or globs (one per line) to leave out of the graph
+    #[arg(long, global = true)]
+    pub blocklist: Option<PathBuf>,
+
+    /// File of addresses or globs (one per line); only these are kept
+    #[arg(long, global = true)]
+    pub allowlist: Option<PathBuf>,
+
+    /// Number of top senders/recipients to report
+    #[arg(long, global = true)]
+    pub top_n: Option<usize>,
+
+    /// Most candidates to list when an address given to `who` or the REPL matches several people
+    #[arg(long, global = true)]
+    pub find_limit: Option<usize>,
+
+    /// Prune nodes whose total degree is below this value
+    #[arg(long, global = true)]
+    pub min_degree: Option<usize>,
+
+    /// Ignore emails people send to themselves
+    #[arg(long, global = true)]
+    pub drop_self_loops: bool,
+
+    /// Remove edges carrying fewer than this many emails
+    #[arg(long, global = true)]
+    pub min_edge_weight: Option<usize>,
+
+    /// Remove people left without any edges by --min-edge-weight
+    #[arg(long, global = true)]
+    pub drop_isolated: bool,
+
+    /// Analyze only the largest connected group of people, ignoring edge direction
+    #[arg(long, global = true)]
+    pub giant_component_only: bool,
+
+    /// Analyze a thinned graph keeping each edge with this probability (0 to 1)
+    #[arg(long, global = true)]
+    pub edge_sample: Option<f64>,
+
+    /// Seed for choosing the edges kept by --edge-sample
+    #[arg(long, global = true)]
+    pub edge_sample_seed: Option<u64>,
+
+    /// With --edge-sample, keep at least one edge of every person who had any
+    #[arg(long, global = true)]
+    pub edge_sample_stratified: bool,
+
+    /// Count emails with more than this many recipients as broadcasts in the report (default 50)
+    #[arg(long, global = true)]
+    pub broadcast_threshold: Option<usize>,
+
+    /// Leave emails with more than this many recipients out of the graph
+    #[arg(long, global = true)]
+    pub drop_broadcasts: Option<usize>,
+
+    /// Algorithm used to detect communities
+    #[arg(long, global = true, value_enum)]
+    pub community_method: Option<CommunityMethod>,
+
+    /// Seed for community detection, for reproducible communities
+    #[arg(long, global = true)]
+    pub lpa_seed: Option<u64>,
+
+    /// Maximum number of label propagation passes
+    #[arg(long, global = true)]
+    pub max_iterations: Option<usize>,
+
+    /// Let senders as well as recipients influence a node's community
+    #[arg(long, global = true)]
+    pub lpa_undirected: bool,
+
+    /// Weigh each neighbor's community by the emails exchanged with them instead of counting neighbors once
+    #[arg(long, global = true)]
+    pub lpa_weighted: bool,
+
+    /// Move members of communities smaller than this into a neighboring community (or label them "unassigned")
+    #[arg(long, global = true)]
+    pub min_community_size: Option<usize>,
+
+    /// CSV of address,label rows whose labels label propagation keeps fixed and spreads
+    #[arg(long, global = true)]
+    pub seed_labels: Option<PathBuf>,
+
+    /// Load the communities from this address,label CSV instead of detecting them (see --partition-csv)
+    #[arg(long, global = true)]
+    pub communities_file: Option<PathBuf>,
+
+    /// Label people no seed label reaches "unlabeled" instead of giving them their own community
+    #[arg(long, global = true)]
+    pub mark_unlabeled: bool,
+
+    /// Detect communities using only mutual (reciprocated) relationships
+    #[arg(long, global = true)]
+    pub mutual_only: bool,
+
+    /// Leave people who only send email out of community detection
+    #[arg(long, global = true)]
+    pub exclude_sources: bool,
+
+    /// Leave people who only receive email out of community detection
+    #[arg(long, global = true)]
+    pub exclude_sinks: bool,
+
+    /// Run label propagation this many times (seeds from --lpa-seed up) and report how stable each person's community is
+    #[arg(long, global = true)]
+    pub consensus_runs: Option<usize>,
+
+    /// Report the z-scores of modularity and clustering against this many degree-preserving rewirings (seeds from --lpa-seed up)
+    #[arg(long, global = true)]
+    pub null_model: Option<usize>,
+
+    /// Compute and report PageRank
+    #[arg(long, global = true)]
+    pub pagerank: bool,
+
+    /// Which PageRank to report: unweighted, weighted by email counts, or both
+    #[arg(long, global = true, value_enum)]
+    pub pagerank_variant: Option<PagerankVariant>,
+
+    /// Compute and report harmonic centrality (slow on large graphs)
+    #[arg(long, global = true)]
+    pub harmonic: bool,
+
+    /// Estimate harmonic centrality from this many randomly chosen source nodes
+    #[arg(long, global = true)]
+    pub harmonic_sample: Option<usize>,
+
+    /// Compute and report betweenness centrality (slow on large graphs)
+    #[arg(long, global = true)]
+    pub betweenness: bool,
+
+    /// Approximate betweenness from this many randomly chosen source nodes
+    #[arg(long, global = true)]
+    pub betweenness_sources: Option<usize>,
+
+    /// Seed for choosing the betweenness source nodes
+    #[arg(long, global = true)]
+    pub betweenness_seed: Option<u64>,
+
+    /// Report the edges the most shortest paths run through (slow on large graphs)
+    #[arg(long, global = true)]
+    pub edge_betweenness: bool,
+
+    /// Report how many people each person's email can transitively reach (slow on large graphs)
+    #[arg(long, global = true)]
+    pub reachability: bool,
+
+    /// Measure reach only for this many randomly chosen people
+    #[arg(long, global = true)]
+    pub reachability_sample: Option<usize>,
+
+    /// Seed for choosing the people measured by --reachability-sample
+    #[arg(long, global = true)]
+    pub reachability_seed: Option<u64>,
+
+    /// Report the radius, diameter and center of the largest connected group (slow on large graphs)
+    #[arg(long, global = true)]
+    pub eccentricity: bool,
+
+    /// Measure eccentricity only for this many randomly chosen people
+    #[arg(long, global = true)]
+    pub eccentricity_sample: Option<usize>,
+
+    /// Seed for choosing the people measured by --eccentricity-sample
+    #[arg(long, global = true)]
+    pub eccentricity_seed: Option<u64>,
+
+    /// Report how many hops apart this many random pairs of the largest connected group are
+    #[arg(long, global = true)]
+    pub distance_pairs: Option<usize>,
+
+    /// Seed for choosing the pairs measured by --distance-pairs
+    #[arg(long, global = true)]
+    pub distance_seed: Option<u64>,
+
+    /// Flag recipients as distribution lists only if they receive at least this many emails
+    #[arg(long, global = true)]
+    pub list_min_emails: Option<usize>,
+
+    /// Flag recipients as distribution lists only if at least this many people email them
+    #[arg(long, global = true)]
+    pub list_min_senders: Option<usize>,
+
+    /// Flag recipients as distribution lists only if they send at most this fraction of what they receive
+    #[arg(long, global = true)]
+    pub list_max_sent_ratio: Option<f64>,
+
+    /// Report people whose degree is more than this many standard deviations from typical
+    #[arg(long, global = true)]
+    pub outlier_z: Option<f64>,
+
+    /// Show the most distinctive subject words of the largest communities
+    #[arg(long, global = true)]
+    pub keywords: bool,
+
+    /// Number of words in each community's keyword profile
+    #[arg(long, global = true)]
+    pub keywords_top_k: Option<usize>,
+
+    /// Write a JSON summary report to this path
+    #[arg(long, global = true)]
+    pub report_json: Option<PathBuf>,
+
+    /// Write per-node metrics as CSV to this path
+    #[arg(long, global = true)]
+    pub node_csv: Option<PathBuf>,
+
+    /// Write the graph as GraphML to this path
+    #[arg(long, global = true)]
+    pub graphml: Option<PathBuf>,
+
+    /// Write the maximum spanning forest (the heaviest-edge backbone) as GraphML to this path
+    #[arg(long, global = true)]
+    pub backbone_graphml: Option<PathBuf>,
+
+    /// Add each edge's first and last email and number of active days to edge lists and GraphML
+    #[arg(long, global = true)]
+    pub edge_activity: bool,
+
+    /// Write a self-contained HTML drawing of the communities to this path
+    #[arg(long, global = true)]
+    pub html: Option<PathBuf>,
+
+    /// Draw at most this many people in the HTML page, keeping the highest-degree ones
+    #[arg(long, global = true)]
+    pub html_max_nodes: Option<usize>,
+
+    /// Write the adjacency matrix in MatrixMarket format to this path, with node_index.csv next to it
+    #[arg(long, global = true)]
+    pub matrix_market: Option<PathBuf>,
+
+    /// Write nodes.csv and relationships.csv for `neo4j-admin import` into this directory
+    #[arg(long, global = true)]
+    pub neo4j_dir: Option<PathBuf>,
+
+    /// Save the emails, nodes and edges to this SQLite database (needs the `sqlite` feature)
+    #[arg(long, global = true)]
+    pub sqlite: Option<PathBuf>,
+
+    /// Write each community's label and size as CSV to this path
+    #[arg(long, global = true)]
+    pub community_sizes: Option<PathBuf>,
+
+    /// Write every community with its members and their degrees to this path (Markdown if it ends in .md)
+    #[arg(long, global = true)]
+    pub community_report: Option<PathBuf>,
+
+    /// Write every community's keyword profile as CSV to this path (implies --keywords)
+    #[arg(long, global = true)]
+    pub keywords_csv: Option<PathBuf>,
+
+    /// Write the address count and traffic of every email domain as CSV to this path
+    #[arg(long, global = true)]
+    pub domain_csv: Option<PathBuf>,
+
+    /// Write every person's community as address,label CSV to this path, for --communities-file
+    #[arg(long, global = true)]
+    pub partition_csv: Option<PathBuf>,
+
+    /// Replace every address with a stable pseudonym such as user_0001
+    #[arg(long, global = true)]
+    pub anonymize: bool,
+
+    /// Keep the real domain in pseudonyms (user_0001@example.com)
+    #[arg(long, global = true)]
+    pub preserve_domains: bool,
+
+    /// CSV file holding the address-to-pseudonym mapping; reused if it exists
+    #[arg(long, global = true)]
+    pub address_map: Option<PathBuf>,
+
+    /// Print more diagnostics: -v lists skipped records and memory use, -vv adds per-file details
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Print nothing but the requested output files and subcommand answers
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+}
+
+/// Alternative modes of operation.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Load the graph once and answer queries typed on stdin
+    Repl,
+    /// Compute shortest paths for every (from,to) pair in a CSV file
+    Paths {
+        /// CSV file of from,to address pairs; an address that is not exactly
+        /// a node is matched as a pattern, and several matches are listed
+        pairs: PathBuf,
+        /// How to match the addresses as patterns
+        #[arg(long = "match", value_enum, default_value_t = Matching::Substring)]
+        matching: Matching,
+        /// Write the results to this CSV file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Write the normalized, filtered and deduplicated emails as a CSV with
+    /// one row per sender and recipient (from,to,timestamp)
+    Clean {
+        /// CSV file to write the rows to
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Extract everyone within a few steps of some seed addresses as an edge list
+    Sample {
+        /// Addresses to start from
+        #[arg(required = true)]
+        seeds: Vec<String>,
+        /// Number of undirected steps to expand from the seeds
+        #[arg(long, default_value_t = 2)]
+        depth: usize,
+        /// Keep at most this many randomly chosen new people per step
+        #[arg(long, default_value_t = 50)]
+        max_per_level: usize,
+        /// Seed for choosing which people to keep when a step is capped
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// CSV file to write the sampled edges to (from,to,weight,emails)
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Draw everyone one person reaches by email within a few steps as a
+    /// Graphviz DOT tree, one level per step
+    InfluenceTree {
+        /// The address at the root of the tree (case-insensitive)
+        root: String,
+        /// Number of steps to follow from the root
+        #[arg(long, default_value_t = 2)]
+        depth: usize,
+        /// Also draw the other emails among the people in the tree, dashed
+        #[arg(long)]
+        non_tree_edges: bool,
+        /// DOT file to write the tree to
+        #[arg(long)]
+        output: PathBuf,
+    },
+    /// Show everything known about one address
+    Who {
+        /// The address to look up (case-insensitive); if no address is exactly
+        /// this, it is matched as a pattern and several matches are listed
+        address: String,
+        /// How to match the address as a pattern
+        #[arg(long = "match", value_enum, default_value_t = Matching::Substring)]
+        matching: Matching,
+    },
+    /// Show how many emails were sent per time bucket, overall and by the top senders
+    Activity {
+        /// Length of each time bucket in days
+        #[arg(long, default_value_t = 7)]
+        bucket_days: i64,
+        /// Write the series of the top senders to this CSV file (bucket_start,sender,count)
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Flag buckets more than this many standard deviations above a sender's mean
+        #[arg(long, default_value_t = 3.0)]
+        burst_sigma: f64,
+    },
+    /// Group emails into conversation threads by subject and show the longest
+    Threads,
+    /// Compare the graphs of two inputs: new and lost contacts and degree changes
+    Diff {
+        /// The earlier input
+        earlier: String,
+        /// The later input, read with the same settings
+        later: String,
+    },
+    /// Run every community detection method on the graph and compare the results
+    CompareCommunities {
+        /// Seeds for the label propagation runs, one run each
+        #[arg(long, value_delimiter = ',', default_values_t = [0, 1, 2])]
+        seeds: Vec<u64>,
+        /// Write the comparison table to this CSV file
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Show how the communities of one partition file split and merge into another's
+    PartitionFlow {
+        /// The earlier partition, as written by --partition-csv
+        earlier: PathBuf,
+        /// The later partition
+        later: PathBuf,
+        /// Write the flows to this CSV file, e.g. for an alluvial diagram
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Pick the people to brief so information spreads furthest (VoteRank)
+    Spreaders {
+        /// Number of people to pick
+        #[arg(long, default_value_t = 10)]
+        k: usize,
+    },
+    /// Remove people step by step and track how the largest connected group shrinks
+    Robustness {
+        /// Which people to remove first
+        #[arg(long, value_enum, default_value_t = Strategy::Degree)]
+        strategy: Strategy,
+        /// Number of equal removal steps
+        #[arg(long, default_value_t = 20)]
+        steps: usize,
+        /// Seed for the random removal order
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Write the curve to this CSV file (fraction_removed,largest_component_fraction)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Time the core graph operations on a generated Barabási–Albert graph, String-keyed and interned
+    Bench {
+        /// Number of people in the generated graph
+        #[arg(long, default_value_t = 10_000)]
+        nodes: usize,
+        /// Emails each new person sends when joining the graph
+        #[arg(long, default_value_t = 5)]
+        edges_per_node: usize,
+        /// Seed for the generator
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Drop random emails step by step and track how the largest connected group shrinks
+    Percolation {
+        /// Number of equal steps between all and no edges
+        #[arg(long, default_value_t = 20)]
+        steps: usize,
+        /// Random edge orders averaged per step
+        #[arg(long, default_value_t = 10)]
+        trials: usize,
+        /// Seed for the random edge orders
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Write the curve to this CSV file (retained_fraction,largest_component_fraction)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Simulate information spreading from some people along the email edges
+    Spread {
+        /// Address where the spread starts (repeat for several)
+        #[arg(long = "seed-node", required = true)]
+        seed_nodes: Vec<String>,
+        /// How the infection passes on
+        #[arg(long, value_enum, default_value_t = Model::Cascade)]
+        model: Model,
+        /// Chance that an infected person infects a recipient
+        #[arg(long, default_value_t = 0.05)]
+        p: f64,
+        /// Chance per round that an infected person recovers (SIR only)
+        #[arg(long, default_value_t = 0.5)]
+        recovery: f64,
+        /// Give every email on an edge its own chance (cascade only)
+        #[arg(long)]
+        weighted: bool,
+        /// Number of simulation runs
+        #[arg(long, default_value_t = 100)]
+        trials: usize,
+        /// Seed for the simulation
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Show graph sizes and community stability per time window
+    Timeline {
+        /// Length of each time window in days
+        #[arg(long, default_value_t = 7)]
+        window_days: i64,
+    },
+    /// Show who could have heard something one person learned on a date,
+    /// following emails in the order they were sent
+    Exposure {
+        /// The address that learned it
+        source: String,
+        /// When the source learned it (e.g. 2001-07-01)
+        #[arg(long, value_parser = parse_cli_date)]
+        since: DateTime<Utc>,
+        /// Only follow an email sent within this many hours of its sender learning it
+        #[arg(long)]
+        max_delay_hours: Option<i64>,
+        /// Show how the news could have reached this address instead
+        #[arg(long)]
+        target: Option<String>,
+    },
+}
+
+/// Spreading model of the `spread` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Model {
+    /// Independent cascade: one chance per recipient
+    Cascade,
+    /// Susceptible-infected-recovered, in rounds
+    Sir,
+}
+
+impl Model {
+    /// Returns the spreading model with the given probabilities.
+    pub fn spread_model(self, p: f64, recovery: f64, weighted: bool) -> SpreadModel {
+        match self {
+            Model::Cascade => SpreadModel::IndependentCascade { p, weighted },
+            Model::Sir => SpreadModel::Sir { infection: p, recovery },
+        }
+    }
+}
+
+/// Pattern matching of the `who` and `paths` subcommands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Matching {
+    /// The text appears anywhere in the address
+    Substring,
+    /// The address starts with the text
+    Prefix,
+    /// The whole address matches, with * and ? as wildcards
+    Glob,
+    /// A regular expression matches anywhere in the address
+    Regex,
+}
+
+impl Matching {
+    /// Returns the graph's match mode.
+    pub fn match_mode(self) -> MatchMode {
+        match self {
+            Matching::Substring => MatchMode::Substring,
+            Matching::Prefix => MatchMode::Prefix,
+            Matching::Glob => MatchMode::Glob,
+            Matching::Regex => MatchMode::Regex,
+        }
+    }
+}
+
+/// Removal order of the `robustness` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Strategy {
+    /// Highest total degree first
+    Degree,
+    /// Highest betweenness first (slow on large graphs)
+    Betweenness,
+    /// Random order, fixed by --seed
+    Random,
+}
+
+impl Strategy {
+    /// Returns the graph removal strategy, using `seed` for the random order.
+    pub fn removal_strategy(self, seed: u64) -> RemovalStrategy {
+        match self {
+            Strategy::Degree => RemovalStrategy::HighestDegree,
+            Strategy::Betweenness => RemovalStrategy::HighestBetweenness,
+            Strategy::Random => RemovalStrategy::Random { seed },
+        }
+    }
+}
+
+impl Cli {
+    /// Overrides config values with every flag that was given on the command line.
+    pub fn apply_overrides(&self, config: &mut Config) {
+        if let Some(input) = &self.input {
+            config.input.path = Some(input.clone());
+        }
+        if let Some(format) = self.format {
+            config.input.format = format;
+        }
+        if self.strict {
+            config.input.strict = true;
+        }
+        if let Some(path) = &self.node_tags {
+            config.input.node_tags = Some(path.clone());
+        }
+        if let Some(delimiter) = self.delimiter {
+            config.input.schema.delimiter = Some(delimiter);
+        }
+        if let Some(after) = self.after {
+            config.filter.after = Some(after);
+        }
+        if let Some(before) = self.before {
+            config.filter.before = Some(before);
+        }
+        if self.include_undated {
+            config.filter.include_undated = true;
+        }
+        if self.keep_senders_without_recipients {
+            config.filter.keep_senders_without_recipients = true;
+        }
+        if let Some(length) = self.max_address_length {
+            config.filter.max_address_length = Some(length);
+        }
+        if let Some(pattern) = &self.subject_regex {
+            config.filter.subject_regex = Some(pattern.clone());
+        }
+        if let Some(pattern) = &self.body_regex {
+            config.filter.body_regex = Some(pattern.clone());
+        }
+        if let Some(top_n) = self.top_n {
+            config.output.top_n = top_n;
+        }
+        if let Some(limit) = self.find_limit {
+            config.output.find_limit = limit;
+        }
+        if let Some(min_degree) = self.min_degree {
+            config.graph.min_degree = min_degree;
+        }
+        if self.drop_self_loops {
+            config.graph.drop_self_loops = true;
+        }
+        if let Some(min_weight) = self.min_edge_weight {
+            config.graph.min_edge_weight = min_weight;
+        }
+        if self.drop_isolated {
+            config.graph.drop_isolated = true;
+        }
+        if self.giant_component_only {
+            config.graph.giant_component_only = true;
+        }
+        if let Some(fraction) = self.edge_sample {
+            config.graph.edge_sample = Some(fraction);
+        }
+        if let Some(seed) = self.edge_sample_seed {
+            config.graph.edge_sample_seed = seed;
+        }
+        if self.edge_sample_stratified {
+            config.graph.edge_sample_stratified = true;
+        }
+        if let Some(threshold) = self.broadcast_threshold {
+            config.graph.broadcast_threshold = Some(threshold);
+        }
+        if let Some(threshold) = self.drop_broadcasts {
+            config.graph.drop_broadcasts = Some(threshold);
+        }
+        if let Some(method) = self.community_method {
+            config.algorithms.community_method = method;
+        }
+        if let Some(seed) = self.lpa_seed {
+            config.algorithms.lpa_seed = Some(seed);
+        }
+        if let Some(max_iterations) = self.max_iterations {
+            config.algorithms.max_iterations = max_iterations;
+        }
+        if self.lpa_undirected {
+            config.algorithms.lpa_undirected = true;
+        }
+        if self.lpa_weighted {
+            config.algorithms.lpa_weighted = true;
+        }
+        if let Some(min_size) = self.min_community_size {
+            config.algorithms.min_community_size = min_size;
+        }
+        if let Some(path) = &self.communities_file {
+            config.algorithms.communities_file = Some(path.clone());
+        }
+        if self.mark_unlabeled {
+            config.algorithms.mark_unlabeled = true;
+        }
+        if self.mutual_only {
+            config.algorithms.mutual_only = true;
+        }
+        if self.exclude_sources {
+            config.algorithms.exclude_sources = true;
+        }
+        if self.exclude_sinks {
+            config.algorithms.exclude_sinks = true;
+        }
+        if let Some(runs) = self.consensus_runs {
+            config.algorithms.consensus_runs = Some(runs);
+        }
+        if let Some(samples) = self.null_model {
+            config.algorithms.null_model = Some(samples);
+        }
+        if self.pagerank {
+            config.algorithms.enable_pagerank = true;
+        }
+        if let Some(variant) = self.pagerank_variant {
+            config.algorithms.pagerank_variant = variant;
+        }
+        if self.harmonic {
+            config.algorithms.enable_harmonic = true;
+        }
+        if let Some(sample) = self.harmonic_sample {
+            config.algorithms.harmonic_sample = Some(sample);
+        }
+        if self.betweenness {
+            config.algorithms.enable_betweenness = true;
+        }
+        if let Some(sources) = self.betweenness_sources {
+            config.algorithms.betweenness_sources = Some(sources);
+        }
+        if let Some(seed) = self.betweenness_seed {
+            config.algorithms.betweenness_seed = seed;
+        }
+        if self.edge_betweenness {
+            config.algorithms.enable_edge_betweenness = true;
+        }
+        if self.reachability {
+            config.algorithms.enable_reachability = true;
+        }
+        if let Some(sample) = self.reachability_sample {
+            config.algorithms.reachability_sample = Some(sample);
+        }
+        if let Some(seed) = self.reachability_seed {
+            config.algorithms.reachability_seed = seed;
+        }
+        if self.eccentricity {
+            config.algorithms.enable_eccentricity = true;
+        }
+        if let Some(sample) = self.eccentricity_sample {
+            config.algorithms.eccentricity_sample = Some(sample);
+        }
+        if let Some(seed) = self.eccentricity_seed {
+            config.algorithms.eccentricity_seed = seed;
+        }
+        if let Some(pairs) = self.distance_pairs {
+            config.algorithms.distance_pairs = Some(pairs);
+        }
+        if let Some(seed) = self.distance_seed {
+            config.algorithms.distance_seed = seed;
+        }
+        if let Some(min_emails) = self.list_min_emails {
+            config.algorithms.list_min_emails = min_emails;
+        }
+        if let Some(min_senders) = self.list_min_senders {
+            config.algorithms.list_min_senders = min_senders;
+        }
+        if let Some(ratio) = self.list_max_sent_ratio {
+            config.algorithms.list_max_sent_ratio = ratio;
+        }
+        if let Some(z) = self.outlier_z {
+            config.algorithms.outlier_z = z;
+        }
+        if self.keywords {
+            config.algorithms.enable_keywords = true;
+        }
+        if let Some(top_k) = self.keywords_top_k {
+            config.algorithms.keywords_top_k = top_k;
+        }
+        if let Some(path) = &self.report_json {
+            config.output.report_json = Some(path.clone());
+        }
+        if let Some(path) = &self.node_csv {
+            config.output.node_csv = Some(path.clone());
+        }
+        if let Some(path) = &self.graphml {
+            config.output.graphml = Some(path.clone());
+        }
+        if let Some(path) = &self.backbone_graphml {
+            config.output.backbone_graphml = Some(path.clone());
+        }
+        if self.edge_activity {
+            config.output.edge_activity = true;
+        }
+        if let Some(path) = &self.html {
+            config.output.html = Some(path.clone());
+        }
+        if let Some(max_nodes) = self.html_max_nodes {
+            config.output.html_max_nodes = max_nodes;
+        }
+        if let Some(path) = &self.matrix_market {
+            config.output.matrix_market = Some(path.clone());
+        }
+        if let Some(path) = &self.neo4j_dir {
+            config.output.neo4j_dir = Some(path.clone());
+        }
+        if let Some(path) = &self.sqlite {
+            config.output.sqlite = Some(path.clone());
+        }
+        if let Some(path) = &self.community_sizes {
+            config.output.community_sizes = Some(path.clone());
+        }
+        if let Some(path) = &self.community_report {
+            config.output.community_report = Some(path.clone());
+        }
+        if let Some(path) = &self.keywords_csv {
+            config.output.keywords_csv = Some(path.clone());
+        }
+        if let Some(path) = &self.domain_csv {
+            config.output.domain_csv = Some(path.clone());
+        }
+        if let Some(path) = &self.partition_csv {
+            config.output.partition_csv = Some(path.clone());
+        }
+        if self.anonymize {
+            config.output.anonymize = true;
+        }
+        if self.preserve_domains {
+            config.output.preserve_domains = true;
+        }
+        if let Some(path) = &self.address_map {
+            config.output.address_map = Some(path.clone());
+        }
+    }
+
+    /// Returns the verbosity asked for with `--quiet` or `-v`, if any.
+    pub fn verbosity(&self) -> Option<Verbosity> {
+        if self.quiet {
+            Some(Verbosity::Quiet)
+        } else if self.verbose > 0 {
+            Some(Verbosity::from_count(self.verbose))
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses a date argument in any format accepted by `parse_date`.
+fn parse_cli_date(text: &str) -> Result<DateTime<Utc>, String> {
+    parse_date(text).ok_or_else(|| format!("unrecognized date '{}' (expected e.g. 2001-07-01)", text))
+}
@@ -0,0 +1,581 @@
+use csv::ReaderBuilder;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use crate::email::normalize_address;
+use crate::graph::{Graph, LpaOptions, UNASSIGNED_LABEL};
+
+/// Modularity gains smaller than this are treated as rounding noise
+const MIN_GAIN: f64 = 1e-12;
+
+/// Cap on local-moving passes, Louvain levels and refinement rounds
+const MAX_ROUNDS: usize = 100;
+
+/// Modularity-based community detection on the weighted undirected
+/// projection of the graph: the weight between two people is the sum of
+/// the edge weights in both directions, and self-loops are left out.
+/// Communities are labeled by their alphabetically smallest member.
+impl Graph {
+    /// Computes the modularity of `labels` on the weighted undirected
+    /// projection. Nodes without a label, or labeled `UNASSIGNED_LABEL`,
+    /// count as communities of their own. A graph without edges has
+    /// modularity 0.
+    pub fn modularity(&self, labels: &HashMap<String, String>) -> f64 {
+        let projection = Projection::of(self);
+        projection.graph.modularity(&projection.membership(labels))
+    }
+
+    /// Detects communities with the Louvain method: nodes repeatedly move
+    /// to the neighboring community that raises modularity most, then each
+    /// community is collapsed into a single node and the process repeats
+    /// until nothing moves. The same `seed` always gives the same result.
+    ///
+    /// Louvain can leave a community held together only by a node that
+    /// later moved away; use [`Graph::leiden`] when every community must
+    /// be connected.
+    pub fn louvain(&self, seed: u64) -> HashMap<String, String> {
+        let projection = Projection::of(self);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let membership = projection.graph.louvain(&mut rng);
+        projection.labels(&membership)
+    }
+
+    /// Runs Louvain followed by [`Graph::refine_partition`], so every
+    /// community induces a connected subgraph. This is the refinement idea
+    /// of the Leiden algorithm applied once after Louvain, not the full
+    /// algorithm; its modularity is never below that of plain Louvain.
+    pub fn leiden(&self, seed: u64) -> HashMap<String, String> {
+        let projection = Projection::of(self);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut membership = projection.graph.louvain(&mut rng);
+        projection.graph.refine(&mut membership, &mut rng);
+        projection.labels(&membership)
+    }
+
+    /// Splits every community of `labels` that is not connected in the
+    /// undirected projection into its connected parts, then lets single
+    /// nodes move to raise modularity, repeating until nothing changes.
+    /// Every returned community is connected; splitting and moving only
+    /// ever raise modularity. Unlabeled nodes start as their own community.
+    pub fn refine_partition(&self, labels: &HashMap<String, String>, seed: u64) -> HashMap<String, String> {
+        let projection = Projection::of(self);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut membership = projection.membership(labels);
+        projection.graph.refine(&mut membership, &mut rng);
+        projection.labels(&membership)
+    }
+
+    /// Checks whether every community of `labels` induces a connected
+    /// subgraph of the undirected projection.
+    pub fn communities_are_connected(&self, labels: &HashMap<String, String>) -> bool {
+        let projection = Projection::of(self);
+        projection.graph.is_connected_partition(&projection.membership(labels))
+    }
+
+    /// Groups the labeled nodes by community, largest community first (ties
+    /// by label), each with its members and their total degree (in + out)
+    /// in the whole graph, highest first (ties by address). Labels of nodes
+    /// not in the graph are ignored.
+    pub fn nodes_by_community_sorted(&self, labels: &HashMap<String, String>) -> Vec<(String, Vec<(String, usize)>)> {
+        let out_degrees = self.calculate_out_degrees();
+        let in_degrees = self.calculate_in_degrees();
+        let mut communities: BTreeMap<&String, Vec<(String, usize)>> = BTreeMap::new();
+        for (node, label) in labels {
+            if let (Some(out_degree), Some(in_degree)) = (out_degrees.get(node), in_degrees.get(node)) {
+                communities.entry(label).or_default().push((node.clone(), out_degree + in_degree));
+            }
+        }
+
+        let mut sorted: Vec<(String, Vec<(String, usize)>)> = communities
+            .into_iter()
+            .map(|(label, mut members)| {
+                members.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                (label.clone(), members)
+            })
+            .collect();
+        // Stable, so equal sizes stay in label order
+        sorted.sort_by_key(|(_, members)| std::cmp::Reverse(members.len()));
+        sorted
+    }
+
+    /// Runs label propagation once per seed in `lpa_seeds` (with the other
+    /// settings from `opts`), then Louvain and Leiden with `seed`, and
+    /// summarizes each partition along with the pairwise NMI between them.
+    pub fn compare_communities(&self, lpa_seeds: &[u64], seed: u64, opts: &LpaOptions) -> CommunityComparison {
+        let mut partitions = Vec::new();
+        let mut run = |method: String, detect: &dyn Fn() -> HashMap<String, String>| {
+            let start = Instant::now();
+            let labels = detect();
+            partitions.push(PartitionSummary::of(self, method, labels, start.elapsed()));
+        };
+        for &lpa_seed in lpa_seeds {
+            let opts = LpaOptions { seed: Some(lpa_seed), ..opts.clone() };
+            run(format!("lpa (seed {})", lpa_seed), &|| self.label_propagation_with_options(&opts));
+        }
+        run("louvain".to_string(), &|| self.louvain(seed));
+        run("leiden".to_string(), &|| self.leiden(seed));
+
+        let nmi = partitions
+            .iter()
+            .map(|a| partitions.iter().map(|b| normalized_mutual_information(&a.labels, &b.labels)).collect())
+            .collect();
+        CommunityComparison { partitions, nmi }
+    }
+}
+
+/// The partitions found by `Graph::compare_communities`, in the order they
+/// were run, and their pairwise NMI: `nmi[i][j]` compares partitions `i`
+/// and `j`.
+#[derive(Debug, Clone)]
+pub struct CommunityComparison {
+    pub partitions: Vec<PartitionSummary>,
+    pub nmi: Vec<Vec<f64>>,
+}
+
+/// One community detection run and how its partition looks.
+#[derive(Debug, Clone)]
+pub struct PartitionSummary {
+    pub method: String,
+    pub labels: HashMap<String, String>,
+    pub communities: usize,
+    pub largest: usize,
+    pub singletons: usize, // Communities with a single member
+    pub modularity: f64,
+    pub runtime: Duration,
+}
+
+impl PartitionSummary {
+    fn of(graph: &Graph, method: String, labels: HashMap<String, String>, runtime: Duration) -> Self {
+        let mut sizes: HashMap<&String, usize> = HashMap::new();
+        for label in labels.values() {
+            *sizes.entry(label).or_insert(0) += 1;
+        }
+        PartitionSummary {
+            method,
+            communities: sizes.len(),
+            largest: sizes.values().copied().max().unwrap_or(0),
+            singletons: sizes.values().filter(|&&size| size == 1).count(),
+            modularity: graph.modularity(&labels),
+            labels,
+            runtime,
+        }
+    }
+}
+
+/// Normalized mutual information between two partitions, over the nodes
+/// labeled in both: 2 I(A; B) / (H(A) + H(B)). It is 1.0 when they group
+/// the nodes identically (whatever the label names) and near 0.0 when one
+/// says nothing about the other. Two partitions that each put everyone in
+/// one community, or share no nodes, count as identical.
+pub fn normalized_mutual_information(a: &HashMap<String, String>, b: &HashMap<String, String>) -> f64 {
+    let mut joint: HashMap<(&String, &String), usize> = HashMap::new();
+    let mut a_sizes: HashMap<&String, usize> = HashMap::new();
+    let mut b_sizes: HashMap<&String, usize> = HashMap::new();
+    for (node, a_label) in a {
+        if let Some(b_label) = b.get(node) {
+            *joint.entry((a_label, b_label)).or_insert(0) += 1;
+            *a_sizes.entry(a_label).or_insert(0) += 1;
+            *b_sizes.entry(b_label).or_insert(0) += 1;
+        }
+    }
+
+    let n = a_sizes.values().sum::<usize>() as f64;
+    let entropy = |sizes: &HashMap<&String, usize>| -> f64 {
+        sizes.values().map(|&size| size as f64 / n).map(|p| -p * p.ln()).sum()
+    };
+    let entropies = entropy(&a_sizes) + entropy(&b_sizes);
+    if entropies == 0.0 {
+        return 1.0;
+    }
+    let mutual: f64 = joint
+        .iter()
+        .map(|((a_label, b_label), &count)| {
+            let p = count as f64 / n;
+            p * (p * n * n / (a_sizes[a_label] * b_sizes[b_label]) as f64).ln()
+        })
+        .sum();
+    (2.0 * mutual / entropies).clamp(0.0, 1.0)
+}
+
+/// Community label in `partition_flow` for nodes missing from the first partition.
+pub const NEW_COMMUNITY: &str = "(new)";
+/// Community label in `partition_flow` for nodes missing from the second partition.
+pub const GONE_COMMUNITY: &str = "(gone)";
+
+/// How many nodes moved from community `from` of one partition to
+/// community `to` of another, e.g. one band of an alluvial diagram.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlowEdge {
+    pub from: String,
+    pub to: String,
+    pub nodes: usize,
+}
+
+/// Counts the nodes shared by every pair of communities of `a` and `b`,
+/// one `FlowEdge` per nonempty intersection, largest flows first (ties by
+/// label). Nodes only in `b` flow from `NEW_COMMUNITY` and nodes only in
+/// `a` flow to `GONE_COMMUNITY`, so the flows out of each community of `a`
+/// add up to its size, and the flows into each community of `b` to its.
+pub fn partition_flow(a: &HashMap<String, String>, b: &HashMap<String, String>) -> Vec<FlowEdge> {
+    let mut counts: HashMap<(&str, &str), usize> = HashMap::new();
+    for (node, a_label) in a {
+        let b_label = b.get(node).map_or(GONE_COMMUNITY, String::as_str);
+        *counts.entry((a_label, b_label)).or_insert(0) += 1;
+    }
+    for (node, b_label) in b {
+        if !a.contains_key(node) {
+            *counts.entry((NEW_COMMUNITY, b_label)).or_insert(0) += 1;
+        }
+    }
+
+    let mut flows: Vec<FlowEdge> = counts
+        .into_iter()
+        .map(|((from, to), nodes)| FlowEdge { from: from.to_string(), to: to.to_string(), nodes })
+        .collect();
+    flows.sort_by(|x, y| y.nodes.cmp(&x.nodes).then_with(|| x.from.cmp(&y.from)).then_with(|| x.to.cmp(&y.to)));
+    flows
+}
+
+/// An undirected weighted graph over the indices `0..len`.
+#[derive(Debug, Clone)]
+struct WeightedGraph {
+    neighbors: Vec<Vec<(usize, f64)>>, // Sorted by index, without self-loops
+    strengths: Vec<f64>, // Total weight at each node, including weight collapsed into it
+    total_weight: f64, // Sum of all strengths, i.e. twice the total edge weight
+}
+
+impl WeightedGraph {
+    fn len(&self) -> usize {
+        self.neighbors.len()
+    }
+
+    /// Modularity of the partition giving node `i` community `membership[i]`.
+    fn modularity(&self, membership: &[usize]) -> f64 {
+        if self.total_weight <= 0.0 {
+            return 0.0;
+        }
+
+        let mut internal = vec![0.0; self.len()];
+        let mut totals = vec![0.0; self.len()];
+        for node in 0..self.len() {
+            totals[membership[node]] += self.strengths[node];
+            for &(neighbor, weight) in &self.neighbors[node] {
+                if membership[neighbor] == membership[node] {
+                    internal[membership[node]] += weight; // Each edge is seen from both ends
+                }
+            }
+        }
+
+        internal.iter()
+            .zip(&totals)
+            .map(|(inside, total)| inside / self.total_weight - (total / self.total_weight).powi(2))
+            .sum()
+    }
+
+    /// Moves single nodes to the neighboring community with the largest
+    /// modularity gain, in random order, until a full pass moves nothing.
+    /// Community ids must be below `len()`. Returns whether any node moved.
+    fn local_moves(&self, membership: &mut [usize], rng: &mut StdRng) -> bool {
+        if self.total_weight <= 0.0 {
+            return false;
+        }
+
+        let mut totals = vec![0.0; self.len()];
+        for node in 0..self.len() {
+            totals[membership[node]] += self.strengths[node];
+        }
+
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        let mut any_moved = false;
+        for _ in 0..MAX_ROUNDS {
+            order.shuffle(rng);
+            let mut moved = false;
+
+            for &node in &order {
+                let strength = self.strengths[node];
+                let current = membership[node];
+
+                // Weight from this node into each neighboring community
+                let mut links: BTreeMap<usize, f64> = BTreeMap::new();
+                for &(neighbor, weight) in &self.neighbors[node] {
+                    *links.entry(membership[neighbor]).or_insert(0.0) += weight;
+                }
+
+                // Gain of joining a community, up to a factor shared by all of them
+                totals[current] -= strength;
+                let gain = |community: usize, link: f64| link - totals[community] * strength / self.total_weight;
+                let mut best = current;
+                let mut best_gain = gain(current, links.get(&current).copied().unwrap_or(0.0));
+                for (&community, &link) in &links {
+                    let candidate = gain(community, link);
+                    if candidate > best_gain + MIN_GAIN {
+                        best = community;
+                        best_gain = candidate;
+                    }
+                }
+                totals[best] += strength;
+
+                if best != current {
+                    membership[node] = best;
+                    moved = true;
+                }
+            }
+
+            any_moved |= moved;
+            if !moved {
+                break;
+            }
+        }
+        any_moved
+    }
+
+    /// Collapses every community into one node. Returns the smaller graph
+    /// and, for each node of this one, the index of its community in it.
+    fn aggregate(&self, membership: &[usize]) -> (WeightedGraph, Vec<usize>) {
+        // Number communities in order of their first member
+        let mut renumbered: HashMap<usize, usize> = HashMap::new();
+        let collapsed: Vec<usize> = membership
+            .iter()
+            .map(|community| {
+                let next = renumbered.len();
+                *renumbered.entry(*community).or_insert(next)
+            })
+            .collect();
+
+        let count = renumbered.len();
+        let mut strengths = vec![0.0; count];
+        let mut weights: Vec<BTreeMap<usize, f64>> = vec![BTreeMap::new(); count];
+        for node in 0..self.len() {
+            strengths[collapsed[node]] += self.strengths[node];
+            for &(neighbor, weight) in &self.neighbors[node] {
+                if collapsed[neighbor] != collapsed[node] {
+                    *weights[collapsed[node]].entry(collapsed[neighbor]).or_insert(0.0) += weight;
+                }
+            }
+        }
+
+        let graph = WeightedGraph {
+            neighbors: weights.into_iter().map(|row| row.into_iter().collect()).collect(),
+            strengths,
+            total_weight: self.total_weight,
+        };
+        (graph, collapsed)
+    }
+
+    /// Runs Louvain from singleton communities and returns the community of each node.
+    fn louvain(&self, rng: &mut StdRng) -> Vec<usize> {
+        let mut membership: Vec<usize> = (0..self.len()).collect();
+        let mut level = self.clone();
+        for _ in 0..MAX_ROUNDS {
+            let mut communities: Vec<usize> = (0..level.len()).collect();
+            if !level.local_moves(&mut communities, rng) {
+                break;
+            }
+            let (next, collapsed) = level.aggregate(&communities);
+            for community in membership.iter_mut() {
+                *community = collapsed[*community];
+            }
+            level = next;
+        }
+        membership
+    }
+
+    /// Alternates splitting disconnected communities and local moves until
+    /// no node moves, then checks that every community is connected.
+    fn refine(&self, membership: &mut Vec<usize>, rng: &mut StdRng) {
+        for _ in 0..MAX_ROUNDS {
+            *membership = self.split_disconnected(membership);
+            if !self.local_moves(membership, rng) {
+                break;
+            }
+        }
+        // The last round may have moved nodes if the cap was hit, which can
+        // disconnect the community they left, so split once more
+        *membership = self.split_disconnected(membership);
+        assert!(self.is_connected_partition(membership), "refined partition has a disconnected community");
+    }
+
+    /// Gives every connected part of every community its own id, numbered
+    /// in order of the smallest node in each part.
+    fn split_disconnected(&self, membership: &[usize]) -> Vec<usize> {
+        let mut split = vec![usize::MAX; self.len()];
+        let mut next = 0;
+        for start in 0..self.len() {
+            if split[start] != usize::MAX {
+                continue;
+            }
+            split[start] = next;
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                for &(neighbor, _) in &self.neighbors[node] {
+                    if split[neighbor] == usize::MAX && membership[neighbor] == membership[start] {
+                        split[neighbor] = next;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            next += 1;
+        }
+        split
+    }
+
+    /// Whether every community is connected, i.e. splitting changes nothing.
+    fn is_connected_partition(&self, membership: &[usize]) -> bool {
+        let communities: HashSet<&usize> = membership.iter().collect();
+        let parts: HashSet<usize> = self.split_disconnected(membership).into_iter().collect();
+        communities.len() == parts.len()
+    }
+}
+
+/// The undirected projection of a `Graph`, with nodes numbered in sorted order.
+struct Projection {
+    nodes: Vec<String>,
+    graph: WeightedGraph,
+}
+
+impl Projection {
+    fn of(graph: &Graph) -> Projection {
+        let mut nodes: Vec<String> = graph.adjacency_list.keys().cloned().collect();
+        nodes.sort();
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, node)| (node, i)).collect();
+
+        let mut weights: Vec<BTreeMap<usize, f64>> = vec![BTreeMap::new(); nodes.len()];
+        for (from, recipients) in &graph.adjacency_list {
+            for to in recipients {
+                let (a, b) = (index[from], index[to]);
+                if a == b {
+                    continue;
+                }
+                let weight = graph.edge_weight(from, to).unwrap_or(1.0);
+                *weights[a].entry(b).or_insert(0.0) += weight;
+                *weights[b].entry(a).or_insert(0.0) += weight;
+            }
+        }
+
+        let neighbors: Vec<Vec<(usize, f64)>> = weights
+            .into_iter()
+            .map(|row| row.into_iter().collect())
+            .collect();
+        let strengths: Vec<f64> = neighbors
+            .iter()
+            .map(|row| row.iter().map(|(_, weight)| weight).sum())
+            .collect();
+        let total_weight = strengths.iter().sum();
+        Projection { nodes, graph: WeightedGraph { neighbors, strengths, total_weight } }
+    }
+
+    /// Numbers the communities of `labels`, giving unlabeled and
+    /// unassigned nodes a community of their own.
+    fn membership(&self, labels: &HashMap<String, String>) -> Vec<usize> {
+        let mut ids: HashMap<&String, usize> = HashMap::new();
+        let mut membership = Vec::with_capacity(self.nodes.len());
+        for (i, node) in self.nodes.iter().enumerate() {
+            let id = match labels.get(node) {
+                Some(label) if label != UNASSIGNED_LABEL => *ids.entry(label).or_insert(i),
+                _ => i,
+            };
+            membership.push(id);
+        }
+        membership
+    }
+
+    /// Labels every node with the smallest member of its community.
+    fn labels(&self, membership: &[usize]) -> HashMap<String, String> {
+        let mut names: HashMap<usize, &String> = HashMap::new();
+        for (node, community) in self.nodes.iter().zip(membership) {
+            names.entry(*community).or_insert(node); // Nodes are sorted, so the first is smallest
+        }
+        self.nodes
+            .iter()
+            .zip(membership)
+            .map(|(node, community)| (node.clone(), names[community].clone()))
+            .collect()
+    }
+}
+
+/// Reads known community labels from a CSV file of `address,label` rows,
+/// for `Graph::label_propagation_seeded`, in the format of `read_partition`.
+pub fn read_seed_labels(path: &Path) -> Result<HashMap<String, String>, csv::Error> {
+    read_partition(path)
+}
+
+/// Writes a community partition as CSV, one `address,label` row per node
+/// after an `address,label` header, sorted by address.
+pub fn write_partition(path: &Path, labels: &HashMap<String, String>) -> io::Result<()> {
+    let mut rows: Vec<(&String, &String)> = labels.iter().collect();
+    rows.sort();
+
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["address", "label"])?;
+    for (address, label) in rows {
+        writer.write_record([address, label])?;
+    }
+    writer.flush()
+}
+
+/// Reads a community partition from a CSV file of `address,label` rows, as
+/// written by `write_partition`. A first row of `address,label` is skipped
+/// as a header, addresses are normalized, and rows with an empty address or
+/// label are ignored. Later rows win for repeated addresses.
+pub fn read_partition(path: &Path) -> Result<HashMap<String, String>, csv::Error> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+
+    let mut seeds = HashMap::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let address = record.get(0).unwrap_or_default().trim();
+        let label = record.get(1).unwrap_or_default().trim();
+        if i == 0 && address.eq_ignore_ascii_case("address") && label.eq_ignore_ascii_case("label") {
+            continue;
+        }
+        if address.is_empty() || label.is_empty() {
+            continue;
+        }
+        seeds.insert(normalize_address(address), label.to_string());
+    }
+    Ok(seeds)
+}
+
+/// A partition read from a file, fitted to a graph by `fit_partition`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FittedPartition {
+    pub labels: HashMap<String, String>, // A label for every node of the graph
+    pub missing: Vec<String>, // Nodes the file had no label for, sorted; each got its own community
+    pub ignored: usize, // Entries of the file for nodes not in the graph
+}
+
+/// Fits a partition loaded with `read_partition` to `graph`: entries for
+/// nodes that aren't in the graph are dropped, and each node without an
+/// entry becomes a singleton community labeled with its own address (with
+/// a `#2`, `#3`, ... suffix should that already be a label).
+pub fn fit_partition(graph: &Graph, mut partition: HashMap<String, String>) -> FittedPartition {
+    let before = partition.len();
+    partition.retain(|node, _| graph.adjacency_list.contains_key(node));
+    let ignored = before - partition.len();
+
+    let mut missing: Vec<String> = graph.adjacency_list
+        .keys()
+        .filter(|node| !partition.contains_key(*node))
+        .cloned()
+        .collect();
+    missing.sort();
+    let mut taken: HashSet<String> = partition.values().cloned().collect();
+    for node in &missing {
+        let mut label = node.clone();
+        let mut suffix = 1;
+        while taken.contains(&label) {
+            suffix += 1;
+            label = format!("{}#{}", node, suffix);
+        }
+        taken.insert(label.clone());
+        partition.insert(node.clone(), label);
+    }
+    FittedPartition { labels: partition, missing, ignored }
+}
@@ -0,0 +1,430 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::email::CsvSchema;
+use crate::error::Error;
+use crate::filter::EmailFilter;
+use crate::graph::{DistributionListOptions, GraphBuildOptions, LpaOptions};
+use crate::logger::Verbosity;
+
+/// Every key accepted in an analysis config file, used for unknown-key warnings.
+pub const VALID_KEYS: &[&str] = &[
+    "input.path",
+    "input.format",
+    "input.strict",
+    "input.node_tags",
+    "input.schema.sender_column",
+    "input.schema.recipient_columns",
+    "input.schema.date_column",
+    "input.schema.subject_column",
+    "input.schema.body_column",
+    "input.schema.index_column",
+    "input.schema.delimiter",
+    "filter.after",
+    "filter.before",
+    "filter.include_undated",
+    "filter.keep_senders_without_recipients",
+    "filter.max_address_length",
+    "filter.subject_regex",
+    "filter.body_regex",
+    "filter.blocklist",
+    "filter.allowlist",
+    "graph.min_degree",
+    "graph.drop_self_loops",
+    "graph.min_edge_weight",
+    "graph.drop_isolated",
+    "graph.giant_component_only",
+    "graph.edge_sample",
+    "graph.edge_sample_seed",
+    "graph.edge_sample_stratified",
+    "graph.broadcast_threshold",
+    "graph.drop_broadcasts",
+    "algorithms.community_method",
+    "algorithms.lpa_seed",
+    "algorithms.max_iterations",
+    "algorithms.lpa_undirected",
+    "algorithms.lpa_weighted",
+    "algorithms.min_community_size",
+    "algorithms.seed_labels",
+    "algorithms.communities_file",
+    "algorithms.mark_unlabeled",
+    "algorithms.mutual_only",
+    "algorithms.exclude_sources",
+    "algorithms.exclude_sinks",
+    "algorithms.consensus_runs",
+    "algorithms.null_model",
+    "algorithms.enable_pagerank",
+    "algorithms.pagerank_variant",
+    "algorithms.enable_harmonic",
+    "algorithms.harmonic_sample",
+    "algorithms.enable_betweenness",
+    "algorithms.betweenness_sources",
+    "algorithms.betweenness_seed",
+    "algorithms.enable_edge_betweenness",
+    "algorithms.enable_reachability",
+    "algorithms.reachability_sample",
+    "algorithms.reachability_seed",
+    "algorithms.enable_eccentricity",
+    "algorithms.eccentricity_sample",
+    "algorithms.eccentricity_seed",
+    "algorithms.distance_pairs",
+    "algorithms.distance_seed",
+    "algorithms.list_min_emails",
+    "algorithms.list_min_senders",
+    "algorithms.list_max_sent_ratio",
+    "algorithms.outlier_z",
+    "algorithms.enable_keywords",
+    "algorithms.keywords_top_k",
+    "output.top_n",
+    "output.find_limit",
+    "output.report_json",
+    "output.node_csv",
+    "output.graphml",
+    "output.backbone_graphml",
+    "output.edge_activity",
+    "output.html",
+    "output.html_max_nodes",
+    "output.matrix_market",
+    "output.neo4j_dir",
+    "output.sqlite",
+    "output.community_sizes",
+    "output.community_report",
+    "output.keywords_csv",
+    "output.domain_csv",
+    "output.partition_csv",
+    "output.anonymize",
+    "output.preserve_domains",
+    "output.address_map",
+    "output.verbose",
+    "output.quiet",
+];
+
+/// Settings for one run of the analysis pipeline, usually loaded from a TOML file.
+/// Every section and key is optional; missing values fall back to the defaults.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub input: InputConfig,
+    pub filter: EmailFilter,
+    pub graph: GraphConfig,
+    pub algorithms: AlgorithmConfig,
+    pub output: OutputConfig,
+}
+
+/// `[input]`: where to read emails from and how the columns are named.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    pub path: Option<String>, // May end in a `*`/`?` pattern to read several files
+    pub format: InputFormat,
+    pub strict: bool, // Stop instead of skipping a file of a pattern that can't be read
+    pub node_tags: Option<PathBuf>, // CSV of address,key,value attributes to attach to people
+    pub schema: CsvSchema,
+}
+
+/// Layout of the input data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    /// A CSV export with one row per email
+    #[default]
+    Csv,
+    /// A Maildir directory (cur/ and new/) or a directory of .eml files
+    Maildir,
+    /// A Parquet file with one row per email, columns named as for CSV
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// Algorithm used to detect communities.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CommunityMethod {
+    /// Label propagation
+    #[default]
+    Lpa,
+    /// Louvain modularity optimization
+    Louvain,
+    /// Louvain followed by a refinement that keeps every community connected
+    Leiden,
+}
+
+impl CommunityMethod {
+    /// Name of the method as shown in the report.
+    pub fn name(self) -> &'static str {
+        match self {
+            CommunityMethod::Lpa => "label propagation",
+            CommunityMethod::Louvain => "Louvain",
+            CommunityMethod::Leiden => "Leiden",
+        }
+    }
+}
+
+/// Which PageRank scores to report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PagerankVariant {
+    /// Every edge counts the same
+    #[default]
+    Unweighted,
+    /// Edges count in proportion to the emails sent along them
+    Weighted,
+    /// Report both rankings
+    Both,
+}
+
+/// `[graph]`: preprocessing applied while building the graph.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct GraphConfig {
+    pub min_degree: usize, // Nodes with a smaller total degree are pruned
+    pub drop_self_loops: bool,
+    pub min_edge_weight: usize, // Edges with fewer emails are removed
+    pub drop_isolated: bool, // Remove people left without edges by min_edge_weight
+    pub giant_component_only: bool, // Analyze only the largest weakly connected component
+    pub edge_sample: Option<f64>, // Keep each edge with this probability, to test robustness to missing data
+    pub edge_sample_seed: u64,
+    pub edge_sample_stratified: bool, // Give every node that loses all its edges one of them back
+    pub broadcast_threshold: Option<usize>, // Emails with more recipients count as broadcasts in the report (default 50)
+    pub drop_broadcasts: Option<usize>, // Leave emails with more recipients out of the graph; also sets the broadcast threshold
+}
+
+impl GraphConfig {
+    /// Returns the options used when building the graph from emails.
+    pub fn build_options(&self) -> GraphBuildOptions {
+        GraphBuildOptions {
+            drop_self_loops: self.drop_self_loops,
+        }
+    }
+
+    /// Returns the recipient count above which an email is a broadcast.
+    pub fn broadcast_threshold(&self) -> usize {
+        self.drop_broadcasts
+            .or(self.broadcast_threshold)
+            .unwrap_or(crate::email::DEFAULT_BROADCAST_THRESHOLD)
+    }
+}
+
+/// `[algorithms]`: which analyses to run and their parameters.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AlgorithmConfig {
+    pub community_method: CommunityMethod,
+    pub lpa_seed: Option<u64>, // Also seeds Louvain and Leiden (0 if unset)
+    pub max_iterations: usize,
+    pub lpa_undirected: bool,
+    pub lpa_weighted: bool, // Weigh neighbors by the emails exchanged with them
+    pub min_community_size: usize,
+    pub seed_labels: HashMap<String, String>, // Known address -> community label, kept fixed by label propagation
+    pub communities_file: Option<PathBuf>, // Load the communities from this address,label CSV instead of detecting them
+    pub mark_unlabeled: bool, // Label people no seed label reaches "unlabeled"
+    pub mutual_only: bool, // Detect communities on reciprocated edges only
+    pub exclude_sources: bool, // Leave people who only send out of community detection
+    pub exclude_sinks: bool, // Leave people who only receive out of community detection
+    pub consensus_runs: Option<usize>, // Repeat label propagation this many times and report how stable communities are
+    pub null_model: Option<usize>, // Compare modularity and clustering against this many degree-preserving rewirings
+    pub enable_pagerank: bool,
+    pub pagerank_variant: PagerankVariant,
+    pub enable_harmonic: bool,
+    pub harmonic_sample: Option<usize>, // Search from only this many random sources
+    pub enable_betweenness: bool,
+    pub betweenness_sources: Option<usize>, // Approximate betweenness from this many random sources
+    pub betweenness_seed: u64,
+    pub enable_edge_betweenness: bool, // Rank the edges most shortest paths run through
+    pub enable_reachability: bool,
+    pub reachability_sample: Option<usize>, // Only measure reach for this many random nodes
+    pub reachability_seed: u64,
+    pub enable_eccentricity: bool,
+    pub eccentricity_sample: Option<usize>, // Only measure eccentricity for this many random nodes
+    pub eccentricity_seed: u64,
+    pub distance_pairs: Option<usize>, // Sample this many pairs for the hop-count distribution
+    pub distance_seed: u64,
+    pub list_min_emails: usize, // Distribution lists receive at least this many emails...
+    pub list_min_senders: usize, // ...from at least this many people...
+    pub list_max_sent_ratio: f64, // ...and send at most this fraction of what they receive
+    pub outlier_z: f64, // Report people whose log-degree is this many standard deviations from the mean
+    pub enable_keywords: bool, // Profile communities by the words in their subjects
+    pub keywords_top_k: usize,
+}
+
+impl Default for AlgorithmConfig {
+    fn default() -> Self {
+        AlgorithmConfig {
+            community_method: CommunityMethod::default(),
+            lpa_seed: None,
+            max_iterations: LpaOptions::default().max_iterations,
+            lpa_undirected: false,
+            lpa_weighted: false,
+            min_community_size: LpaOptions::default().min_community_size,
+            seed_labels: HashMap::new(),
+            communities_file: None,
+            mark_unlabeled: false,
+            mutual_only: false,
+            exclude_sources: false,
+            exclude_sinks: false,
+            consensus_runs: None,
+            null_model: None,
+            enable_pagerank: false,
+            pagerank_variant: PagerankVariant::default(),
+            enable_harmonic: false,
+            harmonic_sample: None,
+            enable_betweenness: false,
+            betweenness_sources: None,
+            betweenness_seed: 0,
+            enable_edge_betweenness: false,
+            enable_reachability: false,
+            reachability_sample: None,
+            reachability_seed: 0,
+            enable_eccentricity: false,
+            eccentricity_sample: None,
+            eccentricity_seed: 0,
+            distance_pairs: None,
+            distance_seed: 0,
+            list_min_emails: DistributionListOptions::default().min_emails_received,
+            list_min_senders: DistributionListOptions::default().min_senders,
+            list_max_sent_ratio: DistributionListOptions::default().max_sent_ratio,
+            outlier_z: 3.0,
+            enable_keywords: false,
+            keywords_top_k: 10,
+        }
+    }
+}
+
+impl AlgorithmConfig {
+    /// Returns the label propagation options described by this section.
+    pub fn lpa_options(&self) -> LpaOptions {
+        LpaOptions {
+            max_iterations: self.max_iterations,
+            seed: self.lpa_seed,
+            use_undirected_neighbors: self.lpa_undirected,
+            weighted: self.lpa_weighted,
+            min_community_size: self.min_community_size,
+            mark_unlabeled: self.mark_unlabeled,
+        }
+    }
+
+    /// Returns the thresholds for flagging distribution lists.
+    pub fn distribution_list_options(&self) -> DistributionListOptions {
+        DistributionListOptions {
+            min_emails_received: self.list_min_emails,
+            min_senders: self.list_min_senders,
+            max_sent_ratio: self.list_max_sent_ratio,
+        }
+    }
+}
+
+/// `[output]`: report size, optional export files and anonymization.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub top_n: usize,
+    pub find_limit: usize, // Most candidates listed when a typed address matches several people
+    pub report_json: Option<PathBuf>,
+    pub node_csv: Option<PathBuf>,
+    pub graphml: Option<PathBuf>,
+    pub backbone_graphml: Option<PathBuf>, // GraphML of the maximum spanning forest only
+    pub edge_activity: bool, // Add each edge's first and last email and active days to edge lists and GraphML
+    pub html: Option<PathBuf>, // Self-contained HTML drawing of the communities
+    pub html_max_nodes: usize, // Larger graphs are cut down to their highest-degree people
+    pub matrix_market: Option<PathBuf>, // Sparse adjacency matrix, with node_index.csv alongside
+    pub neo4j_dir: Option<PathBuf>, // nodes.csv and relationships.csv for neo4j-admin import
+    pub sqlite: Option<PathBuf>, // Database of emails, nodes and edges; needs the `sqlite` feature
+    pub community_sizes: Option<PathBuf>, // CSV of label,size per community
+    pub community_report: Option<PathBuf>, // Every community with its members by degree; Markdown if it ends in .md
+    pub keywords_csv: Option<PathBuf>, // CSV of the keyword profile of every community
+    pub domain_csv: Option<PathBuf>, // CSV of the per-domain statistics
+    pub partition_csv: Option<PathBuf>, // Every person's community as address,label, readable by algorithms.communities_file
+    pub anonymize: bool, // Replace addresses with pseudonyms before any analysis
+    pub preserve_domains: bool, // Keep the real domain in pseudonyms
+    pub address_map: Option<PathBuf>, // CSV mapping loaded (if present) and saved when anonymizing
+    pub verbose: bool, // Like -v: list skipped records and report memory use
+    pub quiet: bool, // Like --quiet: no progress, diagnostics or analysis report
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            top_n: 10,
+            find_limit: crate::graph::DEFAULT_FIND_LIMIT,
+            report_json: None,
+            node_csv: None,
+            graphml: None,
+            backbone_graphml: None,
+            edge_activity: false,
+            html: None,
+            html_max_nodes: crate::export::DEFAULT_HTML_MAX_NODES,
+            matrix_market: None,
+            neo4j_dir: None,
+            sqlite: None,
+            community_sizes: None,
+            community_report: None,
+            keywords_csv: None,
+            domain_csv: None,
+            partition_csv: None,
+            anonymize: false,
+            preserve_domains: false,
+            address_map: None,
+            verbose: false,
+            quiet: false,
+        }
+    }
+}
+
+impl OutputConfig {
+    /// Returns the verbosity set by `quiet` and `verbose`; `quiet` wins.
+    pub fn verbosity(&self) -> Verbosity {
+        if self.quiet {
+            Verbosity::Quiet
+        } else if self.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+}
+
+impl Config {
+    /// Parses a config from TOML text.
+    /// Returns the config together with the dotted paths of any unknown keys.
+    pub fn from_toml_str(text: &str) -> Result<(Config, Vec<String>), toml::de::Error> {
+        let mut unknown_keys = Vec::new();
+        let config = serde_ignored::deserialize(toml::Deserializer::new(text), |path| {
+            unknown_keys.push(path.to_string());
+        })?;
+        Ok((config, unknown_keys))
+    }
+
+    /// Reads and parses a config file.
+    pub fn load(path: &Path) -> Result<(Config, Vec<String>), Error> {
+        let text = fs::read_to_string(path).map_err(|source| Error::ConfigRead {
+            path: path.display().to_string(),
+            source,
+        })?;
+        Config::from_toml_str(&text).map_err(|source| Error::ConfigParse {
+            path: path.display().to_string(),
+            source,
+        })
+    }
+}
+
+/// Builds the warning shown for an unknown config key, listing the valid
+/// keys of the same section (or all keys if the section itself is unknown).
+pub fn unknown_key_warning(key: &str) -> String {
+    let section = key.split('.').next().unwrap_or(key);
+    let prefix = format!("{}.", section);
+    let mut candidates: Vec<&str> = VALID_KEYS
+        .iter()
+        .copied()
+        .filter(|valid| valid.starts_with(&prefix))
+        .collect();
+    if candidates.is_empty() {
+        candidates = VALID_KEYS.to_vec();
+    }
+    format!(
+        "unknown config key '{}' (valid keys: {})",
+        key,
+        candidates.join(", ")
+    )
+}
@@ -0,0 +1,259 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use std::collections::{HashMap, VecDeque};
+use crate::email::ParsedEmail;
+use crate::graph::{Direction, Graph, LpaOptions};
+
+/// A read-only email graph with interned addresses and compressed sparse
+/// row (CSR) adjacency: node `i` is `names[i]`, its recipients are
+/// `targets[offsets[i]..offsets[i + 1]]` and its senders the same range of
+/// the reverse arrays. Ids follow the sorted order of the addresses and
+/// every neighbor range is sorted, so anything ordered by id is ordered by
+/// address, as in `Graph`.
+#[derive(Debug, Clone, Default)]
+pub struct CsrGraph {
+    names: Vec<String>, // Address of each node id
+    index: HashMap<String, u32>, // Node id of each address
+    offsets: Vec<usize>, // Start of each node's recipients in `targets`, plus the end
+    targets: Vec<u32>, // Recipients of all nodes, grouped by sender
+    counts: Vec<usize>, // Emails along each edge, parallel to `targets`
+    reverse_offsets: Vec<usize>, // Start of each node's senders in `sources`, plus the end
+    sources: Vec<u32>, // Senders of all nodes, grouped by recipient
+}
+
+/// Labels from `CsrGraph::label_propagation`, one label id per node id.
+#[derive(Debug, Clone)]
+pub struct CsrLpaResult {
+    pub labels: Vec<u32>,
+    pub iterations_run: usize, // Full passes over the nodes, including the final unchanged one
+    pub converged: bool, // A pass changed no label before the iteration cap was hit
+}
+
+impl CsrGraph {
+    /// Builds the graph from parsed emails. Like `Graph::build_from_emails`,
+    /// senders without recipients are left out and repeated emails along an
+    /// edge only add to its count.
+    pub fn build_from_emails(emails: &[ParsedEmail]) -> Self {
+        // Intern in order of appearance first, then renumber by address
+        let mut first_ids: HashMap<&str, u32> = HashMap::new();
+        let mut first_names: Vec<&str> = Vec::new();
+        let mut edges: Vec<(u32, u32)> = Vec::new();
+        for email in emails.iter().filter(|email| !email.to.is_empty()) {
+            let from = intern(&mut first_ids, &mut first_names, &email.from);
+            for to in &email.to {
+                let to = intern(&mut first_ids, &mut first_names, to);
+                edges.push((from, to));
+            }
+        }
+
+        let mut order: Vec<u32> = (0..first_names.len() as u32).collect();
+        order.sort_unstable_by_key(|&id| first_names[id as usize]);
+        let mut rank = vec![0; order.len()];
+        for (id, &first_id) in order.iter().enumerate() {
+            rank[first_id as usize] = id as u32;
+        }
+        let names: Vec<String> = order.iter().map(|&first_id| first_names[first_id as usize].to_string()).collect();
+        let index = names.iter().enumerate().map(|(id, name)| (name.clone(), id as u32)).collect();
+
+        for edge in &mut edges {
+            *edge = (rank[edge.0 as usize], rank[edge.1 as usize]);
+        }
+        edges.sort_unstable();
+        let mut pairs: Vec<(u32, u32)> = Vec::with_capacity(edges.len());
+        let mut counts: Vec<usize> = Vec::with_capacity(edges.len());
+        for edge in edges {
+            if pairs.last() == Some(&edge) {
+                *counts.last_mut().expect("counts run parallel to pairs") += 1;
+            } else {
+                pairs.push(edge);
+                counts.push(1);
+            }
+        }
+
+        let (offsets, targets) = compress(names.len(), &pairs);
+        let mut reversed: Vec<(u32, u32)> = pairs.iter().map(|&(from, to)| (to, from)).collect();
+        reversed.sort_unstable();
+        let (reverse_offsets, sources) = compress(names.len(), &reversed);
+        CsrGraph { names, index, offsets, targets, counts, reverse_offsets, sources }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Number of distinct directed edges.
+    pub fn edge_count(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// Returns the address of node `id`.
+    pub fn name(&self, id: u32) -> &str {
+        &self.names[id as usize]
+    }
+
+    /// Returns the addresses of `ids`, in the same order.
+    pub fn names_of(&self, ids: &[u32]) -> Vec<&str> {
+        ids.iter().map(|&id| self.name(id)).collect()
+    }
+
+    /// Returns the id of `address`, or `None` if it is not in the graph.
+    pub fn id(&self, address: &str) -> Option<u32> {
+        self.index.get(address).copied()
+    }
+
+    /// Recipients of node `id`, sorted.
+    pub fn recipients(&self, id: u32) -> &[u32] {
+        &self.targets[self.offsets[id as usize]..self.offsets[id as usize + 1]]
+    }
+
+    /// Senders to node `id`, sorted.
+    pub fn senders(&self, id: u32) -> &[u32] {
+        &self.sources[self.reverse_offsets[id as usize]..self.reverse_offsets[id as usize + 1]]
+    }
+
+    /// Number of emails from `from` to `to`, or `None` if there is no such edge.
+    pub fn email_count(&self, from: u32, to: u32) -> Option<usize> {
+        let start = self.offsets[from as usize];
+        self.recipients(from).binary_search(&to).ok().map(|position| self.counts[start + position])
+    }
+
+    /// Returns true if `graph` has the same nodes and edges, with each
+    /// edge weighing as many emails as it counts here.
+    pub fn matches(&self, graph: &Graph) -> bool {
+        self.node_count() == graph.node_count()
+            && self.edge_count() == graph.edge_count()
+            && (0..self.node_count() as u32).all(|from| {
+                graph.adjacency_list.contains_key(self.name(from))
+                    && self.recipients(from).iter().all(|&to| {
+                        graph.edge_weight(self.name(from), self.name(to)) == self.email_count(from, to).map(|count| count as f64)
+                    })
+            })
+    }
+
+    /// Out-degree of every node, indexed by id.
+    pub fn out_degrees(&self) -> Vec<usize> {
+        self.offsets.windows(2).map(|range| range[1] - range[0]).collect()
+    }
+
+    /// In-degree of every node, indexed by id.
+    pub fn in_degrees(&self) -> Vec<usize> {
+        self.reverse_offsets.windows(2).map(|range| range[1] - range[0]).collect()
+    }
+
+    /// Like `Graph::bfs_layers`: the nodes at each distance from `source`,
+    /// following edges in `direction`, each layer sorted.
+    pub fn bfs_layers(&self, source: u32, direction: Direction) -> Vec<Vec<u32>> {
+        let mut distances = vec![usize::MAX; self.node_count()];
+        distances[source as usize] = 0;
+        let mut layers: Vec<Vec<u32>> = vec![vec![source]];
+        let mut queue = VecDeque::from([source]);
+        while let Some(current) = queue.pop_front() {
+            let distance = distances[current as usize] + 1;
+            let recipients = if direction != Direction::In { self.recipients(current) } else { &[] };
+            let senders = if direction != Direction::Out { self.senders(current) } else { &[] };
+            for &neighbor in recipients.iter().chain(senders) {
+                if distances[neighbor as usize] == usize::MAX {
+                    distances[neighbor as usize] = distance;
+                    if layers.len() <= distance {
+                        layers.push(Vec::new());
+                    }
+                    layers[distance].push(neighbor);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        for layer in &mut layers {
+            layer.sort_unstable();
+        }
+        layers
+    }
+
+    /// Label propagation with the update rule, node order and random
+    /// choices of `Graph::label_propagation_detailed`, so both give the same
+    /// labels for the same seed. Only `max_iterations`, `seed` and
+    /// `use_undirected_neighbors` are honored: every neighbor counts once
+    /// and small communities are kept as they are.
+    pub fn label_propagation(&self, opts: &LpaOptions) -> CsrLpaResult {
+        let mut rng = match opts.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        // Ids are in address order, which is where `Graph` starts shuffling from
+        let mut labels: Vec<u32> = (0..self.node_count() as u32).collect();
+        let mut nodes = labels.clone();
+        let mut label_counts = vec![0usize; self.node_count()];
+        let mut counted: Vec<u32> = Vec::new(); // Labels with a nonzero entry in label_counts
+
+        let mut iterations_run = 0;
+        let mut converged = false;
+        for _ in 0..opts.max_iterations {
+            let mut changes = 0;
+            nodes.shuffle(&mut rng);
+            for &node in &nodes {
+                let recipients = self.recipients(node);
+                let senders = if opts.use_undirected_neighbors { self.senders(node) } else { &[] };
+                // A sender who is also a recipient is one neighbor, as in the HashSet of `Graph`
+                let senders = senders.iter().filter(|sender| recipients.binary_search(sender).is_err());
+                for &neighbor in recipients.iter().chain(senders) {
+                    let label = labels[neighbor as usize];
+                    if label_counts[label as usize] == 0 {
+                        counted.push(label);
+                    }
+                    label_counts[label as usize] += 1;
+                }
+                if counted.is_empty() {
+                    continue; // No neighbors to influence the label
+                }
+
+                let max_count = counted.iter().map(|&label| label_counts[label as usize]).max().unwrap_or(0);
+                let mut best_labels: Vec<u32> = counted
+                    .iter()
+                    .copied()
+                    .filter(|&label| label_counts[label as usize] == max_count)
+                    .collect();
+                for label in counted.drain(..) {
+                    label_counts[label as usize] = 0;
+                }
+
+                if best_labels.contains(&labels[node as usize]) {
+                    continue;
+                }
+                best_labels.sort_unstable();
+                labels[node as usize] = *best_labels.choose(&mut rng).unwrap();
+                changes += 1;
+            }
+            iterations_run += 1;
+
+            if changes == 0 {
+                converged = true;
+                break;
+            }
+        }
+
+        CsrLpaResult { labels, iterations_run, converged }
+    }
+}
+
+/// Returns the id of `name`, giving it the next free one if it is new.
+fn intern<'a>(ids: &mut HashMap<&'a str, u32>, names: &mut Vec<&'a str>, name: &'a str) -> u32 {
+    *ids.entry(name).or_insert_with(|| {
+        names.push(name);
+        (names.len() - 1) as u32
+    })
+}
+
+/// Groups sorted, distinct `(node, neighbor)` pairs into CSR offsets and neighbors.
+fn compress(node_count: usize, pairs: &[(u32, u32)]) -> (Vec<usize>, Vec<u32>) {
+    let mut offsets = vec![0; node_count + 1];
+    for &(node, _) in pairs {
+        offsets[node as usize + 1] += 1;
+    }
+    let mut total = 0;
+    for offset in &mut offsets {
+        total += *offset;
+        *offset = total;
+    }
+    (offsets, pairs.iter().map(|&(_, neighbor)| neighbor).collect())
+}
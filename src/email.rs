@@ -1,95 +1,1137 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use flate2::read::GzDecoder;
 use std::fs::File;
-use std::error::Error;
-use csv::ReaderBuilder;
+use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::path::{Path, PathBuf};
+use csv::{ReaderBuilder, StringRecord};
+use crate::error::EmailError;
+use crate::filter::{glob_match, EmailFilter, FilterDecision};
+use crate::logger::Logger;
 
-/// Struct to represent each email record in the CSV
-#[derive(Debug, Deserialize)]
+/// Struct to represent each email record in the CSV, extracted by column
+/// name according to a `CsvSchema`
+#[derive(Debug)]
 pub struct EmailRecord {
-    // The first column is an unnamed index, which we'll map to 'index'
-    #[serde(rename = "")]
-    pub index: usize,
+    pub index: Option<usize>, // Value of the index column, if the schema has one
     pub date: String,
     pub sender: String,
-    pub recipient1: String,
+    pub recipients: Vec<String>, // Raw contents of every recipient column
     pub subject: String,
     pub text: String,
 }
 
-/// Struct to represent the parsed email with only 'from' and 'to' addresses
-#[derive(Debug)]
+/// Struct to represent the parsed email with its 'from' and 'to' addresses
+/// and, when the date column could be parsed, the time it was sent
+#[derive(Debug, Clone, PartialEq)]
 pub struct ParsedEmail {
     pub from: String,
     pub to: Vec<String>,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// A parsed email together with its subject, for analyses that look at
+/// content as well as who wrote to whom.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedEmailFull {
+    pub email: ParsedEmail,
+    pub subject: String,
 }
 
-/// Parses the recipient string into a vector of individual email addresses
+/// Parses the date column into a UTC timestamp.
+/// Accepts RFC 3339, RFC 2822 (as found in raw email headers, with an optional
+/// trailing "(PDT)"-style comment), and `YYYY-MM-DD[ HH:MM:SS[+zz:zz]]`.
+/// Dates without a time zone are taken to be UTC.
+pub fn parse_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    if date.is_empty() {
+        return None;
+    }
+
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(date) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+
+    // Drop a trailing zone comment such as "(PDT)" before trying RFC 2822
+    let without_comment = match date.rfind(" (") {
+        Some(position) if date.ends_with(')') => &date[..position],
+        _ => date,
+    };
+    if let Ok(parsed) = DateTime::parse_from_rfc2822(without_comment) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+
+    if let Ok(parsed) = DateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S%:z") {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    if let Ok(parsed) = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S") {
+        return Some(parsed.and_utc());
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .map(|day| day.and_time(NaiveTime::MIN).and_utc())
+}
+
+/// Parses the recipient string into a vector of individual email addresses.
+/// See `parse_recipients_with_stats` for the accepted formats.
 pub fn parse_recipients(recipient: &str) -> Vec<String> {
-    recipient
-        .split(',')
-        .map(|s| s.trim().to_lowercase())
-        .filter(|s| !s.is_empty())
+    parse_recipients_with_stats(recipient).0
+}
+
+/// Parses a recipient list such as `"Doe, Jane" <jane@corp.com>; john@corp.com`.
+///
+/// Entries are separated by `,` or `;` (separators inside quotes or angle
+/// brackets don't count). From each entry the address inside `<...>` is used
+/// if present, otherwise the whole entry; surrounding whitespace and quotes
+/// are stripped and the result is lowercased. Entries that are not a valid
+/// address (see `validate_address`) are dropped. Returns the addresses and
+/// the number of dropped entries.
+pub fn parse_recipients_with_stats(recipient: &str) -> (Vec<String>, usize) {
+    let (addresses, rejected) = parse_recipients_checked(recipient, DEFAULT_MAX_ADDRESS_LENGTH);
+    (addresses, rejected.total())
+}
+
+/// Like `parse_recipients_with_stats`, but with addresses longer than
+/// `max_length` bytes rejected, and the dropped entries counted by reason.
+pub fn parse_recipients_checked(recipient: &str, max_length: usize) -> (Vec<String>, RejectedAddresses) {
+    let mut addresses = Vec::new();
+    let mut rejected = RejectedAddresses::default();
+
+    for entry in split_address_list(recipient) {
+        if entry.trim().is_empty() {
+            continue; // Stray separator, not a real entry
+        }
+        match extract_address(entry, max_length) {
+            Ok(address) => addresses.push(address),
+            Err(reason) => rejected.record(reason),
+        }
+    }
+
+    (addresses, rejected)
+}
+
+/// Longest address accepted by default, in bytes: the limit of RFC 5321.
+pub const DEFAULT_MAX_ADDRESS_LENGTH: usize = 254;
+
+/// Why a recipient entry is not accepted as an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressRejection {
+    /// Not exactly one `@`, or nothing before or after it
+    Malformed,
+    /// Longer than the maximum address length
+    TooLong,
+    /// Contains whitespace or a control character, e.g. a pasted message body
+    InvalidCharacter,
+}
+
+/// Recipient entries dropped while parsing, by reason.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RejectedAddresses {
+    pub malformed: usize,
+    pub too_long: usize,
+    pub invalid_character: usize,
+}
+
+impl RejectedAddresses {
+    /// Returns the number of dropped entries over all reasons.
+    pub fn total(&self) -> usize {
+        self.malformed + self.too_long + self.invalid_character
+    }
+
+    fn record(&mut self, reason: AddressRejection) {
+        match reason {
+            AddressRejection::Malformed => self.malformed += 1,
+            AddressRejection::TooLong => self.too_long += 1,
+            AddressRejection::InvalidCharacter => self.invalid_character += 1,
+        }
+    }
+
+    /// Adds the counts of `other`.
+    pub fn add(&mut self, other: RejectedAddresses) {
+        self.malformed += other.malformed;
+        self.too_long += other.too_long;
+        self.invalid_character += other.invalid_character;
+    }
+}
+
+/// Checks that a normalized address is plausible: at most `max_length`
+/// bytes, free of whitespace and control characters, and made of a
+/// nonempty local part and domain around exactly one `@`. The checks run
+/// in that order, so a pasted message body counts as too long.
+pub fn validate_address(address: &str, max_length: usize) -> Result<(), AddressRejection> {
+    if address.len() > max_length {
+        return Err(AddressRejection::TooLong);
+    }
+    if address.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(AddressRejection::InvalidCharacter);
+    }
+    match address.split_once('@') {
+        Some((local, domain)) if !local.is_empty() && !domain.is_empty() && !domain.contains('@') => Ok(()),
+        _ => Err(AddressRejection::Malformed),
+    }
+}
+
+/// Splits an address list on `,` and `;`, ignoring separators that appear
+/// inside double quotes or angle brackets.
+fn split_address_list(list: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut in_quotes = false;
+    let mut in_angle = false;
+    let mut start = 0;
+
+    for (position, c) in list.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '<' if !in_quotes => in_angle = true,
+            '>' if !in_quotes => in_angle = false,
+            ',' | ';' if !in_quotes && !in_angle => {
+                entries.push(&list[start..position]);
+                start = position + 1;
+            }
+            _ => {}
+        }
+    }
+    entries.push(&list[start..]);
+
+    entries
+}
+
+/// Normalizes an address for use as a graph node: trims whitespace, takes
+/// the part inside `<...>` if a display name is present, strips surrounding
+/// quotes and lowercases the result, so `"Jane Doe" <Jane@Corp.com>` becomes
+/// `jane@corp.com`. Apply it to user-supplied addresses before graph lookups.
+pub fn normalize_address(address: &str) -> String {
+    let address = address.trim();
+    let address = match (address.rfind('<'), address.rfind('>')) {
+        (Some(open), Some(close)) if open < close => &address[open + 1..close],
+        _ => address,
+    };
+    address
+        .trim()
+        .trim_matches(|c: char| c == '"' || c == '\'' || c.is_whitespace())
+        .to_lowercase()
+}
+
+/// Returns the display name of an entry such as `"Doe, Jane" <jane@corp.com>`,
+/// with surrounding whitespace and quotes stripped, or `None` if it has no
+/// `<...>` part or nothing before it.
+pub fn display_name(entry: &str) -> Option<String> {
+    let entry = entry.trim();
+    let open = entry.rfind('<').filter(|&open| entry[open..].contains('>'))?;
+    let name = entry[..open].trim().trim_matches(|c: char| c == '"' || c == '\'' || c.is_whitespace());
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Returns the (address, display name) pair of every entry of an address
+/// list that has both, split the same way as `parse_recipients`.
+pub fn parse_display_names(list: &str) -> Vec<(String, String)> {
+    split_address_list(list)
+        .into_iter()
+        .filter_map(|entry| Some((extract_address(entry, DEFAULT_MAX_ADDRESS_LENGTH).ok()?, display_name(entry)?)))
         .collect()
 }
 
+/// Normalizes a single list entry and checks it with `validate_address`,
+/// failing with `AddressRejection::TooLong` past `max_length` bytes,
+/// `InvalidCharacter` for whitespace or control characters, and
+/// `Malformed` unless a nonempty local part and domain surround exactly one `@`.
+fn extract_address(entry: &str, max_length: usize) -> Result<String, AddressRejection> {
+    let address = normalize_address(entry);
+    validate_address(&address, max_length).map(|()| address)
+}
+
+/// Returns true for optional recipient columns: recipient2..N, cc and bcc.
+fn is_extra_recipient_column(header: &str) -> bool {
+    let header = header.trim().to_lowercase();
+    header == "cc" || header == "bcc" || header.starts_with("recipient")
+}
+
+/// Merges the parsed addresses of several recipient fields, keeping the
+/// first occurrence of each address. Also returns the entries dropped for
+/// not being a plausible address, by reason.
+fn merge_recipients<'a>(fields: impl IntoIterator<Item = &'a str>, max_length: usize) -> (Vec<String>, RejectedAddresses) {
+    let mut seen = HashSet::new();
+    let mut merged = Vec::new();
+    let mut rejected = RejectedAddresses::default();
+
+    for field in fields {
+        let (addresses, field_rejected) = parse_recipients_checked(field, max_length);
+        rejected.add(field_rejected);
+        merged.extend(addresses.into_iter().filter(|address| seen.insert(address.clone())));
+    }
+
+    (merged, rejected)
+}
+
+/// Result of reading a CSV file: the successfully parsed emails together with
+/// every row-level problem encountered along the way.
+#[derive(Debug, Default)]
+pub struct ParseOutcome {
+    pub emails: Vec<ParsedEmail>,
+    pub subjects: Vec<String>, // Subject of each email, in the same order as `emails`
+    pub display_names: HashMap<String, String>, // First display name seen for each address of the parsed emails
+    pub row_errors: Vec<EmailError>,
+    pub outside_date_range: usize, // Valid rows dropped by the filter's date range
+    pub undated_excluded: usize, // Rows dropped because a date range was set but their date didn't parse
+    pub pattern_excluded: usize, // Rows dropped because their subject or body didn't match the filter
+    pub address_excluded: usize, // Rows dropped because the sender or every recipient was filtered out
+    pub blocked_recipients: usize, // Recipient entries removed by the address lists
+    pub rejected_recipients: RejectedAddresses, // Recipient entries that are not a plausible address, by reason
+    /// Senders of records without any recipient address, one per record,
+    /// kept because of `EmailFilter::keep_senders_without_recipients`
+    pub senders_without_recipients: Vec<String>,
+}
+
+impl ParseOutcome {
+    /// Returns the number of rows that were skipped.
+    pub fn failed_count(&self) -> usize {
+        self.row_errors.len()
+    }
+
+    /// Returns the number of rows (or messages) read: parsed, failed or excluded.
+    pub fn row_count(&self) -> usize {
+        self.emails.len()
+            + self.failed_count()
+            + self.outside_date_range
+            + self.undated_excluded
+            + self.pattern_excluded
+            + self.address_excluded
+            + self.senders_without_recipients.len()
+    }
+
+    /// Returns the number of recipients over all parsed emails, i.e. how many
+    /// edge additions building the graph will take.
+    pub fn recipient_count(&self) -> usize {
+        self.emails.iter().map(|email| email.to.len()).sum()
+    }
+
+    /// Roughly estimates the heap memory held by the parsed emails and
+    /// subjects: the vectors' capacity plus the bytes of every string.
+    /// Row errors are left out.
+    pub fn estimate_memory_bytes(&self) -> usize {
+        let emails = self.emails.capacity() * std::mem::size_of::<ParsedEmail>()
+            + self.emails
+                .iter()
+                .map(|email| {
+                    email.from.capacity()
+                        + email.to.capacity() * std::mem::size_of::<String>()
+                        + email.to.iter().map(String::capacity).sum::<usize>()
+                })
+                .sum::<usize>();
+        let subjects = self.subjects.capacity() * std::mem::size_of::<String>()
+            + self.subjects.iter().map(String::capacity).sum::<usize>();
+        emails + subjects
+    }
+
+    /// Reports every skipped row of `file_path`, shown at `-v`.
+    pub fn log_row_errors(&self, file_path: &str, logger: &Logger) {
+        for err in &self.row_errors {
+            logger.verbose(format_args!("{}: skipped {}", file_path, err));
+        }
+    }
+
+    /// Appends the emails and adds the counters of another read, e.g. of the
+    /// next file of a sharded export.
+    pub fn merge(&mut self, other: ParseOutcome) {
+        self.emails.extend(other.emails);
+        self.subjects.extend(other.subjects);
+        for (address, name) in other.display_names {
+            self.display_names.entry(address).or_insert(name);
+        }
+        self.row_errors.extend(other.row_errors);
+        self.outside_date_range += other.outside_date_range;
+        self.undated_excluded += other.undated_excluded;
+        self.pattern_excluded += other.pattern_excluded;
+        self.address_excluded += other.address_excluded;
+        self.blocked_recipients += other.blocked_recipients;
+        self.rejected_recipients.add(other.rejected_recipients);
+        self.senders_without_recipients.extend(other.senders_without_recipients);
+    }
+
+    /// Remembers the display names of the addresses of `email` among `names`,
+    /// unless one was seen before.
+    pub(crate) fn record_display_names(&mut self, email: &ParsedEmail, names: Vec<(String, String)>) {
+        for (address, name) in names {
+            if address == email.from || email.to.contains(&address) {
+                self.display_names.entry(address).or_insert(name);
+            }
+        }
+    }
+
+    /// Pairs every parsed email with its subject.
+    pub fn into_full_emails(self) -> Vec<ParsedEmailFull> {
+        self.emails
+            .into_iter()
+            .zip(self.subjects)
+            .map(|(email, subject)| ParsedEmailFull { email, subject })
+            .collect()
+    }
+}
+
+/// Names of the CSV columns the email fields are read from.
+/// The defaults match the layout of the original pandas export.
+///
+/// Besides the configured recipient columns, any further `recipient*`, `cc`
+/// and `bcc` columns in the file are picked up automatically.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CsvSchema {
+    pub sender_column: String,
+    pub recipient_columns: Vec<String>,
+    pub date_column: String,
+    pub subject_column: String,
+    pub body_column: String,
+    pub index_column: Option<String>, // Optional: files without this column are read all the same
+    pub delimiter: Option<Delimiter>, // `None` to detect it from the header line
+}
+
+/// Field separator of a delimited email export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    Comma,
+    Tab,
+    Semicolon,
+    Pipe,
+}
+
+impl Delimiter {
+    const ALL: [Delimiter; 4] = [Delimiter::Comma, Delimiter::Tab, Delimiter::Semicolon, Delimiter::Pipe];
+
+    pub fn as_byte(self) -> u8 {
+        match self {
+            Delimiter::Comma => b',',
+            Delimiter::Tab => b'\t',
+            Delimiter::Semicolon => b';',
+            Delimiter::Pipe => b'|',
+        }
+    }
+
+    /// Picks the delimiter that occurs most often outside quotes in a header
+    /// line, preferring a comma when none occurs at all.
+    pub fn sniff(header_line: &[u8]) -> Delimiter {
+        let mut counts = [0usize; 4];
+        let mut in_quotes = false;
+        for &byte in header_line.iter().take_while(|&&byte| byte != b'\n') {
+            if byte == b'"' {
+                in_quotes = !in_quotes;
+            } else if !in_quotes {
+                if let Some(i) = Delimiter::ALL.iter().position(|d| d.as_byte() == byte) {
+                    counts[i] += 1;
+                }
+            }
+        }
+        // max_by_key returns the last maximum, so scan in reverse to favour the comma on ties
+        Delimiter::ALL
+            .into_iter()
+            .zip(counts)
+            .rev()
+            .max_by_key(|&(_, count)| count)
+            .map_or(Delimiter::Comma, |(delimiter, _)| delimiter)
+    }
+}
+
+impl std::str::FromStr for Delimiter {
+    type Err = String;
+
+    /// Accepts the delimiter character itself, `\t` written out, or its name.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "," | "comma" => Ok(Delimiter::Comma),
+            "\t" | "\\t" | "tab" => Ok(Delimiter::Tab),
+            ";" | "semicolon" => Ok(Delimiter::Semicolon),
+            "|" | "pipe" => Ok(Delimiter::Pipe),
+            _ => Err(format!("unsupported delimiter '{}' (expected one of , \\t ; |)", text)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Delimiter {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Default for CsvSchema {
+    fn default() -> Self {
+        CsvSchema {
+            sender_column: "sender".to_string(),
+            recipient_columns: vec!["recipient1".to_string()],
+            date_column: "date".to_string(),
+            subject_column: "subject".to_string(),
+            body_column: "text".to_string(),
+            index_column: Some(String::new()), // pandas writes the index under an empty header
+            delimiter: None,
+        }
+    }
+}
+
+/// Positions of the schema's columns within a particular file's header.
+struct ColumnLayout {
+    index: Option<usize>,
+    date: usize,
+    sender: usize,
+    recipients: Vec<usize>,
+    subject: usize,
+    text: usize,
+}
+
+impl CsvSchema {
+    /// Resolves the configured column names against a header row.
+    fn resolve(&self, headers: &StringRecord) -> Result<ColumnLayout, EmailError> {
+        let find = |column: &str| {
+            headers
+                .iter()
+                .position(|header| header == column)
+                .or_else(|| headers.iter().position(|header| header.trim().eq_ignore_ascii_case(column.trim())))
+                .ok_or_else(|| EmailError::MissingColumn {
+                    column: column.to_string(),
+                    found: headers.iter().map(str::to_string).collect(),
+                })
+        };
+
+        let sender = find(&self.sender_column)?;
+        let mut recipients = self.recipient_columns
+            .iter()
+            .map(|column| find(column))
+            .collect::<Result<Vec<usize>, EmailError>>()?;
+        let layout = ColumnLayout {
+            sender,
+            recipients: Vec::new(),
+            date: find(&self.date_column)?,
+            subject: find(&self.subject_column)?,
+            text: find(&self.body_column)?,
+            // The index is informational only, so a file without it is fine
+            index: self.index_column.as_deref().and_then(|column| find(column).ok()),
+        };
+
+        // Pick up further recipient-like columns that aren't mapped to another field
+        let claimed = [layout.date, layout.sender, layout.subject, layout.text];
+        for (position, header) in headers.iter().enumerate() {
+            if is_extra_recipient_column(header)
+                && !recipients.contains(&position)
+                && !claimed.contains(&position)
+                && layout.index != Some(position)
+            {
+                recipients.push(position);
+            }
+        }
+
+        Ok(ColumnLayout { recipients, ..layout })
+    }
+}
+
+impl ColumnLayout {
+    /// Extracts an `EmailRecord` from a raw row.
+    fn extract(&self, raw: &StringRecord) -> Result<EmailRecord, csv::Error> {
+        let field = |position: usize| raw.get(position).unwrap_or("").to_string();
+        let index = match self.index {
+            // Let csv's deserializer parse the index so bad values surface as csv errors
+            Some(position) => Some(StringRecord::from(vec![raw.get(position).unwrap_or("")]).deserialize(None)?),
+            None => None,
+        };
+        Ok(EmailRecord {
+            index,
+            date: field(self.date),
+            sender: field(self.sender),
+            recipients: self.recipients.iter().map(|&position| field(position)).collect(),
+            subject: field(self.subject),
+            text: field(self.text),
+        })
+    }
+}
+
+/// Read buffer size; large enough that the header line is available for sniffing.
+const SNIFF_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Magic bytes at the start of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Input path that stands for standard input.
+pub const STDIN_PATH: &str = "-";
+
+/// Opens an input file, transparently decompressing it if it is gzipped.
+/// A file counts as gzipped if it has a `.gz` extension or starts with the
+/// gzip magic bytes. Decompression is streamed; nothing is buffered up front.
+/// `STDIN_PATH` reads standard input, gzipped if it starts with the magic bytes.
+pub fn open_input(path: &Path) -> io::Result<Box<dyn Read>> {
+    if path == Path::new(STDIN_PATH) {
+        // Standard input can't be rewound, so peek at its buffered start instead
+        let mut stdin = BufReader::new(io::stdin().lock());
+        let gzipped = stdin.fill_buf()?.starts_with(&GZIP_MAGIC);
+        return Ok(if gzipped { Box::new(GzDecoder::new(stdin)) } else { Box::new(stdin) });
+    }
+
+    let mut file = File::open(path)?;
+    let has_gz_extension = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gz"));
+
+    let mut magic = [0u8; 2];
+    let starts_with_magic = match file.read_exact(&mut magic) {
+        Ok(()) => magic == GZIP_MAGIC,
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => false,
+        Err(err) => return Err(err),
+    };
+    file.rewind()?;
+
+    if has_gz_extension || starts_with_magic {
+        Ok(Box::new(GzDecoder::new(BufReader::new(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Returns true if the last component of an input path contains the
+/// wildcards `*` or `?`, so it names a set of files.
+pub fn is_input_pattern(path: &str) -> bool {
+    Path::new(path)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains(['*', '?']))
+}
+
+/// Expands an input path whose last component may contain wildcards (`*`
+/// matches any run of characters, `?` any single one) into the matching
+/// entries of its directory, sorted by name. A path without wildcards is
+/// returned as is, whether or not it exists.
+pub fn expand_input_pattern(path: &str) -> io::Result<Vec<PathBuf>> {
+    if !is_input_pattern(path) {
+        return Ok(vec![PathBuf::from(path)]);
+    }
+    let path = Path::new(path);
+    let pattern = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut matches = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if glob_match(&pattern, &entry.file_name().to_string_lossy()) {
+            matches.push(dir.join(entry.file_name()));
+        }
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// Removes emails identical to an earlier one (same sender, recipients and
+/// timestamp), e.g. a message filed in several folders or shards that
+/// overlap, keeping the first. Returns the number removed.
+pub fn dedupe_emails(emails: &mut Vec<ParsedEmail>) -> usize {
+    let before = emails.len();
+    let mut seen = HashSet::new();
+    emails.retain(|email| seen.insert((email.from.clone(), email.to.clone(), email.timestamp)));
+    before - emails.len()
+}
+
+/// Recipient count above which `email_statistics` calls an email a
+/// broadcast, unless told otherwise.
+pub const DEFAULT_BROADCAST_THRESHOLD: usize = 50;
+
+/// Recipients per email and the broadcasts among them, as returned by `email_statistics`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailStats {
+    pub emails: usize,
+    pub mean_recipients: f64,
+    pub median_recipients: f64,
+    pub max_recipients: usize,
+    pub broadcast_threshold: usize,
+    pub broadcasts: usize, // Emails with more than `broadcast_threshold` recipients
+    /// Senders of broadcasts with how many each sent, most first, ties by address
+    pub top_broadcast_senders: Vec<(String, usize)>,
+}
+
+/// Summarizes how many recipients the emails have, counting those with
+/// more than `broadcast_threshold` recipients as broadcasts. All zero for
+/// no emails.
+pub fn email_statistics(emails: &[ParsedEmail], broadcast_threshold: usize) -> EmailStats {
+    let mut counts: Vec<usize> = emails.iter().map(|email| email.to.len()).collect();
+    counts.sort_unstable();
+    let n = counts.len();
+    let median_recipients = match n {
+        0 => 0.0,
+        _ if n % 2 == 1 => counts[n / 2] as f64,
+        _ => (counts[n / 2 - 1] + counts[n / 2]) as f64 / 2.0,
+    };
+
+    let mut senders: HashMap<&str, usize> = HashMap::new();
+    for email in emails.iter().filter(|email| email.to.len() > broadcast_threshold) {
+        *senders.entry(&email.from).or_insert(0) += 1;
+    }
+    let mut top_broadcast_senders: Vec<(String, usize)> = senders
+        .into_iter()
+        .map(|(sender, count)| (sender.to_string(), count))
+        .collect();
+    top_broadcast_senders.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    EmailStats {
+        emails: n,
+        mean_recipients: if n == 0 { 0.0 } else { counts.iter().sum::<usize>() as f64 / n as f64 },
+        median_recipients,
+        max_recipients: counts.last().copied().unwrap_or(0),
+        broadcast_threshold,
+        broadcasts: top_broadcast_senders.iter().map(|(_, count)| count).sum(),
+        top_broadcast_senders,
+    }
+}
+
+/// Turns a CSV error caused by the underlying reader into `EmailError::Decode`.
+/// Any other CSV error is handed back unchanged.
+fn decode_error(file_path: &str, source: csv::Error) -> Result<csv::Error, EmailError> {
+    if !source.is_io_error() {
+        return Ok(source);
+    }
+    match source.into_kind() {
+        csv::ErrorKind::Io(source) => Err(EmailError::Decode {
+            path: file_path.to_string(),
+            source,
+        }),
+        _ => unreachable!("is_io_error() guarantees an I/O error kind"),
+    }
+}
+
 /// Reads and parses the email data from a CSV file.
-/// Returns a vector of `ParsedEmail` instances.
-pub fn read_csv(file_path: &str) -> Result<Vec<ParsedEmail>, Box<dyn Error>> {
-    let file = File::open(file_path)?;
+/// Rows that cannot be parsed are collected in `ParseOutcome::row_errors`
+/// instead of aborting the whole read. Gzipped files are decompressed on the fly.
+/// Nothing is logged; see `read_csv_with` for a reader that reports skipped rows.
+pub fn read_csv(file_path: &str) -> Result<ParseOutcome, EmailError> {
+    read_csv_with_schema(file_path, &CsvSchema::default())
+}
+
+/// Same as `read_csv`, but returns a `ParseReport` listing every skipped
+/// row with the reason it was skipped.
+pub fn read_csv_detailed(file_path: &str) -> Result<ParseReport, EmailError> {
+    read_csv(file_path).map(ParseReport::from)
+}
+
+/// Why a row was left out of a `ParseReport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The row could not be deserialized, e.g. a non-numeric index
+    DeserializeError,
+    /// The sender field is empty
+    MissingSender,
+    /// No recipient field holds an address
+    NoRecipients,
+}
+
+/// A skipped row: its line in the input file (the header is line 1) and why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkippedRecord {
+    pub row: usize,
+    pub reason: SkipReason,
+}
+
+impl SkippedRecord {
+    /// Returns the skipped row described by `error`, or `None` if the error
+    /// is not about a single row.
+    pub fn from_error(error: &EmailError) -> Option<Self> {
+        let reason = match error {
+            EmailError::CsvDeserialize { .. } => SkipReason::DeserializeError,
+            EmailError::MissingSender { .. } => SkipReason::MissingSender,
+            EmailError::EmptyRecipients { .. } => SkipReason::NoRecipients,
+            _ => return None,
+        };
+        Some(SkippedRecord { row: error.row()?, reason })
+    }
+}
+
+/// Result of reading a CSV file for library use: the parsed emails, the
+/// number of data rows read and the rows that were skipped, in input order.
+#[derive(Debug, Default)]
+pub struct ParseReport {
+    pub emails: Vec<ParsedEmail>,
+    pub total_rows: usize,
+    pub skipped: Vec<SkippedRecord>,
+}
+
+impl ParseReport {
+    /// Returns the total number of recipients across all parsed emails.
+    pub fn recipient_count(&self) -> usize {
+        self.emails.iter().map(|email| email.to.len()).sum()
+    }
+}
+
+impl From<ParseOutcome> for ParseReport {
+    fn from(mut outcome: ParseOutcome) -> Self {
+        outcome.take_report()
+    }
+}
+
+impl ParseOutcome {
+    /// Moves the parsed emails into a `ParseReport` of this outcome, leaving
+    /// the other fields in place, e.g. to summarize the report and then put
+    /// the emails back.
+    pub fn take_report(&mut self) -> ParseReport {
+        let total_rows = self.row_count();
+        let skipped = self.row_errors.iter().filter_map(SkippedRecord::from_error).collect();
+        ParseReport { emails: std::mem::take(&mut self.emails), total_rows, skipped }
+    }
+}
+
+/// Same as `read_csv`, but reads the fields from the columns named in `schema`.
+pub fn read_csv_with_schema(file_path: &str, schema: &CsvSchema) -> Result<ParseOutcome, EmailError> {
+    read_csv_with(file_path, schema, &EmailFilter::default(), &Logger::silent())
+}
+
+/// Same as `read_csv`, but only keeps the records accepted by `filter`.
+pub fn read_csv_filtered(file_path: &str, filter: &EmailFilter) -> Result<ParseOutcome, EmailError> {
+    read_csv_with(file_path, &CsvSchema::default(), filter, &Logger::silent())
+}
+
+/// Reads the columns named in `schema`, keeping only the records accepted by
+/// `filter`. Each skipped row is reported to `logger` at `-v`.
+/// A `file_path` of `STDIN_PATH` reads standard input.
+pub fn read_csv_with(file_path: &str, schema: &CsvSchema, filter: &EmailFilter, logger: &Logger) -> Result<ParseOutcome, EmailError> {
+    let outcome = read_rows(open_csv(file_path, schema)?, file_path, filter)?;
+    outcome.log_row_errors(file_path, logger);
+    Ok(outcome)
+}
+
+/// Name given to the input in errors from `read_csv_from_reader`.
+const READER_NAME: &str = "<reader>";
+
+/// Same as `read_csv_with_schema`, but reads the CSV from any reader, e.g.
+/// a pipe or an in-memory buffer. The data must already be decompressed.
+pub fn read_csv_from_reader<R: Read>(reader: R, schema: &CsvSchema) -> Result<ParseOutcome, EmailError> {
+    read_rows(open_csv_reader(reader, READER_NAME, schema)?, READER_NAME, &EmailFilter::default())
+}
+
+/// Parses every record after the header, keeping those accepted by `filter`.
+fn read_rows<R: Read>((mut rdr, layout): CsvInput<R>, file_path: &str, filter: &EmailFilter) -> Result<ParseOutcome, EmailError> {
+    let mut outcome = ParseOutcome::default();
+
+    // Iterate over each raw record so the line number is available for error reporting
+    for result in rdr.records() {
+        match result {
+            Ok(raw) => outcome.record(parse_row(&layout, &raw, filter)),
+            Err(source) => outcome.record(RowOutcome::failed(read_error(file_path, source)?)),
+        }
+    }
+    Ok(outcome)
+}
+
+/// Number of raw records handed to the thread pool at a time by `read_csv_parallel`.
+#[cfg(feature = "parallel")]
+const PARALLEL_BATCH_ROWS: usize = 16 * 1024;
+
+/// Same as `read_csv_with`, but parses rows on the rayon thread pool.
+///
+/// Records are still read from the file serially, in batches; each batch is
+/// then extracted, split and normalized in parallel. Emails come out in file
+/// order and the skipped-row accounting matches `read_csv_with` exactly.
+#[cfg(feature = "parallel")]
+pub fn read_csv_parallel(file_path: &str, schema: &CsvSchema, filter: &EmailFilter, logger: &Logger) -> Result<ParseOutcome, EmailError> {
+    use rayon::prelude::*;
+
+    let (mut rdr, layout) = open_csv(file_path, schema)?;
+    let mut outcome = ParseOutcome::default();
+    let mut records = rdr.records();
+
+    loop {
+        // Read errors are resolved here, in order, so the batch only holds owned values
+        let mut batch: Vec<Result<StringRecord, EmailError>> = Vec::with_capacity(PARALLEL_BATCH_ROWS);
+        for result in records.by_ref().take(PARALLEL_BATCH_ROWS) {
+            batch.push(match result {
+                Ok(raw) => Ok(raw),
+                Err(source) => Err(read_error(file_path, source)?),
+            });
+        }
+        if batch.is_empty() {
+            break;
+        }
+
+        let rows: Vec<RowOutcome> = batch
+            .into_par_iter()
+            .map(|entry| match entry {
+                Ok(raw) => parse_row(&layout, &raw, filter),
+                Err(err) => RowOutcome::failed(err),
+            })
+            .collect();
+        for row in rows {
+            outcome.record(row);
+        }
+    }
+
+    outcome.log_row_errors(file_path, logger);
+    Ok(outcome)
+}
+
+/// Number of rows per record batch read by `read_parquet_with`.
+#[cfg(feature = "parquet")]
+const PARQUET_BATCH_ROWS: usize = 8 * 1024;
+
+/// Reads the emails from a Parquet file, taking the columns named in
+/// `schema`. See `read_parquet_with`.
+#[cfg(feature = "parquet")]
+pub fn read_parquet(file_path: &str, schema: &CsvSchema) -> Result<Vec<ParsedEmail>, EmailError> {
+    read_parquet_with(file_path, schema, &EmailFilter::default()).map(|outcome| outcome.emails)
+}
+
+/// Reads the columns named in `schema` from a Parquet file, keeping only
+/// the records accepted by `filter`, with the same address normalization,
+/// recipient splitting and row accounting as `read_csv_with`.
+///
+/// Only the sender, recipient and date columns are decoded; the subject
+/// and body columns are read (and must exist) only when the filter has text
+/// patterns, so subjects are otherwise empty. The index column is ignored.
+/// Any column type that converts to text is accepted; timestamps without a
+/// time zone are taken to be UTC. The file is read a batch of rows at a
+/// time, so memory stays bounded by the row group size. Row numbers in
+/// errors are those of the same records in a CSV file with a header line,
+/// so the first record is row 2.
+#[cfg(feature = "parquet")]
+pub fn read_parquet_with(file_path: &str, schema: &CsvSchema, filter: &EmailFilter) -> Result<ParseOutcome, EmailError> {
+    use arrow_array::cast::AsArray;
+    use arrow_array::Array;
+    use arrow_schema::DataType;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::arrow::ProjectionMask;
+    use parquet::errors::ParquetError;
+
+    let parquet_error = |source: ParquetError| EmailError::Parquet {
+        path: file_path.to_string(),
+        source,
+    };
+    let file = File::open(file_path).map_err(|source| EmailError::Io {
+        path: file_path.to_string(),
+        source,
+    })?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file).map_err(parquet_error)?;
+
+    // Resolve the schema against the file's top-level columns. Without text
+    // patterns the subject and body are never read, so they may be missing:
+    // placeholders past the real columns stand in for them.
+    let mut headers: Vec<String> = builder.schema().fields().iter().map(|field| field.name().clone()).collect();
+    let width = headers.len();
+    let needs_text = filter.has_text_patterns();
+    for column in [&schema.subject_column, &schema.body_column] {
+        if !needs_text && !headers.iter().any(|header| header.trim().eq_ignore_ascii_case(column.trim())) {
+            headers.push(column.clone());
+        }
+    }
+    let layout = ColumnLayout { index: None, ..schema.resolve(&StringRecord::from(headers.clone()))? };
+
+    let mut projected: Vec<usize> = layout.recipients.clone();
+    projected.extend([layout.sender, layout.date]);
+    if needs_text {
+        projected.extend([layout.subject, layout.text]);
+    }
+    projected.retain(|&position| position < width);
+    projected.sort();
+    projected.dedup();
+    let mask = ProjectionMask::roots(builder.parquet_schema(), projected.iter().copied());
+    let reader = builder
+        .with_projection(mask)
+        .with_batch_size(PARQUET_BATCH_ROWS)
+        .build()
+        .map_err(parquet_error)?;
+
+    let mut outcome = ParseOutcome::default();
+    let mut row = 1; // The header line of the equivalent CSV file
+    for batch in reader {
+        let batch = batch.map_err(|source| parquet_error(source.into()))?;
+        // Projected columns keep the file's column order
+        let mut columns = Vec::with_capacity(projected.len());
+        for (&position, column) in projected.iter().zip(batch.columns()) {
+            let column = match column.data_type() {
+                DataType::Timestamp(unit, None) => {
+                    arrow_cast::cast(column, &DataType::Timestamp(*unit, Some("+00:00".into())))
+                        .map_err(|source| parquet_error(source.into()))?
+                }
+                _ => column.clone(),
+            };
+            let text = arrow_cast::cast(&column, &DataType::Utf8).map_err(|source| parquet_error(source.into()))?;
+            columns.push((position, text));
+        }
+
+        for index in 0..batch.num_rows() {
+            row += 1;
+            let mut fields = vec![""; headers.len()];
+            for (position, text) in &columns {
+                let text = text.as_string::<i32>();
+                if text.is_valid(index) {
+                    fields[*position] = text.value(index);
+                }
+            }
+            let mut raw = StringRecord::from(fields);
+            let mut position = csv::Position::new();
+            position.set_line(row);
+            raw.set_position(Some(position));
+            outcome.record(parse_row(&layout, &raw, filter));
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// A CSV reader positioned after the header row, with its column layout.
+type CsvInput<R> = (csv::Reader<BufReader<R>>, ColumnLayout);
+
+/// Opens a CSV file, determines its delimiter and resolves `schema` against its header.
+fn open_csv(file_path: &str, schema: &CsvSchema) -> Result<CsvInput<Box<dyn Read>>, EmailError> {
+    let input = open_input(Path::new(file_path)).map_err(|source| EmailError::Io {
+        path: file_path.to_string(),
+        source,
+    })?;
+    open_csv_reader(input, file_path, schema)
+}
+
+/// Determines the delimiter of a CSV stream and resolves `schema` against
+/// its header. `file_path` names the input in errors.
+fn open_csv_reader<R: Read>(input: R, file_path: &str, schema: &CsvSchema) -> Result<CsvInput<R>, EmailError> {
+    let io_error = |source| EmailError::Io {
+        path: file_path.to_string(),
+        source,
+    };
+    let mut input = BufReader::with_capacity(SNIFF_BUFFER_SIZE, input);
+    let delimiter = match schema.delimiter {
+        Some(delimiter) => delimiter,
+        // Peek at the buffered start of the file without consuming it
+        None => Delimiter::sniff(input.fill_buf().map_err(io_error)?),
+    };
     let mut rdr = ReaderBuilder::new()
         .has_headers(true)
-        .from_reader(file);
-
-    let mut parsed_emails = Vec::new(); // Vector to store successfully parsed emails
-    let mut failed_parses = 0; // Counter for the number of failed parse attempts
-
-    // Iterate over each deserialized record in the CSV
-    for result in rdr.deserialize() {
-        // Attempt to deserialize the current record into an EmailRecord struct
-        let record: EmailRecord = match result {
-            Ok(rec) => rec, // Successfully deserialized record
-            Err(e) => {
-                // Log the error and increment the failed parse counter
-                eprintln!("Failed to deserialize a record: {}", e);
-                failed_parses += 1;
-                continue; // Skip to the next record
+        .delimiter(delimiter.as_byte())
+        .from_reader(input);
+    let headers = match rdr.headers() {
+        Ok(headers) => headers.clone(),
+        Err(source) => return Err(EmailError::Header(decode_error(file_path, source)?)),
+    };
+    let layout = schema.resolve(&headers)?;
+    Ok((rdr, layout))
+}
+
+/// Turns an error from reading the next record into a row error, or fails the
+/// whole read if the input stream itself is broken.
+fn read_error(file_path: &str, source: csv::Error) -> Result<EmailError, EmailError> {
+    // A broken input stream can't be skipped past like a bad row
+    let source = decode_error(file_path, source)?;
+    let row = source.position().map_or(0, |pos| pos.line() as usize);
+    Ok(EmailError::CsvDeserialize { row, source })
+}
+
+/// What became of a single input row.
+enum RowVerdict {
+    Parsed(ParsedEmail, String),
+    Failed(EmailError),
+    SenderOnly(String),
+    OutsideDateRange,
+    Undated,
+    PatternExcluded,
+    AddressExcluded,
+}
+
+/// The verdict on a row plus the counters it contributes to `ParseOutcome`.
+struct RowOutcome {
+    verdict: RowVerdict,
+    rejected_recipients: RejectedAddresses,
+    blocked_recipients: usize,
+    display_names: Vec<(String, String)>, // (address, name) of every sender and recipient entry that has one
+}
+
+impl RowOutcome {
+    fn failed(err: EmailError) -> Self {
+        RowOutcome::from(RowVerdict::Failed(err))
+    }
+}
+
+impl From<RowVerdict> for RowOutcome {
+    fn from(verdict: RowVerdict) -> Self {
+        RowOutcome { verdict, rejected_recipients: RejectedAddresses::default(), blocked_recipients: 0, display_names: Vec::new() }
+    }
+}
+
+impl ParseOutcome {
+    /// Adds one row's result to the totals.
+    fn record(&mut self, row: RowOutcome) {
+        self.rejected_recipients.add(row.rejected_recipients);
+        self.blocked_recipients += row.blocked_recipients;
+        match row.verdict {
+            RowVerdict::Parsed(email, subject) => {
+                self.record_display_names(&email, row.display_names);
+                self.emails.push(email);
+                self.subjects.push(subject);
             }
-        };
+            RowVerdict::Failed(err) => self.row_errors.push(err),
+            RowVerdict::SenderOnly(sender) => self.senders_without_recipients.push(sender),
+            RowVerdict::OutsideDateRange => self.outside_date_range += 1,
+            RowVerdict::Undated => self.undated_excluded += 1,
+            RowVerdict::PatternExcluded => self.pattern_excluded += 1,
+            RowVerdict::AddressExcluded => self.address_excluded += 1,
+        }
+    }
+}
 
-        // Parse the recipients string into a vector of email addresses
-        let recipients = parse_recipients(&record.recipient1);
+/// Extracts, validates and filters one raw record.
+fn parse_row(layout: &ColumnLayout, raw: &StringRecord, filter: &EmailFilter) -> RowOutcome {
+    let row = raw.position().map_or(0, |pos| pos.line() as usize);
 
-        // Check for missing sender or recipients to ensure data completeness
-        if record.sender.is_empty() || recipients.is_empty() {
-            // Log the incomplete record details and increment the failed parse counter
-            eprintln!(
-                "Incomplete record found at index {}: sender='{}', recipient1='{}'",
-                record.index, record.sender, record.recipient1
-            );
-            failed_parses += 1;
-            continue; // Skip to the next record
+    // Extract the configured columns into an EmailRecord
+    let record = match layout.extract(raw) {
+        Ok(rec) => rec, // Successfully extracted record
+        Err(source) => return RowOutcome::failed(EmailError::CsvDeserialize { row, source }),
+    };
+
+    // Check for missing sender or recipients to ensure data completeness
+    let sender = normalize_address(&record.sender);
+    if sender.is_empty() {
+        return RowOutcome::failed(EmailError::MissingSender { row });
+    }
+
+    // Parse every recipient column into one deduplicated list
+    let fields = record.recipients.iter().map(String::as_str);
+    let (mut recipients, rejected_recipients) = merge_recipients(fields, filter.max_address_length());
+    let mut blocked_recipients = 0;
+    let verdict = 'verdict: {
+        if recipients.is_empty() && !filter.keep_senders_without_recipients {
+            break 'verdict RowVerdict::Failed(EmailError::EmptyRecipients { row });
+        }
+
+        // Apply the address lists, if any
+        if !filter.allows_address(&sender) {
+            break 'verdict RowVerdict::AddressExcluded;
+        }
+        let listed = recipients.len();
+        recipients.retain(|recipient| filter.allows_address(recipient));
+        blocked_recipients = listed - recipients.len();
+        if recipients.is_empty() && listed > 0 {
+            break 'verdict RowVerdict::AddressExcluded;
+        }
+
+        // Apply the date range, if any
+        let timestamp = parse_date(&record.date);
+        match filter.check_date(timestamp) {
+            FilterDecision::Keep => {}
+            FilterDecision::OutsideDateRange => break 'verdict RowVerdict::OutsideDateRange,
+            FilterDecision::Undated => break 'verdict RowVerdict::Undated,
+        }
+
+        // Apply the subject and body patterns, if any
+        if !filter.matches_text(&record.subject, &record.text) {
+            break 'verdict RowVerdict::PatternExcluded;
+        }
+
+        // A sender kept without recipients has passed the same filters as any email
+        if recipients.is_empty() {
+            break 'verdict RowVerdict::SenderOnly(sender);
         }
 
         // Create a ParsedEmail instance with the sender and parsed recipients
-        let parsed_email = ParsedEmail {
-            from: record.sender.clone(), // Clone the sender's email address
+        let email = ParsedEmail {
+            from: sender, // Normalized the same way as the recipients
             to: recipients, // Assign the vector of recipient email addresses
+            timestamp,
         };
+        RowVerdict::Parsed(email, record.subject)
+    };
 
-        parsed_emails.push(parsed_email); // Add the ParsedEmail to the collection
-    }
-    
-    // Print the number of successfully parsed emails
-    println!(
-        "Successfully parsed {} emails.",
-        parsed_emails.len()
-    );
-
-    // If there were any failed parses, log the total count
-    if failed_parses > 0 {
-        println!("Failed to parse {} records.", failed_parses);
-    }
-    
-    // Return the vector of ParsedEmail instances
-    Ok(parsed_emails)
-}
\ No newline at end of file
+    let display_names = match verdict {
+        RowVerdict::Parsed(..) => std::iter::once(record.sender.as_str())
+            .chain(record.recipients.iter().map(String::as_str))
+            .flat_map(parse_display_names)
+            .collect(),
+        _ => Vec::new(),
+    };
+    RowOutcome { verdict, rejected_recipients, blocked_recipients, display_names }
+}
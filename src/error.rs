@@ -0,0 +1,246 @@
+use thiserror::Error;
+
+/// Errors that can occur while reading and parsing email data.
+///
+/// Row numbers refer to the line of the input file the record started on,
+/// counting the header as line 1.
+#[derive(Debug, Error)]
+pub enum EmailError {
+    /// The input file could not be opened or read
+    #[error("could not read '{path}': {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The input could be opened but its compressed stream is corrupt
+    #[error("could not decompress '{path}': {source}")]
+    Decode {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A Parquet file could not be decoded
+    #[cfg(feature = "parquet")]
+    #[error("could not read Parquet file '{path}': {source}")]
+    Parquet {
+        path: String,
+        #[source]
+        source: parquet::errors::ParquetError,
+    },
+
+    /// The header row of the CSV could not be read
+    #[error("could not read CSV header: {0}")]
+    Header(#[source] csv::Error),
+
+    /// A column named in the schema does not appear in the header row
+    #[error("column '{column}' not found in CSV header (found: {})", found.join(", "))]
+    MissingColumn { column: String, found: Vec<String> },
+
+    /// A row could not be deserialized into an `EmailRecord`
+    #[error("row {row}: malformed record: {source}")]
+    CsvDeserialize {
+        row: usize,
+        #[source]
+        source: csv::Error,
+    },
+
+    /// A row has an empty sender field
+    #[error("row {row}: missing sender")]
+    MissingSender { row: usize },
+
+    /// A row has no usable recipient addresses
+    #[error("row {row}: no recipients")]
+    EmptyRecipients { row: usize },
+
+    /// A message file in a mail directory could not be read
+    #[error("could not read message '{path}': {source}")]
+    UnreadableMessage {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A message has no usable From header
+    #[error("message '{path}': missing sender")]
+    MessageWithoutSender { path: String },
+
+    /// A message has no usable To, Cc or Bcc addresses
+    #[error("message '{path}': no recipients")]
+    MessageWithoutRecipients { path: String },
+}
+
+impl EmailError {
+    /// Returns the row this error refers to, if it is a row-level problem.
+    pub fn row(&self) -> Option<usize> {
+        match self {
+            EmailError::CsvDeserialize { row, .. }
+            | EmailError::MissingSender { row }
+            | EmailError::EmptyRecipients { row } => Some(*row),
+            _ => None,
+        }
+    }
+}
+
+/// Crate-level error type returned by the analysis pipeline.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Email(#[from] EmailError),
+
+    /// The config file could not be read
+    #[error("could not read config '{path}': {source}")]
+    ConfigRead {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The config file is not valid TOML or has values of the wrong type
+    #[error("invalid config '{path}': {source}")]
+    ConfigParse {
+        path: String,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// An address list file could not be read
+    #[error("could not read address list '{path}': {source}")]
+    AddressListRead {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// An existing address mapping file could not be read
+    #[error("could not read address map '{path}': {source}")]
+    AddressMapRead {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A file of address pairs could not be read
+    #[error("could not read pairs from '{path}': {source}")]
+    PairsRead {
+        path: String,
+        #[source]
+        source: csv::Error,
+    },
+
+    /// A file of seed community labels could not be read
+    #[error("could not read seed labels from '{path}': {source}")]
+    SeedLabelsRead {
+        path: String,
+        #[source]
+        source: csv::Error,
+    },
+
+    /// A file of community labels could not be read
+    #[error("could not read communities from '{path}': {source}")]
+    PartitionRead {
+        path: String,
+        #[source]
+        source: csv::Error,
+    },
+
+    /// A file of node tags could not be read
+    #[error("could not read node tags from '{path}': {source}")]
+    NodeTagsRead {
+        path: String,
+        #[source]
+        source: csv::Error,
+    },
+
+    /// An input pattern matched no files
+    #[error("no input files match '{pattern}'")]
+    NoInputFiles { pattern: String },
+
+    /// The input was read but not a single email survived parsing and filtering
+    #[error("no valid emails parsed from '{input}'")]
+    NoValidEmails { input: String },
+
+    /// Both inputs of a comparison were standard input, which can only be read once
+    #[error("standard input can only be read once; give a file for one of the inputs")]
+    StdinReadTwice,
+
+    /// The benchmark's rebuilt graph disagreed with the generated one
+    #[error("benchmark check failed: {operation} gave a different graph than the generator")]
+    BenchMismatch { operation: String },
+
+    /// A queried address does not appear in the graph
+    #[error("address '{address}' is not in the graph{}", did_you_mean(suggestions))]
+    UnknownAddress { address: String, suggestions: Vec<String> },
+
+    /// A queried address normalizes to the address of several people
+    #[error("address '{address}' could be any of: {}", candidates.join(", "))]
+    AmbiguousAddress { address: String, candidates: Vec<String> },
+
+    /// A pattern for finding addresses is not a valid regular expression
+    #[error("invalid pattern '{pattern}': {source}")]
+    InvalidPattern {
+        pattern: String,
+        #[source]
+        source: regex::Error,
+    },
+
+    /// The SQLite database could not be written or read
+    #[cfg(feature = "sqlite")]
+    #[error("could not use SQLite database '{path}': {source}")]
+    Sqlite {
+        path: String,
+        #[source]
+        source: rusqlite::Error,
+    },
+
+    /// An output file could not be written
+    #[error("could not write '{path}': {source}")]
+    Write {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// A broken internal invariant of a `Graph`, reported by `Graph::validate`.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum GraphInvariantViolation {
+    /// An adjacency set names a node that is not a key of `adjacency_list`
+    #[error("edge {from} -> {to} points to a node that is not in the graph")]
+    DanglingNeighbor { from: String, to: String },
+
+    /// A node is a key of only one of the adjacency and reverse adjacency maps
+    #[error("node {node} is missing from the reverse adjacency or has no adjacency entry")]
+    ReverseNodeMismatch { node: String },
+
+    /// An edge is not recorded among the senders of its recipient
+    #[error("edge {from} -> {to} is missing from the reverse adjacency")]
+    MissingReverseEdge { from: String, to: String },
+
+    /// The reverse adjacency records an edge that does not exist
+    #[error("reverse adjacency records {from} -> {to}, which is not an edge")]
+    ExtraReverseEdge { from: String, to: String },
+
+    /// The maintained edge count differs from the number of edges
+    #[error("edge count is {recorded} but the adjacency sets hold {actual} edges")]
+    EdgeCountMismatch { recorded: usize, actual: usize },
+
+    /// An edge has no weight, or a weight of zero
+    #[error("edge {from} -> {to} has no nonzero weight")]
+    MissingWeight { from: String, to: String },
+
+    /// A weight is recorded for a pair that is not an edge
+    #[error("weight recorded for {from} -> {to}, which is not an edge")]
+    StrayWeight { from: String, to: String },
+}
+
+/// Formats suggested addresses as "; did you mean a, b?", or nothing.
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!("; did you mean {}?", suggestions.join(", "))
+    }
+}
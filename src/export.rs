@@ -0,0 +1,666 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::community::{CommunityComparison, FlowEdge};
+use crate::email::ParsedEmail;
+use crate::graph::{Direction, DomainStats, EdgeInfo, Graph, RobustnessPoint};
+use crate::temporal::ActivitySeries;
+
+/// Returns the graph's nodes in sorted order so exports are deterministic.
+fn sorted_nodes(graph: &Graph) -> Vec<&String> {
+    let mut nodes: Vec<&String> = graph.adjacency_list.keys().collect();
+    nodes.sort();
+    nodes
+}
+
+/// Escapes the characters that are not allowed verbatim in XML text and attributes.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Writes one CSV row per node with its degrees, whether it looks like a
+/// distribution list and, if known, its harmonic centrality, community and
+/// `Graph::community_embeddedness` (empty without `labels`).
+/// Columns: address,out_degree,in_degree,avg_neighbor_degree,harmonic_centrality,community,embeddedness,likely_list,
+/// then one per node attribute in the order of `Graph::node_attribute_keys` (empty where unset)
+pub fn write_node_csv(
+    graph: &Graph,
+    labels: Option<&HashMap<String, String>>,
+    harmonic: Option<&HashMap<String, f64>>,
+    lists: &HashSet<String>,
+    path: &Path,
+) -> io::Result<()> {
+    let out_degrees = graph.calculate_out_degrees();
+    let in_degrees = graph.calculate_in_degrees();
+    let neighbor_degrees = graph.average_neighbor_degree();
+    let embeddedness = labels.map(|labels| graph.community_embeddedness(labels));
+
+    let attribute_keys = graph.node_attribute_keys();
+
+    let mut writer = csv::Writer::from_path(path)?;
+    let mut header = vec!["address", "out_degree", "in_degree", "avg_neighbor_degree", "harmonic_centrality", "community", "embeddedness", "likely_list"];
+    header.extend(&attribute_keys);
+    writer.write_record(&header)?;
+    for node in sorted_nodes(graph) {
+        let community = labels.and_then(|labels| labels.get(node)).map_or("", |label| label.as_str());
+        let harmonic = harmonic
+            .and_then(|scores| scores.get(node))
+            .map_or_else(String::new, |score| format!("{:.4}", score));
+        let embeddedness = embeddedness
+            .as_ref()
+            .map_or_else(String::new, |scores| format!("{:.4}", scores[node]));
+        let mut record = vec![
+            node.to_string(),
+            out_degrees[node].to_string(),
+            in_degrees[node].to_string(),
+            format!("{:.4}", neighbor_degrees[node]),
+            harmonic,
+            community.to_string(),
+            embeddedness,
+            lists.contains(node).to_string(),
+        ];
+        record.extend(attribute_keys.iter().map(|key| graph.get_node_attr(node, key).unwrap_or_default().to_string()));
+        writer.write_record(&record)?;
+    }
+    writer.flush()
+}
+
+/// Writes one CSV row per community, largest first (ties by label).
+/// Columns: label,size
+pub fn write_community_sizes(community_map: &HashMap<String, Vec<String>>, path: &Path) -> io::Result<()> {
+    let mut communities: Vec<(&String, usize)> = community_map
+        .iter()
+        .map(|(label, members)| (label, members.len()))
+        .collect();
+    communities.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["label", "size"])?;
+    for (label, size) in communities {
+        writer.write_record([label.as_str(), &size.to_string()])?;
+    }
+    writer.flush()
+}
+
+/// Writes one CSV row per directed edge, sorted by sender then recipient.
+/// Columns: from,to,weight,emails, followed by first_seen,last_seen,active_days
+/// with `edge_activity` (dates empty when unknown)
+pub fn write_edge_list(graph: &Graph, edge_activity: bool, path: &Path) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    let mut header = vec!["from", "to", "weight", "emails"];
+    if edge_activity {
+        header.extend(["first_seen", "last_seen", "active_days"]);
+    }
+    writer.write_record(&header)?;
+    for from in sorted_nodes(graph) {
+        let mut neighbors: Vec<&String> = graph.adjacency_list[from].iter().collect();
+        neighbors.sort();
+        for to in neighbors {
+            let mut record = vec![
+                from.to_string(),
+                to.to_string(),
+                graph.edge_weight(from, to).unwrap_or(0.0).to_string(),
+                graph.edge_multiplicity(from, to).to_string(),
+            ];
+            if edge_activity {
+                record.extend(edge_activity_fields(graph.edge_info(from, to)));
+            }
+            writer.write_record(&record)?;
+        }
+    }
+    writer.flush()
+}
+
+/// The first and last email (RFC 3339, empty if unknown) and the number of
+/// active days of an edge.
+fn edge_activity_fields(info: Option<&EdgeInfo>) -> [String; 3] {
+    let date = |timestamp: Option<DateTime<Utc>>| timestamp.map_or_else(String::new, |timestamp| timestamp.to_rfc3339());
+    [
+        date(info.and_then(|info| info.first_seen)),
+        date(info.and_then(|info| info.last_seen)),
+        info.map_or(0, |info| info.active_days).to_string(),
+    ]
+}
+
+/// Writes the graph as the two CSV files `neo4j-admin import` reads: one
+/// `Person` node per address (with its community as an extra property when
+/// `labels` are given) and one `EMAILED` relationship per directed edge,
+/// weighted by the number of emails. A self-loop is a single relationship
+/// from the node to itself. Rows are sorted by address.
+/// Node columns: emailAddress:ID,:LABEL[,community]
+/// Relationship columns: :START_ID,:END_ID,:TYPE,weight:int
+pub fn export_neo4j(
+    graph: &Graph,
+    labels: Option<&HashMap<String, String>>,
+    nodes_path: &Path,
+    rels_path: &Path,
+) -> io::Result<()> {
+    let mut nodes = csv::Writer::from_path(nodes_path)?;
+    let mut header = vec!["emailAddress:ID", ":LABEL"];
+    if labels.is_some() {
+        header.push("community");
+    }
+    nodes.write_record(&header)?;
+    for node in sorted_nodes(graph) {
+        let mut record = vec![node.as_str(), "Person"];
+        if let Some(labels) = labels {
+            record.push(labels.get(node).map_or("", |label| label.as_str()));
+        }
+        nodes.write_record(&record)?;
+    }
+    nodes.flush()?;
+
+    let mut relationships = csv::Writer::from_path(rels_path)?;
+    relationships.write_record([":START_ID", ":END_ID", ":TYPE", "weight:int"])?;
+    for from in sorted_nodes(graph) {
+        let mut recipients: Vec<&String> = graph.adjacency_list[from].iter().collect();
+        recipients.sort();
+        for to in recipients {
+            relationships.write_record([from.as_str(), to, "EMAILED", &graph.edge_multiplicity(from, to).to_string()])?;
+        }
+    }
+    relationships.flush()
+}
+
+/// Writes every community with all its members and their total degree, as
+/// grouped and ordered by `Graph::nodes_by_community_sorted`. A path ending
+/// in `.md` gets a Markdown document with one table per community; any
+/// other path gets plain text.
+pub fn write_community_report(graph: &Graph, labels: &HashMap<String, String>, path: &Path) -> io::Result<()> {
+    let markdown = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("md"));
+    let mut out = BufWriter::new(File::create(path)?);
+    let communities = graph.nodes_by_community_sorted(labels);
+
+    if markdown {
+        writeln!(out, "# Communities\n")?;
+        writeln!(out, "{} communities, {} people.", communities.len(), labels.len())?;
+    }
+    for (label, members) in &communities {
+        if markdown {
+            writeln!(out, "\n## {} ({} members)\n", markdown_escape(label), members.len())?;
+            writeln!(out, "| Member | Degree |")?;
+            writeln!(out, "| --- | ---: |")?;
+            for (member, degree) in members {
+                writeln!(out, "| {} | {} |", markdown_escape(member), degree)?;
+            }
+        } else {
+            writeln!(out, "Community {} ({} members)", label, members.len())?;
+            for (member, degree) in members {
+                writeln!(out, "  {} {}", member, degree)?;
+            }
+            writeln!(out)?;
+        }
+    }
+    out.flush()
+}
+
+/// Escapes the characters that would break a Markdown table cell or heading.
+fn markdown_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|").replace('*', "\\*").replace('_', "\\_")
+}
+
+/// Writes parsed emails in exploded form: one row per recipient of every
+/// email, so an email to three people becomes three rows and the row count
+/// exceeds the email count. The timestamp is RFC 3339, empty when unknown.
+/// Columns: from,to,timestamp
+pub fn write_parsed_emails<W: Write>(emails: &[ParsedEmail], writer: W) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["from", "to", "timestamp"])?;
+    for email in emails {
+        let timestamp = email.timestamp.map_or_else(String::new, |timestamp| timestamp.to_rfc3339());
+        for recipient in &email.to {
+            writer.write_record([email.from.as_str(), recipient, &timestamp])?;
+        }
+    }
+    writer.flush()
+}
+
+/// Writes the address behind each row/column of `Graph::to_matrix_market`.
+/// Columns: index,address (1-based, matching the matrix)
+pub fn write_node_index(graph: &Graph, path: &Path) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["index", "address"])?;
+    for (i, node) in sorted_nodes(graph).into_iter().enumerate() {
+        writer.write_record([&(i + 1).to_string(), node])?;
+    }
+    writer.flush()
+}
+
+/// Writes the activity series of the given senders, one row per sender and bucket.
+/// Columns: bucket_start,sender,count
+pub fn write_activity_csv(series: &HashMap<String, ActivitySeries>, senders: &[String], path: &Path) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["bucket_start", "sender", "count"])?;
+    for sender in senders {
+        for (start, count) in series.get(sender).into_iter().flatten() {
+            writer.write_record([&start.to_rfc3339(), sender, &count.to_string()])?;
+        }
+    }
+    writer.flush()
+}
+
+/// Writes a robustness curve, one row per point.
+/// Columns: fraction_removed,largest_component_fraction
+pub fn write_robustness_csv(points: &[RobustnessPoint], path: &Path) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["fraction_removed", "largest_component_fraction"])?;
+    for point in points {
+        writer.write_record([
+            format!("{:.4}", point.fraction_removed),
+            format!("{:.4}", point.largest_component_fraction),
+        ])?;
+    }
+    writer.flush()
+}
+
+/// Writes an edge percolation curve, one row per retained-edge fraction.
+/// Columns: retained_fraction,largest_component_fraction
+pub fn write_percolation_csv(points: &[(f64, f64)], path: &Path) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["retained_fraction", "largest_component_fraction"])?;
+    for (retained, largest) in points {
+        writer.write_record([format!("{:.4}", retained), format!("{:.4}", largest)])?;
+    }
+    writer.flush()
+}
+
+/// Writes a community-detection comparison, one row per method, followed
+/// by its NMI against every method (one column per method, same order).
+/// Columns: method,communities,largest,singletons,modularity,runtime_ms,<method>...
+pub fn write_community_comparison_csv(comparison: &CommunityComparison, path: &Path) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    let mut header: Vec<&str> = vec!["method", "communities", "largest", "singletons", "modularity", "runtime_ms"];
+    header.extend(comparison.partitions.iter().map(|partition| partition.method.as_str()));
+    writer.write_record(&header)?;
+    for (partition, nmi) in comparison.partitions.iter().zip(&comparison.nmi) {
+        let mut record = vec![
+            partition.method.clone(),
+            partition.communities.to_string(),
+            partition.largest.to_string(),
+            partition.singletons.to_string(),
+            format!("{:.4}", partition.modularity),
+            format!("{:.3}", partition.runtime.as_secs_f64() * 1000.0),
+        ];
+        record.extend(nmi.iter().map(|value| format!("{:.4}", value)));
+        writer.write_record(&record)?;
+    }
+    writer.flush()
+}
+
+/// Writes the flows between two partitions from `community::partition_flow`,
+/// in its order, as the links of an alluvial or Sankey diagram.
+/// Columns: from,to,nodes
+pub fn write_partition_flow_csv(flows: &[FlowEdge], path: &Path) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["from", "to", "nodes"])?;
+    for flow in flows {
+        writer.write_record([flow.from.as_str(), flow.to.as_str(), &flow.nodes.to_string()])?;
+    }
+    writer.flush()
+}
+
+/// Writes each community's keywords, communities sorted by label.
+/// Columns: community,rank,token,score
+pub fn write_keywords_csv(keywords: &HashMap<String, Vec<(String, f64)>>, path: &Path) -> io::Result<()> {
+    let mut labels: Vec<&String> = keywords.keys().collect();
+    labels.sort();
+
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(["community", "rank", "token", "score"])?;
+    for label in labels {
+        for (i, (token, score)) in keywords[label].iter().enumerate() {
+            writer.write_record([label.as_str(), &(i + 1).to_string(), token, &format!("{:.6}", score)])?;
+        }
+    }
+    writer.flush()
+}
+
+/// Writes the graph in GraphML format, with the community label and the
+/// node attributes (keys `attr0`, `attr1`, ... named after the attribute)
+/// as node data and, with `edge_activity`, the email count, first and last
+/// email and active days as edge attributes.
+pub fn write_graphml(
+    graph: &Graph,
+    labels: Option<&HashMap<String, String>>,
+    edge_activity: bool,
+    path: &Path,
+) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+    writeln!(out, r#"  <key id="community" for="node" attr.name="community" attr.type="string"/>"#)?;
+    let attribute_keys = graph.node_attribute_keys();
+    for (i, key) in attribute_keys.iter().enumerate() {
+        writeln!(out, r#"  <key id="attr{}" for="node" attr.name="{}" attr.type="string"/>"#, i, xml_escape(key))?;
+    }
+    if edge_activity {
+        writeln!(out, r#"  <key id="emails" for="edge" attr.name="emails" attr.type="int"/>"#)?;
+        writeln!(out, r#"  <key id="first_seen" for="edge" attr.name="first_seen" attr.type="string"/>"#)?;
+        writeln!(out, r#"  <key id="last_seen" for="edge" attr.name="last_seen" attr.type="string"/>"#)?;
+        writeln!(out, r#"  <key id="active_days" for="edge" attr.name="active_days" attr.type="int"/>"#)?;
+    }
+    writeln!(out, r#"  <graph id="emails" edgedefault="directed">"#)?;
+
+    let nodes = sorted_nodes(graph);
+    for node in &nodes {
+        let id = xml_escape(node);
+        let mut data: Vec<(String, &str)> = Vec::new();
+        if let Some(label) = labels.and_then(|labels| labels.get(*node)) {
+            data.push(("community".to_string(), label));
+        }
+        for (i, key) in attribute_keys.iter().enumerate() {
+            if let Some(value) = graph.get_node_attr(node, key) {
+                data.push((format!("attr{}", i), value));
+            }
+        }
+        if data.is_empty() {
+            writeln!(out, r#"    <node id="{}"/>"#, id)?;
+            continue;
+        }
+        writeln!(out, r#"    <node id="{}">"#, id)?;
+        for (key, value) in data {
+            writeln!(out, r#"      <data key="{}">{}</data>"#, key, xml_escape(value))?;
+        }
+        writeln!(out, "    </node>")?;
+    }
+
+    for from in &nodes {
+        let mut neighbors: Vec<&String> = graph.adjacency_list[*from].iter().collect();
+        neighbors.sort();
+        for to in neighbors {
+            if !edge_activity {
+                writeln!(out, r#"    <edge source="{}" target="{}"/>"#, xml_escape(from), xml_escape(to))?;
+                continue;
+            }
+            writeln!(out, r#"    <edge source="{}" target="{}">"#, xml_escape(from), xml_escape(to))?;
+            writeln!(out, r#"      <data key="emails">{}</data>"#, graph.edge_multiplicity(from, to))?;
+            let [first_seen, last_seen, active_days] = edge_activity_fields(graph.edge_info(from, to));
+            for (key, value) in [("first_seen", first_seen), ("last_seen", last_seen)] {
+                if !value.is_empty() {
+                    writeln!(out, r#"      <data key="{}">{}</data>"#, key, value)?;
+                }
+            }
+            writeln!(out, r#"      <data key="active_days">{}</data>"#, active_days)?;
+            writeln!(out, "    </edge>")?;
+        }
+    }
+
+    writeln!(out, "  </graph>")?;
+    writeln!(out, "</graphml>")?;
+    out.flush()
+}
+
+/// Graphs with more nodes than this are cut down before being drawn as HTML.
+pub const DEFAULT_HTML_MAX_NODES: usize = 500;
+
+/// Page template for `export_html_visualization`; the graph JSON replaces `__GRAPH_DATA__`.
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Email communities</title>
+<style>
+  body { margin: 0; font-family: sans-serif; }
+  canvas { display: block; }
+  #info { position: absolute; top: 8px; left: 8px; background: #fffd; padding: 4px 8px; }
+</style>
+</head>
+<body>
+<div id="info"></div>
+<canvas id="graph"></canvas>
+<script id="graph-data" type="application/json">__GRAPH_DATA__</script>
+<script>
+const data = JSON.parse(document.getElementById("graph-data").textContent);
+const canvas = document.getElementById("graph");
+const ctx = canvas.getContext("2d");
+const info = document.getElementById("info");
+canvas.width = window.innerWidth;
+canvas.height = window.innerHeight;
+
+const communities = [...new Set(data.nodes.map(n => n.community))];
+const color = c => `hsl(${(communities.indexOf(c) * 137.5) % 360}, 65%, 50%)`;
+const index = new Map(data.nodes.map((n, i) => [n.id, i]));
+const nodes = data.nodes.map(n => ({ ...n,
+  x: canvas.width / 2 + (Math.random() - 0.5) * canvas.width / 2,
+  y: canvas.height / 2 + (Math.random() - 0.5) * canvas.height / 2,
+  vx: 0, vy: 0, r: 3 + 2 * Math.sqrt(n.degree) }));
+const edges = data.edges.map(e => [index.get(e.source), index.get(e.target)]);
+info.textContent = `${nodes.length} people, ${edges.length} edges, ${communities.length} communities` +
+  (data.truncated ? ` (largest ${nodes.length} of ${data.total_nodes} people shown)` : "");
+
+function step() {
+  for (const a of nodes) {
+    for (const b of nodes) {
+      if (a === b) continue;
+      const dx = a.x - b.x, dy = a.y - b.y, d2 = dx * dx + dy * dy + 0.01;
+      a.vx += 200 * dx / d2; a.vy += 200 * dy / d2;
+    }
+    a.vx += (canvas.width / 2 - a.x) * 0.001; a.vy += (canvas.height / 2 - a.y) * 0.001;
+  }
+  for (const [s, t] of edges) {
+    const a = nodes[s], b = nodes[t], dx = b.x - a.x, dy = b.y - a.y;
+    a.vx += dx * 0.005; a.vy += dy * 0.005; b.vx -= dx * 0.005; b.vy -= dy * 0.005;
+  }
+  for (const n of nodes) {
+    n.x += n.vx; n.y += n.vy; n.vx *= 0.6; n.vy *= 0.6;
+  }
+}
+
+function draw() {
+  ctx.clearRect(0, 0, canvas.width, canvas.height);
+  ctx.strokeStyle = "#9994";
+  for (const [s, t] of edges) {
+    ctx.beginPath(); ctx.moveTo(nodes[s].x, nodes[s].y); ctx.lineTo(nodes[t].x, nodes[t].y); ctx.stroke();
+  }
+  for (const n of nodes) {
+    ctx.fillStyle = color(n.community);
+    ctx.beginPath(); ctx.arc(n.x, n.y, n.r, 0, 2 * Math.PI); ctx.fill();
+  }
+}
+
+canvas.addEventListener("mousemove", ev => {
+  const hit = nodes.find(n => Math.hypot(n.x - ev.offsetX, n.y - ev.offsetY) <= n.r);
+  canvas.title = hit ? `${hit.id} (community ${hit.community}, degree ${hit.degree})` : "";
+});
+
+let ticks = 0;
+(function frame() {
+  if (ticks++ < 300) step();
+  draw();
+  requestAnimationFrame(frame);
+})();
+</script>
+</body>
+</html>
+"##;
+
+/// Node as embedded in the HTML page.
+#[derive(Serialize)]
+struct HtmlNode<'a> {
+    id: &'a str,
+    community: &'a str,
+    degree: usize,
+}
+
+/// Edge as embedded in the HTML page.
+#[derive(Serialize)]
+struct HtmlEdge<'a> {
+    source: &'a str,
+    target: &'a str,
+    weight: f64,
+}
+
+/// Everything the HTML page draws.
+#[derive(Serialize)]
+struct HtmlGraph<'a> {
+    nodes: Vec<HtmlNode<'a>>,
+    edges: Vec<HtmlEdge<'a>>,
+    total_nodes: usize,
+    truncated: bool,
+}
+
+/// Writes a single self-contained HTML page that draws the graph with a small
+/// force-directed layout, coloring people by community and sizing them by
+/// total degree. Graphs above `DEFAULT_HTML_MAX_NODES` are cut down as in
+/// `export_html_visualization_with_limit`.
+pub fn export_html_visualization(graph: &Graph, labels: &HashMap<String, String>, path: &Path) -> io::Result<usize> {
+    export_html_visualization_with_limit(graph, labels, path, DEFAULT_HTML_MAX_NODES)
+}
+
+/// Like `export_html_visualization`, but keeps only the `max_nodes` people
+/// with the highest total degree (ties by address) when the graph is larger,
+/// since the page becomes unusable with thousands of nodes.
+/// Returns the number of people drawn.
+pub fn export_html_visualization_with_limit(
+    graph: &Graph,
+    labels: &HashMap<String, String>,
+    path: &Path,
+    max_nodes: usize,
+) -> io::Result<usize> {
+    let out_degrees = graph.calculate_out_degrees();
+    let in_degrees = graph.calculate_in_degrees();
+    let degree = |node: &String| out_degrees[node] + in_degrees[node];
+
+    let mut nodes = sorted_nodes(graph);
+    let truncated = nodes.len() > max_nodes;
+    if truncated {
+        nodes.sort_by(|a, b| degree(b).cmp(&degree(a)).then_with(|| a.cmp(b)));
+        nodes.truncate(max_nodes);
+        nodes.sort();
+    }
+    let kept: HashSet<&String> = nodes.iter().copied().collect();
+
+    let mut edges = Vec::new();
+    for from in &nodes {
+        let mut neighbors: Vec<&String> = graph.adjacency_list[*from].iter().filter(|to| kept.contains(to)).collect();
+        neighbors.sort();
+        for to in neighbors {
+            edges.push(HtmlEdge { source: from, target: to, weight: graph.edge_weight(from, to).unwrap_or(0.0) });
+        }
+    }
+    let data = HtmlGraph {
+        nodes: nodes
+            .iter()
+            .map(|node| HtmlNode {
+                id: node,
+                community: labels.get(*node).map_or("", |label| label.as_str()),
+                degree: degree(node),
+            })
+            .collect(),
+        edges,
+        total_nodes: graph.node_count(),
+        truncated,
+    };
+
+    // `<`, `>` and `&` are escaped so no address can close the script element
+    let json = serde_json::to_string(&data)?
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026");
+    let mut out = BufWriter::new(File::create(path)?);
+    out.write_all(HTML_TEMPLATE.replace("__GRAPH_DATA__", &json).as_bytes())?;
+    out.flush()?;
+    Ok(data.nodes.len())
+}
+
+/// Quotes a node name as a DOT identifier.
+fn dot_quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Writes a Graphviz DOT file of the breadth-first tree of everyone `root`
+/// reaches by email within `depth` steps. Each person is drawn once, under
+/// the first sender (by address) in the layer above that emailed them, and
+/// every layer is a `rank=same` group so the tree is laid out level by level.
+/// With `non_tree_edges` the other emails among the drawn people are added as
+/// dashed grey edges that do not affect the layout.
+/// Returns the number of people drawn, 0 if `root` is not in the graph.
+pub fn export_influence_tree(graph: &Graph, root: &str, depth: usize, non_tree_edges: bool, path: &Path) -> io::Result<usize> {
+    let mut layers = graph.bfs_layers(root, Direction::Out);
+    layers.truncate(depth + 1);
+    let included: HashSet<&String> = layers.iter().flatten().collect();
+
+    let mut tree_edges: Vec<(&String, &String)> = Vec::new();
+    for pair in layers.windows(2) {
+        let next: HashSet<&String> = pair[1].iter().collect();
+        let mut placed: HashSet<&String> = HashSet::new();
+        for from in &pair[0] {
+            let mut children: Vec<&String> =
+                graph.adjacency_list[from].iter().filter(|to| next.contains(to) && !placed.contains(to)).collect();
+            children.sort();
+            for to in children {
+                placed.insert(to);
+                tree_edges.push((from, to));
+            }
+        }
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "digraph influence {{")?;
+    writeln!(out, "  rankdir=TB;")?;
+    writeln!(out, "  node [shape=box];")?;
+    for (distance, layer) in layers.iter().enumerate() {
+        let names: Vec<String> = layer.iter().map(|node| dot_quote(node)).collect();
+        writeln!(out, "  {{ rank=same; {}; }} // layer {}", names.join("; "), distance)?;
+    }
+    for (from, to) in &tree_edges {
+        writeln!(out, "  {} -> {};", dot_quote(from), dot_quote(to))?;
+    }
+    if non_tree_edges {
+        let tree: HashSet<(&String, &String)> = tree_edges.iter().copied().collect();
+        for from in layers.iter().flatten() {
+            let mut neighbors: Vec<&String> = graph.adjacency_list[from]
+                .iter()
+                .filter(|to| included.contains(to) && !tree.contains(&(from, *to)))
+                .collect();
+            neighbors.sort();
+            for to in neighbors {
+                writeln!(out, "  {} -> {} [style=dashed, color=grey, constraint=false];", dot_quote(from), dot_quote(to))?;
+            }
+        }
+    }
+    writeln!(out, "}}")?;
+    out.flush()?;
+    Ok(included.len())
+}
+
+/// Serializes a report as pretty-printed JSON.
+pub fn write_json<T: Serialize>(report: &T, path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut out, report)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+/// Writes per-domain statistics, in the order given.
+/// Columns: domain,addresses,emails_sent,emails_received,top_external_domain,top_external_emails
+pub fn write_domain_stats_csv(stats: &[DomainStats], path: &Path) -> io::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record([
+        "domain",
+        "addresses",
+        "emails_sent",
+        "emails_received",
+        "top_external_domain",
+        "top_external_emails",
+    ])?;
+    for domain in stats {
+        writer.write_record([
+            domain.domain.clone(),
+            domain.addresses.to_string(),
+            domain.emails_sent.to_string(),
+            domain.emails_received.to_string(),
+            domain.top_external_domain.clone().unwrap_or_default(),
+            domain.top_external_emails.to_string(),
+        ])?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,218 @@
+use chrono::{DateTime, Utc};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use crate::email::normalize_address;
+use crate::email::parse_date;
+
+/// Criteria deciding which email records are kept while reading.
+/// Every criterion is optional; the default filter keeps everything.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct EmailFilter {
+    /// Keep only emails sent at or after this moment
+    #[serde(deserialize_with = "deserialize_date")]
+    pub after: Option<DateTime<Utc>>,
+    /// Keep only emails sent strictly before this moment
+    #[serde(deserialize_with = "deserialize_date")]
+    pub before: Option<DateTime<Utc>>,
+    /// Keep records without a parseable date even when a date range is set
+    pub include_undated: bool,
+    /// Keep only emails whose subject matches this pattern
+    pub subject_regex: Option<TextPattern>,
+    /// Keep only emails whose body matches this pattern
+    pub body_regex: Option<TextPattern>,
+    /// Addresses to drop: blocked senders lose the whole email, blocked
+    /// recipients are removed from the recipient list
+    pub blocklist: Vec<AddressPattern>,
+    /// If non-empty, only these addresses are kept (same rules as `blocklist`)
+    pub allowlist: Vec<AddressPattern>,
+    /// Keep the sender of a record without any recipient address as a
+    /// person without edges instead of rejecting the record
+    pub keep_senders_without_recipients: bool,
+    /// Drop recipient entries longer than this many bytes (default 254)
+    pub max_address_length: Option<usize>,
+}
+
+/// An email address, or a glob over addresses where `*` matches any run of
+/// characters and `?` any single one (e.g. `noreply@*` or `*@lists.example.com`).
+/// Patterns are normalized like addresses, so matching is case-insensitive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AddressPattern(String);
+
+impl AddressPattern {
+    pub fn new(pattern: &str) -> AddressPattern {
+        AddressPattern(normalize_address(pattern))
+    }
+
+    /// Returns true if `address` (already normalized) matches the pattern.
+    pub fn matches(&self, address: &str) -> bool {
+        glob_match(&self.0, address)
+    }
+}
+
+/// Returns true if all of `text` matches `pattern`, where `*` matches any
+/// run of characters, `?` any single one and everything else itself.
+/// Address patterns, input file patterns and node search all match with this.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None; // Pattern index after the last `*`, and where its run ends in the text
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                last_star = Some((p + 1, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            // On a mismatch, let the last `*` swallow one more character and retry
+            _ => match last_star {
+                Some((after_star, run_end)) => {
+                    last_star = Some((after_star, run_end + 1));
+                    p = after_star;
+                    t = run_end + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+impl<'de> Deserialize<'de> for AddressPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|pattern| AddressPattern::new(&pattern))
+    }
+}
+
+/// Reads address patterns from a file with one pattern per line.
+/// Blank lines and lines starting with `#` are ignored.
+pub fn load_address_patterns(path: &Path) -> io::Result<Vec<AddressPattern>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(AddressPattern::new)
+        .collect())
+}
+
+/// A regular expression matched against email subjects or bodies.
+/// Matching is case-insensitive unless the pattern turns it off with `(?-i)`.
+#[derive(Debug, Clone)]
+pub struct TextPattern(Regex);
+
+impl TextPattern {
+    /// Compiles `pattern` case-insensitively.
+    pub fn new(pattern: &str) -> Result<TextPattern, regex::Error> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(TextPattern)
+    }
+
+    /// Returns true if the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    /// Returns the pattern as it was written.
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for TextPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl FromStr for TextPattern {
+    type Err = regex::Error;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        TextPattern::new(pattern)
+    }
+}
+
+impl<'de> Deserialize<'de> for TextPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        TextPattern::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Outcome of checking a record against an `EmailFilter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterDecision {
+    Keep,
+    OutsideDateRange,
+    Undated,
+}
+
+impl EmailFilter {
+    /// Returns the longest recipient address accepted, in bytes.
+    pub fn max_address_length(&self) -> usize {
+        self.max_address_length.unwrap_or(crate::email::DEFAULT_MAX_ADDRESS_LENGTH)
+    }
+
+    /// Returns true if an `after` or `before` bound is set.
+    pub fn has_date_range(&self) -> bool {
+        self.after.is_some() || self.before.is_some()
+    }
+
+    /// Returns true if a subject or body pattern is set.
+    pub fn has_text_patterns(&self) -> bool {
+        self.subject_regex.is_some() || self.body_regex.is_some()
+    }
+
+    /// Checks a record's subject and body against the text patterns.
+    /// A missing pattern accepts everything.
+    pub fn matches_text(&self, subject: &str, body: &str) -> bool {
+        self.subject_regex.as_ref().is_none_or(|pattern| pattern.is_match(subject))
+            && self.body_regex.as_ref().is_none_or(|pattern| pattern.is_match(body))
+    }
+
+    /// Returns true if `address` passes the blocklist and allowlist.
+    pub fn allows_address(&self, address: &str) -> bool {
+        !self.blocklist.iter().any(|pattern| pattern.matches(address))
+            && (self.allowlist.is_empty() || self.allowlist.iter().any(|pattern| pattern.matches(address)))
+    }
+
+    /// Checks a record's timestamp against the date range.
+    pub fn check_date(&self, timestamp: Option<DateTime<Utc>>) -> FilterDecision {
+        if !self.has_date_range() {
+            return FilterDecision::Keep;
+        }
+        let Some(timestamp) = timestamp else {
+            return if self.include_undated {
+                FilterDecision::Keep
+            } else {
+                FilterDecision::Undated
+            };
+        };
+        let after_start = self.after.is_none_or(|after| timestamp >= after);
+        let before_end = self.before.is_none_or(|before| timestamp < before);
+        if after_start && before_end {
+            FilterDecision::Keep
+        } else {
+            FilterDecision::OutsideDateRange
+        }
+    }
+}
+
+/// Deserializes an optional date in any format accepted by `parse_date`.
+fn deserialize_date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_date(&text)
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("unrecognized date '{}'", text)))
+}
@@ -0,0 +1,94 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand::Rng;
+use std::collections::HashSet;
+use crate::graph::Graph;
+
+/// Synthetic random graphs for testing and benchmarking the algorithms without
+/// a real dataset. Nodes are named `n0`, `n1`, ... and every generator is
+/// deterministic for a given seed.
+impl Graph {
+    /// Generates a directed Erdős–Rényi graph: each of the `n * (n - 1)`
+    /// ordered pairs of distinct nodes is an edge with probability `p`.
+    pub fn erdos_renyi(n: usize, p: f64, seed: u64) -> Graph {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut graph = Graph::with_nodes(n);
+        for from in 0..n {
+            for to in 0..n {
+                if from != to && rng.gen_bool(p) {
+                    graph.add_edge(node_name(from), node_name(to));
+                }
+            }
+        }
+        graph
+    }
+
+    /// Generates a directed Barabási–Albert graph by preferential attachment.
+    /// Starting from `m` unconnected nodes, each new node sends edges to `m`
+    /// distinct existing nodes, chosen with probability proportional to their
+    /// total degree. Edges point from the newer node to the older one.
+    pub fn barabasi_albert(n: usize, m: usize, seed: u64) -> Graph {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut graph = Graph::with_nodes(n);
+        if m == 0 {
+            return graph;
+        }
+
+        // Every endpoint of every edge, so a uniform pick is degree-proportional
+        let mut endpoints: Vec<usize> = Vec::new();
+        for node in m..n {
+            let targets: HashSet<usize> = if node == m {
+                (0..m).collect() // The first new node links to all starting nodes
+            } else {
+                let mut targets = HashSet::new();
+                while targets.len() < m {
+                    targets.insert(*endpoints.choose(&mut rng).unwrap());
+                }
+                targets
+            };
+
+            let mut targets: Vec<usize> = targets.into_iter().collect();
+            targets.sort(); // Keep the endpoint order independent of hash order
+            for target in targets {
+                graph.add_edge(node_name(node), node_name(target));
+                endpoints.push(node);
+                endpoints.push(target);
+            }
+        }
+        graph
+    }
+
+    /// Generates a directed planted-partition graph of `communities` groups of
+    /// `size` nodes each. Node `i` belongs to group `i / size`; each ordered
+    /// pair is an edge with probability `p_in` inside a group and `p_out`
+    /// between groups.
+    pub fn planted_partition(communities: usize, size: usize, p_in: f64, p_out: f64, seed: u64) -> Graph {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let n = communities * size;
+        let mut graph = Graph::with_nodes(n);
+        for from in 0..n {
+            for to in 0..n {
+                let p = if from / size == to / size { p_in } else { p_out };
+                if from != to && rng.gen_bool(p) {
+                    graph.add_edge(node_name(from), node_name(to));
+                }
+            }
+        }
+        graph
+    }
+
+    /// Creates a graph with nodes `n0` to `n{n-1}` and no edges.
+    fn with_nodes(n: usize) -> Graph {
+        let mut graph = Graph::new();
+        for node in 0..n {
+            graph.insert_node(node_name(node));
+        }
+        graph
+    }
+}
+
+/// Returns the synthetic name of node `index`.
+fn node_name(index: usize) -> String {
+    format!("n{}", index)
+}
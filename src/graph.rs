@@ -1,63 +1,549 @@
-use std::collections::{HashSet, HashMap};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::collections::{BTreeMap, HashSet, HashMap, VecDeque};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::OnceLock;
 use crate::ParsedEmail;
+use crate::email::normalize_address;
+use crate::filter::glob_match;
+use crate::anonymize::AddressMapper;
+use crate::error::GraphInvariantViolation;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{Rng, SeedableRng};
 
-/// Struct to represent a directed, unweighted graph using an adjacency list
-#[derive(Debug)]
+/// Struct to represent a directed graph using an adjacency list.
+/// Every edge also carries an accumulated weight; with `add_edge` each email
+/// contributes 1.0, so by default the weight is the number of emails sent.
+/// The number of emails is tracked separately as well, so it stays available
+/// when the weights are something else (e.g. recency-decayed), along with
+/// when the edge was active (see `EdgeInfo`).
+///
+/// Two graphs are equal when they have the same nodes, the same edges and
+/// the same edge weights; email counts, dates and node metadata are not
+/// compared.
+#[derive(Debug, Clone)]
 pub struct Graph {
-    pub num_vertices: usize, // Number of unique nodes in the graph
     pub adjacency_list: HashMap<String, HashSet<String>>, // Adjacency list mapping each node to its neighbors
+    pub edge_weights: HashMap<String, HashMap<String, f64>>, // Weight of each edge, keyed by sender then recipient
+    pub edge_info: HashMap<String, HashMap<String, EdgeInfo>>, // Emails behind each edge and when they were sent, keyed the same way
+    pub node_attributes: HashMap<String, NodeMeta>, // Metadata of the nodes that have any
+    reverse_adjacency: HashMap<String, HashSet<String>>, // Senders of each node, kept in step with adjacency_list
+    edge_count: usize, // Number of distinct directed edges, maintained by add_weighted_edge
+    normalized_nodes: OnceLock<HashMap<String, Vec<String>>>, // Normalized address -> nodes, built by resolve_node and reset when nodes change
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for Graph {
+    fn eq(&self, other: &Self) -> bool {
+        self.adjacency_list == other.adjacency_list
+            && self.adjacency_list.iter().all(|(from, neighbors)| {
+                neighbors.iter().all(|to| self.edge_weight(from, to) == other.edge_weight(from, to))
+            })
+    }
 }
 
 impl Graph {
     /// Creates a new, empty Graph.
     pub fn new() -> Self {
         Graph {
-            num_vertices: 0, // Initialize the vertex count to zero
             adjacency_list: HashMap::new(), // Initialize an empty adjacency list
+            edge_weights: HashMap::new(), // Initialize an empty weight map
+            edge_info: HashMap::new(),
+            node_attributes: HashMap::new(),
+            reverse_adjacency: HashMap::new(),
+            edge_count: 0,
+            normalized_nodes: OnceLock::new(),
+        }
+    }
+
+    /// Builds a graph with one email along each `(from, to)` pair; repeated
+    /// pairs add up. Handy for small fixtures.
+    pub fn from_edges(edges: &[(&str, &str)]) -> Self {
+        let mut graph = Graph::new();
+        for (from, to) in edges {
+            graph.add_edge(from.to_string(), to.to_string());
         }
+        graph
     }
 
-    /// Adds an edge from `from_node` to `to_node`.
-    /// Increments `num_vertices` if a new node is added.
+    /// Adds an edge from `from_node` to `to_node`, adding 1.0 to its weight.
+    /// Either node is created if it doesn't exist yet.
     pub fn add_edge(&mut self, from_node: String, to_node: String) {
-        // Insert sender node if it doesn't exist; increment num_vertices
-        self.adjacency_list.entry(from_node.clone())
-            .or_insert_with(|| {
-                self.num_vertices += 1;
-                HashSet::new()
-            });
+        self.add_weighted_edge(from_node, to_node, 1.0);
+    }
 
-        // Insert recipient node if it doesn't exist; increment num_vertices
-        self.adjacency_list.entry(to_node.clone())
-            .or_insert_with(|| {
-                self.num_vertices += 1;
-                HashSet::new()
-            });
+    /// Adds an edge from `from_node` to `to_node`, adding `weight` to the
+    /// weight it already has (edges start at 0.0). Counts as one undated email.
+    pub fn add_weighted_edge(&mut self, from_node: String, to_node: String, weight: f64) {
+        self.add_dated_edge(from_node, to_node, weight, None);
+    }
 
-        // Add the recipient to the sender's set of neighbors
-        self.adjacency_list
+    /// Like `add_weighted_edge`, recording when the email was sent (if known)
+    /// in the edge's `EdgeInfo`.
+    pub fn add_dated_edge(&mut self, from_node: String, to_node: String, weight: f64, timestamp: Option<DateTime<Utc>>) {
+        let mut email = EdgeInfo::with_count(1);
+        if let Some(timestamp) = timestamp {
+            email.record(timestamp);
+        }
+        self.accumulate_edge(from_node, to_node, weight, &email);
+    }
+
+    /// Adds `weight` to the weight of the edge from `from_node` to `to_node`
+    /// and merges `info` into its `EdgeInfo`, creating the edge and its nodes
+    /// as needed.
+    pub(crate) fn accumulate_edge(&mut self, from_node: String, to_node: String, weight: f64, info: &EdgeInfo) {
+        // Insert sender and recipient nodes if they don't exist yet
+        self.insert_node(from_node.clone());
+        self.insert_node(to_node.clone());
+
+        // Accumulate the edge weight and email activity
+        *self.edge_weights
+            .entry(from_node.clone())
+            .or_default()
+            .entry(to_node.clone())
+            .or_insert(0.0) += weight;
+        self.edge_info
+            .entry(from_node.clone())
+            .or_default()
+            .entry(to_node.clone())
+            .or_default()
+            .merge(info);
+
+        // Add the recipient to the sender's set of neighbors, and the reverse
+        self.reverse_adjacency
+            .get_mut(&to_node)
+            .unwrap()
+            .insert(from_node.clone());
+        let is_new_edge = self.adjacency_list
             .get_mut(&from_node)
             .unwrap()
-            .insert(to_node.clone());
+            .insert(to_node);
+        if is_new_edge {
+            self.edge_count += 1;
+        }
+    }
+
+    /// Removes `node` with every edge to or from it. Returns whether the
+    /// node was in the graph.
+    pub fn remove_node(&mut self, node: &str) -> bool {
+        let Some(recipients) = self.adjacency_list.remove(node) else {
+            return false;
+        };
+        self.normalized_nodes.take();
+        let senders = self.reverse_adjacency.remove(node).unwrap_or_default();
+
+        // A self-loop shows up on both sides but is a single edge
+        let self_loop = recipients.contains(node);
+        self.edge_count -= recipients.len() + senders.len() - usize::from(self_loop);
+
+        for recipient in &recipients {
+            if let Some(their_senders) = self.reverse_adjacency.get_mut(recipient) {
+                their_senders.remove(node);
+            }
+        }
+        for sender in &senders {
+            if let Some(their_recipients) = self.adjacency_list.get_mut(sender) {
+                their_recipients.remove(node);
+            }
+            if let Some(weights) = self.edge_weights.get_mut(sender) {
+                weights.remove(node);
+            }
+            if let Some(infos) = self.edge_info.get_mut(sender) {
+                infos.remove(node);
+            }
+        }
+        self.edge_weights.remove(node);
+        self.edge_info.remove(node);
+        self.node_attributes.remove(node);
+        true
+    }
+
+    /// Checks that the public maps and the internal bookkeeping agree: every
+    /// neighbor is a node, the reverse adjacency is the exact transpose of
+    /// `adjacency_list`, the edge count matches, and every edge (and only
+    /// edges) has a nonzero weight. Returns every violation found, in order
+    /// of sender then recipient.
+    pub fn validate(&self) -> Result<(), Vec<GraphInvariantViolation>> {
+        let mut violations = Vec::new();
+        let mut nodes: Vec<&String> = self.adjacency_list.keys().chain(self.reverse_adjacency.keys()).collect();
+        nodes.sort();
+        nodes.dedup();
+
+        let mut actual_edges = 0;
+        for node in nodes {
+            if self.adjacency_list.contains_key(node) != self.reverse_adjacency.contains_key(node) {
+                violations.push(GraphInvariantViolation::ReverseNodeMismatch { node: node.clone() });
+            }
+            let recipients = self.adjacency_list.get(node);
+            actual_edges += recipients.map_or(0, HashSet::len);
+            for to in sorted_refs(recipients.into_iter().flatten()) {
+                let edge = || (node.clone(), to.clone());
+                if !self.adjacency_list.contains_key(to) {
+                    let (from, to) = edge();
+                    violations.push(GraphInvariantViolation::DanglingNeighbor { from, to });
+                }
+                if !self.reverse_adjacency.get(to).is_some_and(|senders| senders.contains(node)) {
+                    let (from, to) = edge();
+                    violations.push(GraphInvariantViolation::MissingReverseEdge { from, to });
+                }
+                if self.edge_weight(node, to).is_none_or(|weight| weight == 0.0) {
+                    let (from, to) = edge();
+                    violations.push(GraphInvariantViolation::MissingWeight { from, to });
+                }
+            }
+            for from in sorted_refs(self.reverse_adjacency.get(node).into_iter().flatten()) {
+                if !self.adjacency_list.get(from).is_some_and(|recipients| recipients.contains(node)) {
+                    violations.push(GraphInvariantViolation::ExtraReverseEdge { from: from.clone(), to: node.clone() });
+                }
+            }
+            for to in sorted_refs(self.edge_weights.get(node).into_iter().flat_map(HashMap::keys)) {
+                if !recipients.is_some_and(|recipients| recipients.contains(to)) {
+                    violations.push(GraphInvariantViolation::StrayWeight { from: node.clone(), to: to.clone() });
+                }
+            }
+        }
+        if actual_edges != self.edge_count {
+            violations.push(GraphInvariantViolation::EdgeCountMismatch { recorded: self.edge_count, actual: actual_edges });
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Returns the number of nodes in the graph.
+    pub fn node_count(&self) -> usize {
+        self.adjacency_list.len()
+    }
+
+    /// Returns the number of distinct directed edges; repeated emails between
+    /// the same pair count once.
+    pub fn edge_count(&self) -> usize {
+        self.edge_count
+    }
+
+    /// Approximates the heap memory held by the graph, in bytes. Every hash
+    /// map and set is counted as its capacity times the size of an entry
+    /// plus one control byte (the layout of the standard hash tables), and
+    /// every stored address as its string capacity, plus the set of active
+    /// days of every edge; each node appears as a key in four maps and each
+    /// edge in three (plus the reverse adjacency),
+    /// so addresses are stored several times. Allocator overhead and spare
+    /// capacity of the strings' allocations are not included, so the true
+    /// usage is somewhat higher.
+    pub fn estimate_memory_bytes(&self) -> usize {
+        fn table<K, V>(capacity: usize) -> usize {
+            capacity * (std::mem::size_of::<(K, V)>() + 1)
+        }
+        fn neighbor_sets(map: &HashMap<String, HashSet<String>>) -> usize {
+            table::<String, HashSet<String>>(map.capacity())
+                + map
+                    .iter()
+                    .map(|(node, set)| node.capacity() + table::<String, ()>(set.capacity()) + set.iter().map(String::capacity).sum::<usize>())
+                    .sum::<usize>()
+        }
+        fn edge_maps<V>(map: &HashMap<String, HashMap<String, V>>) -> usize {
+            table::<String, HashMap<String, V>>(map.capacity())
+                + map
+                    .iter()
+                    .map(|(node, edges)| node.capacity() + table::<String, V>(edges.capacity()) + edges.keys().map(String::capacity).sum::<usize>())
+                    .sum::<usize>()
+        }
+
+        neighbor_sets(&self.adjacency_list)
+            + neighbor_sets(&self.reverse_adjacency)
+            + edge_maps(&self.edge_weights)
+            + edge_maps(&self.edge_info)
+            + self.edge_info
+                .values()
+                .flat_map(HashMap::values)
+                .map(|info| table::<NaiveDate, ()>(info.days.capacity()))
+                .sum::<usize>()
+    }
+
+    /// Returns the accumulated weight of the edge from `from` to `to`, if it exists.
+    pub fn edge_weight(&self, from: &str, to: &str) -> Option<f64> {
+        self.edge_weights.get(from)?.get(to).copied()
+    }
+
+    /// Returns how many emails were sent from `from` to `to` (0 if none).
+    pub fn edge_multiplicity(&self, from: &str, to: &str) -> usize {
+        self.edge_info(from, to).map_or(0, |info| info.count)
+    }
+
+    /// Returns the emails and activity behind the edge from `from` to `to`, if it exists.
+    pub fn edge_info(&self, from: &str, to: &str) -> Option<&EdgeInfo> {
+        self.edge_info.get(from)?.get(to)
+    }
+
+    /// Sets the attribute `key` of `node`: `display_name` and `domain` set
+    /// those fields of its `NodeMeta`, any other key a tag. Returns false
+    /// (and sets nothing) if the node is not in the graph.
+    pub fn set_node_attr(&mut self, node: &str, key: &str, value: String) -> bool {
+        if !self.adjacency_list.contains_key(node) {
+            return false;
+        }
+        let meta = self.node_attributes.entry(node.to_string()).or_default();
+        match key {
+            DISPLAY_NAME_ATTR => meta.display_name = Some(value),
+            DOMAIN_ATTR => meta.domain = Some(value),
+            _ => {
+                meta.tags.insert(key.to_string(), value);
+            }
+        }
+        true
+    }
+
+    /// Returns the attribute `key` of `node`, as set by `set_node_attr`. The
+    /// domain defaults to the part of the address after the last `@`.
+    pub fn get_node_attr(&self, node: &str, key: &str) -> Option<&str> {
+        let meta = self.node_attributes.get(node);
+        match key {
+            DISPLAY_NAME_ATTR => meta?.display_name.as_deref(),
+            DOMAIN_ATTR => meta
+                .and_then(|meta| meta.domain.as_deref())
+                .or_else(|| self.adjacency_list.get_key_value(node)?.0.rsplit_once('@').map(|(_, domain)| domain)),
+            _ => meta?.tags.get(key).map(String::as_str),
+        }
+    }
+
+    /// Returns every attribute of `node` that has a value, as (key, value)
+    /// pairs: the display name and domain first, then the tags by key.
+    pub fn node_attrs(&self, node: &str) -> Vec<(&str, &str)> {
+        self.node_attribute_keys()
+            .into_iter()
+            .filter_map(|key| Some((key, self.get_node_attr(node, key)?)))
+            .collect()
+    }
+
+    /// Returns the attribute keys in use: `display_name` if any node has
+    /// one, `domain`, then every tag key in sorted order.
+    pub fn node_attribute_keys(&self) -> Vec<&str> {
+        let mut keys = Vec::new();
+        if self.node_attributes.values().any(|meta| meta.display_name.is_some()) {
+            keys.push(DISPLAY_NAME_ATTR);
+        }
+        keys.push(DOMAIN_ATTR);
+        let tags: std::collections::BTreeSet<&str> = self.node_attributes
+            .values()
+            .flat_map(|meta| meta.tags.keys().map(String::as_str))
+            .collect();
+        keys.extend(tags);
+        keys
+    }
+
+    /// Returns the domain `node` is grouped under by `domain_statistics`:
+    /// its `domain` attribute, or `INVALID_DOMAIN` if it has none.
+    fn domain_of(&self, node: &str) -> &str {
+        match self.get_node_attr(node, DOMAIN_ATTR) {
+            Some(domain) if !domain.is_empty() => domain,
+            _ => INVALID_DOMAIN,
+        }
+    }
+
+    /// Aggregates the graph by email domain (the `domain` attribute, so
+    /// overrides apply); addresses without one are grouped under
+    /// `INVALID_DOMAIN`. Emails are counted once per recipient, and mail
+    /// within a domain counts as both sent and received by it. Sorted by
+    /// address count descending, then by domain.
+    pub fn domain_statistics(&self) -> Vec<DomainStats> {
+        let mut stats: HashMap<&str, DomainStats> = HashMap::new();
+        let mut external: HashMap<&str, HashMap<&str, usize>> = HashMap::new();
+        for node in self.adjacency_list.keys() {
+            let domain = self.domain_of(node);
+            stats.entry(domain).or_insert_with(|| DomainStats::new(domain)).addresses += 1;
+        }
+        for (from, targets) in &self.edge_info {
+            let from_domain = self.domain_of(from);
+            for (to, info) in targets {
+                let to_domain = self.domain_of(to);
+                if let Some(entry) = stats.get_mut(from_domain) {
+                    entry.emails_sent += info.count;
+                }
+                if let Some(entry) = stats.get_mut(to_domain) {
+                    entry.emails_received += info.count;
+                }
+                if from_domain != to_domain {
+                    *external.entry(from_domain).or_default().entry(to_domain).or_insert(0) += info.count;
+                    *external.entry(to_domain).or_default().entry(from_domain).or_insert(0) += info.count;
+                }
+            }
+        }
+        for (domain, partners) in external {
+            let top = partners
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)));
+            if let (Some(entry), Some((partner, count))) = (stats.get_mut(domain), top) {
+                entry.top_external_domain = Some(partner.to_string());
+                entry.top_external_emails = count;
+            }
+        }
+        let mut stats: Vec<DomainStats> = stats.into_values().collect();
+        stats.sort_by(|a, b| b.addresses.cmp(&a.addresses).then_with(|| a.domain.cmp(&b.domain)));
+        stats
+    }
+
+    /// Sets node attributes from a CSV file of `address,key,value` rows. A
+    /// first row of `address,key,value` is skipped as a header, addresses
+    /// are normalized, and rows with an empty address or key are ignored;
+    /// later rows win for repeated attributes. Returns the number of rows
+    /// applied, leaving out addresses that are not in the graph.
+    pub fn load_node_tags(&mut self, path: &Path) -> Result<usize, csv::Error> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_path(path)?;
+
+        let mut applied = 0;
+        for (i, record) in reader.records().enumerate() {
+            let record = record?;
+            let field = |index| record.get(index).unwrap_or_default().trim();
+            let (address, key, value) = (field(0), field(1), field(2));
+            let is_header = i == 0
+                && address.eq_ignore_ascii_case("address")
+                && key.eq_ignore_ascii_case("key")
+                && value.eq_ignore_ascii_case("value");
+            if is_header || address.is_empty() || key.is_empty() {
+                continue;
+            }
+            if self.set_node_attr(&normalize_address(address), key, value.to_string()) {
+                applied += 1;
+            }
         }
+        Ok(applied)
+    }
+
+    /// Copies the metadata of the nodes `other` has as well.
+    fn copy_node_attributes_to(&self, other: &mut Graph) {
+        for (node, meta) in &self.node_attributes {
+            if other.adjacency_list.contains_key(node) {
+                other.node_attributes.insert(node.clone(), meta.clone());
+            }
+        }
+    }
+
+    /// Adds `node` as a person without edges; does nothing if it is already
+    /// in the graph. The address is used as given, not normalized.
+    pub fn add_node(&mut self, node: String) {
+        self.insert_node(node);
+    }
+
+    /// Inserts a node without any edges if it doesn't exist yet.
+    pub(crate) fn insert_node(&mut self, node: String) {
+        if !self.adjacency_list.contains_key(&node) {
+            self.normalized_nodes.take();
+        }
+        self.reverse_adjacency.entry(node.clone()).or_default();
+        self.adjacency_list.entry(node).or_default();
+    }
 
     /// Builds the graph from a list of parsed emails.
     pub fn build_from_emails(parsed_emails: Vec<ParsedEmail>) -> Self {
+        Graph::build_from_emails_with_options(parsed_emails, &GraphBuildOptions::default()).0
+    }
+
+    /// Builds the graph from a list of parsed emails, applying `opts`.
+    /// Returns the graph together with what went into it.
+    pub fn build_from_emails_with_options(parsed_emails: Vec<ParsedEmail>, opts: &GraphBuildOptions) -> (Self, AddStats) {
         let mut graph = Graph::new(); // Initialize an empty graph
+        let stats = graph.add_emails_with_options(parsed_emails, opts);
+        (graph, stats) // Return the fully constructed graph
+    }
 
-        for email in parsed_emails {
+    /// Adds more emails to the graph, accumulating weights on edges that
+    /// already exist, so a graph can be grown one batch at a time.
+    pub fn add_emails(&mut self, emails: impl IntoIterator<Item = ParsedEmail>) -> AddStats {
+        self.add_emails_with_options(emails, &GraphBuildOptions::default())
+    }
+
+    /// Like `add_emails`, applying `opts` to every email.
+    pub fn add_emails_with_options(&mut self, emails: impl IntoIterator<Item = ParsedEmail>, opts: &GraphBuildOptions) -> AddStats {
+        let (nodes_before, edges_before) = (self.node_count(), self.edge_count());
+        let mut stats = AddStats::default();
+        let mut edges_added = 0;
+
+        for email in emails {
             let sender = email.from; // Extract the sender's email address
             let recipients = email.to; // Extract the list of recipients
 
             // Add an edge from the sender to each recipient
             for recipient in recipients {
-                graph.add_edge(sender.clone(), recipient);
+                if opts.drop_self_loops && recipient == sender {
+                    // The sender still appears, just without the loop
+                    self.insert_node(recipient);
+                    stats.self_loops_skipped += 1;
+                    continue;
+                }
+                self.add_dated_edge(sender.clone(), recipient, 1.0, email.timestamp);
+                edges_added += 1;
+            }
+        }
+
+        stats.new_nodes = self.node_count() - nodes_before;
+        stats.new_edges = self.edge_count() - edges_before;
+        stats.duplicate_edges = edges_added - stats.new_edges;
+        stats
+    }
+
+    /// Builds a graph whose edge weights decay with the age of each email:
+    /// an email sent `age` before `reference_time` contributes 0.5^(age / half_life).
+    /// Emails without a timestamp contribute `RecencyWeights::DEFAULT_UNDATED_WEIGHT`.
+    pub fn build_from_emails_weighted_by_recency(
+        parsed_emails: Vec<ParsedEmail>,
+        reference_time: DateTime<Utc>,
+        half_life: Duration,
+    ) -> Self {
+        Graph::build_from_emails_weighted(parsed_emails, &RecencyWeights::new(reference_time, half_life))
+    }
+
+    /// Builds a graph where each email contributes the weight assigned by `weights`.
+    pub fn build_from_emails_weighted(parsed_emails: Vec<ParsedEmail>, weights: &RecencyWeights) -> Self {
+        let mut graph = Graph::new();
+
+        for email in parsed_emails {
+            let weight = weights.weight(email.timestamp);
+            for recipient in email.to {
+                graph.add_dated_edge(email.from.clone(), recipient, weight, email.timestamp);
+            }
+        }
+
+        graph
+    }
+
+    /// Returns a copy of the graph with every address replaced by its pseudonym.
+    /// Nodes are mapped in sorted order, so a fresh mapper numbers them alphabetically.
+    /// Tags are kept, but display names and domain overrides would give the
+    /// people away and are dropped.
+    pub fn anonymize(&self, mapper: &mut AddressMapper) -> Graph {
+        let mut nodes: Vec<&String> = self.adjacency_list.keys().collect();
+        nodes.sort();
+
+        let mut anonymized = Graph::new();
+        for node in nodes {
+            anonymized.insert_node(mapper.pseudonym(node));
+        }
+        for (from, weights) in &self.edge_weights {
+            for (to, weight) in weights {
+                let info = &self.edge_info[from][to];
+                anonymized.accumulate_edge(mapper.pseudonym(from), mapper.pseudonym(to), *weight, info);
+            }
+        }
+        for (node, meta) in &self.node_attributes {
+            if !meta.tags.is_empty() {
+                let tags = NodeMeta { tags: meta.tags.clone(), ..Default::default() };
+                anonymized.node_attributes.insert(mapper.pseudonym(node), tags);
             }
         }
 
-        graph // Return the fully constructed graph
+        anonymized
     }
 
     /// Returns the neighbors of a given node.
@@ -65,94 +551,2535 @@ impl Graph {
         self.adjacency_list.get(node)
     }
 
-    /// Calculates the out-degree for each node.
-    pub fn calculate_out_degrees(&self) -> HashMap<String, usize> {
-        let mut out_degrees = HashMap::new(); // Initialize an empty HashMap to store out-degrees
+    /// Returns up to `DEFAULT_FIND_LIMIT` nodes whose name matches `pattern`;
+    /// see `find_nodes_with_limit`.
+    pub fn find_nodes(&self, pattern: &str, mode: MatchMode) -> Result<Vec<String>, regex::Error> {
+        self.find_nodes_with_limit(pattern, mode, DEFAULT_FIND_LIMIT)
+    }
 
-        // Iterate over each node in the adjacency list
-        for node in self.adjacency_list.keys() {
-            // Retrieve the number of neighbors (recipients) for the node
-            let degree = self
-                .get_neighbors(node)
-                .map_or(0, |neighbors| neighbors.len());
-            out_degrees.insert(node.clone(), degree); // Insert the out-degree into the HashMap
+    /// Returns the nodes whose name matches `pattern` the way `mode` says,
+    /// ignoring case, most connected first (by total degree, ties by name)
+    /// and at most `limit` of them. Fails only for an invalid regex.
+    pub fn find_nodes_with_limit(&self, pattern: &str, mode: MatchMode, limit: usize) -> Result<Vec<String>, regex::Error> {
+        let lowered = pattern.to_lowercase();
+        let regex = match mode {
+            MatchMode::Regex => Some(regex::RegexBuilder::new(pattern).case_insensitive(true).build()?),
+            _ => None,
+        };
+        let is_match = |node: &String| {
+            let node = node.to_lowercase();
+            match mode {
+                MatchMode::Substring => node.contains(&lowered),
+                MatchMode::Prefix => node.starts_with(&lowered),
+                MatchMode::Glob => glob_match(&lowered, &node),
+                MatchMode::Regex => regex.as_ref().is_some_and(|regex| regex.is_match(&node)),
+            }
+        };
+
+        let degree = |node: &String| self.adjacency_list[node].len() + self.reverse_adjacency[node].len();
+        let mut matches: Vec<&String> = self.adjacency_list.keys().filter(|node| is_match(node)).collect();
+        matches.sort_by(|a, b| degree(b).cmp(&degree(a)).then_with(|| a.cmp(b)));
+        matches.truncate(limit);
+        Ok(matches.into_iter().cloned().collect())
+    }
+
+    /// Returns the node an address typed by a person refers to: the node of
+    /// that exact name, or else the one node whose name normalizes (as
+    /// addresses are at parse time) to the same address. Returns `None` if
+    /// there is no such node or several; see `resolve_node_detailed`.
+    pub fn resolve_node(&self, input: &str) -> Option<&String> {
+        if let Some((node, _)) = self.adjacency_list.get_key_value(input) {
+            return Some(node);
+        }
+        match self.normalized_matches(input) {
+            [node] => self.adjacency_list.get_key_value(node).map(|(node, _)| node),
+            _ => None,
         }
+    }
 
-        out_degrees // Return the complete mapping of out-degrees
+    /// Like `resolve_node`, but says why an address didn't resolve: the
+    /// nodes that collide on its normalized form, or up to `limit` nodes
+    /// whose name contains its part before the `@`, as suggestions.
+    pub fn resolve_node_detailed(&self, input: &str, limit: usize) -> NodeResolution {
+        if let Some(node) = self.resolve_node(input) {
+            return NodeResolution::Resolved(node.clone());
+        }
+        let colliding = self.normalized_matches(input);
+        if colliding.len() > 1 {
+            return NodeResolution::Ambiguous(colliding.to_vec());
+        }
+        let address = normalize_address(input);
+        let local = address.split('@').next().unwrap_or_default();
+        let suggestions = if local.is_empty() {
+            Vec::new()
+        } else {
+            self.find_nodes_with_limit(local, MatchMode::Substring, limit).expect("substring patterns always compile")
+        };
+        NodeResolution::Unknown { suggestions }
     }
 
-    /// Calculates the in-degree for each node.
-    pub fn calculate_in_degrees(&self) -> HashMap<String, usize> {
-        let mut in_degrees = HashMap::new(); // Initialize an empty HashMap to store in-degrees
+    /// Returns the nodes whose normalized name equals the normalized
+    /// `input`, sorted, building the lookup map on first use.
+    fn normalized_matches(&self, input: &str) -> &[String] {
+        let index = self.normalized_nodes.get_or_init(|| {
+            let mut index: HashMap<String, Vec<String>> = HashMap::new();
+            for node in self.adjacency_list.keys() {
+                index.entry(normalize_address(node)).or_default().push(node.clone());
+            }
+            for nodes in index.values_mut() {
+                nodes.sort();
+            }
+            index
+        });
+        index.get(&normalize_address(input)).map_or(&[], Vec::as_slice)
+    }
 
-        // Initialize in-degrees to zero
-        for node in self.adjacency_list.keys() {
-            in_degrees.insert(node.clone(), 0);
+    /// Resolves a name typed by a person: the node `resolve_node` finds for
+    /// it (every colliding node if its normalized form is ambiguous),
+    /// otherwise whatever `find_nodes_with_limit` turns up for it.
+    pub fn lookup_node(&self, query: &str, mode: MatchMode, limit: usize) -> Result<NodeLookup, regex::Error> {
+        if let Some(node) = self.resolve_node(query) {
+            return Ok(NodeLookup::Found(node.clone()));
+        }
+        let colliding = self.normalized_matches(query);
+        if colliding.len() > 1 {
+            return Ok(NodeLookup::Ambiguous(colliding.to_vec()));
         }
+        let mut matches = self.find_nodes_with_limit(query, mode, limit)?;
+        Ok(match matches.len() {
+            0 => NodeLookup::NotFound,
+            1 => NodeLookup::Found(matches.remove(0)),
+            _ => NodeLookup::Ambiguous(matches),
+        })
+    }
 
-        // Iterate over each node's neighbors to count in-degrees
-        for neighbors in self.adjacency_list.values() {
+    /// Collects everything known about one address. The address is resolved
+    /// with `resolve_node` first, so lookups are case-insensitive. Returns
+    /// `None` if it doesn't resolve.
+    pub fn node_report(&self, node: &str, labels: Option<&HashMap<String, String>>) -> Option<NodeReport> {
+        let address = self.resolve_node(node)?.clone();
+        let mut out_neighbors: Vec<String> = self.adjacency_list[&address].iter().cloned().collect();
+        out_neighbors.sort();
+        let in_neighbors: Vec<String> = self.in_neighbors(&address).into_iter().cloned().collect();
+
+        // Position in the degree rankings: degree descending, ties by address
+        let rank = |degrees: &HashMap<String, usize>| {
+            let degree = degrees[&address];
+            1 + degrees
+                .iter()
+                .filter(|(other, other_degree)| **other_degree > degree || (**other_degree == degree && **other < address))
+                .count()
+        };
+        let out_degree_rank = rank(&self.calculate_out_degrees());
+        let in_degree_rank = rank(&self.calculate_in_degrees());
+
+        Some(NodeReport {
+            out_degree: out_neighbors.len(),
+            in_degree: in_neighbors.len(),
+            weighted_out_degree: self.edge_weights.get(&address).map_or(0.0, |weights| weights.values().sum()),
+            weighted_in_degree: in_neighbors.iter().filter_map(|from| self.edge_weight(from, &address)).sum(),
+            community: labels.and_then(|labels| labels.get(&address)).cloned(),
+            attributes: self.node_attrs(&address).into_iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            out_degree_rank,
+            in_degree_rank,
+            out_neighbors,
+            in_neighbors,
+            address,
+        })
+    }
+
+    /// Returns the nodes that have an edge pointing to `node`, in sorted order.
+    pub fn in_neighbors(&self, node: &str) -> Vec<&String> {
+        let mut senders: Vec<&String> = self.reverse_adjacency.get(node).into_iter().flatten().collect();
+        senders.sort();
+        senders
+    }
+
+    /// Finds a shortest directed path from `from` to `to` using BFS.
+    /// Returns the nodes along the path including both endpoints,
+    /// or `None` if either node is missing or `to` is unreachable.
+    pub fn shortest_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        self.bfs_path(from, to, |node| self.adjacency_list[node].iter().collect())
+    }
+
+    /// Finds a shortest path from `from` to `to` ignoring edge direction.
+    /// Ties are broken the same way as in `shortest_path`.
+    pub fn shortest_undirected_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        let mut undirected: HashMap<&String, Vec<&String>> = HashMap::new();
+        for (node, neighbors) in &self.adjacency_list {
             for neighbor in neighbors {
-                if let Some(count) = in_degrees.get_mut(neighbor) {
-                    *count += 1;
+                undirected.entry(node).or_default().push(neighbor);
+                undirected.entry(neighbor).or_default().push(node);
+            }
+        }
+        self.bfs_path(from, to, |node| undirected.get(node).cloned().unwrap_or_default())
+    }
+
+    /// Returns every shortest directed path from `from` to `to`, in sorted
+    /// order, enumerating at most `DEFAULT_MAX_SHORTEST_PATHS` of them.
+    /// Use `shortest_paths_with_limit` to pick the cap or get the full count.
+    pub fn all_shortest_paths(&self, from: &str, to: &str) -> Vec<Vec<String>> {
+        self.shortest_paths_with_limit(from, to, DEFAULT_MAX_SHORTEST_PATHS).paths
+    }
+
+    /// Counts the shortest directed paths from `from` to `to` and enumerates
+    /// up to `max_paths` of them. BFS records, for each node, its shortest-path
+    /// parents and number of shortest paths; the paths are then rebuilt by
+    /// backtracking from `to` through the parent sets.
+    pub fn shortest_paths_with_limit(&self, from: &str, to: &str, max_paths: usize) -> ShortestPaths {
+        let none = ShortestPaths { count: 0, paths: Vec::new() };
+        let Some((start, _)) = self.adjacency_list.get_key_value(from) else {
+            return none;
+        };
+        let Some((target, _)) = self.adjacency_list.get_key_value(to) else {
+            return none;
+        };
+
+        let mut distance: HashMap<&String, usize> = HashMap::from([(start, 0)]);
+        let mut path_counts: HashMap<&String, u64> = HashMap::from([(start, 1)]);
+        let mut parents: HashMap<&String, Vec<&String>> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+
+        while let Some(current) = queue.pop_front() {
+            let current_distance = distance[current];
+            // Nodes beyond the target's layer can't be on a shortest path to it
+            if distance.get(target).is_some_and(|&target_distance| current_distance >= target_distance) {
+                break;
+            }
+            for neighbor in &self.adjacency_list[current] {
+                let neighbor_distance = *distance.entry(neighbor).or_insert_with(|| {
+                    queue.push_back(neighbor);
+                    current_distance + 1
+                });
+                if neighbor_distance == current_distance + 1 {
+                    let via_current = path_counts[current];
+                    let count = path_counts.entry(neighbor).or_insert(0);
+                    *count = count.saturating_add(via_current);
+                    parents.entry(neighbor).or_default().push(current);
                 }
             }
         }
 
-        in_degrees
+        let Some(&count) = path_counts.get(target) else {
+            return none;
+        };
+
+        // Walk the parent sets back from the target, stopping once enough paths are found
+        let mut paths = Vec::new();
+        let mut suffix = vec![target];
+        collect_paths(&parents, &mut suffix, &mut paths, max_paths);
+        paths.sort();
+
+        ShortestPaths { count, paths }
     }
 
-    /// Performs community detection using the Label Propagation Algorithm.
-    /// Returns a HashMap where each node is mapped to its community label.
-    pub fn label_propagation(&self) -> HashMap<String, String> {
-        // Initialize labels: each node is its own label
-        let mut labels: HashMap<String, String> = self.adjacency_list
-            .keys()
-            .map(|node| (node.clone(), node.clone()))
-            .collect();
-        
-        // Initialize a random number generator
-        let mut rng = thread_rng();
+    /// Breadth-first search from `from` to `to` over the given neighbor function.
+    fn bfs_path<'a>(
+        &'a self,
+        from: &str,
+        to: &str,
+        neighbors_of: impl Fn(&'a String) -> Vec<&'a String>,
+    ) -> Option<Vec<String>> {
+        let (start, _) = self.adjacency_list.get_key_value(from)?;
+        if !self.adjacency_list.contains_key(to) {
+            return None;
+        }
 
-        let max_iterations = 500; // Prevent infinite loops
-        for iteration in 0..max_iterations {
-            let mut changed = false;
+        let mut parents: HashMap<&String, &String> = HashMap::new();
+        let mut visited: HashSet<&String> = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
 
-            // Collect all nodes and shuffle their order for random updates
-            let mut nodes: Vec<&String> = self.adjacency_list.keys().collect();
-            nodes.shuffle(&mut rng);
-            
-            // Iterate over each node in the shuffled order
-            for node in nodes {
-                // Retrieve the node's neighbors (recipients)
-                let neighbors = match self.get_neighbors(node) {
-                    Some(neigh) => neigh,
-                    None => continue, // Isolated node
-                };
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                // Walk the parent links back to the start
+                let mut path = vec![current.clone()];
+                let mut node = current;
+                while let Some(&parent) = parents.get(node) {
+                    path.push(parent.clone());
+                    node = parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
 
-                if neighbors.is_empty() {
-                    continue; // No neighbors to influence the label
+            // Visit neighbors in sorted order so the returned path is deterministic
+            let mut neighbors = neighbors_of(current);
+            neighbors.sort();
+            for neighbor in neighbors {
+                if visited.insert(neighbor) {
+                    parents.insert(neighbor, current);
+                    queue.push_back(neighbor);
                 }
+            }
+        }
 
-                // Count the frequency of each label in the neighborhood
-                let mut label_counts: HashMap<&String, usize> = HashMap::new();
-                for neighbor in neighbors {
-                    if let Some(label) = labels.get(neighbor) {
-                        *label_counts.entry(label).or_insert(0) += 1;
-                    }
+        None
+    }
+
+    /// Counts the nodes reachable from `node` along directed edges, not
+    /// counting `node` itself. Unknown nodes reach nothing.
+    pub fn reachable_set_size(&self, node: &str) -> usize {
+        let Some((start, _)) = self.adjacency_list.get_key_value(node) else {
+            return 0;
+        };
+
+        let mut visited: HashSet<&String> = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            for neighbor in &self.adjacency_list[current] {
+                if visited.insert(neighbor) {
+                    queue.push_back(neighbor);
                 }
+            }
+        }
+
+        visited.len() - 1
+    }
 
-                // Identify the label(s) with the highest frequency
-                if let Some((&max_label, &max_count)) = label_counts.iter().max_by_key(|&(_, count)| count) {
-                    let current_label = labels.get(node).unwrap(); // Get the current label of the node
-                    if current_label != max_label {
-                        labels.insert(node.clone(), max_label.clone()); // Update the node's label to the most frequent neighbor label
-                        changed = true; // Indicate that a label change has occurred
+    /// Computes `reachable_set_size` for every node, or for a random sample of
+    /// `sample` nodes when given (one BFS per node gets slow on large graphs).
+    /// The same `seed` picks the same sample.
+    pub fn reachability_distribution(&self, sample: Option<usize>, seed: u64) -> HashMap<String, usize> {
+        let mut nodes: Vec<&String> = self.adjacency_list.keys().collect();
+        if let Some(sample) = sample.filter(|&sample| sample < nodes.len()) {
+            nodes.sort(); // Sample from a fixed order, not hash order
+            let (chosen, _) = nodes.partial_shuffle(&mut StdRng::seed_from_u64(seed), sample);
+            nodes = chosen.to_vec();
+        }
+
+        nodes
+            .into_iter()
+            .map(|node| (node.clone(), self.reachable_set_size(node)))
+            .collect()
+    }
+
+    /// Splits the nodes into weakly connected components, i.e. connected
+    /// when edge direction is ignored. Components are sorted largest first
+    /// (ties by smallest member) and the members of each are sorted.
+    pub fn weakly_connected_components(&self) -> Vec<Vec<String>> {
+        let mut visited: HashSet<&String> = HashSet::new();
+        let mut components: Vec<Vec<String>> = Vec::new();
+        for start in self.sorted_nodes() {
+            if !visited.insert(start) {
+                continue;
+            }
+            let mut component = vec![start.clone()];
+            let mut queue = VecDeque::from([start]);
+            while let Some(current) = queue.pop_front() {
+                for neighbor in self.adjacency_list[current].iter().chain(&self.reverse_adjacency[current]) {
+                    if visited.insert(neighbor) {
+                        component.push(neighbor.clone());
+                        queue.push_back(neighbor);
                     }
                 }
             }
+            component.sort();
+            components.push(component);
         }
-        labels // Return the final community labels for all nodes
+
+        // Stable sort keeps components with equal sizes in order of their smallest member
+        components.sort_by_key(|component| std::cmp::Reverse(component.len()));
+        components
+    }
+
+    /// Computes the eccentricity of nodes in the largest weakly connected
+    /// component: the most undirected steps from the node to any other node
+    /// of the component. Only `sample` randomly chosen nodes of the component
+    /// are measured when given, since every node costs one BFS; the same
+    /// `seed` picks the same nodes. Nodes outside the component are left
+    /// out; an empty graph gives an empty map.
+    pub fn eccentricities(&self, sample: Option<usize>, seed: u64) -> HashMap<String, usize> {
+        let Some(component) = self.weakly_connected_components().into_iter().next() else {
+            return HashMap::new();
+        };
+        let mut nodes: Vec<&String> = component.iter().collect();
+        if let Some(sample) = sample.filter(|&sample| sample < nodes.len()) {
+            let (chosen, _) = nodes.partial_shuffle(&mut StdRng::seed_from_u64(seed), sample);
+            nodes = chosen.to_vec();
+        }
+
+        nodes
+            .into_iter()
+            .map(|node| {
+                let farthest = self.distances_from(node, Direction::Undirected).into_values().max().unwrap_or(0);
+                (node.clone(), farthest)
+            })
+            .collect()
+    }
+
+    /// Histogram of undirected hop counts between `num_pairs` random pairs
+    /// of the largest weakly connected component, keyed by distance. See
+    /// `sample_distances`, which also counts directed unreachability.
+    pub fn distance_distribution(&self, num_pairs: usize, seed: u64) -> BTreeMap<usize, usize> {
+        self.sample_distances(num_pairs, seed).hops
+    }
+
+    /// Samples `num_pairs` distinct ordered pairs of different people from
+    /// the largest weakly connected component (reproducibly for a given
+    /// `seed`) and measures the undirected distance between each. Since the
+    /// component is connected when direction is ignored, every pair has a
+    /// distance; `unreachable` instead counts the pairs where the first
+    /// person has no directed chain of emails to the second. Asking for at
+    /// least as many pairs as the component has measures every pair.
+    pub fn sample_distances(&self, num_pairs: usize, seed: u64) -> DistanceSample {
+        let mut sample = DistanceSample::default();
+        let Some(component) = self.weakly_connected_components().into_iter().next() else {
+            return sample;
+        };
+        let n = component.len();
+        let total_pairs = n * (n - 1);
+        let num_pairs = num_pairs.min(total_pairs);
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        // Draw index pairs; enumerate when most pairs are wanted anyway
+        let mut pairs: Vec<(usize, usize)> = if num_pairs * 2 >= total_pairs {
+            let mut all: Vec<(usize, usize)> = (0..n)
+                .flat_map(|from| (0..n).filter(move |&to| to != from).map(move |to| (from, to)))
+                .collect();
+            all.partial_shuffle(&mut rng, num_pairs);
+            all.truncate(num_pairs);
+            all
+        } else {
+            let mut seen: HashSet<(usize, usize)> = HashSet::new();
+            while seen.len() < num_pairs {
+                let from = rng.gen_range(0..n);
+                let to = rng.gen_range(0..n);
+                if from != to {
+                    seen.insert((from, to));
+                }
+            }
+            seen.into_iter().collect()
+        };
+        pairs.sort(); // Group by source so each source needs one pair of searches
+
+        for group in pairs.chunk_by(|a, b| a.0 == b.0) {
+            let source = &component[group[0].0];
+            let undirected = self.distances_from(source, Direction::Undirected);
+            let directed = self.distances_from(source, Direction::Out);
+            for &(_, to) in group {
+                let target = &component[to];
+                *sample.hops.entry(undirected[target]).or_insert(0) += 1;
+                if !directed.contains_key(target) {
+                    sample.unreachable += 1;
+                }
+            }
+        }
+        sample.pairs = pairs.len();
+        sample
+    }
+
+    /// Returns the number of steps from `source` to every node it reaches
+    /// in the given direction, `source` itself at 0. An unknown source
+    /// reaches nothing.
+    pub fn bfs_distances(&self, source: &str, direction: Direction) -> HashMap<String, usize> {
+        self.distances_from(source, direction)
+            .into_iter()
+            .map(|(node, distance)| (node.clone(), distance))
+            .collect()
     }
-}
 
+    /// Groups the nodes reached from `source` by their distance: layer 0 is
+    /// `source` itself, layer 1 its neighbors in the given direction, and so
+    /// on. Each layer is sorted. An unknown source gives no layers.
+    pub fn bfs_layers(&self, source: &str, direction: Direction) -> Vec<Vec<String>> {
+        let mut layers: Vec<Vec<String>> = Vec::new();
+        for (node, distance) in self.distances_from(source, direction) {
+            if layers.len() <= distance {
+                layers.resize(distance + 1, Vec::new());
+            }
+            layers[distance].push(node.clone());
+        }
+        for layer in &mut layers {
+            layer.sort();
+        }
+        layers
+    }
+
+    /// Breadth-first distances from `source`, borrowing the node names.
+    fn distances_from(&self, source: &str, direction: Direction) -> HashMap<&String, usize> {
+        let Some((start, _)) = self.adjacency_list.get_key_value(source) else {
+            return HashMap::new();
+        };
+        let mut distances: HashMap<&String, usize> = HashMap::from([(start, 0)]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            let distance = distances[current] + 1;
+            let recipients = (direction != Direction::In).then(|| &self.adjacency_list[current]);
+            let senders = (direction != Direction::Out).then(|| &self.reverse_adjacency[current]);
+            for neighbor in recipients.into_iter().flatten().chain(senders.into_iter().flatten()) {
+                if !distances.contains_key(neighbor) {
+                    distances.insert(neighbor, distance);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        distances
+    }
+
+    /// Calculates the out-degree for each node.
+    pub fn calculate_out_degrees(&self) -> HashMap<String, usize> {
+        let mut out_degrees = HashMap::new(); // Initialize an empty HashMap to store out-degrees
+
+        // Iterate over each node in the adjacency list
+        for node in self.adjacency_list.keys() {
+            // Retrieve the number of neighbors (recipients) for the node
+            let degree = self
+                .get_neighbors(node)
+                .map_or(0, |neighbors| neighbors.len());
+            out_degrees.insert(node.clone(), degree); // Insert the out-degree into the HashMap
+        }
+
+        out_degrees // Return the complete mapping of out-degrees
+    }
+
+    /// Counts the emails each node sent, over all recipients (its weighted out-degree or out-strength).
+    pub fn calculate_out_strengths(&self) -> HashMap<String, usize> {
+        self.adjacency_list
+            .keys()
+            .map(|node| {
+                let total = self.edge_info.get(node).map_or(0, |infos| infos.values().map(|info| info.count).sum());
+                (node.clone(), total)
+            })
+            .collect()
+    }
+
+    /// Counts the emails each node received, over all senders (its weighted in-degree or in-strength).
+    pub fn calculate_in_strengths(&self) -> HashMap<String, usize> {
+        let mut in_volumes: HashMap<String, usize> = self.adjacency_list
+            .keys()
+            .map(|node| (node.clone(), 0))
+            .collect();
+
+        for infos in self.edge_info.values() {
+            for (neighbor, info) in infos {
+                if let Some(total) = in_volumes.get_mut(neighbor) {
+                    *total += info.count;
+                }
+            }
+        }
+
+        in_volumes
+    }
+
+    /// Calculates, for each node, the mean total (in + out) degree of the
+    /// nodes it sends to. Nodes without out-neighbors map to 0.0.
+    pub fn average_neighbor_degree(&self) -> HashMap<String, f64> {
+        self.mean_neighbor_degree(&self.adjacency_list)
+    }
+
+    /// Like `average_neighbor_degree`, but over the nodes that send to each node.
+    pub fn average_in_neighbor_degree(&self) -> HashMap<String, f64> {
+        self.mean_neighbor_degree(&self.reverse_adjacency)
+    }
+
+    /// Averages the total degree over each node's neighbors in `neighbor_sets`.
+    fn mean_neighbor_degree(&self, neighbor_sets: &HashMap<String, HashSet<String>>) -> HashMap<String, f64> {
+        let out_degrees = self.calculate_out_degrees();
+        let in_degrees = self.calculate_in_degrees();
+        let total_degree = |node: &String| (out_degrees[node] + in_degrees[node]) as f64;
+
+        neighbor_sets
+            .iter()
+            .map(|(node, neighbors)| {
+                let average = if neighbors.is_empty() {
+                    0.0
+                } else {
+                    neighbors.iter().map(total_degree).sum::<f64>() / neighbors.len() as f64
+                };
+                (node.clone(), average)
+            })
+            .collect()
+    }
+
+    /// Calculates the total weight of each node's outgoing edges.
+    pub fn calculate_weighted_out_degrees(&self) -> HashMap<String, f64> {
+        self.adjacency_list
+            .keys()
+            .map(|node| {
+                let total = self.edge_weights.get(node).map_or(0.0, |weights| weights.values().sum());
+                (node.clone(), total)
+            })
+            .collect()
+    }
+
+    /// Calculates the total weight of each node's incoming edges.
+    pub fn calculate_weighted_in_degrees(&self) -> HashMap<String, f64> {
+        let mut in_weights: HashMap<String, f64> = self.adjacency_list
+            .keys()
+            .map(|node| (node.clone(), 0.0))
+            .collect();
+
+        for weights in self.edge_weights.values() {
+            for (neighbor, weight) in weights {
+                if let Some(total) = in_weights.get_mut(neighbor) {
+                    *total += weight;
+                }
+            }
+        }
+
+        in_weights
+    }
+
+    /// Calculates the in-degree for each node.
+    pub fn calculate_in_degrees(&self) -> HashMap<String, usize> {
+        let mut in_degrees = HashMap::new(); // Initialize an empty HashMap to store in-degrees
+
+        // Initialize in-degrees to zero
+        for node in self.adjacency_list.keys() {
+            in_degrees.insert(node.clone(), 0);
+        }
+
+        // Iterate over each node's neighbors to count in-degrees
+        for neighbors in self.adjacency_list.values() {
+            for neighbor in neighbors {
+                if let Some(count) = in_degrees.get_mut(neighbor) {
+                    *count += 1;
+                }
+            }
+        }
+
+        in_degrees
+    }
+
+    /// Classifies every node by whether it sends and receives email.
+    /// Self-loops are ignored, so a node that only emails itself is `Isolated`.
+    pub fn classify_nodes(&self) -> HashMap<String, NodeClass> {
+        self.adjacency_list
+            .iter()
+            .map(|(node, neighbors)| {
+                let sends = neighbors.iter().any(|neighbor| neighbor != node);
+                let receives = self.reverse_adjacency[node].iter().any(|sender| sender != node);
+                let class = match (sends, receives) {
+                    (true, true) => NodeClass::Connector,
+                    (true, false) => NodeClass::Source,
+                    (false, true) => NodeClass::Sink,
+                    (false, false) => NodeClass::Isolated,
+                };
+                (node.clone(), class)
+            })
+            .collect()
+    }
+
+    /// Returns the addresses that look like distribution lists or shared
+    /// mailboxes rather than people: they receive many emails from many
+    /// different senders but send next to nothing themselves. Self-loops are
+    /// ignored.
+    pub fn likely_distribution_lists(&self, options: &DistributionListOptions) -> HashSet<String> {
+        let out_strengths = self.calculate_out_strengths();
+        let in_strengths = self.calculate_in_strengths();
+        self.adjacency_list
+            .keys()
+            .filter(|node| {
+                let self_emails = self.edge_multiplicity(node, node);
+                let received = in_strengths[*node] - self_emails;
+                let sent = out_strengths[*node] - self_emails;
+                let senders = self.reverse_adjacency[*node].iter().filter(|sender| sender != node).count();
+                received >= options.min_emails_received
+                    && senders >= options.min_senders
+                    && sent as f64 <= options.max_sent_ratio * received as f64
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the nodes whose in- or out-degree lies more than `z_threshold`
+    /// standard deviations from the mean, in either direction. Degrees are
+    /// compared as `ln(1 + degree)`, since on heavy-tailed email data plain
+    /// z-scores flag half the hubs. A degree that is the same for every
+    /// node gives z-scores of 0. Sorted by the larger |z|, then by address.
+    pub fn degree_outliers(&self, z_threshold: f64) -> Vec<DegreeOutlier> {
+        let out_degrees = self.calculate_out_degrees();
+        let in_degrees = self.calculate_in_degrees();
+        let z_scores = |degrees: &HashMap<String, usize>| -> HashMap<String, f64> {
+            let logs: Vec<(&String, f64)> = degrees.iter().map(|(node, &degree)| (node, (degree as f64).ln_1p())).collect();
+            let n = logs.len() as f64;
+            let mean = logs.iter().map(|(_, value)| value).sum::<f64>() / n;
+            let deviation = (logs.iter().map(|(_, value)| (value - mean).powi(2)).sum::<f64>() / n).sqrt();
+            logs.into_iter()
+                .map(|(node, value)| {
+                    let z = if deviation > 0.0 { (value - mean) / deviation } else { 0.0 };
+                    (node.clone(), z)
+                })
+                .collect()
+        };
+        let (out_z, in_z) = (z_scores(&out_degrees), z_scores(&in_degrees));
+
+        let mut outliers: Vec<DegreeOutlier> = self.adjacency_list
+            .keys()
+            .map(|node| DegreeOutlier {
+                node: node.clone(),
+                in_degree: in_degrees[node],
+                out_degree: out_degrees[node],
+                in_z: in_z[node],
+                out_z: out_z[node],
+            })
+            .filter(|outlier| outlier.max_abs_z() > z_threshold)
+            .collect();
+        outliers.sort_by(|a, b| b.max_abs_z().total_cmp(&a.max_abs_z()).then_with(|| a.node.cmp(&b.node)));
+        outliers
+    }
+
+    /// Computes the Pearson correlation between the in-degree and out-degree
+    /// of the nodes: near 1.0 when people who send to many also hear from
+    /// many. Returns 0.0 when either degree is the same for every node (or
+    /// there are fewer than two nodes), where the correlation is undefined.
+    pub fn degree_correlation(&self) -> f64 {
+        let out_degrees = self.calculate_out_degrees();
+        let in_degrees = self.calculate_in_degrees();
+        let pairs: Vec<(f64, f64)> = out_degrees
+            .iter()
+            .map(|(node, &out_degree)| (in_degrees[node] as f64, out_degree as f64))
+            .collect();
+        pearson_correlation(&pairs)
+    }
+
+    /// Returns up to `n` nodes with the largest gap between out-degree and
+    /// in-degree, as `out - in`: positive for people who mostly send,
+    /// negative for people who mostly receive. Sorted by the size of the gap,
+    /// ties broken by address; nodes without a gap are left out.
+    pub fn most_asymmetric_nodes(&self, n: usize) -> Vec<(String, i64)> {
+        let out_degrees = self.calculate_out_degrees();
+        let in_degrees = self.calculate_in_degrees();
+        let mut gaps: Vec<(String, i64)> = out_degrees
+            .into_iter()
+            .map(|(node, out_degree)| {
+                let gap = out_degree as i64 - in_degrees[&node] as i64;
+                (node, gap)
+            })
+            .filter(|(_, gap)| *gap != 0)
+            .collect();
+        gaps.sort_by(|a, b| b.1.abs().cmp(&a.1.abs()).then_with(|| a.0.cmp(&b.0)));
+        gaps.truncate(n);
+        gaps
+    }
+
+    /// Performs community detection using the Label Propagation Algorithm.
+    /// Returns a HashMap where each node is mapped to its community label.
+    pub fn label_propagation(&self) -> HashMap<String, String> {
+        self.label_propagation_with_options(&LpaOptions::default())
+    }
+
+    /// Label propagation with an explicit iteration cap and optional RNG seed.
+    /// With a seed the result is fully reproducible across runs.
+    pub fn label_propagation_with_options(&self, opts: &LpaOptions) -> HashMap<String, String> {
+        self.label_propagation_detailed(opts).labels
+    }
+
+    /// Like `label_propagation_with_options`, but also reports how many
+    /// passes ran, whether the labels stabilized before
+    /// `opts.max_iterations`, and how many labels changed in each pass.
+    pub fn label_propagation_detailed(&self, opts: &LpaOptions) -> LpaResult {
+        // Initialize labels: each node is its own label
+        let labels: HashMap<String, String> = self.adjacency_list
+            .keys()
+            .map(|node| (node.clone(), node.clone()))
+            .collect();
+        let mut result = self.propagate_labels(labels, &HashSet::new(), opts);
+
+        if opts.min_community_size > 1 {
+            result.labels = self.merge_small_communities(&result.labels, opts.min_community_size);
+        }
+        result
+    }
+
+    /// Semi-supervised label propagation: nodes in `fixed` keep their label
+    /// throughout, every other node starts without a label and only adopts
+    /// labels from labeled neighbors, so the known labels spread outward.
+    /// Nodes no known label reaches end up with their own name, or with
+    /// `UNLABELED_LABEL` if `opts.mark_unlabeled` is set. Entries of `fixed`
+    /// that are not in the graph are ignored, and so is
+    /// `opts.min_community_size`, so fixed labels are never overwritten.
+    pub fn label_propagation_seeded(&self, fixed: &HashMap<String, String>, opts: &LpaOptions) -> HashMap<String, String> {
+        self.label_propagation_seeded_detailed(fixed, opts).labels
+    }
+
+    /// `label_propagation_seeded` with the convergence diagnostics of
+    /// `label_propagation_detailed`.
+    pub fn label_propagation_seeded_detailed(&self, fixed: &HashMap<String, String>, opts: &LpaOptions) -> LpaResult {
+        let labels: HashMap<String, String> = fixed
+            .iter()
+            .filter(|(node, _)| self.adjacency_list.contains_key(*node))
+            .map(|(node, label)| (node.clone(), label.clone()))
+            .collect();
+        let frozen: HashSet<String> = labels.keys().cloned().collect();
+        let mut result = self.propagate_labels(labels, &frozen, opts);
+
+        for node in self.adjacency_list.keys() {
+            if !result.labels.contains_key(node) {
+                let label = if opts.mark_unlabeled { UNLABELED_LABEL.to_string() } else { node.clone() };
+                result.labels.insert(node.clone(), label);
+            }
+        }
+        result
+    }
+
+    /// Runs label propagation `runs` times with the seeds `base_seed`,
+    /// `base_seed + 1`, ... and groups the nodes that keep ending up together.
+    /// See `consensus_communities_with_options`.
+    pub fn consensus_communities(&self, runs: usize, base_seed: u64) -> ConsensusResult {
+        self.consensus_communities_with_options(runs, base_seed, &LpaOptions::default())
+    }
+
+    /// Like `consensus_communities`, with the other label propagation options
+    /// taken from `opts` (its seed is ignored). Co-assignment is counted for
+    /// every pair of nodes joined by an edge, the only pairs that pull each
+    /// other into a community. Pairs that share a label in at least
+    /// `CONSENSUS_THRESHOLD` of the runs are linked, and each connected group
+    /// of linked nodes is a consensus community named after its smallest
+    /// member. A node's stability is the fraction of runs in which it had the
+    /// most common label among its consensus community (ties by first seen).
+    pub fn consensus_communities_with_options(&self, runs: usize, base_seed: u64, opts: &LpaOptions) -> ConsensusResult {
+        let nodes = self.sorted_nodes();
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, node)| (*node, i)).collect();
+
+        // Each run's labels, as small integers per node index
+        let mut run_labels: Vec<Vec<usize>> = Vec::with_capacity(runs);
+        for run in 0..runs {
+            let run_opts = LpaOptions { seed: Some(base_seed.wrapping_add(run as u64)), ..opts.clone() };
+            let labels = self.label_propagation_detailed(&run_opts).labels;
+            let mut ids: HashMap<&str, usize> = HashMap::new();
+            run_labels.push(
+                nodes
+                    .iter()
+                    .map(|node| {
+                        let next = ids.len();
+                        *ids.entry(labels[*node].as_str()).or_insert(next)
+                    })
+                    .collect(),
+            );
+        }
+
+        let index = &index;
+        let mut pairs: Vec<(usize, usize)> = self.adjacency_list
+            .iter()
+            .flat_map(|(from, recipients)| {
+                let from = index[from];
+                recipients.iter().map(move |to| (from.min(index[to]), from.max(index[to])))
+            })
+            .filter(|(a, b)| a != b)
+            .collect();
+        pairs.sort();
+        pairs.dedup();
+
+        let mut co_assignment = HashMap::new();
+        let mut linked: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+        for (a, b) in pairs {
+            let shared = run_labels.iter().filter(|labels| labels[a] == labels[b]).count();
+            let fraction = if runs == 0 { 0.0 } else { shared as f64 / runs as f64 };
+            co_assignment.insert((nodes[a].clone(), nodes[b].clone()), fraction);
+            if runs > 0 && fraction >= CONSENSUS_THRESHOLD {
+                linked[a].push(b);
+                linked[b].push(a);
+            }
+        }
+
+        // Nodes are visited in sorted order, so each group starts at its smallest member
+        let mut labels = HashMap::new();
+        let mut stability = HashMap::new();
+        let mut visited = vec![false; nodes.len()];
+        for start in 0..nodes.len() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut group = vec![start];
+            let mut queue = VecDeque::from([start]);
+            while let Some(current) = queue.pop_front() {
+                for &neighbor in &linked[current] {
+                    if !visited[neighbor] {
+                        visited[neighbor] = true;
+                        group.push(neighbor);
+                        queue.push_back(neighbor);
+                    }
+                }
+            }
+
+            let mut stays = vec![0usize; group.len()];
+            for labels in &run_labels {
+                let mut counts: HashMap<usize, usize> = HashMap::new();
+                for &member in &group {
+                    *counts.entry(labels[member]).or_insert(0) += 1;
+                }
+                let (majority, _) = counts
+                    .into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+                    .expect("groups are never empty");
+                for (stay, &member) in stays.iter_mut().zip(&group) {
+                    if labels[member] == majority {
+                        *stay += 1;
+                    }
+                }
+            }
+            for (stay, &member) in stays.into_iter().zip(&group) {
+                labels.insert(nodes[member].clone(), nodes[start].clone());
+                stability.insert(nodes[member].clone(), if runs == 0 { 1.0 } else { stay as f64 / runs as f64 });
+            }
+        }
+        ConsensusResult { labels, stability, co_assignment, runs }
+    }
+
+    /// Runs label propagation passes until no label changes. Nodes missing
+    /// from `labels` have no label yet and don't influence their neighbors;
+    /// nodes in `frozen` never change.
+    fn propagate_labels(&self, mut labels: HashMap<String, String>, frozen: &HashSet<String>, opts: &LpaOptions) -> LpaResult {
+        // Initialize a random number generator
+        let mut rng = match opts.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        // Sort once so the shuffle below only depends on the RNG, not on HashMap order
+        let mut nodes: Vec<&String> = self.adjacency_list
+            .keys()
+            .filter(|node| !frozen.contains(*node))
+            .collect();
+        nodes.sort();
+
+        let mut changes_per_iteration = Vec::new();
+        let mut converged = false;
+        for _ in 0..opts.max_iterations {
+            let mut changes = 0;
+
+            // Shuffle the node order for random updates
+            nodes.shuffle(&mut rng);
+
+            // Iterate over each node in the shuffled order
+            for &node in &nodes {
+                // Retrieve the node's neighbors (recipients, plus senders in undirected mode)
+                let mut neighbors: HashSet<&String> = self.adjacency_list[node].iter().collect();
+                if opts.use_undirected_neighbors {
+                    neighbors.extend(&self.reverse_adjacency[node]);
+                }
+
+                if neighbors.is_empty() {
+                    continue; // No neighbors to influence the label
+                }
+
+                // Count the frequency of each label in the neighborhood, or in
+                // weighted mode the total weight of the edges to (and in
+                // undirected mode from) the neighbors carrying it
+                let mut label_counts: HashMap<&String, f64> = HashMap::new();
+                for neighbor in neighbors {
+                    if let Some(label) = labels.get(neighbor) {
+                        let mut weight = 1.0;
+                        if opts.weighted {
+                            weight = self.edge_weight(node, neighbor).unwrap_or(0.0);
+                            if opts.use_undirected_neighbors {
+                                weight += self.edge_weight(neighbor, node).unwrap_or(0.0);
+                            }
+                        }
+                        *label_counts.entry(label).or_insert(0.0) += weight;
+                    }
+                }
+
+                // Identify the label(s) with the highest frequency; weights are
+                // summed in HashSet order, so equal totals may differ in the last bits
+                let max_count = match label_counts.values().copied().reduce(f64::max) {
+                    Some(count) => count,
+                    None => continue,
+                };
+                let tolerance = 1e-9 * max_count.abs().max(1.0);
+                let mut best_labels: Vec<&String> = label_counts
+                    .iter()
+                    .filter(|&(_, &count)| count >= max_count - tolerance)
+                    .map(|(&label, _)| label)
+                    .collect();
+
+                // Keep the current label if it is among the most frequent ones,
+                // otherwise break ties randomly
+                if labels.get(node).is_some_and(|current_label| best_labels.contains(&current_label)) {
+                    continue;
+                }
+                best_labels.sort();
+                let new_label = (*best_labels.choose(&mut rng).unwrap()).clone();
+                labels.insert(node.clone(), new_label); // Update the node's label to the most frequent neighbor label
+                changes += 1;
+            }
+            changes_per_iteration.push(changes);
+
+            // Stop once a full pass leaves every label unchanged
+            if changes == 0 {
+                converged = true;
+                break;
+            }
+        }
+
+        LpaResult { labels, iterations_run: changes_per_iteration.len(), converged, changes_per_iteration }
+    }
+
+    /// Reassigns every member of a community with fewer than `min_size`
+    /// members to the large community it has the most edges with, counting
+    /// both senders and recipients (ties go to the smaller label). Members
+    /// with no neighbor in any large community get `UNASSIGNED_LABEL`.
+    /// Which communities are large is decided once, before any reassignment.
+    pub fn merge_small_communities(&self, labels: &HashMap<String, String>, min_size: usize) -> HashMap<String, String> {
+        let mut sizes: HashMap<&String, usize> = HashMap::new();
+        for label in labels.values() {
+            *sizes.entry(label).or_insert(0) += 1;
+        }
+        let is_large = |label: &String| label != UNASSIGNED_LABEL && sizes[label] >= min_size;
+
+        labels
+            .iter()
+            .map(|(node, label)| {
+                if is_large(label) {
+                    return (node.clone(), label.clone());
+                }
+
+                // Count edges from this member to each large community
+                let mut shared_edges: HashMap<&String, usize> = HashMap::new();
+                let neighbors = self.adjacency_list.get(node).into_iter().flatten()
+                    .chain(self.reverse_adjacency.get(node).into_iter().flatten());
+                for neighbor in neighbors {
+                    if let Some(neighbor_label) = labels.get(neighbor).filter(|neighbor_label| is_large(neighbor_label)) {
+                        *shared_edges.entry(neighbor_label).or_insert(0) += 1;
+                    }
+                }
+                let target = shared_edges
+                    .into_iter()
+                    .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+                    .map_or(UNASSIGNED_LABEL.to_string(), |(target, _)| target.clone());
+                (node.clone(), target)
+            })
+            .collect()
+    }
+
+    /// Returns, for every node, the share of the weight of its incoming and
+    /// outgoing edges that stays inside its own community of `labels`.
+    /// Neighbors without a label, or labeled `UNASSIGNED_LABEL`, are outside
+    /// every community; self-loops stay inside. Nodes without edges get 0.0.
+    pub fn community_embeddedness(&self, labels: &HashMap<String, String>) -> HashMap<String, f64> {
+        let community = |node: &String| labels.get(node).filter(|label| *label != UNASSIGNED_LABEL);
+        self.adjacency_list
+            .keys()
+            .map(|node| {
+                let own = community(node);
+                let (mut inside, mut total) = (0.0, 0.0);
+                let edges = self.adjacency_list[node]
+                    .iter()
+                    .map(|to| (to, self.edge_weight(node, to)))
+                    .chain(self.reverse_adjacency[node].iter().map(|from| (from, self.edge_weight(from, node))));
+                for (neighbor, weight) in edges {
+                    let weight = weight.unwrap_or(0.0);
+                    total += weight;
+                    if neighbor == node || (own.is_some() && community(neighbor) == own) {
+                        inside += weight;
+                    }
+                }
+                let score = if total > 0.0 { inside / total } else { 0.0 };
+                (node.clone(), score)
+            })
+            .collect()
+    }
+
+    /// Compares this graph (before) with `other` (after). Degree deltas cover
+    /// every node in either graph and use total (in + out) degree, counting
+    /// a missing node as degree 0.
+    pub fn diff(&self, other: &Graph) -> GraphDiff {
+        let edges = |graph: &Graph| -> HashSet<(String, String)> {
+            graph.adjacency_list
+                .iter()
+                .flat_map(|(from, neighbors)| neighbors.iter().map(move |to| (from.clone(), to.clone())))
+                .collect()
+        };
+        let (before, after) = (edges(self), edges(other));
+        let mut added_edges: Vec<(String, String)> = after.difference(&before).cloned().collect();
+        let mut removed_edges: Vec<(String, String)> = before.difference(&after).cloned().collect();
+        added_edges.sort();
+        removed_edges.sort();
+
+        let mut added_nodes: Vec<String> = other.adjacency_list.keys().filter(|node| !self.adjacency_list.contains_key(*node)).cloned().collect();
+        let mut removed_nodes: Vec<String> = self.adjacency_list.keys().filter(|node| !other.adjacency_list.contains_key(*node)).cloned().collect();
+        added_nodes.sort();
+        removed_nodes.sort();
+
+        let mut weight_changes: Vec<WeightChange> = before
+            .intersection(&after)
+            .filter_map(|(from, to)| {
+                let (old, new) = (self.edge_weight(from, to)?, other.edge_weight(from, to)?);
+                (old != new).then(|| WeightChange { from: from.clone(), to: to.clone(), before: old, after: new })
+            })
+            .collect();
+        weight_changes.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+
+        let total_degrees = |graph: &Graph| {
+            let mut degrees = graph.calculate_out_degrees();
+            for (node, degree) in graph.calculate_in_degrees() {
+                *degrees.entry(node).or_insert(0) += degree;
+            }
+            degrees
+        };
+        let (degrees_before, degrees_after) = (total_degrees(self), total_degrees(other));
+        let degree_deltas = degrees_before
+            .keys()
+            .chain(degrees_after.keys())
+            .map(|node| {
+                let before = degrees_before.get(node).copied().unwrap_or(0) as i64;
+                let after = degrees_after.get(node).copied().unwrap_or(0) as i64;
+                (node.clone(), after - before)
+            })
+            .collect();
+
+        GraphDiff {
+            added_nodes,
+            removed_nodes,
+            added_edges,
+            removed_edges,
+            weight_changes,
+            degree_deltas,
+        }
+    }
+
+    /// Writes the weighted adjacency matrix in MatrixMarket coordinate format.
+    /// Row and column `i` (1-based) is the `i`-th address in sorted order, as
+    /// listed by `export::write_node_index`; self-loops land on the diagonal.
+    pub fn to_matrix_market<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut out = io::BufWriter::new(writer);
+        let nodes = self.sorted_nodes();
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, node)| (*node, i + 1)).collect();
+
+        writeln!(out, "%%MatrixMarket matrix coordinate real general")?;
+        writeln!(out, "% Email graph: entry (i, j) is the weight of the edge from node i to node j")?;
+        writeln!(out, "{} {} {}", nodes.len(), nodes.len(), self.edge_count())?;
+        for from in &nodes {
+            let mut neighbors: Vec<&String> = self.adjacency_list[*from].iter().collect();
+            neighbors.sort();
+            for to in neighbors {
+                let weight = self.edge_weight(from, to).unwrap_or(1.0);
+                writeln!(out, "{} {} {}", index[from], index[to], weight)?;
+            }
+        }
+        out.flush()
+    }
+
+    /// Returns the addresses in sorted order with the dense matrix of email
+    /// counts between them (`matrix[i][j]` is the number of emails from `i`
+    /// to `j`), or `None` if the graph has more than `DENSE_MATRIX_MAX_NODES`
+    /// nodes, where the matrix would take too much memory.
+    pub fn to_dense_counts(&self) -> Option<(Vec<String>, Vec<Vec<usize>>)> {
+        if self.node_count() > DENSE_MATRIX_MAX_NODES {
+            return None;
+        }
+
+        let nodes = self.sorted_nodes();
+        let matrix = nodes
+            .iter()
+            .map(|from| nodes.iter().map(|to| self.edge_multiplicity(from, to)).collect())
+            .collect();
+        Some((nodes.into_iter().cloned().collect(), matrix))
+    }
+
+    /// Returns the nodes in sorted order, the order used by matrix exports.
+    fn sorted_nodes(&self) -> Vec<&String> {
+        let mut nodes: Vec<&String> = self.adjacency_list.keys().collect();
+        nodes.sort();
+        nodes
+    }
+
+    /// Returns the subgraph induced by the largest weakly connected component
+    /// (ties go to the one with the smallest member). An empty graph gives
+    /// an empty graph.
+    pub fn largest_component_subgraph(&self) -> Graph {
+        let giant: HashSet<String> = self.weakly_connected_components()
+            .into_iter()
+            .next()
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        self.induced_subgraph(&giant)
+    }
+
+    /// Returns a new graph containing only the given nodes and the edges between them.
+    pub fn induced_subgraph(&self, nodes: &HashSet<String>) -> Graph {
+        let mut subgraph = Graph::new();
+
+        for node in nodes {
+            if self.adjacency_list.contains_key(node) {
+                subgraph.insert_node(node.clone());
+            }
+        }
+
+        for (from, neighbors) in &self.adjacency_list {
+            if !nodes.contains(from) {
+                continue;
+            }
+            for to in neighbors.iter().filter(|to| nodes.contains(*to)) {
+                let weight = self.edge_weight(from, to).unwrap_or(1.0);
+                subgraph.accumulate_edge(from.clone(), to.clone(), weight, &self.edge_info[from][to]);
+            }
+        }
+        self.copy_node_attributes_to(&mut subgraph);
+
+        subgraph
+    }
+
+    /// Collects everyone within `depth` undirected steps of the `seeds` and
+    /// returns the subgraph they induce. The graph is expanded one level at a
+    /// time; whenever a level would add more than `max_per_level` new nodes, a
+    /// random `max_per_level` of them (chosen by `seed`) are kept and the rest
+    /// are not expanded further. Seeds that are not in the graph are ignored
+    /// and the seeds themselves are never truncated.
+    pub fn snowball_sample(&self, seeds: &[String], depth: usize, max_per_level: usize, seed: u64) -> Graph {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut sampled: HashSet<&String> = seeds
+            .iter()
+            .filter_map(|node| self.adjacency_list.get_key_value(node).map(|(node, _)| node))
+            .collect();
+        let mut frontier: Vec<&String> = sampled.iter().copied().collect();
+
+        for _ in 0..depth {
+            let mut next: Vec<&String> = frontier
+                .iter()
+                .flat_map(|node| self.adjacency_list[*node].iter().chain(&self.reverse_adjacency[*node]))
+                .filter(|neighbor| !sampled.contains(neighbor))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+            next.sort(); // Sample from a fixed order, not hash order
+            if next.len() > max_per_level {
+                let (chosen, _) = next.partial_shuffle(&mut rng, max_per_level);
+                next = chosen.to_vec();
+            }
+            if next.is_empty() {
+                break;
+            }
+            sampled.extend(next.iter().copied());
+            frontier = next;
+        }
+
+        self.induced_subgraph(&sampled.into_iter().cloned().collect())
+    }
+
+    /// Returns the graph of mutual relationships: an edge is kept, in both
+    /// directions and with its original weights, only when both (u, v) and
+    /// (v, u) exist. Self-loops are dropped, and so is everyone left without a
+    /// mutual edge.
+    pub fn mutual_graph(&self) -> Graph {
+        let mut mutual = Graph::new();
+        for (from, neighbors) in &self.adjacency_list {
+            for to in neighbors {
+                if from != to && self.adjacency_list[to].contains(from) {
+                    let weight = self.edge_weight(from, to).unwrap_or(1.0);
+                    mutual.accumulate_edge(from.clone(), to.clone(), weight, &self.edge_info[from][to]);
+                }
+            }
+        }
+        self.copy_node_attributes_to(&mut mutual);
+        mutual
+    }
+
+    /// Returns a copy of the graph without the edges that carry fewer than
+    /// `min_weight` emails, so one-off emails don't stitch unrelated groups
+    /// together. Every node is kept, even if it loses all its edges.
+    pub fn filter_by_weight(&self, min_weight: usize) -> Graph {
+        let mut filtered = Graph::new();
+        for (from, neighbors) in &self.adjacency_list {
+            filtered.insert_node(from.clone());
+            for to in neighbors {
+                let info = &self.edge_info[from][to];
+                if info.count >= min_weight {
+                    let weight = self.edge_weight(from, to).unwrap_or(1.0);
+                    filtered.accumulate_edge(from.clone(), to.clone(), weight, info);
+                }
+            }
+        }
+        self.copy_node_attributes_to(&mut filtered);
+        filtered
+    }
+
+    /// Returns a copy of the graph keeping each edge, with its weight and
+    /// activity, independently with probability `fraction`, so degrees shrink
+    /// in proportion but keep their shape. The same `seed` keeps the same
+    /// edges. Nodes left without any kept edge are dropped; see
+    /// `sample_edges_stratified` to keep every node.
+    pub fn sample_edges(&self, fraction: f64, seed: u64) -> Graph {
+        self.thin_edges(fraction, seed, false)
+    }
+
+    /// Like `sample_edges`, but keeps every node: a node that had edges and
+    /// lost them all gets one of its edges (to or from it) back, chosen at
+    /// random, and nodes without edges stay as they are. Slightly more than
+    /// `fraction` of the edges are kept as a result.
+    pub fn sample_edges_stratified(&self, fraction: f64, seed: u64) -> Graph {
+        self.thin_edges(fraction, seed, true)
+    }
+
+    /// Keeps each edge with probability `fraction`, then with `keep_nodes`
+    /// gives every node that lost all its edges one of them back.
+    fn thin_edges(&self, fraction: f64, seed: u64, keep_nodes: bool) -> Graph {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Sorted so the coin flips only depend on the seed, not on HashMap order
+        let mut edges: Vec<(&String, &String)> = self.adjacency_list
+            .iter()
+            .flat_map(|(from, recipients)| recipients.iter().map(move |to| (from, to)))
+            .collect();
+        edges.sort();
+        let mut kept: HashSet<(&String, &String)> = edges.iter().copied().filter(|_| rng.gen::<f64>() < fraction).collect();
+
+        let mut sampled = Graph::new();
+        if keep_nodes {
+            let mut covered: HashSet<&String> = kept.iter().flat_map(|&(from, to)| [from, to]).collect();
+            for node in self.sorted_nodes() {
+                if covered.contains(node) {
+                    continue;
+                }
+                sampled.insert_node(node.clone());
+                let mut incident: Vec<(&String, &String)> = self.adjacency_list[node]
+                    .iter()
+                    .map(|to| (node, to))
+                    .chain(self.reverse_adjacency[node].iter().map(|from| (from, node)))
+                    .collect();
+                incident.sort();
+                incident.dedup(); // A self-loop shows up on both sides
+                if let Some(&(from, to)) = incident.choose(&mut rng) {
+                    kept.insert((from, to));
+                    covered.extend([from, to]);
+                }
+            }
+        }
+
+        for (from, to) in edges.into_iter().filter(|edge| kept.contains(edge)) {
+            let weight = self.edge_weight(from, to).unwrap_or(1.0);
+            sampled.accumulate_edge(from.clone(), to.clone(), weight, &self.edge_info[from][to]);
+        }
+        self.copy_node_attributes_to(&mut sampled);
+        sampled
+    }
+
+    /// Returns a random graph with exactly the same in- and out-degree for
+    /// every node, for use as a null model: `num_swaps` double-edge swaps
+    /// turn a pair of edges `a -> b`, `c -> d` into `a -> d`, `c -> b`.
+    /// Swaps that would duplicate an edge or create a self-loop are
+    /// rejected, and after 100 attempts per requested swap it gives up. Each
+    /// edge keeps the weight and emails of the edge whose sender it kept.
+    pub fn rewire_preserving_degrees(&self, num_swaps: usize, seed: u64) -> Graph {
+        self.rewire_preserving_degrees_with_options(num_swaps, seed, false)
+    }
+
+    /// Like `rewire_preserving_degrees`, optionally allowing swaps that create self-loops.
+    pub fn rewire_preserving_degrees_with_options(&self, num_swaps: usize, seed: u64, allow_self_loops: bool) -> Graph {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Sorted so the swaps only depend on the seed, not on HashMap order
+        let mut edges: Vec<(&String, &String)> = self.adjacency_list
+            .iter()
+            .flat_map(|(from, recipients)| recipients.iter().map(move |to| (from, to)))
+            .collect();
+        edges.sort();
+        let originals = edges.clone();
+        let mut present: HashSet<(&String, &String)> = edges.iter().copied().collect();
+
+        let (mut swaps, mut attempts) = (0, 0);
+        while swaps < num_swaps && edges.len() >= 2 && attempts < num_swaps.saturating_mul(100) {
+            attempts += 1;
+            let (i, j) = (rng.gen_range(0..edges.len()), rng.gen_range(0..edges.len()));
+            let ((a, b), (c, d)) = (edges[i], edges[j]);
+            if a == c || b == d {
+                continue; // Swapping would change nothing
+            }
+            if !allow_self_loops && (a == d || c == b) {
+                continue;
+            }
+            if present.contains(&(a, d)) || present.contains(&(c, b)) {
+                continue;
+            }
+            present.remove(&(a, b));
+            present.remove(&(c, d));
+            present.insert((a, d));
+            present.insert((c, b));
+            edges[i] = (a, d);
+            edges[j] = (c, b);
+            swaps += 1;
+        }
+
+        let mut rewired = Graph::new();
+        for node in self.sorted_nodes() {
+            rewired.insert_node(node.clone());
+        }
+        // Edges only ever trade recipients, so position i still has the sender of originals[i]
+        for ((from, to), (_, original_to)) in edges.into_iter().zip(originals) {
+            let weight = self.edge_weight(from, original_to).unwrap_or(1.0);
+            rewired.accumulate_edge(from.clone(), to.clone(), weight, &self.edge_info[from][original_to]);
+        }
+        self.copy_node_attributes_to(&mut rewired);
+        rewired
+    }
+
+    /// Returns the average local clustering coefficient of the undirected
+    /// projection: for each node, the share of pairs of its neighbors that
+    /// are themselves linked, averaged over all nodes. Self-loops are
+    /// ignored and nodes with fewer than two neighbors count as 0.
+    pub fn average_clustering(&self) -> f64 {
+        let neighbors: HashMap<&String, HashSet<&String>> = self.adjacency_list
+            .keys()
+            .map(|node| {
+                let linked = self.adjacency_list[node]
+                    .iter()
+                    .chain(&self.reverse_adjacency[node])
+                    .filter(|other| *other != node)
+                    .collect();
+                (node, linked)
+            })
+            .collect();
+        if neighbors.is_empty() {
+            return 0.0;
+        }
+        let total: f64 = neighbors
+            .values()
+            .map(|linked| {
+                let k = linked.len();
+                if k < 2 {
+                    return 0.0;
+                }
+                let links = linked
+                    .iter()
+                    .map(|u| neighbors[u].iter().filter(|v| linked.contains(*v)).count())
+                    .sum::<usize>() / 2; // Each link is seen from both ends
+                links as f64 / (k * (k - 1) / 2) as f64
+            })
+            .sum();
+        total / neighbors.len() as f64
+    }
+
+    /// Returns the maximum spanning forest of the undirected projection,
+    /// where each pair of people is joined by the summed weight of their
+    /// edges in both directions. Built with Kruskal's algorithm, so among
+    /// the spanning forests it keeps the heaviest pairs; a disconnected
+    /// graph gives one tree per weakly connected component. Every node is
+    /// kept. Each tree edge points the way most of the weight flowed (ties
+    /// from the smaller address) and carries the pair's combined weight and
+    /// email activity. Self-loops are dropped.
+    pub fn maximum_spanning_tree(&self) -> Graph {
+        let nodes = self.sorted_nodes();
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, node)| (*node, i)).collect();
+
+        // One candidate per unordered pair, heaviest first; ties by pair keep it deterministic
+        let mut pairs: Vec<(usize, usize, f64)> = Vec::new();
+        for (from, recipients) in &self.adjacency_list {
+            for to in recipients {
+                let (u, v) = (index[from], index[to]);
+                let reciprocated = self.adjacency_list[to].contains(from);
+                if u < v || (u > v && !reciprocated) {
+                    let weight = self.edge_weight(from, to).unwrap_or(0.0)
+                        + self.edge_weight(to, from).unwrap_or(0.0);
+                    pairs.push((u.min(v), u.max(v), weight));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| (a.0, a.1).cmp(&(b.0, b.1))));
+
+        let mut forest = Graph::new();
+        for node in &nodes {
+            forest.insert_node((*node).clone());
+        }
+        let mut components = UnionFind::new(nodes.len());
+        for (u, v, weight) in pairs {
+            if !components.union(u, v) {
+                continue;
+            }
+            let (a, b) = (nodes[u], nodes[v]);
+            let (from, to) = if self.edge_weight(b, a).unwrap_or(0.0) > self.edge_weight(a, b).unwrap_or(0.0) {
+                (b, a)
+            } else {
+                (a, b)
+            };
+            let mut info = self.edge_info(a, b).cloned().unwrap_or_default();
+            info.merge(self.edge_info(b, a).unwrap_or(&EdgeInfo::default()));
+            forest.accumulate_edge(from.clone(), to.clone(), weight, &info);
+        }
+        self.copy_node_attributes_to(&mut forest);
+        forest
+    }
+
+    /// Returns a copy of the graph without the nodes that have no edges at all.
+    pub fn without_isolated_nodes(&self) -> Graph {
+        let connected: HashSet<String> = self.adjacency_list
+            .iter()
+            .filter(|(node, neighbors)| !neighbors.is_empty() || !self.reverse_adjacency[*node].is_empty())
+            .map(|(node, _)| node.clone())
+            .collect();
+        self.induced_subgraph(&connected)
+    }
+
+    /// Returns the subgraph induced by the nodes whose total (in + out) degree
+    /// is at least `min_degree`. Degrees are measured on the original graph.
+    pub fn prune_min_degree(&self, min_degree: usize) -> Graph {
+        let out_degrees = self.calculate_out_degrees();
+        let in_degrees = self.calculate_in_degrees();
+
+        let kept: HashSet<String> = self.adjacency_list
+            .keys()
+            .filter(|node| out_degrees[*node] + in_degrees[*node] >= min_degree)
+            .cloned()
+            .collect();
+
+        self.induced_subgraph(&kept)
+    }
+
+    /// Computes PageRank scores with the power-iteration method.
+    /// Dangling nodes (no out-neighbors) spread their rank uniformly over all nodes.
+    /// Iteration stops once the L1 change between rounds drops below `tol`.
+    pub fn pagerank(&self, damping: f64, tol: f64, max_iter: usize) -> HashMap<String, f64> {
+        self.power_iteration(damping, tol, max_iter, |_, _| 1.0)
+    }
+
+    /// Computes PageRank where a random walk leaves `u` along each edge in
+    /// proportion to its weight, so a 500-email relationship passes on far
+    /// more rank than a one-off email. Dangling nodes and convergence are
+    /// handled as in [`Graph::pagerank`], and with equal weights on every
+    /// edge the scores are the same.
+    pub fn weighted_pagerank(&self, damping: f64, tol: f64, max_iter: usize) -> HashMap<String, f64> {
+        self.power_iteration(damping, tol, max_iter, |from, to| {
+            self.edge_weight(from, to).unwrap_or(0.0)
+        })
+    }
+
+    /// Runs PageRank power iteration, following each out-edge with
+    /// probability proportional to `weight(from, to)`. Nodes whose out-edges
+    /// weigh nothing in total count as dangling.
+    fn power_iteration(
+        &self,
+        damping: f64,
+        tol: f64,
+        max_iter: usize,
+        weight: impl Fn(&str, &str) -> f64,
+    ) -> HashMap<String, f64> {
+        let n = self.adjacency_list.len();
+        if n == 0 {
+            return HashMap::new();
+        }
+
+        // Transition probabilities only depend on the edges, so compute them once
+        let transitions: HashMap<&String, Vec<(&String, f64)>> = self.adjacency_list
+            .iter()
+            .filter_map(|(node, neighbors)| {
+                let weighted: Vec<(&String, f64)> = neighbors
+                    .iter()
+                    .map(|neighbor| (neighbor, weight(node, neighbor)))
+                    .collect();
+                let total: f64 = weighted.iter().map(|(_, w)| w).sum();
+                (total > 0.0).then(|| {
+                    let probabilities = weighted.into_iter().map(|(neighbor, w)| (neighbor, w / total)).collect();
+                    (node, probabilities)
+                })
+            })
+            .collect();
+
+        let uniform = 1.0 / n as f64;
+        let mut ranks: HashMap<&String, f64> = self.adjacency_list
+            .keys()
+            .map(|node| (node, uniform))
+            .collect();
+
+        for _ in 0..max_iter {
+            // Rank held by dangling nodes is redistributed to everyone
+            let dangling_mass: f64 = self.adjacency_list
+                .keys()
+                .filter(|node| !transitions.contains_key(node))
+                .map(|node| ranks[node])
+                .sum();
+            let base = (1.0 - damping) * uniform + damping * dangling_mass * uniform;
+
+            let mut next: HashMap<&String, f64> = self.adjacency_list
+                .keys()
+                .map(|node| (node, base))
+                .collect();
+
+            for (node, probabilities) in &transitions {
+                let rank = damping * ranks[node];
+                for (neighbor, probability) in probabilities {
+                    *next.get_mut(neighbor).unwrap() += rank * probability;
+                }
+            }
+
+            let delta: f64 = next.iter().map(|(node, rank)| (rank - ranks[node]).abs()).sum();
+            ranks = next;
+            if delta < tol {
+                break;
+            }
+        }
+
+        ranks.into_iter().map(|(node, rank)| (node.clone(), rank)).collect()
+    }
+
+    /// Computes unnormalized betweenness centrality on the directed graph
+    /// using Brandes' algorithm. Runs one BFS per node, so it is O(V * E).
+    pub fn betweenness_centrality(&self) -> HashMap<String, f64> {
+        let mut centrality: HashMap<String, f64> = self.adjacency_list
+            .keys()
+            .map(|node| (node.clone(), 0.0))
+            .collect();
+
+        for source in self.adjacency_list.keys() {
+            self.accumulate_betweenness(source, &mut centrality);
+        }
+
+        centrality
+    }
+
+    /// Computes unnormalized edge betweenness on the directed graph: for
+    /// every edge, the number of shortest paths between ordered pairs of
+    /// nodes that use it, split evenly among equally short alternatives.
+    /// Uses the same Brandes passes as `betweenness_centrality`, so it is
+    /// also O(V * E). Every edge is included; self-loops score 0.
+    pub fn edge_betweenness(&self) -> HashMap<(String, String), f64> {
+        let mut centrality: HashMap<(&String, &String), f64> = self.adjacency_list
+            .iter()
+            .flat_map(|(from, recipients)| recipients.iter().map(move |to| ((from, to), 0.0)))
+            .collect();
+
+        for source in self.adjacency_list.keys() {
+            self.brandes_from(source, |_, _| {}, |from, to, share| {
+                *centrality.get_mut(&(from, to)).unwrap() += share;
+            });
+        }
+
+        centrality
+            .into_iter()
+            .map(|((from, to), score)| ((from.clone(), to.clone()), score))
+            .collect()
+    }
+
+    /// Returns the `n` edges with the highest edge betweenness, highest
+    /// first, ties broken by sender then recipient.
+    pub fn top_edges_by_betweenness(&self, n: usize) -> Vec<((String, String), f64)> {
+        let mut ranked: Vec<((String, String), f64)> = self.edge_betweenness().into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(n);
+        ranked
+    }
+
+    /// Estimates betweenness centrality by running Brandes' accumulation from
+    /// `num_sources` randomly chosen source nodes and scaling the sums by
+    /// `V / num_sources`. The same `seed` always picks the same sources.
+    ///
+    /// The estimate is unbiased over the choice of sources, but any single run
+    /// has high variance for nodes that sit on few shortest paths: a node whose
+    /// centrality comes from one or two sources is either missed entirely or
+    /// heavily overestimated. Rankings of the most central nodes are far more
+    /// stable than their individual scores. Sampling every node gives the exact
+    /// result.
+    pub fn approximate_betweenness(&self, num_sources: usize, seed: u64) -> HashMap<String, f64> {
+        let mut centrality: HashMap<String, f64> = self.adjacency_list
+            .keys()
+            .map(|node| (node.clone(), 0.0))
+            .collect();
+        if num_sources == 0 {
+            return centrality;
+        }
+
+        let mut nodes: Vec<&String> = self.adjacency_list.keys().collect();
+        nodes.sort(); // Sample from a fixed order, not hash order
+        let num_sources = num_sources.min(nodes.len());
+        let (sources, _) = nodes.partial_shuffle(&mut StdRng::seed_from_u64(seed), num_sources);
+        for source in sources.iter() {
+            self.accumulate_betweenness(source, &mut centrality);
+        }
+
+        let scale = self.node_count() as f64 / num_sources as f64;
+        for score in centrality.values_mut() {
+            *score *= scale;
+        }
+        centrality
+    }
+
+    /// Computes harmonic centrality: for each node, the sum of `1 / d` over
+    /// every other node that reaches it along directed edges in `d` steps.
+    /// Unreachable nodes add nothing, so unlike closeness this stays
+    /// meaningful on disconnected graphs. With `sample`, only that many
+    /// randomly chosen source nodes are searched and the sums are scaled by
+    /// `V / sample`, giving an unbiased estimate.
+    pub fn harmonic_centrality(&self, sample: Option<usize>) -> HashMap<String, f64> {
+        let mut centrality: HashMap<String, f64> = self.adjacency_list
+            .keys()
+            .map(|node| (node.clone(), 0.0))
+            .collect();
+
+        let mut sources: Vec<&String> = self.adjacency_list.keys().collect();
+        let mut scale = 1.0;
+        if let Some(sample) = sample.filter(|&sample| sample < sources.len()) {
+            if sample == 0 {
+                return centrality;
+            }
+            sources.sort(); // Sample from a fixed order, not hash order
+            let (chosen, _) = sources.partial_shuffle(&mut rand::thread_rng(), sample);
+            sources = chosen.to_vec();
+            scale = self.node_count() as f64 / sample as f64;
+        }
+
+        for source in sources {
+            for (target, distance) in self.distances_from(source, Direction::Out) {
+                if distance > 0 {
+                    *centrality.get_mut(target).unwrap() += scale / distance as f64;
+                }
+            }
+        }
+        centrality
+    }
+
+    /// Picks up to `k` influential spreaders with the VoteRank heuristic.
+    /// Every node votes for its out-neighbors with a voting ability that
+    /// starts at 1; the node with the most votes is selected and stops
+    /// voting, and each node that voted for it loses `1 / average out-degree`
+    /// of its ability (never going below 0). Repeating this spreads the
+    /// picks out instead of choosing hubs that reach the same people.
+    /// Ties go to the smaller address; selection stops early once no
+    /// unselected node gets any votes.
+    pub fn vote_rank(&self, k: usize) -> Vec<String> {
+        let edges: usize = self.adjacency_list.values().map(HashSet::len).sum();
+        if edges == 0 {
+            return Vec::new();
+        }
+        let dampening = self.node_count() as f64 / edges as f64; // 1 / average out-degree
+
+        let mut ability: HashMap<&String, f64> = self.adjacency_list.keys().map(|node| (node, 1.0)).collect();
+        let mut selected: Vec<String> = Vec::new();
+        let mut candidates = self.sorted_nodes();
+        while selected.len() < k {
+            // Scan in sorted order so the first maximum is the smallest address
+            let mut best: Option<(usize, f64)> = None;
+            for (i, node) in candidates.iter().enumerate() {
+                let votes: f64 = self.reverse_adjacency[*node]
+                    .iter()
+                    .filter(|voter| *voter != *node)
+                    .map(|voter| ability[voter])
+                    .sum();
+                if votes > best.map_or(0.0, |(_, most)| most) {
+                    best = Some((i, votes));
+                }
+            }
+            let Some((i, _)) = best else {
+                break;
+            };
+
+            let winner = candidates.remove(i);
+            ability.insert(winner, 0.0);
+            for voter in &self.reverse_adjacency[winner] {
+                let remaining = ability.get_mut(voter).unwrap();
+                *remaining = (*remaining - dampening).max(0.0);
+            }
+            selected.push(winner.clone());
+        }
+        selected
+    }
+
+    /// Simulates an attack on the network: nodes are removed from a copy of
+    /// the graph in the order `strategy` gives, and after each of `steps`
+    /// equal batches the size of the largest weakly connected component is
+    /// recorded. The order is fixed up front from the intact graph, so
+    /// betweenness is computed only once. The first point is the intact
+    /// graph and the last has every node removed.
+    pub fn robustness_profile(&self, strategy: RemovalStrategy, steps: usize) -> Vec<RobustnessPoint> {
+        let n = self.node_count();
+        let largest_fraction = |graph: &Graph| {
+            let largest = graph.weakly_connected_components().first().map_or(0, Vec::len);
+            if n == 0 { 0.0 } else { largest as f64 / n as f64 }
+        };
+
+        let mut order = self.sorted_nodes();
+        match strategy {
+            RemovalStrategy::HighestDegree => {
+                let out_degrees = self.calculate_out_degrees();
+                let in_degrees = self.calculate_in_degrees();
+                // Stable sort keeps ties in address order
+                order.sort_by_key(|node| std::cmp::Reverse(out_degrees[*node] + in_degrees[*node]));
+            }
+            RemovalStrategy::HighestBetweenness => {
+                let betweenness = self.betweenness_centrality();
+                order.sort_by(|a, b| betweenness[*b].total_cmp(&betweenness[*a]));
+            }
+            RemovalStrategy::Random { seed } => order.shuffle(&mut StdRng::seed_from_u64(seed)),
+        }
+
+        let mut remaining = self.clone();
+        let mut points = vec![RobustnessPoint { fraction_removed: 0.0, largest_component_fraction: largest_fraction(self) }];
+        let mut removed = 0;
+        for step in 1..=steps {
+            let target = step * n / steps;
+            for node in &order[removed..target] {
+                remaining.remove_node(node);
+            }
+            removed = target;
+            points.push(RobustnessPoint {
+                fraction_removed: removed as f64 / n.max(1) as f64,
+                largest_component_fraction: largest_fraction(&remaining),
+            });
+        }
+        points
+    }
+
+    /// Simulates random loss of emails: each of `trials` times the edges
+    /// are put in a random order and kept one by one, and at each of
+    /// `steps` equal retained-edge fractions the largest weakly connected
+    /// component is measured relative to the node count. Returns
+    /// `(retained_fraction, mean_largest_component_fraction)` pairs from the
+    /// intact graph (1.0) down to no edges (0.0). Every node stays in place,
+    /// so the components are grown with a union-find over the edge order
+    /// rather than rebuilt; `seed` fixes all the orders.
+    pub fn edge_percolation_curve(&self, steps: usize, trials: usize, seed: u64) -> Vec<(f64, f64)> {
+        let (steps, trials) = (steps.max(1), trials.max(1));
+        let nodes = self.sorted_nodes();
+        let n = nodes.len();
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, node)| (*node, i)).collect();
+        let mut edges: Vec<(usize, usize)> = nodes
+            .iter()
+            .flat_map(|from| self.adjacency_list[*from].iter().map(|to| (index[from], index[to])))
+            .collect();
+        edges.sort_unstable(); // The adjacency sets iterate in arbitrary order
+        let m = edges.len();
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut totals = vec![0.0; steps + 1];
+        for _ in 0..trials {
+            edges.shuffle(&mut rng);
+            let mut components = UnionFind::new(n);
+            let mut largest = usize::from(n > 0);
+            let mut kept = 0;
+            for (step, total) in totals.iter_mut().enumerate() {
+                let target = step * m / steps;
+                for &(u, v) in &edges[kept..target] {
+                    if components.union(u, v) {
+                        largest = largest.max(components.set_size(u));
+                    }
+                }
+                kept = target;
+                *total += if n == 0 { 0.0 } else { largest as f64 / n as f64 };
+            }
+        }
+        (0..=steps)
+            .rev()
+            .map(|step| (step as f64 / steps as f64, totals[step] / trials as f64))
+            .collect()
+    }
+
+    /// Adds the dependencies of every node on `source` to `centrality`.
+    fn accumulate_betweenness(&self, source: &String, centrality: &mut HashMap<String, f64>) {
+        self.brandes_from(source, |node, dependency| {
+            *centrality.get_mut(node).unwrap() += dependency;
+        }, |_, _, _| {});
+    }
+
+    /// Runs one BFS from `source` followed by Brandes' back-propagation,
+    /// reporting the dependency of every other reached node to `on_node`
+    /// and the share of shortest paths through every edge to `on_edge`.
+    fn brandes_from<'a>(
+        &'a self,
+        source: &'a String,
+        mut on_node: impl FnMut(&'a String, f64),
+        mut on_edge: impl FnMut(&'a String, &'a String, f64),
+    ) {
+        // BFS from the source, recording shortest-path counts and predecessors
+        let mut stack: Vec<&String> = Vec::new();
+        let mut predecessors: HashMap<&String, Vec<&String>> = HashMap::new();
+        let mut sigma: HashMap<&String, f64> = HashMap::new();
+        let mut distance: HashMap<&String, usize> = HashMap::new();
+        let mut queue = VecDeque::new();
+
+        sigma.insert(source, 1.0);
+        distance.insert(source, 0);
+        queue.push_back(source);
+
+        while let Some(v) = queue.pop_front() {
+            stack.push(v);
+            let dist_v = distance[v];
+            for w in self.adjacency_list.get(v).into_iter().flatten() {
+                if !distance.contains_key(w) {
+                    distance.insert(w, dist_v + 1);
+                    queue.push_back(w);
+                }
+                if distance[w] == dist_v + 1 {
+                    *sigma.entry(w).or_insert(0.0) += sigma[v];
+                    predecessors.entry(w).or_default().push(v);
+                }
+            }
+        }
+
+        // Accumulate dependencies in order of non-increasing distance
+        let mut delta: HashMap<&String, f64> = HashMap::new();
+        while let Some(w) = stack.pop() {
+            let delta_w = delta.get(w).copied().unwrap_or(0.0);
+            for &v in predecessors.get(w).into_iter().flatten() {
+                let share = sigma[v] / sigma[w] * (1.0 + delta_w);
+                *delta.entry(v).or_insert(0.0) += share;
+                on_edge(v, w, share);
+            }
+            if w != source {
+                on_node(w, delta_w);
+            }
+        }
+    }
+}
+
+/// Largest graph `to_dense_counts` will build a matrix for (n² counts).
+pub const DENSE_MATRIX_MAX_NODES: usize = 5000;
+
+/// Options for building a graph from emails.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GraphBuildOptions {
+    pub drop_self_loops: bool, // Skip recipients that are the sender themselves
+}
+
+/// What a batch of emails added to a graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddStats {
+    pub new_nodes: usize,
+    pub new_edges: usize, // Sender/recipient pairs seen for the first time
+    pub duplicate_edges: usize, // Emails along edges that already existed, which only add weight
+    pub self_loops_skipped: usize, // Self-addressed recipients dropped by `drop_self_loops`
+}
+
+/// Exponential-decay weighting of emails by age, used for "who talks to whom now" graphs.
+#[derive(Debug, Clone)]
+pub struct RecencyWeights {
+    pub reference_time: DateTime<Utc>, // Emails sent at this moment weigh 1.0
+    pub half_life: Duration, // Age at which an email's weight has halved
+    pub undated_weight: f64, // Weight of emails without a timestamp
+}
+
+impl RecencyWeights {
+    /// Undated emails are counted like current ones unless configured otherwise
+    pub const DEFAULT_UNDATED_WEIGHT: f64 = 1.0;
+
+    /// Creates a weighting with the default weight for undated emails.
+    pub fn new(reference_time: DateTime<Utc>, half_life: Duration) -> Self {
+        RecencyWeights {
+            reference_time,
+            half_life,
+            undated_weight: Self::DEFAULT_UNDATED_WEIGHT,
+        }
+    }
+
+    /// Returns 0.5^(age / half_life). Emails newer than the reference time
+    /// are treated as having age zero.
+    pub fn weight(&self, timestamp: Option<DateTime<Utc>>) -> f64 {
+        let Some(timestamp) = timestamp else {
+            return self.undated_weight;
+        };
+        let age = (self.reference_time - timestamp).max(Duration::zero());
+        let age_seconds = age.num_milliseconds() as f64 / 1000.0;
+        let half_life_seconds = self.half_life.num_milliseconds() as f64 / 1000.0;
+        0.5_f64.powf(age_seconds / half_life_seconds)
+    }
+}
+
+/// Default cap on the number of paths `Graph::all_shortest_paths` enumerates.
+pub const DEFAULT_MAX_SHORTEST_PATHS: usize = 1000;
+
+/// Which edges a traversal such as `Graph::bfs_distances` follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From sender to recipient
+    Out,
+    /// From recipient back to sender
+    In,
+    /// Either way
+    Undirected,
+}
+
+/// Order in which `Graph::robustness_profile` removes nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalStrategy {
+    /// Most connected first, by total (in + out) degree
+    HighestDegree,
+    /// Highest betweenness centrality first
+    HighestBetweenness,
+    /// A random order fixed by the seed
+    Random { seed: u64 },
+}
+
+/// One point of a robustness curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RobustnessPoint {
+    pub fraction_removed: f64,
+    /// Size of the largest weakly connected component, relative to the intact graph
+    pub largest_component_fraction: f64,
+}
+
+/// The emails behind one directed edge and when they were sent, as returned
+/// by `Graph::edge_info`. Emails without a timestamp count, but leave the
+/// dates alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EdgeInfo {
+    pub count: usize, // Number of emails, whatever the edge weight
+    pub first_seen: Option<DateTime<Utc>>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub active_days: usize, // Distinct UTC calendar days with at least one email
+    days: HashSet<NaiveDate>,
+}
+
+impl EdgeInfo {
+    /// Info for `count` emails with unknown dates.
+    pub fn with_count(count: usize) -> Self {
+        EdgeInfo { count, ..Default::default() }
+    }
+
+    /// Rebuilds the info of `count` emails from its parts, as stored by the
+    /// `sqlite` feature.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn restore(
+        count: usize,
+        first_seen: Option<DateTime<Utc>>,
+        last_seen: Option<DateTime<Utc>>,
+        days: HashSet<NaiveDate>,
+    ) -> Self {
+        EdgeInfo { count, first_seen, last_seen, active_days: days.len(), days }
+    }
+
+    /// The distinct days with at least one email, in no particular order.
+    pub fn days(&self) -> impl Iterator<Item = &NaiveDate> {
+        self.days.iter()
+    }
+
+    /// Records the date of an email already counted.
+    fn record(&mut self, timestamp: DateTime<Utc>) {
+        self.first_seen = Some(self.first_seen.map_or(timestamp, |first| first.min(timestamp)));
+        self.last_seen = Some(self.last_seen.map_or(timestamp, |last| last.max(timestamp)));
+        self.days.insert(timestamp.date_naive());
+        self.active_days = self.days.len();
+    }
+
+    /// Adds the emails of `other` to these.
+    fn merge(&mut self, other: &EdgeInfo) {
+        self.count += other.count;
+        self.first_seen = self.first_seen.into_iter().chain(other.first_seen).min();
+        self.last_seen = self.last_seen.into_iter().chain(other.last_seen).max();
+        self.days.extend(&other.days);
+        self.active_days = self.days.len();
+    }
+}
+
+/// Role of a node by the direction of its edges, as returned by `Graph::classify_nodes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeClass {
+    /// Sends email but never receives any, e.g. an automated system
+    Source,
+    /// Receives email but never sends any, e.g. an external address or a list
+    Sink,
+    /// Both sends and receives email
+    Connector,
+    /// Neither sends nor receives email, apart from to itself
+    Isolated,
+}
+
+/// A node with an unusual degree, from `Graph::degree_outliers`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DegreeOutlier {
+    pub node: String,
+    pub in_degree: usize,
+    pub out_degree: usize,
+    pub in_z: f64, // z-score of ln(1 + in-degree)
+    pub out_z: f64, // z-score of ln(1 + out-degree)
+}
+
+impl DegreeOutlier {
+    /// The larger of the two z-scores by absolute value.
+    pub fn max_abs_z(&self) -> f64 {
+        self.in_z.abs().max(self.out_z.abs())
+    }
+}
+
+/// Thresholds for `Graph::likely_distribution_lists`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DistributionListOptions {
+    pub min_emails_received: usize, // In-strength, not counting emails to itself
+    pub min_senders: usize, // Distinct people emailing the address
+    pub max_sent_ratio: f64, // Emails sent, as a fraction of emails received
+}
+
+impl Default for DistributionListOptions {
+    fn default() -> Self {
+        DistributionListOptions {
+            min_emails_received: 20,
+            min_senders: 5,
+            max_sent_ratio: 0.05,
+        }
+    }
+}
+
+/// Key of the display name in `Graph::set_node_attr` and `Graph::get_node_attr`.
+pub const DISPLAY_NAME_ATTR: &str = "display_name";
+
+/// Key of the domain in `Graph::set_node_attr` and `Graph::get_node_attr`.
+pub const DOMAIN_ATTR: &str = "domain";
+
+/// Domain that `Graph::domain_statistics` groups addresses without one under.
+pub const INVALID_DOMAIN: &str = "(invalid)";
+
+/// Traffic of one email domain, as returned by `Graph::domain_statistics`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DomainStats {
+    pub domain: String,
+    pub addresses: usize,
+    pub emails_sent: usize, // One per recipient
+    pub emails_received: usize,
+    /// The other domain this one exchanges the most emails with (both
+    /// directions), ties broken by name; `None` if all mail stays inside
+    pub top_external_domain: Option<String>,
+    pub top_external_emails: usize,
+}
+
+impl DomainStats {
+    fn new(domain: &str) -> Self {
+        DomainStats {
+            domain: domain.to_string(),
+            addresses: 0,
+            emails_sent: 0,
+            emails_received: 0,
+            top_external_domain: None,
+            top_external_emails: 0,
+        }
+    }
+}
+
+/// Metadata attached to a node with `Graph::set_node_attr`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeMeta {
+    pub display_name: Option<String>, // The name in "Jane Doe <jane@corp.com>"
+    pub domain: Option<String>, // Overrides the domain taken from the address
+    pub tags: BTreeMap<String, String>, // User-supplied, e.g. department
+}
+
+/// Default cap on the number of nodes `Graph::find_nodes` returns.
+pub const DEFAULT_FIND_LIMIT: usize = 20;
+
+/// How `Graph::find_nodes` matches a pattern against node names.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The pattern appears anywhere in the name
+    #[default]
+    Substring,
+    /// The name starts with the pattern
+    Prefix,
+    /// The whole name matches a pattern of `*` (any run of characters) and `?` (one character)
+    Glob,
+    /// A regular expression matches anywhere in the name
+    Regex,
+}
+
+/// Result of `Graph::lookup_node`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeLookup {
+    /// Exactly one node fits
+    Found(String),
+    /// Several nodes match, most connected first
+    Ambiguous(Vec<String>),
+    NotFound,
+}
+
+/// Result of `Graph::resolve_node_detailed`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeResolution {
+    Resolved(String),
+    /// Several nodes normalize to the same address, sorted
+    Ambiguous(Vec<String>),
+    /// No node has the address; the suggestions are most connected first
+    Unknown { suggestions: Vec<String> },
+}
+
+/// Everything known about one node, as returned by `Graph::node_report`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeReport {
+    pub address: String,
+    pub out_neighbors: Vec<String>, // Sorted
+    pub in_neighbors: Vec<String>, // Sorted
+    pub out_degree: usize,
+    pub in_degree: usize,
+    pub weighted_out_degree: f64,
+    pub weighted_in_degree: f64,
+    pub community: Option<String>,
+    pub attributes: Vec<(String, String)>, // As returned by `Graph::node_attrs`
+    /// 1-based position among all nodes by out-degree, ties broken by address
+    pub out_degree_rank: usize,
+    /// 1-based position among all nodes by in-degree, ties broken by address
+    pub in_degree_rank: usize,
+}
+
+/// What changed between two graphs, as returned by `Graph::diff`. Lists are sorted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphDiff {
+    pub added_nodes: Vec<String>,
+    pub removed_nodes: Vec<String>,
+    pub added_edges: Vec<(String, String)>,
+    pub removed_edges: Vec<(String, String)>,
+    pub weight_changes: Vec<WeightChange>, // Edges present in both graphs with a different weight
+    pub degree_deltas: HashMap<String, i64>, // Change in total degree of every node in either graph
+}
+
+impl GraphDiff {
+    /// Returns true if the graphs have the same nodes, edges and weights.
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.weight_changes.is_empty()
+    }
+
+    /// Lists the differences one per line: `-` for what only the earlier
+    /// graph has, `+` for what only the later one has and `~` for changed
+    /// weights. Degree deltas are left out, as they follow from the edges.
+    pub fn summary(&self) -> String {
+        let mut lines = Vec::new();
+        lines.extend(self.removed_nodes.iter().map(|node| format!("- node {}", node)));
+        lines.extend(self.added_nodes.iter().map(|node| format!("+ node {}", node)));
+        lines.extend(self.removed_edges.iter().map(|(from, to)| format!("- edge {} -> {}", from, to)));
+        lines.extend(self.added_edges.iter().map(|(from, to)| format!("+ edge {} -> {}", from, to)));
+        lines.extend(
+            self.weight_changes
+                .iter()
+                .map(|change| format!("~ edge {} -> {}: weight {} -> {}", change.from, change.to, change.before, change.after)),
+        );
+        lines.join("\n")
+    }
+}
+
+/// The weight of one edge before and after.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightChange {
+    pub from: String,
+    pub to: String,
+    pub before: f64,
+    pub after: f64,
+}
+
+/// The shortest paths between two nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShortestPaths {
+    /// Total number of distinct shortest paths (saturating), even if not all were enumerated
+    pub count: u64,
+    /// The enumerated paths, sorted; at most the requested limit
+    pub paths: Vec<Vec<String>>,
+}
+
+impl ShortestPaths {
+    /// Returns true if some shortest paths were left out of `paths`.
+    pub fn is_truncated(&self) -> bool {
+        (self.paths.len() as u64) < self.count
+    }
+}
+
+/// Extends `suffix` (a path ending at the target, stored in reverse) back
+/// through every parent until the start is reached, up to `max_paths` paths.
+fn collect_paths<'a>(
+    parents: &HashMap<&'a String, Vec<&'a String>>,
+    suffix: &mut Vec<&'a String>,
+    paths: &mut Vec<Vec<String>>,
+    max_paths: usize,
+) {
+    if paths.len() >= max_paths {
+        return;
+    }
+    let node = suffix[suffix.len() - 1];
+    let Some(node_parents) = parents.get(node) else {
+        // Only the start has no parents
+        paths.push(suffix.iter().rev().map(|node| (*node).clone()).collect());
+        return;
+    };
+
+    let mut node_parents = node_parents.clone();
+    node_parents.sort();
+    for parent in node_parents {
+        suffix.push(parent);
+        collect_paths(parents, suffix, paths, max_paths);
+        suffix.pop();
+    }
+}
+
+/// Renames communities to `C0`, `C1`, ... in order of descending size, ties
+/// going to the community whose smallest member sorts first, so the names
+/// no longer depend on which member label propagation happened to pick.
+/// `UNASSIGNED_LABEL` is kept as it is.
+///
+/// Returns the renamed labels and, for each canonical name, the original label.
+pub fn canonicalize_labels(labels: &HashMap<String, String>) -> (HashMap<String, String>, HashMap<String, String>) {
+    let mut members: HashMap<&String, Vec<&String>> = HashMap::new();
+    for (node, label) in labels {
+        if label != UNASSIGNED_LABEL {
+            members.entry(label).or_default().push(node);
+        }
+    }
+
+    let mut communities: Vec<(&String, usize, &String)> = members
+        .into_iter()
+        .map(|(label, members)| (label, members.len(), members.into_iter().min().unwrap()))
+        .collect();
+    communities.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.2.cmp(b.2)));
+
+    let renamed: HashMap<&String, String> = communities
+        .iter()
+        .enumerate()
+        .map(|(i, (label, _, _))| (*label, format!("C{}", i)))
+        .collect();
+    let canonical = labels
+        .iter()
+        .map(|(node, label)| (node.clone(), renamed.get(label).cloned().unwrap_or_else(|| label.clone())))
+        .collect();
+    let originals = renamed.into_iter().map(|(label, name)| (name, label.clone())).collect();
+    (canonical, originals)
+}
+
+/// Returns the smallest eccentricity in `eccentricities` (from
+/// `Graph::eccentricities`), or `None` if it is empty. With a sample this
+/// is an upper bound on the true radius.
+pub fn radius(eccentricities: &HashMap<String, usize>) -> Option<usize> {
+    eccentricities.values().copied().min()
+}
+
+/// Returns the largest eccentricity, i.e. the diameter of the component.
+/// With a sample this is a lower bound on the true diameter.
+pub fn diameter(eccentricities: &HashMap<String, usize>) -> Option<usize> {
+    eccentricities.values().copied().max()
+}
+
+/// Returns the nodes with the smallest eccentricity, sorted: those from
+/// which everyone else is closest.
+pub fn center(eccentricities: &HashMap<String, usize>) -> Vec<String> {
+    nodes_with_eccentricity(eccentricities, radius(eccentricities))
+}
+
+/// Returns the nodes with the largest eccentricity, sorted.
+pub fn periphery(eccentricities: &HashMap<String, usize>) -> Vec<String> {
+    nodes_with_eccentricity(eccentricities, diameter(eccentricities))
+}
+
+/// The sorted nodes whose eccentricity equals `target`.
+fn nodes_with_eccentricity(eccentricities: &HashMap<String, usize>, target: Option<usize>) -> Vec<String> {
+    let mut nodes: Vec<String> = eccentricities
+        .iter()
+        .filter(|(_, &eccentricity)| Some(eccentricity) == target)
+        .map(|(node, _)| node.clone())
+        .collect();
+    nodes.sort();
+    nodes
+}
+
+/// Disjoint sets over `0..n` with path halving and union by size.
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        UnionFind { parent: (0..n).collect(), size: vec![1; n] }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    /// Returns the number of elements in the set of `x`.
+    fn set_size(&mut self, x: usize) -> usize {
+        let root = self.find(x);
+        self.size[root]
+    }
+
+    /// Merges the sets of `a` and `b`; returns false if they were already one set.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+        true
+    }
+}
+
+/// Returns how many standard deviations `observed` lies above the mean of
+/// `null_samples` (the sample standard deviation), e.g. of a metric over
+/// rewired graphs. Returns 0.0 with fewer than two samples or if they
+/// don't vary.
+pub fn metric_zscore(observed: f64, null_samples: &[f64]) -> f64 {
+    let n = null_samples.len();
+    if n < 2 {
+        return 0.0;
+    }
+    let mean = null_samples.iter().sum::<f64>() / n as f64;
+    let variance = null_samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    (observed - mean) / variance.sqrt()
+}
+
+/// Pearson correlation of the `(x, y)` pairs, or 0.0 if either has no variance.
+fn pearson_correlation(pairs: &[(f64, f64)]) -> f64 {
+    if pairs.len() < 2 {
+        return 0.0;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y) in pairs {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x).powi(2);
+        variance_y += (y - mean_y).powi(2);
+    }
+    if variance_x == 0.0 || variance_y == 0.0 {
+        return 0.0;
+    }
+    covariance / (variance_x * variance_y).sqrt()
+}
+
+/// Spearman rank correlation between every pair of node metrics, keyed by
+/// metric name: the Pearson correlation of the nodes' ranks, with tied
+/// scores sharing their average rank. Each pair is compared over the nodes
+/// both metrics score, and is 0.0 when either ranking is constant there.
+/// Pairs come out sorted by metric name, each with the smaller name first.
+pub fn metric_rank_correlation(metrics: &HashMap<String, HashMap<String, f64>>) -> Vec<(String, String, f64)> {
+    let mut names: Vec<&String> = metrics.keys().collect();
+    names.sort();
+
+    let mut correlations = Vec::new();
+    for (i, &first) in names.iter().enumerate() {
+        for &second in &names[i + 1..] {
+            let (x, y) = (&metrics[first], &metrics[second]);
+            let mut common: Vec<&String> = x.keys().filter(|node| y.contains_key(*node)).collect();
+            common.sort(); // Keep the summation order, and so the result, stable
+            let x_ranks = average_ranks(&common.iter().map(|node| x[*node]).collect::<Vec<_>>());
+            let y_ranks = average_ranks(&common.iter().map(|node| y[*node]).collect::<Vec<_>>());
+            let pairs: Vec<(f64, f64)> = x_ranks.into_iter().zip(y_ranks).collect();
+            correlations.push((first.clone(), second.clone(), pearson_correlation(&pairs)));
+        }
+    }
+    correlations
+}
+
+/// Ranks the values from 1 (smallest) up, giving tied values the average
+/// of the ranks they span.
+fn average_ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut position = 0;
+    for tied in order.chunk_by(|&a, &b| values[a] == values[b]) {
+        let average = position as f64 + (tied.len() as f64 + 1.0) / 2.0;
+        for &index in tied {
+            ranks[index] = average;
+        }
+        position += tied.len();
+    }
+    ranks
+}
+
+/// Label given to members of too-small communities with no edges into a large one.
+pub const UNASSIGNED_LABEL: &str = "unassigned";
+
+/// Label given by seeded label propagation to nodes no seed label reaches,
+/// when `LpaOptions::mark_unlabeled` is set.
+pub const UNLABELED_LABEL: &str = "unlabeled";
+
+/// Distances between sampled pairs of people, from `Graph::sample_distances`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DistanceSample {
+    pub pairs: usize,
+    pub hops: BTreeMap<usize, usize>, // Undirected distance -> number of sampled pairs
+    pub unreachable: usize, // Pairs with no directed path from the first person to the second
+}
+
+impl DistanceSample {
+    /// Fraction of the sampled pairs at most `max_hops` apart, ignoring direction.
+    pub fn fraction_within(&self, max_hops: usize) -> f64 {
+        if self.pairs == 0 {
+            return 0.0;
+        }
+        self.hops.range(..=max_hops).map(|(_, count)| count).sum::<usize>() as f64 / self.pairs as f64
+    }
+
+    /// Fraction of the sampled pairs without a directed path.
+    pub fn unreachable_fraction(&self) -> f64 {
+        if self.pairs == 0 {
+            return 0.0;
+        }
+        self.unreachable as f64 / self.pairs as f64
+    }
+}
+
+/// Tuning knobs for label propagation.
+#[derive(Debug, Clone)]
+pub struct LpaOptions {
+    pub max_iterations: usize, // Upper bound on full passes over the nodes
+    pub seed: Option<u64>, // RNG seed; `None` draws a fresh seed each run
+    pub use_undirected_neighbors: bool, // Let senders influence a node's label as well as recipients
+    pub weighted: bool, // Count each neighbor's label by the weight of the edges to it instead of once
+    pub min_community_size: usize, // Members of smaller communities join a neighboring one or are unassigned
+    pub mark_unlabeled: bool, // With seed labels, label unreached nodes UNLABELED_LABEL instead of their own name
+}
+
+/// Labels from label propagation together with convergence diagnostics.
+#[derive(Debug, Clone)]
+pub struct LpaResult {
+    pub labels: HashMap<String, String>,
+    pub iterations_run: usize, // Full passes over the nodes, including the final unchanged one
+    pub converged: bool, // A pass changed no label before the iteration cap was hit
+    pub changes_per_iteration: Vec<usize>, // Labels changed in each pass, in order
+}
+
+/// Collects borrowed names in sorted order.
+fn sorted_refs<'a>(names: impl IntoIterator<Item = &'a String>) -> Vec<&'a String> {
+    let mut names: Vec<&String> = names.into_iter().collect();
+    names.sort();
+    names
+}
+
+/// Share of runs in which two neighbors must share a label to be put in the
+/// same consensus community by `Graph::consensus_communities`.
+pub const CONSENSUS_THRESHOLD: f64 = 0.5;
+
+/// Communities agreed on by several label propagation runs.
+#[derive(Debug, Clone)]
+pub struct ConsensusResult {
+    pub labels: HashMap<String, String>, // Consensus community of every node, named after its smallest member
+    pub stability: HashMap<String, f64>, // Fraction of runs each node stayed with its consensus community
+    pub co_assignment: HashMap<(String, String), f64>, // Fraction of runs each pair of neighbors shared a label, smaller address first
+    pub runs: usize,
+}
+
+impl ConsensusResult {
+    /// Mean stability over all nodes, 1.0 for an empty graph.
+    pub fn average_stability(&self) -> f64 {
+        if self.stability.is_empty() {
+            return 1.0;
+        }
+        self.stability.values().sum::<f64>() / self.stability.len() as f64
+    }
+
+    /// Returns the nodes whose stability is below `threshold`, sorted.
+    pub fn unstable_nodes(&self, threshold: f64) -> Vec<&String> {
+        let mut nodes: Vec<&String> = self.stability
+            .iter()
+            .filter(|(_, stability)| **stability < threshold)
+            .map(|(node, _)| node)
+            .collect();
+        nodes.sort();
+        nodes
+    }
+
+    /// Number of distinct consensus communities.
+    pub fn community_count(&self) -> usize {
+        self.labels.values().collect::<HashSet<_>>().len()
+    }
+}
+
+impl Default for LpaOptions {
+    fn default() -> Self {
+        LpaOptions {
+            max_iterations: 500, // Prevent infinite loops
+            seed: None,
+            use_undirected_neighbors: false,
+            weighted: false,
+            min_community_size: 1, // Keep every community
+            mark_unlabeled: false,
+        }
+    }
+}
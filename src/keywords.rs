@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use crate::email::ParsedEmailFull;
+use crate::threads::normalize_subject;
+
+/// Common words that say nothing about what a community discusses.
+const STOPWORDS: &[&str] = &[
+    "a", "about", "an", "and", "are", "as", "at", "be", "by", "for", "from", "has", "have", "i", "in", "is",
+    "it", "me", "my", "of", "on", "or", "our", "that", "the", "this", "to", "was", "we", "will", "with",
+    "you", "your",
+];
+
+/// Splits a subject into lowercase words, dropping reply markers, surrounding
+/// punctuation, stopwords and single characters.
+pub fn tokenize(subject: &str) -> Vec<String> {
+    normalize_subject(subject)
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()).to_string())
+        .filter(|word| word.chars().count() > 1 && !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Finds the `top_k` most distinguishing subject words of each community.
+///
+/// Every email counts towards its sender's community; senders without a label
+/// are ignored. Each community's subjects form one document, and words are
+/// scored by TF-IDF across those documents: term frequency within the
+/// community times ln(communities / communities using the word). Words used
+/// by every community therefore score 0 and are left out. Ties are broken
+/// alphabetically.
+pub fn community_keywords(
+    emails: &[ParsedEmailFull],
+    labels: &HashMap<String, String>,
+    top_k: usize,
+) -> HashMap<String, Vec<(String, f64)>> {
+    // Word counts per community
+    let mut counts: HashMap<&String, HashMap<String, usize>> = HashMap::new();
+    for email in emails {
+        if let Some(label) = labels.get(&email.email.from) {
+            let words = counts.entry(label).or_default();
+            for word in tokenize(&email.subject) {
+                *words.entry(word).or_insert(0) += 1;
+            }
+        }
+    }
+
+    // Number of communities using each word
+    let mut document_frequency: HashMap<&String, usize> = HashMap::new();
+    for words in counts.values() {
+        for word in words.keys() {
+            *document_frequency.entry(word).or_insert(0) += 1;
+        }
+    }
+
+    let communities = counts.len() as f64;
+    counts
+        .iter()
+        .map(|(label, words)| {
+            let total: usize = words.values().sum();
+            let mut scored: Vec<(String, f64)> = words
+                .iter()
+                .map(|(word, &count)| {
+                    let idf = (communities / document_frequency[word] as f64).ln();
+                    (word.clone(), count as f64 / total as f64 * idf)
+                })
+                .filter(|(_, score)| *score > 0.0)
+                .collect();
+            scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            scored.truncate(top_k);
+            ((*label).clone(), scored)
+        })
+        .collect()
+}
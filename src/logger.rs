@@ -0,0 +1,115 @@
+use std::fmt::Display;
+use std::sync::Mutex;
+
+/// How much the binary says besides its results, from least to most.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Nothing but the requested output files and subcommand answers
+    Quiet,
+    /// Warnings, progress and the analysis report
+    #[default]
+    Normal,
+    /// Also every skipped record and the memory use after each phase
+    Verbose,
+    /// Also per-file and per-phase details
+    Debug,
+}
+
+impl Verbosity {
+    /// Maps the number of `-v` flags to a level; more than two count as two.
+    pub fn from_count(count: u8) -> Self {
+        match count {
+            0 => Verbosity::Normal,
+            1 => Verbosity::Verbose,
+            _ => Verbosity::Debug,
+        }
+    }
+}
+
+/// Where a `Logger` writes its messages.
+#[derive(Debug)]
+enum Sink {
+    Stderr,
+    Capture(Mutex<Vec<String>>),
+}
+
+/// Progress and diagnostic messages, kept apart from the results on stdout.
+/// Messages above the logger's verbosity are dropped. Readers and analysis
+/// steps take a `&Logger` instead of printing, so tests can capture what
+/// they report with `Logger::capturing`.
+#[derive(Debug)]
+pub struct Logger {
+    verbosity: Verbosity,
+    sink: Sink,
+}
+
+impl Logger {
+    /// Creates a logger writing to stderr.
+    pub fn new(verbosity: Verbosity) -> Self {
+        Logger { verbosity, sink: Sink::Stderr }
+    }
+
+    /// Creates a logger that drops every message.
+    pub fn silent() -> Self {
+        Logger::new(Verbosity::Quiet)
+    }
+
+    /// Creates a logger that keeps its messages in memory; see `messages`.
+    pub fn capturing(verbosity: Verbosity) -> Self {
+        Logger { verbosity, sink: Sink::Capture(Mutex::new(Vec::new())) }
+    }
+
+    pub fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
+    /// Returns whether results meant for people, like the analysis report,
+    /// should be printed at all.
+    pub fn is_quiet(&self) -> bool {
+        self.verbosity == Verbosity::Quiet
+    }
+
+    /// Returns whether messages of the given level are shown.
+    pub fn enabled(&self, level: Verbosity) -> bool {
+        level != Verbosity::Quiet && level <= self.verbosity
+    }
+
+    /// Something the user should know about; prefixed with "warning: ".
+    pub fn warn(&self, message: impl Display) {
+        self.log(Verbosity::Normal, format_args!("warning: {}", message));
+    }
+
+    /// Progress and summary counts.
+    pub fn info(&self, message: impl Display) {
+        self.log(Verbosity::Normal, message);
+    }
+
+    /// Details shown with `-v`.
+    pub fn verbose(&self, message: impl Display) {
+        self.log(Verbosity::Verbose, message);
+    }
+
+    /// Details shown with `-vv`.
+    pub fn debug(&self, message: impl Display) {
+        self.log(Verbosity::Debug, message);
+    }
+
+    /// The messages captured so far, oldest first. Always empty for a
+    /// logger writing to stderr.
+    pub fn messages(&self) -> Vec<String> {
+        match &self.sink {
+            Sink::Stderr => Vec::new(),
+            Sink::Capture(messages) => messages.lock().unwrap().clone(),
+        }
+    }
+
+    fn log(&self, level: Verbosity, message: impl Display) {
+        if !self.enabled(level) {
+            return;
+        }
+        match &self.sink {
+            Sink::Stderr => eprintln!("{}", message),
+            Sink::Capture(messages) => messages.lock().unwrap().push(message.to_string()),
+        }
+    }
+}
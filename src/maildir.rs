@@ -0,0 +1,328 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use crate::email::{open_input, parse_date, parse_display_names, parse_recipients_checked, normalize_address, ParseOutcome, ParsedEmail};
+use crate::error::EmailError;
+use crate::filter::{EmailFilter, FilterDecision};
+
+/// Reads every message in a Maildir-style directory.
+///
+/// If the directory has `cur/` or `new/` subdirectories, every file in them is
+/// read; otherwise all `.eml` files below the directory are. Only the header
+/// block of each message is read, so bodies are only loaded (one at a time)
+/// when the filter has a body pattern.
+/// Messages that can't be read or lack a sender/recipients are recorded in
+/// `ParseOutcome::row_errors` and skipped.
+pub fn read_maildir(dir: &Path) -> Result<ParseOutcome, EmailError> {
+    read_maildir_with(dir, &EmailFilter::default())
+}
+
+/// Same as `read_maildir`, but only keeps the messages accepted by `filter`.
+pub fn read_maildir_with(dir: &Path, filter: &EmailFilter) -> Result<ParseOutcome, EmailError> {
+    let mut outcome = ParseOutcome::default();
+
+    for path in message_paths(dir)? {
+        let headers = match read_message(&path, filter.body_regex.is_some()) {
+            Ok(headers) => headers,
+            Err(source) => {
+                outcome.row_errors.push(EmailError::UnreadableMessage {
+                    path: path.display().to_string(),
+                    source,
+                });
+                continue;
+            }
+        };
+
+        let sender = headers.from.as_deref().map(decode_encoded_words).map(|from| normalize_address(&from));
+        let Some(sender) = sender.filter(|sender| !sender.is_empty()) else {
+            outcome.row_errors.push(EmailError::MessageWithoutSender { path: path.display().to_string() });
+            continue;
+        };
+
+        // Gather To, Cc and Bcc, dropping duplicates across headers
+        let mut recipients: Vec<String> = Vec::new();
+        for field in &headers.recipients {
+            let (addresses, rejected) = parse_recipients_checked(&decode_encoded_words(field), filter.max_address_length());
+            outcome.rejected_recipients.add(rejected);
+            for address in addresses {
+                if !recipients.contains(&address) {
+                    recipients.push(address);
+                }
+            }
+        }
+        if recipients.is_empty() && !filter.keep_senders_without_recipients {
+            outcome.row_errors.push(EmailError::MessageWithoutRecipients { path: path.display().to_string() });
+            continue;
+        }
+
+        // Apply the address lists, if any
+        if !filter.allows_address(&sender) {
+            outcome.address_excluded += 1;
+            continue;
+        }
+        let listed = recipients.len();
+        recipients.retain(|recipient| filter.allows_address(recipient));
+        outcome.blocked_recipients += listed - recipients.len();
+        if recipients.is_empty() && listed > 0 {
+            outcome.address_excluded += 1;
+            continue;
+        }
+
+        let timestamp = headers.date.as_deref().and_then(parse_date);
+        match filter.check_date(timestamp) {
+            FilterDecision::Keep => {}
+            FilterDecision::OutsideDateRange => {
+                outcome.outside_date_range += 1;
+                continue;
+            }
+            FilterDecision::Undated => {
+                outcome.undated_excluded += 1;
+                continue;
+            }
+        }
+
+        let subject = headers.subject.as_deref().map(decode_encoded_words).unwrap_or_default();
+        if !filter.matches_text(&subject, headers.body.as_deref().unwrap_or_default()) {
+            outcome.pattern_excluded += 1;
+            continue;
+        }
+
+        // A sender kept without recipients has passed the same filters as any message
+        if recipients.is_empty() {
+            outcome.senders_without_recipients.push(sender);
+            continue;
+        }
+
+        let email = ParsedEmail {
+            from: sender,
+            to: recipients,
+            timestamp,
+        };
+        let names = headers.from.iter()
+            .chain(&headers.recipients)
+            .flat_map(|field| parse_display_names(&decode_encoded_words(field)))
+            .collect();
+        outcome.record_display_names(&email, names);
+        outcome.emails.push(email);
+        outcome.subjects.push(subject);
+    }
+
+    Ok(outcome)
+}
+
+/// Lists the message files to read, in sorted order.
+fn message_paths(dir: &Path) -> Result<Vec<PathBuf>, EmailError> {
+    let io_error = |path: &Path| {
+        let path = path.display().to_string();
+        move |source| EmailError::Io { path, source }
+    };
+
+    let maildir_subdirs: Vec<PathBuf> = ["cur", "new"]
+        .iter()
+        .map(|name| dir.join(name))
+        .filter(|subdir| subdir.is_dir())
+        .collect();
+
+    let mut paths = Vec::new();
+    if maildir_subdirs.is_empty() {
+        // Plain directory tree of .eml files
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(current) = pending.pop() {
+            for entry in fs::read_dir(&current).map_err(io_error(&current))? {
+                let path = entry.map_err(io_error(&current))?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("eml")) {
+                    paths.push(path);
+                }
+            }
+        }
+    } else {
+        for subdir in maildir_subdirs {
+            for entry in fs::read_dir(&subdir).map_err(io_error(&subdir))? {
+                let path = entry.map_err(io_error(&subdir))?.path();
+                if path.is_file() {
+                    paths.push(path);
+                }
+            }
+        }
+    }
+
+    paths.sort();
+    Ok(paths)
+}
+
+/// The parts of a message needed to build and filter a `ParsedEmail`.
+#[derive(Debug, Default)]
+struct MessageHeaders {
+    from: Option<String>,
+    recipients: Vec<String>, // Values of every To, Cc and Bcc header
+    date: Option<String>,
+    subject: Option<String>,
+    body: Option<String>, // Only read when asked for
+}
+
+/// Reads the header block of a message, unfolding continuation lines.
+/// Stops at the first empty line unless `with_body` is set, in which case
+/// the rest of the message is read as the body. Gzipped messages are
+/// decompressed on the fly.
+fn read_message(path: &Path, with_body: bool) -> std::io::Result<MessageHeaders> {
+    let mut reader = BufReader::new(open_input(path)?);
+    let mut headers = MessageHeaders::default();
+    let mut current: Option<String> = None;
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        // Header bytes aren't guaranteed to be UTF-8; decode lossily
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\r', '\n']);
+
+        if text.starts_with([' ', '\t']) {
+            // Folded continuation of the previous header
+            if let Some(field) = current.as_mut() {
+                field.push(' ');
+                field.push_str(text.trim());
+            }
+            continue;
+        }
+
+        if let Some(field) = current.take() {
+            store_header(&mut headers, &field);
+        }
+        if text.is_empty() {
+            break; // End of the header block
+        }
+        current = Some(text.to_string());
+    }
+    if let Some(field) = current {
+        store_header(&mut headers, &field);
+    }
+
+    if with_body {
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body)?;
+        headers.body = Some(String::from_utf8_lossy(&body).into_owned());
+    }
+
+    Ok(headers)
+}
+
+/// Records a complete `Name: value` header line if it is one we care about.
+fn store_header(headers: &mut MessageHeaders, field: &str) {
+    let Some((name, value)) = field.split_once(':') else {
+        return;
+    };
+    let value = value.trim().to_string();
+    match name.trim().to_ascii_lowercase().as_str() {
+        "from" => headers.from = Some(value),
+        "to" | "cc" | "bcc" => headers.recipients.push(value),
+        "date" => headers.date = Some(value),
+        "subject" => headers.subject = Some(value),
+        _ => {}
+    }
+}
+
+/// Decodes RFC 2047 encoded-words (`=?charset?B?...?=` and `=?charset?Q?...?=`)
+/// in a header value. UTF-8 and Latin-1 charsets are understood; words that
+/// can't be decoded are left as they are.
+pub fn decode_encoded_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    let mut previous_was_encoded = false;
+
+    while let Some(start) = rest.find("=?") {
+        let word = &rest[start..];
+        let Some((text, length)) = decode_encoded_word(word) else {
+            decoded.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            previous_was_encoded = false;
+            continue;
+        };
+        // Whitespace between two adjacent encoded words is not part of the text
+        let between = &rest[..start];
+        if !(previous_was_encoded && between.trim().is_empty()) {
+            decoded.push_str(between);
+        }
+        decoded.push_str(&text);
+        rest = &word[length..];
+        previous_was_encoded = true;
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+/// Decodes one encoded-word at the start of `word`, returning the text and
+/// the number of bytes consumed.
+fn decode_encoded_word(word: &str) -> Option<(String, usize)> {
+    let inner = word.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let payload = &inner[..end];
+    let length = 2 + charset.len() + 1 + encoding.len() + 1 + end + 2;
+
+    let bytes = match encoding.to_ascii_uppercase().as_str() {
+        "B" => decode_base64(payload)?,
+        "Q" => decode_quoted_printable_word(payload)?,
+        _ => return None,
+    };
+
+    // Ignore an RFC 2231 language suffix such as "utf-8*en"
+    let charset = charset.split('*').next().unwrap_or(charset).to_ascii_lowercase();
+    let text = match charset.as_str() {
+        "iso-8859-1" | "latin1" | "us-ascii" => bytes.iter().map(|&byte| byte as char).collect(),
+        _ => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+    Some((text, length))
+}
+
+/// Decodes the "Q" encoding used in encoded-words: `_` is a space and
+/// `=XX` is a hex-encoded byte.
+fn decode_quoted_printable_word(payload: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(payload.len());
+    let mut iter = payload.bytes();
+    while let Some(byte) = iter.next() {
+        match byte {
+            b'_' => bytes.push(b' '),
+            b'=' => {
+                let hex = [iter.next()?, iter.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    Some(bytes)
+}
+
+/// Decodes standard base64, ignoring padding.
+fn decode_base64(payload: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a' + 26) as u32),
+            b'0'..=b'9' => Some((c - b'0' + 52) as u32),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    };
+
+    let mut bytes = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in payload.bytes().filter(|&c| c != b'=') {
+        buffer = (buffer << 6) | value(c)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Some(bytes)
+}
@@ -1,77 +1,339 @@
+pub mod anonymize;
+pub mod cli;
+pub mod community;
+pub mod config;
+pub mod csr;
 pub mod email;
+pub mod error;
+pub mod export;
+pub mod filter;
+pub mod generators;
 pub mod graph;
+pub mod keywords;
+pub mod logger;
+pub mod maildir;
+pub mod paths;
+pub mod repl;
+pub mod spread;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod temporal;
+pub mod threads;
 
 
-use email::{ParsedEmail, read_csv};
-use graph::{Graph};
-use std::error::Error;
+use anonymize::AddressMapper;
+use clap::Parser;
+use cli::{Cli, Command};
+use config::{CommunityMethod, Config, InputFormat, PagerankVariant, unknown_key_warning};
+use csr::CsrGraph;
+use email::{ParsedEmail, ParseOutcome, ParseReport, SkipReason, SkippedRecord};
+#[cfg(not(feature = "parallel"))]
+use email::read_csv_with;
+use error::Error;
+use graph::{Graph, NodeClass};
+use logger::{Logger, Verbosity};
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
+use std::process::ExitCode;
+#[cfg(test)]
+use email::read_csv;
+#[cfg(test)]
+use std::collections::BTreeMap;
+#[cfg(test)]
+use error::GraphInvariantViolation;
+use error::EmailError;
 
-fn analyze_degree_distribution(graph: &Graph) {
+/// Input file used when neither the config nor the command line names one
+const DEFAULT_INPUT: &str = "emaildata_100000_0.csv";
+
+/// Number of people the benchmark runs a breadth-first search from.
+const BENCH_BFS_SOURCES: usize = 100;
+
+/// Most addresses suggested when a typed address is not in the graph.
+const SUGGESTIONS: usize = 5;
+
+/// Double-edge swaps per edge when rewiring a graph for the null model.
+const NULL_MODEL_SWAPS_PER_EDGE: usize = 10;
+
+/// Number of domains listed in the analysis report.
+const TOP_DOMAINS: usize = 15;
+
+/// Summary of the in- and out-degree distributions of a non-empty graph,
+/// together with the strength (emails sent/received per person) distributions.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DegreeStats {
+    pub total_nodes: usize,
+    pub total_out_degree: usize,
+    pub average_out_degree: f64,
+    pub max_out_degree: usize,
+    pub min_out_degree: usize,
+    pub total_in_degree: usize,
+    pub average_in_degree: f64,
+    pub max_in_degree: usize,
+    pub min_in_degree: usize,
+    pub out_percentiles: DegreePercentiles,
+    pub in_percentiles: DegreePercentiles,
+    pub strength: StrengthStats,
+    pub node_classes: NodeClassCounts,
+    /// Pearson correlation between in- and out-degree across nodes
+    pub degree_correlation: f64,
+}
+
+/// Number of nodes in each `NodeClass`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct NodeClassCounts {
+    pub sources: usize,
+    pub sinks: usize,
+    pub connectors: usize,
+    pub isolated: usize,
+}
+
+impl NodeClassCounts {
+    /// Counts the classes of all nodes of `graph`.
+    pub fn of(graph: &Graph) -> NodeClassCounts {
+        let mut counts = NodeClassCounts::default();
+        for class in graph.classify_nodes().values() {
+            match class {
+                NodeClass::Source => counts.sources += 1,
+                NodeClass::Sink => counts.sinks += 1,
+                NodeClass::Connector => counts.connectors += 1,
+                NodeClass::Isolated => counts.isolated += 1,
+            }
+        }
+        counts
+    }
+}
+
+/// Summary of the emails sent and received per person, counting every email
+/// on an edge rather than the edge once.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StrengthStats {
+    pub total_emails: usize,
+    pub average_out_strength: f64,
+    pub max_out_strength: usize,
+    pub min_out_strength: usize,
+    pub average_in_strength: f64,
+    pub max_in_strength: usize,
+    pub min_in_strength: usize,
+}
+
+/// Selected percentiles of a degree distribution.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DegreePercentiles {
+    pub median: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl DegreePercentiles {
+    /// Computes the percentiles of `degrees`, or `None` if it is empty.
+    pub fn of(degrees: &[usize]) -> Option<DegreePercentiles> {
+        Some(DegreePercentiles {
+            median: percentile(degrees, 0.5)?,
+            p90: percentile(degrees, 0.9)?,
+            p95: percentile(degrees, 0.95)?,
+            p99: percentile(degrees, 0.99)?,
+        })
+    }
+}
+
+/// Returns the `fraction` percentile of `values` (which need not be sorted),
+/// interpolating linearly between the two closest ranks, so the median of an
+/// even-length slice is the mean of its middle values. A fraction of 0.0
+/// gives the minimum and 1.0 the maximum; fractions outside that range are
+/// clamped. Returns `None` for an empty slice.
+pub fn percentile(values: &[usize], fraction: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let rank = fraction.clamp(0.0, 1.0) * (values.len() - 1) as f64;
+    let lower_rank = rank.floor() as usize;
+    let mut values = values.to_vec();
+    let (_, &mut lower, above) = values.select_nth_unstable(lower_rank);
+    if lower_rank as f64 == rank {
+        return Some(lower as f64);
+    }
+    // The next rank up is the smallest value above the selected one
+    let upper = above.iter().copied().min().unwrap_or(lower);
+    Some(lower as f64 + (rank - lower_rank as f64) * (upper as f64 - lower as f64))
+}
+
+/// Computes the degree statistics, or `None` if the graph has no nodes.
+pub fn degree_statistics(graph: &Graph) -> Option<DegreeStats> {
     // Calculate out-degrees and in-degrees
     let out_degrees = graph.calculate_out_degrees();
     let in_degrees = graph.calculate_in_degrees();
 
-    // Calculate statistics for out-degrees
     let total_nodes = out_degrees.len();
-    let total_out_degree: usize = out_degrees.values().sum();
-    let average_out_degree = total_out_degree as f64 / total_nodes as f64;
-    let max_out_degree = out_degrees.values().cloned().max().unwrap_or(0);
-    let min_out_degree = out_degrees.values().cloned().min().unwrap_or(0);
+    if total_nodes == 0 {
+        return None; // Averages are undefined without nodes
+    }
 
-    // Calculate statistics for in-degrees
+    // Calculate statistics for out-degrees and in-degrees
+    let total_out_degree: usize = out_degrees.values().sum();
     let total_in_degree: usize = in_degrees.values().sum();
-    let average_in_degree = total_in_degree as f64 / total_nodes as f64;
-    let max_in_degree = in_degrees.values().cloned().max().unwrap_or(0);
-    let min_in_degree = in_degrees.values().cloned().min().unwrap_or(0);
+    let out_values: Vec<usize> = out_degrees.values().copied().collect();
+    let in_values: Vec<usize> = in_degrees.values().copied().collect();
+    Some(DegreeStats {
+        total_nodes,
+        total_out_degree,
+        average_out_degree: total_out_degree as f64 / total_nodes as f64,
+        max_out_degree: out_degrees.values().cloned().max().unwrap_or(0),
+        min_out_degree: out_degrees.values().cloned().min().unwrap_or(0),
+        total_in_degree,
+        average_in_degree: total_in_degree as f64 / total_nodes as f64,
+        max_in_degree: in_degrees.values().cloned().max().unwrap_or(0),
+        min_in_degree: in_degrees.values().cloned().min().unwrap_or(0),
+        out_percentiles: DegreePercentiles::of(&out_values)?,
+        in_percentiles: DegreePercentiles::of(&in_values)?,
+        strength: strength_statistics(graph, total_nodes),
+        node_classes: NodeClassCounts::of(graph),
+        degree_correlation: graph.degree_correlation(),
+    })
+}
+
+/// Computes the strength statistics of a graph with `total_nodes` nodes (at least one).
+fn strength_statistics(graph: &Graph, total_nodes: usize) -> StrengthStats {
+    let out_strengths = graph.calculate_out_strengths();
+    let in_strengths = graph.calculate_in_strengths();
+
+    // Every email is sent once and received once, so both sums agree
+    let total_emails: usize = out_strengths.values().sum();
+    StrengthStats {
+        total_emails,
+        average_out_strength: total_emails as f64 / total_nodes as f64,
+        max_out_strength: out_strengths.values().copied().max().unwrap_or(0),
+        min_out_strength: out_strengths.values().copied().min().unwrap_or(0),
+        average_in_strength: in_strengths.values().sum::<usize>() as f64 / total_nodes as f64,
+        max_in_strength: in_strengths.values().copied().max().unwrap_or(0),
+        min_in_strength: in_strengths.values().copied().min().unwrap_or(0),
+    }
+}
+
+/// Prints the degree statistics, with the `top_n` most one-sided senders and
+/// recipients, and returns them (`None` for an empty graph).
+fn analyze_degree_distribution(graph: &Graph, top_n: usize) -> Option<DegreeStats> {
+    let Some(stats) = degree_statistics(graph) else {
+        println!("--- Degree Statistics ---");
+        println!("The graph has no nodes.");
+        return None;
+    };
 
     // Display Out-Degree Statistics
     println!("--- Out-Degree Statistics ---");
-    println!("Total Nodes: {}", total_nodes);
-    println!("Total Out-Degree: {}", total_out_degree);
-    println!("Average Out-Degree: {:.2}", average_out_degree);
-    println!("Maximum Out-Degree: {}", max_out_degree);
-    println!("Minimum Out-Degree: {}", min_out_degree);
+    println!("Total Nodes: {}", stats.total_nodes);
+    println!("Total Out-Degree: {}", stats.total_out_degree);
+    println!("Average Out-Degree: {:.2}", stats.average_out_degree);
+    println!("Maximum Out-Degree: {}", stats.max_out_degree);
+    println!("Minimum Out-Degree: {}", stats.min_out_degree);
+    print_percentiles("Out-Degree", &stats.out_percentiles);
 
     // Display In-Degree Statistics
     println!("\n--- In-Degree Statistics ---");
-    println!("Total Nodes: {}", total_nodes);
-    println!("Total In-Degree: {}", total_in_degree);
-    println!("Average In-Degree: {:.2}", average_in_degree);
-    println!("Maximum In-Degree: {}", max_in_degree);
-    println!("Minimum In-Degree: {}", min_in_degree);
+    println!("Total Nodes: {}", stats.total_nodes);
+    println!("Total In-Degree: {}", stats.total_in_degree);
+    println!("Average In-Degree: {:.2}", stats.average_in_degree);
+    println!("Maximum In-Degree: {}", stats.max_in_degree);
+    println!("Minimum In-Degree: {}", stats.min_in_degree);
+    print_percentiles("In-Degree", &stats.in_percentiles);
+
+    // Strength counts emails, so a person writing often to one contact stands out here
+    let strength = &stats.strength;
+    println!("\n--- Strength Statistics (emails per person) ---");
+    println!("Total Emails: {}", strength.total_emails);
+    println!("Average Emails Sent: {:.2}", strength.average_out_strength);
+    println!("Maximum Emails Sent: {}", strength.max_out_strength);
+    println!("Minimum Emails Sent: {}", strength.min_out_strength);
+    println!("Average Emails Received: {:.2}", strength.average_in_strength);
+    println!("Maximum Emails Received: {}", strength.max_in_strength);
+    println!("Minimum Emails Received: {}", strength.min_in_strength);
+
+    // Pure senders and recipients are often automated systems and outside addresses
+    let classes = &stats.node_classes;
+    println!("\n--- Node Roles ---");
+    println!("Sources (only send): {}", classes.sources);
+    println!("Sinks (only receive): {}", classes.sinks);
+    println!("Connectors (send and receive): {}", classes.connectors);
+    println!("Isolated (no email to or from others): {}", classes.isolated);
+
+    println!("\n--- Sending vs Receiving ---");
+    println!("In/Out-Degree Correlation: {:.3}", stats.degree_correlation);
+    println!("Most one-sided people (out-degree minus in-degree):");
+    for (node, gap) in graph.most_asymmetric_nodes(top_n) {
+        println!("  {} ({:+})", node, gap);
+    }
+
+    Some(stats)
 }
 
-/// Identifies the top N senders based on out-degree.
-pub fn identify_top_senders(out_degrees: &HashMap<String, usize>, top_n: usize) -> Vec<(String, usize)> {
-    let mut senders: Vec<(String, usize)> = out_degrees.iter()
+/// Prints the percentile lines of one degree distribution.
+fn print_percentiles(name: &str, percentiles: &DegreePercentiles) {
+    println!("Median {}: {:.2}", name, percentiles.median);
+    println!("90th Percentile {}: {:.2}", name, percentiles.p90);
+    println!("95th Percentile {}: {:.2}", name, percentiles.p95);
+    println!("99th Percentile {}: {:.2}", name, percentiles.p99);
+}
+
+/// A ranking cut off after N entries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopList {
+    pub entries: Vec<(String, usize)>,
+    /// Nodes left out by the cutoff that tie with the last entry
+    pub omitted_ties: usize,
+}
+
+/// Ranks nodes by degree, descending, breaking ties by name so the order is
+/// the same on every run. With `include_ties`, every node tying with the
+/// N-th entry is kept as well.
+pub fn rank_top(degrees: &HashMap<String, usize>, top_n: usize, include_ties: bool) -> TopList {
+    let mut ranked: Vec<(String, usize)> = degrees.iter()
         .map(|(node, degree)| (node.clone(), *degree))
         .collect();
-    
-    // Sort senders by out-degree in descending order
-    senders.sort_by(|a, b| b.1.cmp(&a.1));
-    
-    // Return the top N senders
-    senders.into_iter().take(top_n).collect()
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let cutoff = top_n.min(ranked.len());
+    let tied = match cutoff.checked_sub(1).map(|last| ranked[last].1) {
+        Some(last_degree) => ranked[cutoff..].iter().take_while(|(_, degree)| *degree == last_degree).count(),
+        None => 0,
+    };
+
+    if include_ties {
+        ranked.truncate(cutoff + tied);
+        TopList { entries: ranked, omitted_ties: 0 }
+    } else {
+        ranked.truncate(cutoff);
+        TopList { entries: ranked, omitted_ties: tied }
+    }
+}
+
+/// Identifies the top N senders based on out-degree.
+pub fn identify_top_senders(out_degrees: &HashMap<String, usize>, top_n: usize) -> Vec<(String, usize)> {
+    rank_top(out_degrees, top_n, false).entries
 }
 
 /// Identifies the top N recipients based on in-degree.
 pub fn identify_top_recipients(in_degrees: &HashMap<String, usize>, top_n: usize) -> Vec<(String, usize)> {
-    let mut recipients: Vec<(String, usize)> = in_degrees.iter()
-        .map(|(node, degree)| (node.clone(), *degree))
-        .collect();
-    
-    // Sort recipients by in-degree in descending order
-    recipients.sort_by(|a, b| b.1.cmp(&a.1));
-    
-    // Return the top N recipients
-    recipients.into_iter().take(top_n).collect()
+    rank_top(in_degrees, top_n, false).entries
 }
 
-/// Identifies key statistics about the community
-fn analyze_communities(community_map: &HashMap<String, Vec<String>>) {
+/// Summary of the community sizes of a non-empty partition.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommunityStats {
+    pub total_communities: usize,
+    pub total_nodes: usize,
+    pub average_size: f64,
+    pub largest_size: usize,
+    pub smallest_size: usize,
+}
+
+/// Computes the community size statistics, or `None` if there are no communities.
+pub fn community_statistics(community_map: &HashMap<String, Vec<String>>) -> Option<CommunityStats> {
     let total_communities = community_map.len();
-    println!("Total Detected Communities: {}", total_communities);
+    if total_communities == 0 {
+        return None;
+    }
 
     // Identify the size of each community
     let mut community_sizes: Vec<usize> = community_map.values().map(|members| members.len()).collect();
@@ -79,293 +341,5814 @@ fn analyze_communities(community_map: &HashMap<String, Vec<String>>) {
 
     // Calculate statistics
     let total_nodes = community_sizes.iter().sum::<usize>();
-    let average_size = total_nodes as f64 / total_communities as f64;
-    let largest_size = community_sizes.first().cloned().unwrap_or(0);
-    let smallest_size = community_sizes.last().cloned().unwrap_or(0);
+    Some(CommunityStats {
+        total_communities,
+        total_nodes,
+        average_size: total_nodes as f64 / total_communities as f64,
+        largest_size: community_sizes.first().cloned().unwrap_or(0),
+        smallest_size: community_sizes.last().cloned().unwrap_or(0),
+    })
+}
 
-    println!("Total Nodes: {}", total_nodes);
-    println!("Average Community Size: {:.2}", average_size);
-    println!("Largest Community Size: {}", largest_size);
-    println!("Smallest Community Size: {}", smallest_size);
+/// Upper bounds (inclusive) of the community size histogram buckets; larger
+/// communities fall into a final open-ended bucket.
+const SIZE_BUCKET_BOUNDS: [usize; 4] = [1, 5, 20, 100];
+
+/// Returns every community's size, largest first.
+pub fn community_size_distribution(community_map: &HashMap<String, Vec<String>>) -> Vec<usize> {
+    let mut sizes: Vec<usize> = community_map.values().map(Vec::len).collect();
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    sizes
 }
 
-/// Prints the top N senders and recipients.
-pub fn print_top_individuals(out_degrees: &HashMap<String, usize>, in_degrees: &HashMap<String, usize>, top_n: usize) {
-    let top_senders = identify_top_senders(out_degrees, top_n);
-    let top_recipients = identify_top_recipients(in_degrees, top_n);
-    
-    println!("\n--- Top {} Senders (Prolific Communicators) ---", top_n);
-    for (i, (sender, degree)) in top_senders.iter().enumerate() {
-        println!("{}. {} - Sent {} emails", i + 1, sender, degree);
-    }
-    
-    println!("\n--- Top {} Recipients (Information Hubs) ---", top_n);
-    for (i, (recipient, degree)) in top_recipients.iter().enumerate() {
-        println!("{}. {} - Received {} emails", i + 1, recipient, degree);
+/// Counts communities per size bucket: 1, 2-5, 6-20, 21-100 and 101+.
+/// Returns `(bucket name, community count)` for every bucket, empty ones included.
+pub fn community_size_histogram(sizes: &[usize]) -> Vec<(String, usize)> {
+    let mut buckets = Vec::new();
+    let mut lower = 1;
+    for upper in SIZE_BUCKET_BOUNDS {
+        let name = if lower == upper { lower.to_string() } else { format!("{}-{}", lower, upper) };
+        buckets.push((name, sizes.iter().filter(|&&size| (lower..=upper).contains(&size)).count()));
+        lower = upper + 1;
     }
+    buckets.push((format!("{}+", lower), sizes.iter().filter(|&&size| size >= lower).count()));
+    buckets
 }
 
-/// Identify and print the smallest and the largest community
-fn identify_extreme_communities(community_map: &HashMap<String, Vec<String>>) {
-    // Find the largest community
-    if let Some((largest_label, largest_members)) = community_map.iter().max_by_key(|&(_, members)| members.len()) {
-        println!("\n--- Largest Community ---");
-        println!("Community Label: {}", largest_label);
-        println!("Number of Members: {}", largest_members.len());
-        println!("Top 10 Members: {:?}", largest_members.iter().take(10).collect::<Vec<&String>>());
+/// Identifies key statistics about the community
+fn analyze_communities(community_map: &HashMap<String, Vec<String>>, merged_nodes: usize) -> Option<CommunityStats> {
+    println!("Total Detected Communities: {}", community_map.len());
+    if merged_nodes > 0 {
+        println!("Members of small communities merged into larger ones: {}", merged_nodes);
     }
+    if let Some(unassigned) = community_map.get(graph::UNASSIGNED_LABEL) {
+        println!("Members of small communities left unassigned: {}", unassigned.len());
+    }
+    let stats = community_statistics(community_map)?;
 
-    // Find the smallest community
-    if let Some((smallest_label, smallest_members)) = community_map.iter().min_by_key(|&(_, members)| members.len()) {
-        println!("\n--- Smallest Community ---");
-        println!("Community Label: {}", smallest_label);
-        println!("Number of Members: {}", smallest_members.len());
-        println!("Member: {:?}", smallest_members);
+    println!("Total Nodes: {}", stats.total_nodes);
+    println!("Average Community Size: {:.2}", stats.average_size);
+    println!("Largest Community Size: {}", stats.largest_size);
+    println!("Smallest Community Size: {}", stats.smallest_size);
+
+    // Many singletons usually mean label propagation found little structure
+    let sizes = community_size_distribution(community_map);
+    let singletons = sizes.iter().filter(|&&size| size == 1).count();
+    println!(
+        "Singleton Communities: {} ({:.1}% of communities)",
+        singletons,
+        100.0 * singletons as f64 / stats.total_communities as f64
+    );
+
+    println!("\n--- Community Size Histogram ---");
+    for (bucket, count) in community_size_histogram(&sizes) {
+        println!("{:>8}: {}", bucket, count);
     }
+    Some(stats)
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    // Path to your CSV file
-    let file_path = "emaildata_100000_0.csv";
+/// Identifies the top N senders by the number of emails sent.
+pub fn identify_top_senders_by_volume(graph: &Graph, top_n: usize) -> Vec<(String, usize)> {
+    rank_top(&graph.calculate_out_strengths(), top_n, false).entries
+}
 
-    // Read and parse the CSV
-    let parsed_emails = read_csv(file_path)?;
+/// Identifies the top N recipients by the number of emails received.
+pub fn identify_top_recipients_by_volume(graph: &Graph, top_n: usize) -> Vec<(String, usize)> {
+    rank_top(&graph.calculate_in_strengths(), top_n, false).entries
+}
 
-    // Build the graph
-    let graph = Graph::build_from_emails(parsed_emails);
+/// Prints the top N senders and recipients, ranked both by distinct contacts
+/// and by email volume. Recipients in `lists` are marked as likely
+/// distribution lists, so they aren't mistaken for information hubs.
+pub fn print_top_individuals(graph: &Graph, top_n: usize, lists: &HashSet<String>) {
+    println!("\n--- Top {} Senders (Prolific Communicators) ---", top_n);
+    print_rankings_side_by_side(
+        ("distinct contacts", &rank_top(&graph.calculate_out_degrees(), top_n, false)),
+        ("emails sent", &rank_top(&graph.calculate_out_strengths(), top_n, false)),
+        &HashSet::new(),
+    );
 
-    // Perform Degree Distribution Analysis
-    analyze_degree_distribution(&graph);
+    println!("\n--- Top {} Recipients (Information Hubs) ---", top_n);
+    let by_degree = rank_top(&graph.calculate_in_degrees(), top_n, false);
+    let by_volume = rank_top(&graph.calculate_in_strengths(), top_n, false);
+    print_rankings_side_by_side(("distinct contacts", &by_degree), ("emails received", &by_volume), lists);
+    let flagged_shown = by_degree.entries.iter().chain(&by_volume.entries).any(|(node, _)| lists.contains(node));
+    if flagged_shown {
+        println!("    [list]: likely a distribution list or shared mailbox rather than a person");
+    }
+}
 
-    // Calculate out-degrees and in-degrees
-     let out_degrees = graph.calculate_out_degrees();
-     let in_degrees = graph.calculate_in_degrees();
-        
-     // Identify and print top N senders and recipients
-     let top_n = 10; // Define how many top individuals to identify
-     print_top_individuals(&out_degrees, &in_degrees, top_n);
-        
-    // Perform Label Propagation
-    let communities = graph.label_propagation();
+/// Precomputed per-node scores for the combined ranking table. Measures
+/// that weren't computed are `None` and left out of the table.
+pub struct CentralityMetrics<'a> {
+    pub degree: &'a HashMap<String, usize>, // Distinct contacts, sent and received
+    pub weighted_degree: &'a HashMap<String, usize>, // Emails sent and received
+    pub pagerank: Option<&'a HashMap<String, f64>>,
+    pub betweenness: Option<&'a HashMap<String, f64>>,
+}
 
-    // Organize nodes by communities
-    let mut community_map: HashMap<String, Vec<String>> = HashMap::new();
-    for (node, label) in communities {
-        community_map.entry(label).or_insert(Vec::new()).push(node);
+/// One row of the combined ranking table: a node's 1-based rank under each
+/// measure, `None` for measures that weren't computed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CombinedRank {
+    pub node: String,
+    pub degree: Option<usize>,
+    pub weighted_degree: Option<usize>,
+    pub pagerank: Option<usize>,
+    pub betweenness: Option<usize>,
+}
+
+impl CombinedRank {
+    /// The node's best rank under any measure.
+    fn best(&self) -> usize {
+        [self.degree, self.weighted_degree, self.pagerank, self.betweenness]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(usize::MAX)
     }
-    // Analyze Communities
-    analyze_communities(&community_map);
+}
 
-    // Identify Extreme Communities
-    identify_extreme_communities(&community_map);
+/// Ranks every node under each measure (highest score first, ties by
+/// address) and keeps the nodes in the top `top_n` of at least one,
+/// ordered by their best rank, then by address.
+pub fn combined_rankings(metrics: &CentralityMetrics, top_n: usize) -> Vec<CombinedRank> {
+    fn positions<'a>(scores: impl Iterator<Item = (&'a String, f64)>) -> HashMap<&'a String, usize> {
+        let mut ranked: Vec<(&String, f64)> = scores.collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        ranked.into_iter().enumerate().map(|(i, (node, _))| (node, i + 1)).collect()
+    }
+    let degree = positions(metrics.degree.iter().map(|(node, count)| (node, *count as f64)));
+    let weighted_degree = positions(metrics.weighted_degree.iter().map(|(node, count)| (node, *count as f64)));
+    let pagerank = metrics.pagerank.map(|scores| positions(scores.iter().map(|(node, score)| (node, *score))));
+    let betweenness = metrics.betweenness.map(|scores| positions(scores.iter().map(|(node, score)| (node, *score))));
 
-    Ok(())
+    let all = [Some(&degree), Some(&weighted_degree), pagerank.as_ref(), betweenness.as_ref()];
+    let selected: HashSet<&String> = all
+        .iter()
+        .flatten()
+        .flat_map(|ranks| ranks.iter().filter(|(_, rank)| **rank <= top_n).map(|(node, _)| *node))
+        .collect();
+
+    let mut rows: Vec<CombinedRank> = selected
+        .into_iter()
+        .map(|node| CombinedRank {
+            node: node.clone(),
+            degree: degree.get(node).copied(),
+            weighted_degree: weighted_degree.get(node).copied(),
+            pagerank: pagerank.as_ref().and_then(|ranks| ranks.get(node).copied()),
+            betweenness: betweenness.as_ref().and_then(|ranks| ranks.get(node).copied()),
+        })
+        .collect();
+    rows.sort_by(|a, b| a.best().cmp(&b.best()).then_with(|| a.node.cmp(&b.node)));
+    rows
 }
 
-#[test]
-fn test_out_degree_calculation() {
-    // Create sample parsed emails
-    let emails = vec![
-        ParsedEmail {
-            from: "alice@example.com".to_string(),
-            to: vec![
-                "bob@example.com".to_string(),
-                "carol@example.com".to_string(),
-            ],
-        },
-        ParsedEmail {
-            from: "bob@example.com".to_string(),
-            to: vec!["dave@example.com".to_string()],
-        },
-        ParsedEmail {
-            from: "carol@example.com".to_string(),
-            to: vec![
-                "dave@example.com".to_string(),
-                "eve@example.com".to_string(),
-                "frank@example.com".to_string(),
-            ],
-        },
-        ParsedEmail {
-            from: "alice@example.com".to_string(),
-            to: vec!["dave@example.com".to_string()],
-        },
-    ];
+/// Prints the combined ranking table: every node in the top `top_n` of any
+/// measure with its rank under each, so people central by position but not
+/// by volume stand out. Addresses in `lists` are marked with "[list]".
+pub fn print_top_individuals_extended(rows: &[CombinedRank], metrics: &CentralityMetrics, top_n: usize, lists: &HashSet<String>) {
+    println!("\n--- Top {} by Any Measure (rank under each) ---", top_n);
+    let mut headings = vec!["Degree", "Weighted"];
+    if metrics.pagerank.is_some() {
+        headings.push("PageRank");
+    }
+    if metrics.betweenness.is_some() {
+        headings.push("Betweenness");
+    }
+    let names: Vec<String> = rows
+        .iter()
+        .map(|row| if lists.contains(&row.node) { format!("{} [list]", row.node) } else { row.node.clone() })
+        .collect();
+    let width = names.iter().map(String::len).chain(["Address".len()]).max().unwrap_or(0);
 
-    // Build the graph
-    let graph = Graph::build_from_emails(emails);
+    let header: Vec<String> = headings.iter().map(|heading| format!("{:>11}", heading)).collect();
+    println!("{:<width$} {}", "Address", header.join(" "));
+    for (row, name) in rows.iter().zip(&names) {
+        let ranks: Vec<String> = [Some(row.degree), Some(row.weighted_degree), metrics.pagerank.map(|_| row.pagerank), metrics.betweenness.map(|_| row.betweenness)]
+            .into_iter()
+            .flatten()
+            .map(|rank| format!("{:>11}", rank.map_or("-".to_string(), |rank| rank.to_string())))
+            .collect();
+        println!("{:<width$} {}", name, ranks.join(" "));
+    }
+}
 
-    // Calculate out-degrees
-    let out_degrees = graph.calculate_out_degrees();
-    // Expected out-degrees
-    let mut expected_out_degrees = HashSet::new();
-    expected_out_degrees.insert(("alice@example.com".to_string(), 3)); // bob and carol
-    expected_out_degrees.insert(("bob@example.com".to_string(), 1));   // dave
-    expected_out_degrees.insert(("carol@example.com".to_string(), 3)); // dave, eve, frank
-    expected_out_degrees.insert(("dave@example.com".to_string(), 0));
-    expected_out_degrees.insert(("eve@example.com".to_string(), 0));
-    expected_out_degrees.insert(("frank@example.com".to_string(), 0));
-
-    // Check out-degrees
-    for (node, degree) in &out_degrees {
-        let expected_degree = match node.as_str() {
-            "alice@example.com" => 3,
-            "bob@example.com" => 1,
-            "carol@example.com" => 3,
-            "dave@example.com" => 0,
-            "eve@example.com" => 0,
-            "frank@example.com" => 0,
-            _ => panic!("Unexpected node in graph: {}", node),
-        };
-        assert_eq!(*degree, expected_degree, "Out-degree for {} should be {}", node, expected_degree);
+/// Prints the Spearman correlations from `graph::metric_rank_correlation`
+/// as a symmetric matrix, one row and column per measure.
+fn print_rank_correlations(correlations: &[(String, String, f64)]) {
+    let mut names: Vec<&str> = correlations
+        .iter()
+        .flat_map(|(first, second, _)| [first.as_str(), second.as_str()])
+        .collect();
+    names.sort();
+    names.dedup();
+    let lookup: HashMap<(&str, &str), f64> = correlations
+        .iter()
+        .flat_map(|(first, second, rho)| [((first.as_str(), second.as_str()), *rho), ((second.as_str(), first.as_str()), *rho)])
+        .collect();
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+
+    println!("\n--- Rank Correlation between Measures (Spearman) ---");
+    let header: Vec<String> = names.iter().map(|name| format!("{:>width$}", name)).collect();
+    println!("{:<width$} {}", "", header.join(" "));
+    for &row in &names {
+        let cells: Vec<String> = names
+            .iter()
+            .map(|&column| {
+                let rho = if row == column { 1.0 } else { lookup[&(row, column)] };
+                format!("{:>width$.2}", rho)
+            })
+            .collect();
+        println!("{:<width$} {}", row, cells.join(" "));
     }
+}
 
-    // Also, check that all expected nodes are present
-    assert_eq!(out_degrees.len(), 6, "Graph should have 6 nodes");
+/// Prints up to `top_n` degree outliers, each with the directions that put
+/// it beyond `z_threshold`.
+fn print_degree_outliers(outliers: &[graph::DegreeOutlier], z_threshold: f64, top_n: usize) {
+    println!("\n--- Anomalies (degree beyond {}σ of typical) ---", z_threshold);
+    if outliers.is_empty() {
+        println!("No outliers found.");
+    }
+    for (i, outlier) in outliers.iter().take(top_n).enumerate() {
+        let reasons: Vec<String> = [("out-degree", outlier.out_degree, outlier.out_z), ("in-degree", outlier.in_degree, outlier.in_z)]
+            .into_iter()
+            .filter(|(_, _, z)| z.abs() > z_threshold)
+            .map(|(name, degree, z)| {
+                let side = if z > 0.0 { "above" } else { "below" };
+                format!("{} {}, {:.1}σ {} mean", name, degree, z.abs(), side)
+            })
+            .collect();
+        println!("{}. {} - {}", i + 1, outlier.node, reasons.join("; "));
+    }
+    if outliers.len() > top_n {
+        println!("... and {} more", outliers.len() - top_n);
+    }
 }
 
-#[test]
-fn test_in_degree_calculation() {
-    // Create sample parsed emails
-    let emails = vec![
-        ParsedEmail {
-            from: "alice@example.com".to_string(),
-            to: vec![
-                "bob@example.com".to_string(),
-                "carol@example.com".to_string(),
-            ],
-        },
-        ParsedEmail {
-            from: "bob@example.com".to_string(),
-            to: vec!["dave@example.com".to_string()],
-        },
-        ParsedEmail {
-            from: "carol@example.com".to_string(),
-            to: vec![
-                "dave@example.com".to_string(),
-                "eve@example.com".to_string(),
-                "frank@example.com".to_string(),
-            ],
-        },
-        ParsedEmail {
-            from: "alice@example.com".to_string(),
-            to: vec!["dave@example.com".to_string()],
-        },
-    ];
+/// Prints two rankings as adjacent columns under their headings, marking
+/// the addresses in `flagged` with "[list]".
+fn print_rankings_side_by_side(left: (&str, &TopList), right: (&str, &TopList), flagged: &HashSet<String>) {
+    let cell = |list: &TopList, i: usize| {
+        list.entries.get(i).map_or(String::new(), |(node, count)| {
+            let marker = if flagged.contains(node) { " [list]" } else { "" };
+            format!("{} ({}){}", node, count, marker)
+        })
+    };
+    let tie_note = |list: &TopList| match list.entries.last() {
+        Some((_, count)) if list.omitted_ties > 0 => format!("+{} more tied at {}", list.omitted_ties, count),
+        _ => String::new(),
+    };
 
-    // Build the graph
-    let graph = Graph::build_from_emails(emails);
+    let (left_heading, right_heading) = (format!("By {}", left.0), format!("By {}", right.0));
+    let rows = left.1.entries.len().max(right.1.entries.len());
+    let width = (0..rows)
+        .map(|i| cell(left.1, i).len())
+        .chain([left_heading.len(), tie_note(left.1).len()])
+        .max()
+        .unwrap_or(0);
 
-    // Calculate in-degrees
-    let in_degrees = graph.calculate_in_degrees();
+    println!("    {:<width$}  | {}", left_heading, right_heading);
+    for i in 0..rows {
+        println!("{:>2}. {:<width$}  | {}", i + 1, cell(left.1, i), cell(right.1, i));
+    }
+    if left.1.omitted_ties > 0 || right.1.omitted_ties > 0 {
+        println!("    {:<width$}  | {}", tie_note(left.1), tie_note(right.1));
+    }
+}
 
-    // Expected in-degrees
-    let mut expected_in_degrees = HashSet::new();
-    expected_in_degrees.insert(("alice@example.com".to_string(), 0));
-    expected_in_degrees.insert(("bob@example.com".to_string(), 1));   // From alice
-    expected_in_degrees.insert(("carol@example.com".to_string(), 1)); // From alice
-    expected_in_degrees.insert(("dave@example.com".to_string(), 3));  // From bob, carol, alice
-    expected_in_degrees.insert(("eve@example.com".to_string(), 1));   // From carol
-    expected_in_degrees.insert(("frank@example.com".to_string(), 1)); // From carol
-
-    // Check in-degrees
-    for (node, degree) in &in_degrees {
-        let expected_degree = match node.as_str() {
-            "alice@example.com" => 0,
-            "bob@example.com" => 1,
-            "carol@example.com" => 1,
-            "dave@example.com" => 3,
-            "eve@example.com" => 1,
-            "frank@example.com" => 1,
-            _ => panic!("Unexpected node in graph: {}", node),
+/// Ranks the members of every community by their degree within that
+/// community (in + out, counting only edges to fellow members), keeping the
+/// top `n` of each. Ties are broken by address.
+pub fn top_members_per_community(
+    graph: &Graph,
+    labels: &HashMap<String, String>,
+    n: usize,
+) -> HashMap<String, Vec<(String, usize)>> {
+    let mut internal_degrees: HashMap<&String, HashMap<String, usize>> = HashMap::new();
+    for (node, label) in labels {
+        internal_degrees.entry(label).or_default().insert(node.clone(), 0);
+    }
+
+    for (from, neighbors) in &graph.adjacency_list {
+        let Some(label) = labels.get(from) else {
+            continue;
         };
-        assert_eq!(*degree, expected_degree, "In-degree for {} should be {}", node, expected_degree);
+        for to in neighbors {
+            if from != to && labels.get(to) == Some(label) {
+                let degrees = internal_degrees.get_mut(label).unwrap();
+                *degrees.get_mut(from).unwrap() += 1;
+                *degrees.get_mut(to).unwrap() += 1;
+            }
+        }
     }
 
-    // Also, check that all expected nodes are present
-    assert_eq!(in_degrees.len(), 6, "Graph should have 6 nodes");
+    internal_degrees
+        .into_iter()
+        .map(|(label, degrees)| (label.clone(), rank_top(&degrees, n, false).entries))
+        .collect()
 }
 
-#[test]
-fn test_self_loops_and_multiple_edges() {
-    // Create sample parsed emails
-    let emails = vec![
-        ParsedEmail {
-            from: "alice@example.com".to_string(),
-            to: vec!["bob@example.com".to_string()],
-        },
-        ParsedEmail {
-            from: "alice@example.com".to_string(),
-            to: vec!["bob@example.com".to_string()], // Duplicate recipient
-        },
-        ParsedEmail {
-            from: "bob@example.com".to_string(),
-            to: vec!["alice@example.com".to_string()], // Creates a cycle
-        },
-        ParsedEmail {
-            from: "carol@example.com".to_string(),
-            to: vec!["carol@example.com".to_string()], // Self-loop
-        },
-    ];
+/// Identify and print the smallest and the largest community, with their
+/// three best-connected members
+fn identify_extreme_communities(
+    graph: &Graph,
+    community_map: &HashMap<String, Vec<String>>,
+    labels: &HashMap<String, String>,
+    original_labels: &HashMap<String, String>,
+) {
+    const LEADERS_SHOWN: usize = 3;
+    let leaders = top_members_per_community(graph, labels, LEADERS_SHOWN);
+    let print_leaders = |label: &String| {
+        for (i, (member, degree)) in leaders.get(label).into_iter().flatten().enumerate() {
+            println!("  {}. {} - {} internal connections", i + 1, member, degree);
+        }
+    };
 
-    // Build the graph
-    let graph = Graph::build_from_emails(emails);
+    // Ties in size go to the smallest label so the choice is stable between runs
+    // Find the largest community
+    if let Some((largest_label, largest_members)) = community_map
+        .iter()
+        .max_by_key(|&(label, members)| (members.len(), std::cmp::Reverse(label)))
+    {
+        println!("\n--- Largest Community ---");
+        print_community_label(largest_label, original_labels);
+        println!("Number of Members: {}", largest_members.len());
+        println!("Top {} Members:", LEADERS_SHOWN);
+        print_leaders(largest_label);
+    }
 
-    // Calculate out-degrees
-    let out_degrees = graph.calculate_out_degrees();
+    // Find the smallest community
+    if let Some((smallest_label, smallest_members)) = community_map
+        .iter()
+        .min_by_key(|&(label, members)| (members.len(), label))
+    {
+        println!("\n--- Smallest Community ---");
+        print_community_label(smallest_label, original_labels);
+        println!("Number of Members: {}", smallest_members.len());
+        println!("Top {} Members:", LEADERS_SHOWN);
+        print_leaders(smallest_label);
+    }
+}
 
-    // Expected out-degrees:
-    // alice: 1 (bob)
-    // bob: 1 (alice)
-    // carol: 1 (carol)
-    assert_eq!(out_degrees.get("alice@example.com"), Some(&1));
-    assert_eq!(out_degrees.get("bob@example.com"), Some(&1));
-    assert_eq!(out_degrees.get("carol@example.com"), Some(&1));
+/// Prints a community's name along with the label it was originally given.
+fn print_community_label(label: &str, original_labels: &HashMap<String, String>) {
+    match original_labels.get(label) {
+        Some(original) => println!("Community Label: {} (originally {})", label, original),
+        None => println!("Community Label: {}", label),
+    }
+}
 
-    // Calculate in-degrees
-    let in_degrees = graph.calculate_in_degrees();
+/// Prints the keyword profiles of the largest communities.
+fn print_community_keywords(community_map: &HashMap<String, Vec<String>>, keywords: &HashMap<String, Vec<(String, f64)>>) {
+    const COMMUNITIES_SHOWN: usize = 5;
 
-    // Expected in-degrees:
-    // alice: 1 (from bob)
-    // bob: 1 (from alice)
-    // carol: 1 (self-loop)
-    assert_eq!(in_degrees.get("alice@example.com"), Some(&1));
-    assert_eq!(in_degrees.get("bob@example.com"), Some(&1));
-    assert_eq!(in_degrees.get("carol@example.com"), Some(&1));
+    let mut largest: Vec<(&String, usize)> = community_map
+        .iter()
+        .filter(|(label, _)| *label != graph::UNASSIGNED_LABEL)
+        .map(|(label, members)| (label, members.len()))
+        .collect();
+    largest.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
 
-    // Ensure only the expected nodes are present
-    assert_eq!(out_degrees.len(), 3, "Graph should have 3 nodes");
-    assert_eq!(in_degrees.len(), 3, "Graph should have 3 nodes");
+    println!("\n--- Community Keywords ---");
+    for (label, size) in largest.into_iter().take(COMMUNITIES_SHOWN) {
+        let words: Vec<&str> = keywords.get(label).into_iter().flatten().map(|(word, _)| word.as_str()).collect();
+        let words = if words.is_empty() { "(no distinctive words)".to_string() } else { words.join(", ") };
+        println!("{} ({} members): {}", label, size, words);
+    }
 }
 
-#[test]
-fn test_label_propagation_small_graph() {
-    let mut graph = Graph::new();
+/// Logs the parse summary: the parsed, read and skipped rows from `report`
+/// and what the filters dropped from the rest of `outcome`, whose emails
+/// have moved to the report. The skipped rows themselves are listed by the
+/// readers at `-v`; by default only their number is shown.
+fn report_parse_outcome(report: &ParseReport, outcome: &ParseOutcome, logger: &Logger) {
+    logger.info(format_args!("Successfully parsed {} emails.", report.emails.len()));
+    logger.info(format_args!(
+        "Read {} rows; the parsed emails have {} recipients in total.",
+        report.total_rows,
+        report.recipient_count()
+    ));
+
+    let rejected = outcome.rejected_recipients;
+    if rejected.total() > 0 {
+        logger.info(format_args!(
+            "Ignored {} recipient entries that are not an address ({} malformed, {} too long, {} with whitespace or control characters).",
+            rejected.total(),
+            rejected.malformed,
+            rejected.too_long,
+            rejected.invalid_character
+        ));
+    }
+    if outcome.outside_date_range > 0 {
+        logger.info(format_args!("Excluded {} emails outside the date range.", outcome.outside_date_range));
+    }
+    if outcome.undated_excluded > 0 {
+        logger.info(format_args!("Excluded {} emails without a parseable date.", outcome.undated_excluded));
+    }
+    if outcome.pattern_excluded > 0 {
+        logger.info(format_args!("Excluded {} emails not matching the subject/body patterns.", outcome.pattern_excluded));
+    }
+    if outcome.blocked_recipients > 0 {
+        logger.info(format_args!("Removed {} recipient entries by address list.", outcome.blocked_recipients));
+    }
+    if outcome.address_excluded > 0 {
+        logger.info(format_args!("Excluded {} emails by address list.", outcome.address_excluded));
+    }
+    if !outcome.senders_without_recipients.is_empty() {
+        logger.info(format_args!(
+            "Kept the senders of {} emails without recipients as people without edges.",
+            outcome.senders_without_recipients.len()
+        ));
+    }
 
-    // Community 1: A, B, C
-    graph.add_edge("A".to_string(), "B".to_string());
-    graph.add_edge("A".to_string(), "C".to_string());
-    graph.add_edge("B".to_string(), "C".to_string());
+    if outcome.failed_count() > 0 {
+        let hint = if logger.enabled(Verbosity::Verbose) { "" } else { "; use -v to list them" };
+        logger.info(format_args!("Failed to parse {} records{}{}.", outcome.failed_count(), describe_skipped(&report.skipped), hint));
+    }
+}
 
-    // Community 2: D, E, F
-    graph.add_edge("D".to_string(), "E".to_string());
-    graph.add_edge("D".to_string(), "F".to_string());
-    graph.add_edge("E".to_string(), "F".to_string());
-    
+/// Counts the skipped rows per reason, e.g. " (1 malformed, 2 without sender)",
+/// or returns an empty string if there are none.
+fn describe_skipped(skipped: &[SkippedRecord]) -> String {
+    let reasons = [
+        (SkipReason::DeserializeError, "malformed"),
+        (SkipReason::MissingSender, "without sender"),
+        (SkipReason::NoRecipients, "without recipients"),
+    ];
+    let parts: Vec<String> = reasons
+        .iter()
+        .map(|&(reason, name)| (skipped.iter().filter(|record| record.reason == reason).count(), name))
+        .filter(|&(count, _)| count > 0)
+        .map(|(count, name)| format!("{} {}", count, name))
+        .collect();
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", parts.join(", "))
+    }
+}
 
-    // Perform Label Propagation
-    let labels = graph.label_propagation();
+/// Logs the estimated size of what a phase produced and, where the
+/// platform reports it, the resident memory of the whole process, at `-v`.
+fn report_memory(phase: &str, estimated_bytes: usize, logger: &Logger) {
+    match resident_memory_bytes() {
+        Some(resident) => logger.verbose(format_args!(
+            "Memory after {}: ~{} estimated, {} resident.",
+            phase,
+            format_bytes(estimated_bytes),
+            format_bytes(resident)
+        )),
+        None => logger.verbose(format_args!("Memory after {}: ~{} estimated.", phase, format_bytes(estimated_bytes))),
+    }
+}
 
-    // Organize nodes by communities
-    let mut community_map: HashMap<String, Vec<String>> = HashMap::new();
-    for (node, label) in labels {
-        community_map.entry(label).or_insert(Vec::new()).push(node);
+/// Reads the resident set size of this process from /proc (Linux only).
+fn resident_memory_bytes() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 MiB`.
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
     }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
 
-    // Expect two communities
-    assert_eq!(community_map.len(), 2, "There should be 2 communities");
+/// Returns the `top_n` highest-scoring nodes of a centrality measure.
+fn top_scores(scores: &HashMap<String, f64>, top_n: usize) -> Vec<(String, f64)> {
+    let mut ranked: Vec<(String, f64)> = scores.iter()
+        .map(|(node, score)| (node.clone(), *score))
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(top_n);
+    ranked
+}
 
-    // Check sizes
-    for members in community_map.values() {
-        assert_eq!(members.len(), 3, "Each community should have 3 members");
+/// Prints the highest-scoring nodes found by `top_scores`.
+fn print_top_scores(title: &str, ranked: &[(String, f64)], top_n: usize) {
+    println!("\n--- Top {} by {} ---", top_n, title);
+    for (i, (node, score)) in ranked.iter().enumerate() {
+        println!("{}. {} - {:.6}", i + 1, node, score);
+    }
+}
+
+/// Prints the `top_n` sender -> recipient edges by edge betweenness.
+fn print_top_edges(graph: &Graph, top_n: usize) {
+    println!("\n--- Top {} Edges by Betweenness ---", top_n);
+    for (i, ((from, to), score)) in graph.top_edges_by_betweenness(top_n).iter().enumerate() {
+        println!("{}. {} -> {} - {:.6}", i + 1, from, to, score);
+    }
+}
+
+/// Prints the `top_n` nodes whose email reaches the most people transitively.
+fn print_top_reach(top: &[(String, usize)], graph: &Graph, sample: Option<usize>, top_n: usize) {
+    match sample {
+        Some(sample) if sample < graph.node_count() => {
+            println!("\n--- Top {} by Reach (sample of {} people) ---", top_n, sample)
+        }
+        _ => println!("\n--- Top {} by Reach ---", top_n),
+    }
+    for (i, (node, size)) in top.iter().enumerate() {
+        println!("{}. {} - reaches {} people", i + 1, node, size);
     }
-}
\ No newline at end of file
+}
+
+/// Prints the radius, diameter and up to `top_n` center nodes of the
+/// largest weakly connected component.
+fn print_distance_summary(graph: &Graph, sample: Option<usize>, seed: u64, top_n: usize) {
+    let eccentricities = graph.eccentricities(sample, seed);
+    println!("\n--- Distances in the Largest Component ---");
+    let (Some(radius), Some(diameter)) = (graph::radius(&eccentricities), graph::diameter(&eccentricities)) else {
+        println!("The graph has no nodes.");
+        return;
+    };
+    let component_size = graph.weakly_connected_components().first().map_or(0, Vec::len);
+    println!("Component Size: {} of {} people", component_size, graph.node_count());
+    if eccentricities.len() < component_size {
+        println!("Measured from {} randomly chosen people: the radius is an upper and the diameter a lower bound.", eccentricities.len());
+    }
+    println!("Radius: {}", radius);
+    println!("Diameter: {}", diameter);
+
+    let center = graph::center(&eccentricities);
+    let shown: Vec<&str> = center.iter().take(top_n).map(String::as_str).collect();
+    println!("Center ({} people, everyone within {} steps): {}", center.len(), radius, shown.join(", "));
+}
+
+/// Prints how many recipients the emails have and who sends the most
+/// broadcasts, up to `top_n` of them.
+fn print_email_statistics(stats: &email::EmailStats, top_n: usize) {
+    println!("\n--- Recipients per Email ---");
+    println!("Emails: {}", stats.emails);
+    println!("Mean: {:.2}, Median: {:.1}, Max: {}", stats.mean_recipients, stats.median_recipients, stats.max_recipients);
+    let share = if stats.emails == 0 { 0.0 } else { 100.0 * stats.broadcasts as f64 / stats.emails as f64 };
+    println!("Broadcasts (more than {} recipients): {} ({:.1}%)", stats.broadcast_threshold, stats.broadcasts, share);
+    for (i, (sender, count)) in stats.top_broadcast_senders.iter().take(top_n).enumerate() {
+        println!("{}. {} - {} broadcasts", i + 1, sender, count);
+    }
+}
+
+/// Prints the `top_n` domains with the most addresses, their traffic and
+/// the other domain each exchanges the most email with.
+fn print_domain_statistics(stats: &[graph::DomainStats], top_n: usize) {
+    println!("\n--- Top {} Domains by Addresses ({} in total) ---", top_n.min(stats.len()), stats.len());
+    for (i, domain) in stats.iter().take(top_n).enumerate() {
+        let partner = match &domain.top_external_domain {
+            Some(partner) => format!("{} ({} emails)", partner, domain.top_external_emails),
+            None => "none".to_string(),
+        };
+        println!(
+            "{}. {} - {} addresses, {} sent, {} received, most with {}",
+            i + 1, domain.domain, domain.addresses, domain.emails_sent, domain.emails_received, partner
+        );
+    }
+}
+
+/// Prints how much of people's email stays inside their own community:
+/// the mean embeddedness and the share of people below one half.
+fn print_embeddedness_summary(embeddedness: &HashMap<String, f64>) {
+    if embeddedness.is_empty() {
+        return;
+    }
+    let n = embeddedness.len() as f64;
+    let mean = embeddedness.values().sum::<f64>() / n;
+    let below_half = embeddedness.values().filter(|&&score| score < 0.5).count();
+    println!("Average Embeddedness: {:.3}", mean);
+    println!("People with Embeddedness Below 0.5: {} ({:.1}%)", below_half, 100.0 * below_half as f64 / n);
+}
+
+/// Prints how consistently repeated label propagation runs group people:
+/// the average stability and how many people switch community in most runs.
+fn print_consensus_stability(consensus: &graph::ConsensusResult) {
+    println!("\n--- Community Stability ({} runs) ---", consensus.runs);
+    println!("Consensus Communities: {}", consensus.community_count());
+    println!("Average Stability: {:.3}", consensus.average_stability());
+    println!("People with Stability Below 0.5: {}", consensus.unstable_nodes(0.5).len());
+}
+
+/// Prints how the modularity and average clustering compare with `samples`
+/// degree-preserving rewirings of the graph, each with its communities
+/// detected the same way, as z-scores.
+fn print_null_model(graph: &Graph, config: &Config, modularity: f64, samples: usize) {
+    let base_seed = config.algorithms.lpa_seed.unwrap_or(0);
+    let num_swaps = NULL_MODEL_SWAPS_PER_EDGE * graph.edge_count();
+    let (mut null_modularity, mut null_clustering) = (Vec::new(), Vec::new());
+    for i in 0..samples {
+        let rewired = graph.rewire_preserving_degrees(num_swaps, base_seed.wrapping_add(i as u64));
+        let communities = detect_communities(&rewired, config, &Logger::silent());
+        null_modularity.push(rewired.modularity(&communities.labels));
+        null_clustering.push(rewired.average_clustering());
+    }
+    let clustering = graph.average_clustering();
+    let mean = |values: &[f64]| values.iter().sum::<f64>() / values.len().max(1) as f64;
+
+    println!("\n--- Null Model ({} degree-preserving rewirings) ---", samples);
+    println!("Metric       Observed  Null mean  z-score");
+    for (name, observed, null) in [("Modularity", modularity, &null_modularity), ("Clustering", clustering, &null_clustering)] {
+        println!("{:<11}  {:>8.4}  {:>9.4}  {:>7.2}", name, observed, mean(null), graph::metric_zscore(observed, null));
+    }
+}
+
+/// Prints the cumulative share of sampled pairs within each hop count, the
+/// usual small-world summary, and how many pairs no email chain connects.
+fn print_distance_distribution(sample: &graph::DistanceSample) {
+    println!("\n--- Pairwise Distances ---");
+    if sample.pairs == 0 {
+        println!("No pairs to sample.");
+        return;
+    }
+    println!("Sampled Pairs: {}", sample.pairs);
+    for &hops in sample.hops.keys() {
+        println!("{:.1}% of pairs within {} hops", 100.0 * sample.fraction_within(hops), hops);
+    }
+    println!(
+        "{:.1}% of pairs have no directed chain of emails from the first person to the second",
+        100.0 * sample.unreachable_fraction()
+    );
+}
+
+/// Summary of a run, written to `output.report_json` when configured.
+#[derive(Debug, Serialize)]
+struct RunReport {
+    input: String,
+    giant_component_only: bool, // Counts and rankings cover only the largest connected component
+    edge_sample: Option<f64>, // Fraction of edges kept when the analysis ran on a sampled graph
+    node_count: usize,
+    edge_count: usize,
+    top_senders: Vec<(String, usize)>,
+    top_recipients: Vec<(String, usize)>,
+    top_senders_by_volume: Vec<(String, usize)>,
+    top_recipients_by_volume: Vec<(String, usize)>,
+    community_count: usize,
+    largest_community_size: usize,
+    modularity: f64,
+    pagerank: Option<Vec<(String, f64)>>,
+    weighted_pagerank: Option<Vec<(String, f64)>>,
+    harmonic: Option<Vec<(String, f64)>>,
+    betweenness: Option<Vec<(String, f64)>>,
+    reach: Option<Vec<(String, usize)>>,
+    centrality_ranks: Vec<CombinedRank>, // Every node in the top N of any measure, with its rank under each
+    rank_correlations: Vec<(String, String, f64)>, // Spearman correlation of every pair of node measures
+}
+
+/// Loads the config file (if any) and applies command-line overrides on top.
+fn resolve_config(cli: &Cli, logger: &Logger) -> Result<Config, Error> {
+    let mut config = match &cli.config {
+        Some(path) => {
+            let (config, unknown_keys) = Config::load(path)?;
+            for key in unknown_keys {
+                logger.warn(unknown_key_warning(&key));
+            }
+            config
+        }
+        None => Config::default(),
+    };
+    cli.apply_overrides(&mut config);
+
+    // Address list files add to any patterns from the config file
+    let load = |path: &std::path::Path| {
+        filter::load_address_patterns(path).map_err(|source| Error::AddressListRead {
+            path: path.display().to_string(),
+            source,
+        })
+    };
+    if let Some(path) = &cli.blocklist {
+        config.filter.blocklist.extend(load(path)?);
+    }
+    if let Some(path) = &cli.allowlist {
+        config.filter.allowlist.extend(load(path)?);
+    }
+
+    // Seed labels from a file add to (and override) any from the config file
+    if let Some(path) = &cli.seed_labels {
+        let seeds = community::read_seed_labels(path).map_err(|source| Error::SeedLabelsRead {
+            path: path.display().to_string(),
+            source,
+        })?;
+        config.algorithms.seed_labels.extend(seeds);
+    }
+    Ok(config)
+}
+
+/// Reads and filters the configured input, logging the parse summary.
+fn load_emails(config: &Config, logger: &Logger) -> Result<Vec<ParsedEmail>, Error> {
+    load_parse_outcome(config, logger).map(|outcome| outcome.emails)
+}
+
+/// Like `load_emails`, but keeps everything the reader returned, subjects included.
+/// An input pattern reads every matching file in name order; a file that
+/// can't be read is skipped with a warning unless `input.strict` is set.
+fn load_parse_outcome(config: &Config, logger: &Logger) -> Result<ParseOutcome, Error> {
+    // Path to your CSV file
+    let input = config.input.path.as_deref().unwrap_or(DEFAULT_INPUT);
+    let files = email::expand_input_pattern(input).map_err(|source| EmailError::Io {
+        path: input.to_string(),
+        source,
+    })?;
+    if files.is_empty() {
+        return Err(Error::NoInputFiles { pattern: input.to_string() });
+    }
+
+    // Read and parse every file
+    let mut outcome = ParseOutcome::default();
+    for file in &files {
+        let file_path = file.to_string_lossy();
+        if file_path == email::STDIN_PATH {
+            logger.debug("Reading standard input");
+        } else {
+            logger.debug(format_args!("Reading {}", file_path));
+        }
+        match read_input_file(config, &file_path, logger) {
+            Ok(file_outcome) => {
+                if files.len() > 1 {
+                    logger.info(format_args!(
+                        "{}: parsed {} emails, {} records failed.",
+                        file_path,
+                        file_outcome.emails.len(),
+                        file_outcome.failed_count()
+                    ));
+                }
+                outcome.merge(file_outcome);
+            }
+            Err(err) if email::is_input_pattern(input) && !config.input.strict => {
+                logger.warn(format_args!("skipping {}", err));
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    let report = outcome.take_report();
+    report_parse_outcome(&report, &outcome, logger);
+    outcome.emails = report.emails;
+    if logger.enabled(Verbosity::Verbose) {
+        report_memory("parsing", outcome.estimate_memory_bytes(), logger);
+    }
+    if outcome.emails.is_empty() {
+        return Err(Error::NoValidEmails { input: input.to_string() });
+    }
+
+    Ok(outcome)
+}
+
+/// Reads and parses one input file in the configured format, logging the
+/// skipped records at `-v`.
+fn read_input_file(config: &Config, file_path: &str, logger: &Logger) -> Result<ParseOutcome, EmailError> {
+    let outcome = match config.input.format {
+        #[cfg(not(feature = "parallel"))]
+        InputFormat::Csv => return read_csv_with(file_path, &config.input.schema, &config.filter, logger),
+        #[cfg(feature = "parallel")]
+        InputFormat::Csv => return email::read_csv_parallel(file_path, &config.input.schema, &config.filter, logger),
+        InputFormat::Maildir => maildir::read_maildir_with(std::path::Path::new(file_path), &config.filter)?,
+        #[cfg(feature = "parquet")]
+        InputFormat::Parquet => email::read_parquet_with(file_path, &config.input.schema, &config.filter)?,
+    };
+    outcome.log_row_errors(file_path, logger);
+    Ok(outcome)
+}
+
+/// Reads the configured input and builds the (optionally pruned) graph.
+fn load_graph(config: &Config, logger: &Logger) -> Result<Graph, Error> {
+    let outcome = load_parse_outcome(config, logger)?;
+    build_graph(config, outcome.emails, outcome.senders_without_recipients, &outcome.display_names, logger)
+}
+
+/// Builds the graph from already loaded emails, applying the graph and
+/// anonymization settings and attaching the display names and node tags.
+fn build_graph(
+    config: &Config,
+    mut emails: Vec<ParsedEmail>,
+    senders_without_recipients: Vec<String>,
+    display_names: &HashMap<String, String>,
+    logger: &Logger,
+) -> Result<Graph, Error> {
+    // Leave out mass mailings, whose recipients would all look connected
+    if let Some(threshold) = config.graph.drop_broadcasts {
+        let before = emails.len();
+        emails.retain(|email| email.to.len() <= threshold);
+        logger.info(format_args!("Dropped {} broadcast emails with more than {} recipients.", before - emails.len(), threshold));
+    }
+
+    // Build the graph, keeping the senders whose emails had no recipients
+    let (mut graph, stats) = Graph::build_from_emails_with_options(emails, &config.graph.build_options());
+    for sender in senders_without_recipients {
+        graph.add_node(sender);
+    }
+    logger.debug(format_args!("Built a graph of {} nodes and {} edges.", graph.node_count(), graph.edge_count()));
+    if stats.self_loops_skipped > 0 {
+        logger.info(format_args!("Skipped {} self-addressed recipients.", stats.self_loops_skipped));
+    }
+    if logger.enabled(Verbosity::Verbose) {
+        report_memory("building the graph", graph.estimate_memory_bytes(), logger);
+    }
+
+    // Drop edges carrying too few emails, and optionally the people they leave isolated
+    if config.graph.min_edge_weight > 1 {
+        let (nodes_before, edges_before) = (graph.node_count(), graph.edge_count());
+        graph = graph.filter_by_weight(config.graph.min_edge_weight);
+        if config.graph.drop_isolated {
+            graph = graph.without_isolated_nodes();
+        }
+        logger.info(format_args!(
+            "Removed {} edges with fewer than {} emails and {} isolated nodes.",
+            edges_before - graph.edge_count(),
+            config.graph.min_edge_weight,
+            nodes_before - graph.node_count()
+        ));
+    }
+
+    // Prune weakly connected people if a minimum degree is configured
+    if config.graph.min_degree > 0 {
+        graph = graph.prune_min_degree(config.graph.min_degree);
+        logger.info(format_args!(
+            "Pruned graph to {} nodes with total degree >= {}.",
+            graph.node_count(), config.graph.min_degree
+        ));
+    }
+
+    // Thin the edges uniformly if requested
+    if let Some(fraction) = config.graph.edge_sample {
+        let (nodes_before, edges_before) = (graph.node_count(), graph.edge_count());
+        graph = if config.graph.edge_sample_stratified {
+            graph.sample_edges_stratified(fraction, config.graph.edge_sample_seed)
+        } else {
+            graph.sample_edges(fraction, config.graph.edge_sample_seed)
+        };
+        logger.info(format_args!(
+            "Sampled {} of {} edges, keeping {} of {} nodes.",
+            graph.edge_count(),
+            edges_before,
+            graph.node_count(),
+            nodes_before
+        ));
+    }
+
+    // Restrict the analysis to the giant component if requested
+    if config.graph.giant_component_only {
+        let (nodes_before, edges_before) = (graph.node_count(), graph.edge_count());
+        graph = graph.largest_component_subgraph();
+        logger.info(format_args!(
+            "Restricted to the largest connected component: excluded {} of {} nodes and {} of {} edges.",
+            nodes_before - graph.node_count(),
+            nodes_before,
+            edges_before - graph.edge_count(),
+            edges_before
+        ));
+    }
+
+    // Attach what is known about the people who are left
+    for (address, name) in display_names {
+        graph.set_node_attr(address, graph::DISPLAY_NAME_ATTR, name.clone());
+    }
+    if let Some(path) = &config.input.node_tags {
+        let applied = graph.load_node_tags(path).map_err(|source| Error::NodeTagsRead {
+            path: path.display().to_string(),
+            source,
+        })?;
+        logger.info(format_args!("Applied {} node tags from {}.", applied, path.display()));
+    }
+
+    if config.output.anonymize {
+        graph = anonymize_graph(&graph, config, logger)?;
+    }
+
+    Ok(graph)
+}
+
+/// Replaces addresses with pseudonyms, reusing and updating the configured mapping file.
+fn anonymize_graph(graph: &Graph, config: &Config, logger: &Logger) -> Result<Graph, Error> {
+    with_address_mapper(config, logger, |mapper| graph.anonymize(mapper))
+}
+
+/// Runs `anonymize` with the configured address mapping (loaded if the file
+/// exists), then saves the updated mapping.
+fn with_address_mapper<T>(config: &Config, logger: &Logger, anonymize: impl FnOnce(&mut AddressMapper) -> T) -> Result<T, Error> {
+    let preserve_domains = config.output.preserve_domains;
+    let mut mapper = match &config.output.address_map {
+        Some(path) if path.exists() => AddressMapper::load_csv(path, preserve_domains).map_err(|source| {
+            Error::AddressMapRead {
+                path: path.display().to_string(),
+                source,
+            }
+        })?,
+        _ => AddressMapper::new(preserve_domains),
+    };
+
+    let anonymized = anonymize(&mut mapper);
+    logger.info(format_args!("Anonymized {} addresses.", mapper.len()));
+
+    if let Some(path) = &config.output.address_map {
+        mapper.save_csv(path).map_err(|source| Error::Write {
+            path: path.display().to_string(),
+            source,
+        })?;
+    }
+    Ok(anonymized)
+}
+
+/// Community labels for every node, as produced by `detect_communities`.
+struct Communities {
+    labels: HashMap<String, String>, // Node -> canonical name (C0, C1, ...)
+    original_labels: HashMap<String, String>, // Canonical name -> label found by detection
+    merged_nodes: usize, // Members of small communities moved into a larger one
+}
+
+/// Runs the configured community detection on the graph, or on its mutual
+/// subgraph with `mutual_only`, in which case people without a mutual edge
+/// get no label, as do pure senders or recipients left out with
+/// `exclude_sources`/`exclude_sinks`. Seed labels turn label propagation
+/// semi-supervised.
+/// Members of communities below `min_community_size` are then moved into a
+/// neighboring community, and communities are renamed `C0`, `C1`, ... by size.
+fn detect_communities(graph: &Graph, config: &Config, logger: &Logger) -> Communities {
+    let options = graph::LpaOptions { min_community_size: 1, ..config.algorithms.lpa_options() };
+    let trimmed;
+    let candidates = if config.algorithms.exclude_sources || config.algorithms.exclude_sinks {
+        trimmed = without_node_classes(graph, config.algorithms.exclude_sources, config.algorithms.exclude_sinks, logger);
+        &trimmed
+    } else {
+        graph
+    };
+    let mutual;
+    let community_graph = if config.algorithms.mutual_only {
+        mutual = candidates.mutual_graph();
+        logger.info(format_args!(
+            "Mutual graph: {} of {} nodes and {} of {} edges are reciprocated.",
+            mutual.node_count(),
+            candidates.node_count(),
+            mutual.edge_count(),
+            candidates.edge_count()
+        ));
+        &mutual
+    } else {
+        candidates
+    };
+
+    let seed = config.algorithms.lpa_seed.unwrap_or(0);
+    let seeds: HashMap<String, String> = config.algorithms.seed_labels
+        .iter()
+        .map(|(address, label)| {
+            let node = community_graph.resolve_node(address).cloned();
+            (node.unwrap_or_else(|| email::normalize_address(address)), label.clone())
+        })
+        .collect();
+    if !seeds.is_empty() && config.algorithms.community_method != CommunityMethod::Lpa {
+        logger.warn("seed labels only apply to label propagation; ignoring them");
+    }
+    let mut labels = match config.algorithms.community_method {
+        CommunityMethod::Lpa => {
+            let result = if seeds.is_empty() {
+                community_graph.label_propagation_detailed(&options)
+            } else {
+                community_graph.label_propagation_seeded_detailed(&seeds, &options)
+            };
+            report_lpa_convergence(&result, logger);
+            result.labels
+        }
+        CommunityMethod::Louvain => community_graph.louvain(seed),
+        CommunityMethod::Leiden => community_graph.leiden(seed),
+    };
+    let mut merged_nodes = 0;
+    if config.algorithms.min_community_size > 1 {
+        let merged = community_graph.merge_small_communities(&labels, config.algorithms.min_community_size);
+        merged_nodes = merged
+            .iter()
+            .filter(|(node, label)| labels[*node] != **label && *label != graph::UNASSIGNED_LABEL)
+            .count();
+        labels = merged;
+    }
+
+    let (labels, original_labels) = graph::canonicalize_labels(&labels);
+    Communities { labels, original_labels, merged_nodes }
+}
+
+/// Returns the communities of `graph`: loaded from the configured
+/// communities file if there is one, otherwise detected. People the file
+/// doesn't list get a community of their own, with a warning; entries for
+/// people not in the graph are ignored.
+fn communities_for(graph: &Graph, config: &Config, logger: &Logger) -> Result<Communities, Error> {
+    let Some(path) = &config.algorithms.communities_file else {
+        return Ok(detect_communities(graph, config, logger));
+    };
+    let partition = community::read_partition(path).map_err(|source| Error::PartitionRead {
+        path: path.display().to_string(),
+        source,
+    })?;
+    let fitted = community::fit_partition(graph, partition);
+    logger.info(format_args!(
+        "Loaded the communities of {} people from {}.",
+        graph.node_count() - fitted.missing.len(),
+        path.display()
+    ));
+    if !fitted.missing.is_empty() {
+        logger.warn(format_args!(
+            "{} people are missing from {}; giving each a community of their own",
+            fitted.missing.len(),
+            path.display()
+        ));
+        for node in &fitted.missing {
+            logger.verbose(format_args!("  no community for {}", node));
+        }
+    }
+    if fitted.ignored > 0 {
+        logger.info(format_args!("Ignored {} entries for people not in the graph.", fitted.ignored));
+    }
+    Ok(Communities { labels: fitted.labels, original_labels: HashMap::new(), merged_nodes: 0 })
+}
+
+/// Says whether label propagation stabilized, or warns that it stopped at
+/// the iteration cap with labels still changing.
+fn report_lpa_convergence(result: &graph::LpaResult, logger: &Logger) {
+    if result.converged {
+        logger.info(format_args!("Label propagation converged after {} iterations.", result.iterations_run));
+    } else {
+        let last_changes = result.changes_per_iteration.last().copied().unwrap_or(0);
+        logger.warn(format_args!(
+            "label propagation did not converge within {} iterations ({} labels changed in the last pass); consider raising --max-iterations",
+            result.iterations_run, last_changes
+        ));
+    }
+}
+
+/// Removes pure senders and/or pure recipients before community detection;
+/// the removed people get no community label.
+fn without_node_classes(graph: &Graph, sources: bool, sinks: bool, logger: &Logger) -> Graph {
+    let classes = graph.classify_nodes();
+    let kept: HashSet<String> = classes
+        .into_iter()
+        .filter(|(_, class)| match class {
+            NodeClass::Source => !sources,
+            NodeClass::Sink => !sinks,
+            NodeClass::Connector | NodeClass::Isolated => true,
+        })
+        .map(|(node, _)| node)
+        .collect();
+    logger.info(format_args!(
+        "Community detection leaves out {} of {} people who only send or only receive.",
+        graph.node_count() - kept.len(),
+        graph.node_count()
+    ));
+    graph.induced_subgraph(&kept)
+}
+
+/// Runs the full analysis pipeline. With a quiet logger the report is not
+/// printed and only the requested output files are written.
+fn run(config: &Config, logger: &Logger) -> Result<(), Error> {
+    let file_path = config.input.path.as_deref().unwrap_or(DEFAULT_INPUT);
+    let report = !logger.is_quiet();
+
+    // Keyword profiles need the subjects, which the graph doesn't keep
+    let want_keywords = config.algorithms.enable_keywords || config.output.keywords_csv.is_some();
+    if want_keywords && config.output.anonymize {
+        logger.warn("keyword profiles are not available together with anonymization; skipping them");
+    }
+    // So does the SQLite database, which would also hold the real addresses
+    let want_sqlite = cfg!(feature = "sqlite") && config.output.sqlite.is_some();
+    if config.output.sqlite.is_some() && !cfg!(feature = "sqlite") {
+        logger.warn("built without the `sqlite` feature; not writing the SQLite database");
+    } else if want_sqlite && config.output.anonymize {
+        logger.warn("the SQLite database is not available together with anonymization; skipping it");
+    }
+    let mut outcome = load_parse_outcome(config, logger)?;
+    if report {
+        print_email_statistics(&email::email_statistics(&outcome.emails, config.graph.broadcast_threshold()), config.output.top_n);
+    }
+    let display_names = std::mem::take(&mut outcome.display_names);
+    let senders_without_recipients = std::mem::take(&mut outcome.senders_without_recipients);
+    let (full_emails, emails) = if (want_keywords || want_sqlite) && !config.output.anonymize {
+        let full_emails = outcome.into_full_emails();
+        let emails = full_emails.iter().map(|full| full.email.clone()).collect();
+        (Some(full_emails), emails)
+    } else {
+        (None, outcome.emails)
+    };
+    let graph = build_graph(config, emails, senders_without_recipients, &display_names, logger)?;
+
+    // Perform Degree Distribution Analysis
+    if let (true, Some(fraction)) = (report, config.graph.edge_sample) {
+        println!(
+            "Note: analyzing a sampled graph that keeps each edge with probability {} (seed {}{}).",
+            fraction,
+            config.graph.edge_sample_seed,
+            if config.graph.edge_sample_stratified { ", stratified" } else { "" }
+        );
+    }
+    if report {
+        analyze_degree_distribution(&graph, config.output.top_n);
+    }
+
+    // Calculate out-degrees and in-degrees
+    let out_degrees = graph.calculate_out_degrees();
+    let in_degrees = graph.calculate_in_degrees();
+
+    // Identify and print top N senders and recipients
+    let top_n = config.output.top_n; // Define how many top individuals to identify
+    let lists = graph.likely_distribution_lists(&config.algorithms.distribution_list_options());
+    if report {
+        print_top_individuals(&graph, top_n, &lists);
+        print_degree_outliers(&graph.degree_outliers(config.algorithms.outlier_z), config.algorithms.outlier_z, top_n);
+    }
+
+    // Optional downstream reach, shown next to the sender rankings
+    let reach = config.algorithms.enable_reachability.then(|| {
+        let sample = config.algorithms.reachability_sample;
+        let reach = graph.reachability_distribution(sample, config.algorithms.reachability_seed);
+        let top = rank_top(&reach, top_n, false).entries;
+        if report {
+            print_top_reach(&top, &graph, sample, top_n);
+        }
+        top
+    });
+
+    // Optional distance profile of the largest component, only ever printed
+    if report && config.algorithms.enable_eccentricity {
+        let algorithms = &config.algorithms;
+        print_distance_summary(&graph, algorithms.eccentricity_sample, algorithms.eccentricity_seed, top_n);
+    }
+    if let (true, Some(pairs)) = (report, config.algorithms.distance_pairs) {
+        print_distance_distribution(&graph.sample_distances(pairs, config.algorithms.distance_seed));
+    }
+
+    // Optional centrality measures
+    let ranked = |title: &str, scores: &HashMap<String, f64>| {
+        let top = top_scores(scores, top_n);
+        if report {
+            print_top_scores(title, &top, top_n);
+        }
+        top
+    };
+    let variant = config.algorithms.pagerank_variant;
+    let pagerank_scores = (config.algorithms.enable_pagerank && variant != PagerankVariant::Weighted)
+        .then(|| graph.pagerank(0.85, 1e-8, 100));
+    let pagerank = pagerank_scores.as_ref()
+        .map(|scores| ranked("PageRank", scores));
+    let weighted_pagerank_scores = (config.algorithms.enable_pagerank && variant != PagerankVariant::Unweighted)
+        .then(|| graph.weighted_pagerank(0.85, 1e-8, 100));
+    let weighted_pagerank = weighted_pagerank_scores.as_ref()
+        .map(|scores| ranked("Weighted PageRank", scores));
+    let harmonic_scores = config.algorithms.enable_harmonic
+        .then(|| graph.harmonic_centrality(config.algorithms.harmonic_sample));
+    let harmonic = harmonic_scores.as_ref()
+        .map(|scores| ranked("Harmonic Centrality", scores));
+    let betweenness_scores = config.algorithms.enable_betweenness.then(|| {
+        match config.algorithms.betweenness_sources {
+            Some(sources) => graph.approximate_betweenness(sources, config.algorithms.betweenness_seed),
+            None => graph.betweenness_centrality(),
+        }
+    });
+    let betweenness = betweenness_scores.as_ref()
+        .map(|scores| ranked("Betweenness", scores));
+    if report && config.algorithms.enable_edge_betweenness {
+        print_top_edges(&graph, top_n);
+    }
+
+    // Ranks under every measure side by side, shown once a centrality measure exists
+    let sum = |a: &HashMap<String, usize>, b: &HashMap<String, usize>| -> HashMap<String, usize> {
+        a.iter().map(|(node, value)| (node.clone(), value + b.get(node).copied().unwrap_or(0))).collect()
+    };
+    let (out_strengths, in_strengths) = (graph.calculate_out_strengths(), graph.calculate_in_strengths());
+    let degree = sum(&out_degrees, &in_degrees);
+    let weighted_degree = sum(&out_strengths, &in_strengths);
+    let metrics = CentralityMetrics {
+        degree: &degree,
+        weighted_degree: &weighted_degree,
+        pagerank: pagerank_scores.as_ref().or(weighted_pagerank_scores.as_ref()),
+        betweenness: betweenness_scores.as_ref(),
+    };
+    let centrality_ranks = combined_rankings(&metrics, top_n);
+    if report && (metrics.pagerank.is_some() || metrics.betweenness.is_some()) {
+        print_top_individuals_extended(&centrality_ranks, &metrics, top_n, &lists);
+    }
+
+    // How far the measures agree; a weak link between degree and the
+    // expensive measures means the latter show something degree doesn't
+    let as_scores = |counts: &HashMap<String, usize>| -> HashMap<String, f64> {
+        counts.iter().map(|(node, count)| (node.clone(), *count as f64)).collect()
+    };
+    let mut node_metrics: HashMap<String, HashMap<String, f64>> = [
+        ("in-degree", &in_degrees),
+        ("out-degree", &out_degrees),
+        ("in-strength", &in_strengths),
+        ("out-strength", &out_strengths),
+    ]
+    .into_iter()
+    .map(|(name, counts)| (name.to_string(), as_scores(counts)))
+    .collect();
+    let centralities = [
+        ("pagerank", &pagerank_scores),
+        ("weighted-pagerank", &weighted_pagerank_scores),
+        ("harmonic", &harmonic_scores),
+        ("betweenness", &betweenness_scores),
+    ];
+    for (name, scores) in centralities {
+        if let Some(scores) = scores {
+            node_metrics.insert(name.to_string(), scores.clone());
+        }
+    }
+    let rank_correlations = graph::metric_rank_correlation(&node_metrics);
+    if report && centralities.iter().any(|(_, scores)| scores.is_some()) {
+        print_rank_correlations(&rank_correlations);
+    }
+
+    // Traffic by email domain
+    let domain_stats = (report || config.output.domain_csv.is_some()).then(|| graph.domain_statistics());
+    if let (true, Some(stats)) = (report, &domain_stats) {
+        print_domain_statistics(stats, TOP_DOMAINS);
+    }
+
+    // Detect communities
+    let Communities { labels: communities, original_labels, merged_nodes } = communities_for(&graph, config, logger)?;
+
+    // Organize nodes by communities
+    let mut community_map: HashMap<String, Vec<String>> = HashMap::new();
+    for (node, label) in &communities {
+        community_map.entry(label.clone()).or_default().push(node.clone());
+    }
+    // Analyze Communities
+    let modularity = graph.modularity(&communities);
+    if report {
+        analyze_communities(&community_map, merged_nodes);
+        let method = match config.algorithms.communities_file {
+            Some(_) => "loaded from file",
+            None => config.algorithms.community_method.name(),
+        };
+        println!("Modularity ({}): {:.4}", method, modularity);
+        print_embeddedness_summary(&graph.community_embeddedness(&communities));
+
+        // Identify Extreme Communities
+        identify_extreme_communities(&graph, &community_map, &communities, &original_labels);
+    }
+    if let (true, Some(runs)) = (report, config.algorithms.consensus_runs) {
+        let options = graph::LpaOptions { min_community_size: 1, ..config.algorithms.lpa_options() };
+        let base_seed = config.algorithms.lpa_seed.unwrap_or(0);
+        print_consensus_stability(&graph.consensus_communities_with_options(runs, base_seed, &options));
+    }
+    if let (true, Some(samples)) = (report, config.algorithms.null_model) {
+        print_null_model(&graph, config, modularity, samples);
+    }
+
+    // Describe the largest communities by their subjects
+    let keywords = full_emails
+        .as_deref()
+        .filter(|_| want_keywords)
+        .map(|emails| keywords::community_keywords(emails, &communities, config.algorithms.keywords_top_k));
+    if let (true, Some(keywords)) = (report, &keywords) {
+        print_community_keywords(&community_map, keywords);
+    }
+
+    // Write the requested output files
+    let write_error = |path: &std::path::Path| {
+        let path = path.display().to_string();
+        move |source| Error::Write { path, source }
+    };
+    if let Some(path) = &config.output.node_csv {
+        export::write_node_csv(&graph, Some(&communities), harmonic_scores.as_ref(), &lists, path).map_err(write_error(path))?;
+    }
+    if let Some(path) = &config.output.graphml {
+        export::write_graphml(&graph, Some(&communities), config.output.edge_activity, path).map_err(write_error(path))?;
+    }
+    if let Some(path) = &config.output.backbone_graphml {
+        export::write_graphml(&graph.maximum_spanning_tree(), Some(&communities), config.output.edge_activity, path).map_err(write_error(path))?;
+    }
+    if let Some(path) = &config.output.html {
+        let max_nodes = config.output.html_max_nodes;
+        let drawn = export::export_html_visualization_with_limit(&graph, &communities, path, max_nodes)
+            .map_err(write_error(path))?;
+        if drawn < graph.node_count() {
+            logger.info(format_args!("HTML view limited to the {} highest-degree of {} people.", drawn, graph.node_count()));
+        }
+    }
+    if let Some(path) = &config.output.matrix_market {
+        let file = std::fs::File::create(path).map_err(write_error(path))?;
+        graph.to_matrix_market(file).map_err(write_error(path))?;
+        let index_path = path.with_file_name("node_index.csv");
+        export::write_node_index(&graph, &index_path).map_err(write_error(&index_path))?;
+    }
+    if let Some(dir) = &config.output.neo4j_dir {
+        std::fs::create_dir_all(dir).map_err(write_error(dir))?;
+        let (nodes_path, rels_path) = (dir.join("nodes.csv"), dir.join("relationships.csv"));
+        export::export_neo4j(&graph, Some(&communities), &nodes_path, &rels_path).map_err(write_error(dir))?;
+    }
+    #[cfg(feature = "sqlite")]
+    if let (Some(path), Some(emails)) = (&config.output.sqlite, &full_emails) {
+        sqlite::persist_to_sqlite(path, emails, &graph, Some(&communities)).map_err(|source| Error::Sqlite {
+            path: path.display().to_string(),
+            source,
+        })?;
+    }
+    if let Some(path) = &config.output.community_sizes {
+        export::write_community_sizes(&community_map, path).map_err(write_error(path))?;
+    }
+    if let Some(path) = &config.output.community_report {
+        export::write_community_report(&graph, &communities, path).map_err(write_error(path))?;
+    }
+    if let (Some(path), Some(keywords)) = (&config.output.keywords_csv, &keywords) {
+        export::write_keywords_csv(keywords, path).map_err(write_error(path))?;
+    }
+    if let Some(path) = &config.output.partition_csv {
+        community::write_partition(path, &communities).map_err(write_error(path))?;
+    }
+    if let (Some(path), Some(stats)) = (&config.output.domain_csv, &domain_stats) {
+        export::write_domain_stats_csv(stats, path).map_err(write_error(path))?;
+    }
+    if let Some(path) = &config.output.report_json {
+        let report = RunReport {
+            input: file_path.to_string(),
+            giant_component_only: config.graph.giant_component_only,
+            edge_sample: config.graph.edge_sample,
+            node_count: graph.node_count(),
+            edge_count: graph.edge_count(),
+            top_senders: identify_top_senders(&out_degrees, top_n),
+            top_recipients: identify_top_recipients(&in_degrees, top_n),
+            top_senders_by_volume: identify_top_senders_by_volume(&graph, top_n),
+            top_recipients_by_volume: identify_top_recipients_by_volume(&graph, top_n),
+            community_count: community_map.len(),
+            largest_community_size: community_map.values().map(Vec::len).max().unwrap_or(0),
+            modularity,
+            pagerank,
+            weighted_pagerank,
+            harmonic,
+            betweenness,
+            reach,
+            centrality_ranks,
+            rank_correlations,
+        };
+        export::write_json(&report, path).map_err(write_error(path))?;
+    }
+
+    Ok(())
+}
+
+/// Loads the graph and community labels once, then answers queries from stdin.
+fn run_repl(config: &Config, logger: &Logger) -> Result<(), Error> {
+    let graph = load_graph(config, logger)?;
+    let labels = communities_for(&graph, config, logger)?.labels;
+    let mut session = repl::Session::new(graph, labels);
+    session.find_limit = config.output.find_limit;
+
+    logger.info("Graph loaded. Type 'help' for a list of commands.");
+    let stdin = std::io::stdin();
+    repl::run(&session, stdin.lock(), std::io::stdout()).map_err(|source| Error::Write {
+        path: "<stdout>".to_string(),
+        source,
+    })
+}
+
+/// Prints the report for one address.
+fn run_who(config: &Config, logger: &Logger, address: &str, mode: graph::MatchMode) -> Result<(), Error> {
+    let graph = load_graph(config, logger)?;
+    let lookup = graph.lookup_node(address, mode, config.output.find_limit).map_err(|source| Error::InvalidPattern {
+        pattern: address.to_string(),
+        source,
+    })?;
+    let node = match lookup {
+        graph::NodeLookup::Found(node) => node,
+        graph::NodeLookup::Ambiguous(candidates) => {
+            print_candidates(address, &candidates, config.output.find_limit);
+            return Ok(());
+        }
+        graph::NodeLookup::NotFound => return resolve_address(&graph, address, SUGGESTIONS).map(|_| ()),
+    };
+    let labels = communities_for(&graph, config, logger)?.labels;
+    let report = graph.node_report(&node, Some(&labels)).ok_or_else(|| Error::UnknownAddress {
+        address: address.to_string(),
+        suggestions: Vec::new(),
+    })?;
+    print_node_report(&report, graph.node_count());
+    Ok(())
+}
+
+/// Returns the node `address` refers to, or an error naming the colliding
+/// nodes or up to `limit` suggestions.
+fn resolve_address(graph: &Graph, address: &str, limit: usize) -> Result<String, Error> {
+    match graph.resolve_node_detailed(address, limit) {
+        graph::NodeResolution::Resolved(node) => Ok(node),
+        graph::NodeResolution::Ambiguous(candidates) => Err(Error::AmbiguousAddress { address: address.to_string(), candidates }),
+        graph::NodeResolution::Unknown { suggestions } => Err(Error::UnknownAddress { address: address.to_string(), suggestions }),
+    }
+}
+
+/// Lists the addresses a query matched, most connected first.
+fn print_candidates(query: &str, candidates: &[String], limit: usize) {
+    let more = if candidates.len() == limit { " or more" } else { "" };
+    println!("'{}' matches {}{} addresses; pick one:", query, candidates.len(), more);
+    for candidate in candidates {
+        println!("  {}", candidate);
+    }
+}
+
+/// Pretty-prints a node report; `node_count` puts the ranks in context.
+fn print_node_report(report: &graph::NodeReport, node_count: usize) {
+    println!("\n--- {} ---", report.address);
+    println!(
+        "Sends to {} people ({:.1} total weight), rank {} of {} by out-degree",
+        report.out_degree, report.weighted_out_degree, report.out_degree_rank, node_count
+    );
+    println!(
+        "Receives from {} people ({:.1} total weight), rank {} of {} by in-degree",
+        report.in_degree, report.weighted_in_degree, report.in_degree_rank, node_count
+    );
+    println!("Community: {}", report.community.as_deref().unwrap_or("(none)"));
+    for (key, value) in &report.attributes {
+        println!("{}: {}", key, value);
+    }
+
+    for (heading, neighbors) in [("Sends to", &report.out_neighbors), ("Receives from", &report.in_neighbors)] {
+        println!("\n{}:", heading);
+        if neighbors.is_empty() {
+            println!("  (nobody)");
+        }
+        for neighbor in neighbors {
+            println!("  {}", neighbor);
+        }
+    }
+}
+
+/// Answers a batch of connectivity questions, writing one result row per pair.
+/// Addresses are looked up like in `who`; pairs with an address that matches
+/// no node or several are reported with the candidates instead of paths.
+fn run_paths(
+    config: &Config,
+    logger: &Logger,
+    pairs_path: &std::path::Path,
+    mode: graph::MatchMode,
+    output: Option<&std::path::Path>,
+) -> Result<(), Error> {
+    let pairs = paths::read_pairs(pairs_path).map_err(|source| Error::PairsRead {
+        path: pairs_path.display().to_string(),
+        source,
+    })?;
+    let graph = load_graph(config, logger)?;
+    let lookup = |address: String| {
+        paths::Endpoint::lookup(&graph, address.clone(), mode, config.output.find_limit)
+            .map_err(|source| Error::InvalidPattern { pattern: address, source })
+    };
+    let pairs = pairs
+        .into_iter()
+        .map(|(from, to)| Ok((lookup(from)?, lookup(to)?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let unresolved = pairs
+        .iter()
+        .filter(|(from, to)| !matches!((from, to), (paths::Endpoint::Node(_), paths::Endpoint::Node(_))))
+        .count();
+    if unresolved > 0 {
+        logger.warn(format_args!("{} pairs have an unknown or ambiguous address; see the candidates columns.", unresolved));
+    }
+
+    let write_error = |path: String| move |source| Error::Write { path, source };
+    match output {
+        Some(path) => {
+            let file = std::fs::File::create(path).map_err(write_error(path.display().to_string()))?;
+            paths::write_path_report(&graph, &pairs, file).map_err(write_error(path.display().to_string()))?;
+            logger.info(format_args!("Wrote paths for {} pairs to {}.", pairs.len(), path.display()));
+        }
+        None => paths::write_path_report(&graph, &pairs, std::io::stdout().lock())
+            .map_err(write_error("<stdout>".to_string()))?,
+    }
+    Ok(())
+}
+
+/// Writes the snowball sample around the seed addresses as an edge list.
+fn run_sample(config: &Config, logger: &Logger, seeds: &[String], depth: usize, max_per_level: usize, seed: u64, output: &std::path::Path) -> Result<(), Error> {
+    let graph = load_graph(config, logger)?;
+    let seeds = seeds
+        .iter()
+        .map(|seed| resolve_address(&graph, seed, SUGGESTIONS))
+        .collect::<Result<Vec<String>, Error>>()?;
+
+    let sample = graph.snowball_sample(&seeds, depth, max_per_level, seed);
+    export::write_edge_list(&sample, config.output.edge_activity, output).map_err(|source| Error::Write {
+        path: output.display().to_string(),
+        source,
+    })?;
+    logger.info(format_args!(
+        "Wrote a sample of {} nodes and {} edges to {}.",
+        sample.node_count(),
+        sample.edge_count(),
+        output.display()
+    ));
+    Ok(())
+}
+
+/// Writes the breadth-first tree of everyone `root` reaches within `depth`
+/// steps to `output` as a DOT file.
+fn run_influence_tree(config: &Config, logger: &Logger, root: &str, depth: usize, non_tree_edges: bool, output: &std::path::Path) -> Result<(), Error> {
+    let graph = load_graph(config, logger)?;
+    let root = resolve_address(&graph, root, SUGGESTIONS)?;
+
+    let drawn = export::export_influence_tree(&graph, &root, depth, non_tree_edges, output).map_err(|source| Error::Write {
+        path: output.display().to_string(),
+        source,
+    })?;
+    logger.info(format_args!("Wrote the influence tree of {} ({} people) to {}.", root, drawn, output.display()));
+    Ok(())
+}
+
+/// Reads and filters the input, drops repeated emails and writes one row
+/// per sender and recipient to `output`.
+fn run_clean(config: &Config, logger: &Logger, output: &std::path::Path) -> Result<(), Error> {
+    let mut emails = load_emails(config, logger)?;
+    let duplicates = email::dedupe_emails(&mut emails);
+    if duplicates > 0 {
+        logger.info(format_args!("Dropped {} repeated emails.", duplicates));
+    }
+    if config.output.anonymize {
+        emails = with_address_mapper(config, logger, |mapper| {
+            emails
+                .into_iter()
+                .map(|email| ParsedEmail {
+                    from: mapper.pseudonym(&email.from),
+                    to: email.to.iter().map(|recipient| mapper.pseudonym(recipient)).collect(),
+                    timestamp: email.timestamp,
+                })
+                .collect()
+        })?;
+    }
+
+    let write_error = |source| Error::Write {
+        path: output.display().to_string(),
+        source,
+    };
+    let file = std::fs::File::create(output).map_err(write_error)?;
+    export::write_parsed_emails(&emails, std::io::BufWriter::new(file)).map_err(write_error)?;
+    let rows: usize = emails.iter().map(|email| email.to.len()).sum();
+    logger.info(format_args!("Wrote {} rows for {} emails to {}.", rows, emails.len(), output.display()));
+    Ok(())
+}
+
+/// Prints the overall email volume per bucket and the largest sending bursts,
+/// and optionally writes the top senders' series as CSV.
+fn run_activity(config: &Config, logger: &Logger, bucket_days: i64, output: Option<&std::path::Path>, burst_sigma: f64) -> Result<(), Error> {
+    let emails = load_emails(config, logger)?;
+    let bucket = chrono::Duration::days(bucket_days);
+    let total = temporal::total_activity_series(&emails, bucket);
+    if total.is_empty() {
+        println!("No dated emails to build an activity series from.");
+        return Ok(());
+    }
+
+    println!("\n--- Emails per {}-day bucket ---", bucket_days);
+    for (start, count) in &total {
+        println!("{}: {}", start.format("%Y-%m-%d"), count);
+    }
+
+    let series = temporal::sender_activity_series(&emails, bucket);
+    let bursts = temporal::detect_bursts(&series, burst_sigma);
+    println!("\n--- Top Bursts (> {} standard deviations above the sender's mean) ---", burst_sigma);
+    if bursts.is_empty() {
+        println!("No bursts found.");
+    }
+    for (i, burst) in bursts.iter().take(config.output.top_n).enumerate() {
+        println!(
+            "{}. {}: {} .. {}, peak {} emails",
+            i + 1,
+            burst.sender,
+            burst.start.format("%Y-%m-%d"),
+            burst.end.format("%Y-%m-%d"),
+            burst.peak
+        );
+    }
+
+    if let Some(path) = output {
+        let totals: HashMap<String, usize> = series
+            .iter()
+            .map(|(sender, counts)| (sender.clone(), counts.iter().map(|(_, count)| count).sum()))
+            .collect();
+        let top: Vec<String> = rank_top(&totals, config.output.top_n, false)
+            .entries
+            .into_iter()
+            .map(|(sender, _)| sender)
+            .collect();
+        export::write_activity_csv(&series, &top, path).map_err(|source| Error::Write {
+            path: path.display().to_string(),
+            source,
+        })?;
+        logger.info(format_args!("Wrote the series of the top {} senders to {}.", top.len(), path.display()));
+    }
+    Ok(())
+}
+
+/// Groups emails into threads by subject and prints the longest ones.
+fn run_threads(config: &Config, logger: &Logger) -> Result<(), Error> {
+    let emails = load_parse_outcome(config, logger)?.into_full_emails();
+    let threads = threads::group_into_threads(&emails);
+    println!("Found {} threads.", threads.len());
+    print_longest_threads(&threads, config.output.top_n);
+    Ok(())
+}
+
+/// Prints the `top_n` threads with the most messages.
+fn print_longest_threads(threads: &HashMap<String, threads::Thread>, top_n: usize) {
+    println!("\n--- Top {} Longest Threads ---", top_n);
+    for (i, (subject, thread)) in threads::longest_threads(threads, top_n).into_iter().enumerate() {
+        let span = match (thread.first, thread.last) {
+            (Some(first), Some(last)) => format!("{} .. {}", first.format("%Y-%m-%d"), last.format("%Y-%m-%d")),
+            _ => "undated".to_string(),
+        };
+        println!(
+            "{}. \"{}\" - {} messages, {} participants, {}",
+            i + 1,
+            subject,
+            thread.message_count,
+            thread.participants.len(),
+            span
+        );
+    }
+}
+
+/// Runs every community detection method, prints how their partitions
+/// compare and optionally writes the comparison table as CSV.
+fn run_compare_communities(config: &Config, logger: &Logger, lpa_seeds: &[u64], output: Option<&std::path::Path>) -> Result<(), Error> {
+    let graph = load_graph(config, logger)?;
+    let options = graph::LpaOptions { min_community_size: 1, ..config.algorithms.lpa_options() };
+    let comparison = graph.compare_communities(lpa_seeds, config.algorithms.lpa_seed.unwrap_or(0), &options);
+
+    println!("\n--- Community Detection Comparison ---");
+    println!("{:<16} {:>11} {:>8} {:>10} {:>10} {:>11}", "Method", "Communities", "Largest", "Singletons", "Modularity", "Runtime ms");
+    for partition in &comparison.partitions {
+        println!(
+            "{:<16} {:>11} {:>8} {:>10} {:>10.4} {:>11.1}",
+            partition.method,
+            partition.communities,
+            partition.largest,
+            partition.singletons,
+            partition.modularity,
+            partition.runtime.as_secs_f64() * 1000.0
+        );
+    }
+
+    println!("\n--- Pairwise NMI ---");
+    print!("{:<16}", "");
+    for partition in &comparison.partitions {
+        print!(" {:>14}", partition.method);
+    }
+    println!();
+    for (partition, row) in comparison.partitions.iter().zip(&comparison.nmi) {
+        print!("{:<16}", partition.method);
+        for value in row {
+            print!(" {:>14.4}", value);
+        }
+        println!();
+    }
+
+    if let Some(path) = output {
+        export::write_community_comparison_csv(&comparison, path).map_err(|source| Error::Write {
+            path: path.display().to_string(),
+            source,
+        })?;
+        logger.info(format_args!("Wrote {} rows to {}.", comparison.partitions.len(), path.display()));
+    }
+    Ok(())
+}
+
+/// Prints the largest flows of nodes between the communities of two
+/// partition files and optionally writes all of them as CSV.
+fn run_partition_flow(
+    config: &Config,
+    logger: &Logger,
+    earlier: &std::path::Path,
+    later: &std::path::Path,
+    output: Option<&std::path::Path>,
+) -> Result<(), Error> {
+    let read = |path: &std::path::Path| {
+        community::read_partition(path).map_err(|source| Error::PartitionRead { path: path.display().to_string(), source })
+    };
+    let flows = community::partition_flow(&read(earlier)?, &read(later)?);
+
+    println!("\n--- Community Flow ---");
+    println!("{:<30} {:<30} {:>8}", "From", "To", "Nodes");
+    for flow in flows.iter().take(config.output.top_n) {
+        println!("{:<30} {:<30} {:>8}", flow.from, flow.to, flow.nodes);
+    }
+    if flows.len() > config.output.top_n {
+        println!("... and {} smaller flows", flows.len() - config.output.top_n);
+    }
+
+    if let Some(path) = output {
+        export::write_partition_flow_csv(&flows, path).map_err(|source| Error::Write {
+            path: path.display().to_string(),
+            source,
+        })?;
+        logger.info(format_args!("Wrote {} rows to {}.", flows.len(), path.display()));
+    }
+    Ok(())
+}
+
+/// Prints the `k` spreaders chosen by VoteRank, and how many of them a
+/// plain ranking by in-degree (the votes before any dampening) would pick.
+fn run_spreaders(config: &Config, logger: &Logger, k: usize) -> Result<(), Error> {
+    let graph = load_graph(config, logger)?;
+    let in_degrees = graph.calculate_in_degrees();
+    let spreaders = graph.vote_rank(k);
+
+    println!("\n--- Top {} Spreaders (VoteRank) ---", k);
+    for (i, node) in spreaders.iter().enumerate() {
+        println!("{}. {} - hears from {} people", i + 1, node, in_degrees[node]);
+    }
+
+    // Hubs sharing the same contacts are picked once by VoteRank but all by degree
+    let by_degree = identify_top_recipients(&in_degrees, k);
+    let overlap = by_degree.iter().filter(|(node, _)| spreaders.contains(node)).count();
+    println!("{} of them are also among the top {} by in-degree.", overlap, k);
+    Ok(())
+}
+
+/// Simulates spreading from `seed_nodes` and prints the reach and the
+/// people infected most often.
+fn run_spread(config: &Config, logger: &Logger, seed_nodes: &[String], model: spread::SpreadModel, trials: usize, seed: u64) -> Result<(), Error> {
+    let graph = load_graph(config, logger)?;
+    let seeds = seed_nodes
+        .iter()
+        .map(|node| resolve_address(&graph, node, SUGGESTIONS))
+        .collect::<Result<Vec<String>, Error>>()?;
+
+    let result = graph.simulate_spread(&seeds, model, trials, seed);
+    println!("\n--- Spread over {} trials ---", result.trials);
+    println!("Mean Reach: {:.2} people", result.mean_reach);
+    println!("Median Reach: {:.1} people", result.median_reach);
+
+    let mut frequent: Vec<(&String, f64)> = result.infection_frequency
+        .iter()
+        .filter(|(node, _)| !seeds.contains(node))
+        .map(|(node, &frequency)| (node, frequency))
+        .collect();
+    frequent.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    println!("\nMost often infected:");
+    for (node, frequency) in frequent.into_iter().take(config.output.top_n) {
+        println!("  {} - {:.1}% of trials", node, frequency * 100.0);
+    }
+    Ok(())
+}
+
+/// Prints (or writes) how the largest component shrinks as people are removed.
+fn run_robustness(
+    config: &Config,
+    logger: &Logger,
+    strategy: graph::RemovalStrategy,
+    steps: usize,
+    output: Option<&std::path::Path>,
+) -> Result<(), Error> {
+    let graph = load_graph(config, logger)?;
+    let points = graph.robustness_profile(strategy, steps);
+    if let Some(path) = output {
+        export::write_robustness_csv(&points, path).map_err(|source| Error::Write {
+            path: path.display().to_string(),
+            source,
+        })?;
+        logger.info(format_args!("Wrote {} points to {}.", points.len(), path.display()));
+        return Ok(());
+    }
+
+    println!("\n--- Robustness ({:?}) ---", strategy);
+    println!("Removed  Largest component");
+    for point in &points {
+        println!("{:>6.1}%  {:>6.1}%", point.fraction_removed * 100.0, point.largest_component_fraction * 100.0);
+    }
+    Ok(())
+}
+
+/// Generates a Barabási–Albert graph and times building it from emails,
+/// both degree calculations, breadth-first search from `BENCH_BFS_SOURCES`
+/// people and label propagation on the String-keyed `Graph` and on the
+/// interned `CsrGraph`, printing each with its throughput in edges per
+/// second (edges times sources for the searches, edges times iterations for
+/// label propagation). Both representations are checked against the
+/// generated graph and against each other before anything else is timed,
+/// and label propagation runs unweighted as `CsrGraph` only supports that.
+fn run_bench(config: &Config, logger: &Logger, nodes: usize, edges_per_node: usize, seed: u64) -> Result<(), Error> {
+    logger.info(format_args!("Generating a Barabási–Albert graph of {} people...", nodes));
+    let generated = Graph::barabasi_albert(nodes, edges_per_node, seed);
+    let emails = emails_along_edges(&generated);
+
+    let time = |run: &mut dyn FnMut()| {
+        let start = std::time::Instant::now();
+        run();
+        start.elapsed()
+    };
+    let mut graph = Graph::new();
+    let mut owned = emails.clone(); // Cloned before the timer, as `CsrGraph` only borrows the emails
+    let build = time(&mut || graph = Graph::build_from_emails(std::mem::take(&mut owned)));
+    let mut csr = CsrGraph::default();
+    let csr_build = time(&mut || csr = CsrGraph::build_from_emails(&emails));
+    graph.validate().map_err(|violations| Error::BenchMismatch {
+        operation: format!("build ({} broken invariants)", violations.len()),
+    })?;
+    ensure_same(graph == generated && csr.matches(&graph), "build")?;
+    let edges = graph.edge_count();
+
+    let sources: Vec<&String> = graph.adjacency_list.keys().take(BENCH_BFS_SOURCES).collect();
+    let csr_sources: Vec<u32> = sources.iter().filter_map(|source| csr.id(source)).collect();
+    let mut options = graph::LpaOptions { min_community_size: 1, weighted: false, ..config.algorithms.lpa_options() };
+    options.seed = options.seed.or(Some(seed));
+
+    logger.info(format_args!("Checking that both representations agree..."));
+    let out_degrees = graph.calculate_out_degrees();
+    let same_out = csr.out_degrees().iter().enumerate().all(|(id, degree)| out_degrees.get(csr.name(id as u32)) == Some(degree));
+    ensure_same(same_out, "out-degrees")?;
+    let in_degrees = graph.calculate_in_degrees();
+    let same_in = csr.in_degrees().iter().enumerate().all(|(id, degree)| in_degrees.get(csr.name(id as u32)) == Some(degree));
+    ensure_same(same_in, "in-degrees")?;
+    let same_layers = sources.len() == csr_sources.len()
+        && sources.iter().zip(&csr_sources).all(|(source, &id)| {
+            let layers: Vec<Vec<&str>> = csr.bfs_layers(id, graph::Direction::Out).iter().map(|layer| csr.names_of(layer)).collect();
+            layers == graph.bfs_layers(source, graph::Direction::Out)
+        });
+    ensure_same(same_layers, "BFS")?;
+    let lpa_result = graph.label_propagation_detailed(&options);
+    let csr_lpa_result = csr.label_propagation(&options);
+    let same_labels = lpa_result.iterations_run == csr_lpa_result.iterations_run
+        && csr_lpa_result.labels.iter().enumerate().all(|(id, &label)| {
+            lpa_result.labels.get(csr.name(id as u32)).map(String::as_str) == Some(csr.name(label))
+        });
+    ensure_same(same_labels, "label propagation")?;
+    let iterations = lpa_result.iterations_run;
+
+    let out_degrees = time(&mut || drop(std::hint::black_box(graph.calculate_out_degrees())));
+    let csr_out_degrees = time(&mut || drop(std::hint::black_box(csr.out_degrees())));
+    let in_degrees = time(&mut || drop(std::hint::black_box(graph.calculate_in_degrees())));
+    let csr_in_degrees = time(&mut || drop(std::hint::black_box(csr.in_degrees())));
+    let bfs = time(&mut || {
+        for source in &sources {
+            std::hint::black_box(graph.bfs_layers(source, graph::Direction::Out));
+        }
+    });
+    let csr_bfs = time(&mut || {
+        for &source in &csr_sources {
+            std::hint::black_box(csr.bfs_layers(source, graph::Direction::Out));
+        }
+    });
+    let lpa = time(&mut || drop(std::hint::black_box(graph.label_propagation_detailed(&options))));
+    let csr_lpa = time(&mut || drop(std::hint::black_box(csr.label_propagation(&options))));
+
+    println!("\n--- Benchmark: {} people, {} edges ---", graph.node_count(), edges);
+    println!(
+        "{:<28}  {:>11}  {:>13}  {:>14}  {:>16}  {:>7}",
+        "Operation", "String (ms)", "Interned (ms)", "String edges/s", "Interned edges/s", "Speedup"
+    );
+    let rows = [
+        ("build from emails".to_string(), build, csr_build, edges),
+        ("out-degrees".to_string(), out_degrees, csr_out_degrees, edges),
+        ("in-degrees".to_string(), in_degrees, csr_in_degrees, edges),
+        (format!("BFS from {} people", sources.len()), bfs, csr_bfs, edges * sources.len()),
+        (format!("label propagation ({} it.)", iterations), lpa, csr_lpa, edges * iterations.max(1)),
+    ];
+    let throughput = |work: usize, seconds: f64| if seconds > 0.0 { work as f64 / seconds } else { 0.0 };
+    for (operation, elapsed, csr_elapsed, work) in rows {
+        let (seconds, csr_seconds) = (elapsed.as_secs_f64(), csr_elapsed.as_secs_f64());
+        let speedup = if csr_seconds > 0.0 { seconds / csr_seconds } else { 0.0 };
+        println!(
+            "{:<28}  {:>11.1}  {:>13.1}  {:>14.0}  {:>16.0}  {:>6.1}x",
+            operation,
+            seconds * 1000.0,
+            csr_seconds * 1000.0,
+            throughput(work, seconds),
+            throughput(work, csr_seconds),
+            speedup,
+        );
+    }
+    Ok(())
+}
+
+/// One email along every edge of `graph`, from each sender to all its recipients.
+fn emails_along_edges(graph: &Graph) -> Vec<ParsedEmail> {
+    graph.adjacency_list
+        .iter()
+        .filter(|(_, recipients)| !recipients.is_empty())
+        .map(|(from, recipients)| ParsedEmail { from: from.clone(), to: recipients.iter().cloned().collect(), timestamp: None })
+        .collect()
+}
+
+/// Fails the benchmark at `operation` unless both representations agreed.
+fn ensure_same(same: bool, operation: &str) -> Result<(), Error> {
+    if same {
+        Ok(())
+    } else {
+        Err(Error::BenchMismatch { operation: operation.to_string() })
+    }
+}
+
+/// Prints (or writes) how the largest component shrinks as random edges are dropped.
+fn run_percolation(
+    config: &Config,
+    logger: &Logger,
+    steps: usize,
+    trials: usize,
+    seed: u64,
+    output: Option<&std::path::Path>,
+) -> Result<(), Error> {
+    let graph = load_graph(config, logger)?;
+    let points = graph.edge_percolation_curve(steps, trials, seed);
+    if let Some(path) = output {
+        export::write_percolation_csv(&points, path).map_err(|source| Error::Write {
+            path: path.display().to_string(),
+            source,
+        })?;
+        logger.info(format_args!("Wrote {} points to {}.", points.len(), path.display()));
+        return Ok(());
+    }
+
+    println!("\n--- Edge Percolation ({} trials) ---", trials.max(1));
+    println!("Edges kept  Largest component");
+    for (retained, largest) in &points {
+        println!("{:>9.1}%  {:>6.1}%", retained * 100.0, largest * 100.0);
+    }
+    Ok(())
+}
+
+/// Builds a graph from each input with the same settings and prints what changed.
+fn run_diff(config: &Config, logger: &Logger, earlier: &str, later: &str) -> Result<(), Error> {
+    if earlier == email::STDIN_PATH && later == email::STDIN_PATH {
+        return Err(Error::StdinReadTwice);
+    }
+    let load = |path: &str| {
+        let mut config = config.clone();
+        config.input.path = Some(path.to_string());
+        load_graph(&config, logger)
+    };
+    let diff = load(earlier)?.diff(&load(later)?);
+
+    println!("\n--- Changes from {} to {} ---", earlier, later);
+    println!("Nodes: +{} -{}", diff.added_nodes.len(), diff.removed_nodes.len());
+    println!("Edges: +{} -{}", diff.added_edges.len(), diff.removed_edges.len());
+    println!("Edges with a changed weight: {}", diff.weight_changes.len());
+
+    let top_n = config.output.top_n;
+    let mut deltas: Vec<(&String, i64)> = diff.degree_deltas.iter().map(|(node, delta)| (node, *delta)).collect();
+    deltas.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    println!("\n--- Top {} Degree Gainers ---", top_n);
+    for (node, delta) in deltas.iter().filter(|(_, delta)| *delta > 0).take(top_n) {
+        println!("{}: +{}", node, delta);
+    }
+    deltas.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(b.0)));
+    println!("\n--- Top {} Degree Losers ---", top_n);
+    for (node, delta) in deltas.iter().filter(|(_, delta)| *delta < 0).take(top_n) {
+        println!("{}: {}", node, delta);
+    }
+
+    let mut changes: Vec<&graph::WeightChange> = diff.weight_changes.iter().collect();
+    changes.sort_by(|a, b| (b.after - b.before).abs().total_cmp(&(a.after - a.before).abs()));
+    println!("\n--- Top {} Weight Changes ---", top_n);
+    for change in changes.into_iter().take(top_n) {
+        println!("{} -> {}: {} -> {}", change.from, change.to, change.before, change.after);
+    }
+    Ok(())
+}
+
+/// Prints everyone a time-respecting chain of emails from `source` reaches
+/// after `since`, or the earliest such chain to `target`. Both addresses
+/// are resolved against the graph of the loaded emails first.
+fn run_exposure(
+    config: &Config,
+    logger: &Logger,
+    source: &str,
+    since: chrono::DateTime<chrono::Utc>,
+    max_delay: Option<chrono::Duration>,
+    target: Option<&str>,
+) -> Result<(), Error> {
+    let emails = load_emails(config, logger)?;
+    let graph = Graph::build_from_emails(emails.clone());
+    let source = resolve_address(&graph, source, SUGGESTIONS)?;
+    if let Some(target) = target {
+        let target = resolve_address(&graph, target, SUGGESTIONS)?;
+        match temporal::temporal_path(&emails, &source, &target, since, max_delay) {
+            Some(path) => {
+                println!("\n--- How {} could have heard from {} ---", target, source);
+                for hop in path {
+                    println!("{}  {} -> {}", hop.timestamp.format("%Y-%m-%d %H:%M"), hop.from, hop.to);
+                }
+            }
+            None => println!("No time-respecting chain of emails leads from {} to {}.", source, target),
+        }
+        return Ok(());
+    }
+
+    let mut reached: Vec<String> = temporal::temporal_reachable_set(&emails, &source, since, max_delay).into_iter().collect();
+    reached.sort();
+    println!("\n--- Could have heard from {} after {} ({} people) ---", source, since.format("%Y-%m-%d"), reached.len());
+    for address in reached {
+        println!("{}", address);
+    }
+    Ok(())
+}
+
+/// Prints per-window graph sizes and how stable the communities are over time.
+fn run_timeline(config: &Config, logger: &Logger, window_days: i64) -> Result<(), Error> {
+    let mut emails = load_emails(config, logger)?;
+    if config.graph.drop_self_loops {
+        for email in &mut emails {
+            let sender = email.from.clone();
+            email.to.retain(|recipient| *recipient != sender);
+        }
+    }
+    let snapshots = temporal::build_temporal_snapshots(&emails, chrono::Duration::days(window_days));
+    if snapshots.is_empty() {
+        println!("No dated emails to build a timeline from.");
+        return Ok(());
+    }
+
+    println!("\n--- Timeline ({}-day windows) ---", window_days);
+    for (i, (range, graph)) in snapshots.iter().enumerate() {
+        println!(
+            "{}. {} .. {}: {} nodes, {} edges",
+            i + 1,
+            range.start().format("%Y-%m-%d"),
+            range.end().format("%Y-%m-%d"),
+            graph.node_count(),
+            graph.edge_count()
+        );
+    }
+
+    println!("\n--- Community Stability (Jaccard of largest communities) ---");
+    for shift in temporal::community_stability(&snapshots, &config.algorithms.lpa_options()) {
+        println!(
+            "{} -> {}: {:.2} ({} -> {} communities)",
+            shift.from_window + 1,
+            shift.to_window + 1,
+            shift.largest_jaccard,
+            shift.communities_before,
+            shift.communities_after
+        );
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // Flags decide the verbosity; otherwise the config file does, once it is read
+    let config_logger = Logger::new(cli.verbosity().unwrap_or_default());
+    let result = resolve_config(&cli, &config_logger).and_then(|config| {
+        let logger = Logger::new(cli.verbosity().unwrap_or_else(|| config.output.verbosity()));
+        let logger = &logger;
+        match cli.command {
+            Some(Command::Repl) => run_repl(&config, logger),
+            Some(Command::Paths { ref pairs, matching, ref output }) => {
+                run_paths(&config, logger, pairs, matching.match_mode(), output.as_deref())
+            }
+            Some(Command::Sample { ref seeds, depth, max_per_level, seed, ref output }) => {
+                run_sample(&config, logger, seeds, depth, max_per_level, seed, output)
+            }
+            Some(Command::InfluenceTree { ref root, depth, non_tree_edges, ref output }) => {
+                run_influence_tree(&config, logger, root, depth, non_tree_edges, output)
+            }
+            Some(Command::Clean { ref output }) => run_clean(&config, logger, output),
+            Some(Command::Who { ref address, matching }) => run_who(&config, logger, address, matching.match_mode()),
+            Some(Command::Activity { bucket_days, ref output, burst_sigma }) => {
+                run_activity(&config, logger, bucket_days, output.as_deref(), burst_sigma)
+            }
+            Some(Command::Threads) => run_threads(&config, logger),
+            Some(Command::Diff { ref earlier, ref later }) => run_diff(&config, logger, earlier, later),
+            Some(Command::CompareCommunities { ref seeds, ref output }) => {
+                run_compare_communities(&config, logger, seeds, output.as_deref())
+            }
+            Some(Command::PartitionFlow { ref earlier, ref later, ref output }) => {
+                run_partition_flow(&config, logger, earlier, later, output.as_deref())
+            }
+            Some(Command::Spreaders { k }) => run_spreaders(&config, logger, k),
+            Some(Command::Spread { ref seed_nodes, model, p, recovery, weighted, trials, seed }) => {
+                run_spread(&config, logger, seed_nodes, model.spread_model(p, recovery, weighted), trials, seed)
+            }
+            Some(Command::Robustness { strategy, steps, seed, ref output }) => {
+                run_robustness(&config, logger, strategy.removal_strategy(seed), steps, output.as_deref())
+            }
+            Some(Command::Bench { nodes, edges_per_node, seed }) => run_bench(&config, logger, nodes, edges_per_node, seed),
+            Some(Command::Percolation { steps, trials, seed, ref output }) => {
+                run_percolation(&config, logger, steps, trials, seed, output.as_deref())
+            }
+            Some(Command::Timeline { window_days }) => run_timeline(&config, logger, window_days),
+            Some(Command::Exposure { ref source, since, max_delay_hours, ref target }) => {
+                run_exposure(&config, logger, source, since, max_delay_hours.map(chrono::Duration::hours), target.as_deref())
+            }
+            None => run(&config, logger),
+        }
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Asserts that two graphs are equal (same nodes, edges and weights) and
+/// otherwise fails with the nodes and edges that differ, from left to right.
+#[cfg(test)]
+macro_rules! assert_graph_eq {
+    ($left:expr, $right:expr $(,)?) => {{
+        let (left, right): (&Graph, &Graph) = (&$left, &$right);
+        if left != right {
+            panic!("graphs differ (left -> right):\n{}", left.diff(right).summary());
+        }
+    }};
+}
+
+/// Panics with every broken invariant of the graph, in debug builds only.
+#[cfg(test)]
+macro_rules! debug_assert_valid {
+    ($graph:expr) => {
+        if cfg!(debug_assertions) {
+            if let Err(violations) = $graph.validate() {
+                let lines: Vec<String> = violations.iter().map(|violation| violation.to_string()).collect();
+                panic!("invalid graph:\n{}", lines.join("\n"));
+            }
+        }
+    };
+}
+
+#[test]
+fn test_out_degree_calculation() {
+    // Create sample parsed emails
+    let emails = vec![
+        ParsedEmail {
+            from: "alice@example.com".to_string(),
+            to: vec![
+                "bob@example.com".to_string(),
+                "carol@example.com".to_string(),
+            ],
+            timestamp: None,
+        },
+        ParsedEmail {
+            from: "bob@example.com".to_string(),
+            to: vec!["dave@example.com".to_string()],
+            timestamp: None,
+        },
+        ParsedEmail {
+            from: "carol@example.com".to_string(),
+            to: vec![
+                "dave@example.com".to_string(),
+                "eve@example.com".to_string(),
+                "frank@example.com".to_string(),
+            ],
+            timestamp: None,
+        },
+        ParsedEmail {
+            from: "alice@example.com".to_string(),
+            to: vec!["dave@example.com".to_string()],
+            timestamp: None,
+        },
+    ];
+
+    // Build the graph: one edge per sender and recipient
+    let graph = Graph::build_from_emails(emails);
+    assert_graph_eq!(graph, Graph::from_edges(&SAMPLE_EDGES));
+
+    // Calculate out-degrees
+    let out_degrees = graph.calculate_out_degrees();
+    assert_eq!(
+        out_degrees,
+        degree_map(&[
+            ("alice@example.com", 3), // bob, carol and dave
+            ("bob@example.com", 1),   // dave
+            ("carol@example.com", 3), // dave, eve, frank
+            ("dave@example.com", 0),
+            ("eve@example.com", 0),
+            ("frank@example.com", 0),
+        ])
+    );
+}
+
+/// The edges of the sample emails in the degree tests.
+#[cfg(test)]
+const SAMPLE_EDGES: [(&str, &str); 7] = [
+    ("alice@example.com", "bob@example.com"),
+    ("alice@example.com", "carol@example.com"),
+    ("bob@example.com", "dave@example.com"),
+    ("carol@example.com", "dave@example.com"),
+    ("carol@example.com", "eve@example.com"),
+    ("carol@example.com", "frank@example.com"),
+    ("alice@example.com", "dave@example.com"),
+];
+
+/// Collects `(node, degree)` pairs into a degree map.
+#[cfg(test)]
+fn degree_map(degrees: &[(&str, usize)]) -> HashMap<String, usize> {
+    degrees.iter().map(|&(node, degree)| (node.to_string(), degree)).collect()
+}
+
+#[test]
+fn test_in_degree_calculation() {
+    let graph = Graph::from_edges(&SAMPLE_EDGES);
+
+    // Calculate in-degrees
+    let in_degrees = graph.calculate_in_degrees();
+    assert_eq!(
+        in_degrees,
+        degree_map(&[
+            ("alice@example.com", 0),
+            ("bob@example.com", 1),   // From alice
+            ("carol@example.com", 1), // From alice
+            ("dave@example.com", 3),  // From bob, carol, alice
+            ("eve@example.com", 1),   // From carol
+            ("frank@example.com", 1), // From carol
+        ])
+    );
+}
+
+#[test]
+fn test_self_loops_and_multiple_edges() {
+    // Create sample parsed emails
+    let emails = vec![
+        ParsedEmail {
+            from: "alice@example.com".to_string(),
+            to: vec!["bob@example.com".to_string()],
+            timestamp: None,
+        },
+        ParsedEmail {
+            from: "alice@example.com".to_string(),
+            to: vec!["bob@example.com".to_string()], // Duplicate recipient
+            timestamp: None,
+        },
+        ParsedEmail {
+            from: "bob@example.com".to_string(),
+            to: vec!["alice@example.com".to_string()], // Creates a cycle
+            timestamp: None,
+        },
+        ParsedEmail {
+            from: "carol@example.com".to_string(),
+            to: vec!["carol@example.com".to_string()], // Self-loop
+            timestamp: None,
+        },
+    ];
+
+    // Build the graph; the duplicate only adds to the weight of alice -> bob
+    let graph = Graph::build_from_emails(emails);
+    assert_graph_eq!(
+        graph,
+        Graph::from_edges(&[
+            ("alice@example.com", "bob@example.com"),
+            ("alice@example.com", "bob@example.com"),
+            ("bob@example.com", "alice@example.com"),
+            ("carol@example.com", "carol@example.com"),
+        ])
+    );
+
+    // Every node sends to and receives from exactly one distinct address
+    let expected = degree_map(&[("alice@example.com", 1), ("bob@example.com", 1), ("carol@example.com", 1)]);
+    assert_eq!(graph.calculate_out_degrees(), expected);
+    assert_eq!(graph.calculate_in_degrees(), expected);
+}
+
+#[test]
+fn test_label_propagation_small_graph() {
+    // Community 1: A, B, C; community 2: D, E, F
+    let graph = Graph::from_edges(&[("A", "B"), ("A", "C"), ("B", "C"), ("D", "E"), ("D", "F"), ("E", "F")]);
+
+    // Perform Label Propagation
+    let labels = graph.label_propagation();
+
+    // Organize nodes by communities
+    let mut community_map: HashMap<String, Vec<String>> = HashMap::new();
+    for (node, label) in labels {
+        community_map.entry(label).or_default().push(node);
+    }
+
+    // Expect two communities
+    assert_eq!(community_map.len(), 2, "There should be 2 communities");
+
+    // Check sizes
+    for members in community_map.values() {
+        assert_eq!(members.len(), 3, "Each community should have 3 members");
+    }
+}
+
+#[test]
+#[should_panic(expected = "graphs differ (left -> right):\n- node c\n- edge b -> c\n+ edge b -> a\n~ edge a -> b: weight 2 -> 1")]
+fn test_assert_graph_eq_reports_the_difference() {
+    assert_graph_eq!(Graph::from_edges(&[("a", "b"), ("a", "b"), ("b", "c")]), Graph::from_edges(&[("a", "b"), ("b", "a")]));
+}
+
+#[test]
+fn test_read_csv_reports_row_errors() {
+    let outcome = read_csv("tests/fixtures/broken_emails.csv").expect("fixture should open");
+
+    // Only the first data row is valid
+    assert_eq!(outcome.emails.len(), 1);
+    assert_eq!(outcome.emails[0].from, "alice@example.com");
+    assert_eq!(outcome.emails[0].to, vec!["bob@example.com".to_string()]);
+
+    // Each broken row produces its own error variant, tagged with its line number
+    assert_eq!(outcome.failed_count(), 3);
+    assert!(matches!(outcome.row_errors[0], EmailError::CsvDeserialize { row: 3, .. }));
+    assert!(matches!(outcome.row_errors[1], EmailError::MissingSender { row: 4 }));
+    assert!(matches!(outcome.row_errors[2], EmailError::EmptyRecipients { row: 5 }));
+}
+
+#[test]
+fn test_senders_without_recipients_can_be_kept_as_isolated_nodes() {
+    let path = "tests/fixtures/broken_emails.csv";
+    let filter = filter::EmailFilter { keep_senders_without_recipients: true, ..Default::default() };
+    let outcome = email::read_csv_with(path, &email::CsvSchema::default(), &filter, &Logger::silent()).unwrap();
+
+    // carol's recipient-less row is kept apart from the real failures
+    assert_eq!(outcome.senders_without_recipients, ["carol@example.com"]);
+    assert_eq!(outcome.failed_count(), 2);
+    assert_eq!(outcome.row_count(), 4);
+
+    let graph = build_graph(
+        &Config::default(),
+        outcome.emails,
+        outcome.senders_without_recipients,
+        &outcome.display_names,
+        &Logger::silent(),
+    )
+    .unwrap();
+    debug_assert_valid!(graph);
+    assert_eq!(graph.node_count(), 3);
+    assert_eq!(graph.edge_count(), 1);
+    assert_eq!(graph.calculate_out_degrees()["carol@example.com"], 0);
+    assert_eq!(graph.calculate_in_degrees()["carol@example.com"], 0);
+
+    // Adding a node that exists leaves it alone
+    let mut graph = graph;
+    graph.add_node("alice@example.com".to_string());
+    assert_eq!((graph.node_count(), graph.edge_count()), (3, 1));
+
+    // The kept senders still have to pass the date range and text patterns
+    let after = filter::EmailFilter { after: email::parse_date("2001-06-01"), ..filter.clone() };
+    let outcome = email::read_csv_with(path, &email::CsvSchema::default(), &after, &Logger::silent()).unwrap();
+    assert!(outcome.senders_without_recipients.is_empty());
+    assert_eq!(outcome.outside_date_range, 2);
+    let pattern = filter::EmailFilter { body_regex: Some("first".parse().unwrap()), ..filter };
+    let outcome = email::read_csv_with(path, &email::CsvSchema::default(), &pattern, &Logger::silent()).unwrap();
+    assert!(outcome.senders_without_recipients.is_empty());
+    assert_eq!(outcome.pattern_excluded, 1);
+    assert_eq!(outcome.emails.len(), 1);
+}
+
+#[test]
+fn test_malformed_address_tokens_are_counted_by_reason() {
+    let path = "tests/fixtures/malformed_addresses.csv";
+    let filter = filter::EmailFilter::default();
+    let outcome = email::read_csv_with(path, &email::CsvSchema::default(), &filter, &Logger::silent()).unwrap();
+
+    // Two '@', empty local part and empty domain; a 312-byte address; pasted headers
+    let expected = email::RejectedAddresses { malformed: 3, too_long: 1, invalid_character: 1 };
+    assert_eq!(outcome.rejected_recipients, expected);
+    assert_eq!(outcome.failed_count(), 0);
+
+    let graph = build_graph(&Config::default(), outcome.emails, Vec::new(), &outcome.display_names, &Logger::silent())
+        .unwrap();
+    debug_assert_valid!(graph);
+    let mut nodes: Vec<&str> = graph.adjacency_list.keys().map(String::as_str).collect();
+    nodes.sort_unstable();
+    assert_eq!(nodes, ["alice@example.com", "boss@example.com", "dave@example.com"]);
+
+    // Raising the limit lets the long address through
+    let filter = filter::EmailFilter { max_address_length: Some(400), ..Default::default() };
+    let outcome = email::read_csv_with(path, &email::CsvSchema::default(), &filter, &Logger::silent()).unwrap();
+    assert_eq!(outcome.rejected_recipients.too_long, 0);
+    assert_eq!(outcome.rejected_recipients.total(), 4);
+
+    assert_eq!(email::validate_address("a@b", 3), Ok(()));
+    assert_eq!(email::validate_address("a@bc", 3), Err(email::AddressRejection::TooLong));
+    assert_eq!(email::validate_address("a\u{7}@b", 254), Err(email::AddressRejection::InvalidCharacter));
+}
+
+#[test]
+fn test_email_statistics_and_dropping_broadcasts() {
+    let email = |from: &str, to: &[&str]| ParsedEmail {
+        from: from.to_string(),
+        to: to.iter().map(|address| address.to_string()).collect(),
+        timestamp: None,
+    };
+    let emails = vec![
+        email("a", &["b"]),
+        email("b", &["a", "c"]),
+        email("boss", &["a", "b", "c", "d", "e"]),
+        email("boss", &["a", "b", "c", "d", "f"]),
+        email("hr", &["a", "b", "c", "d"]),
+        email("c", &["d"]),
+    ];
+
+    let stats = email::email_statistics(&emails, 3);
+    assert_eq!(stats.emails, 6);
+    assert!((stats.mean_recipients - 18.0 / 6.0).abs() < 1e-12);
+    assert_eq!(stats.median_recipients, 3.0); // Counts 1, 1, 2, 4, 5, 5
+    assert_eq!(stats.max_recipients, 5);
+    assert_eq!(stats.broadcasts, 3);
+    assert_eq!(stats.top_broadcast_senders, [("boss".to_string(), 2), ("hr".to_string(), 1)]);
+    assert_eq!(email::email_statistics(&emails, 4).broadcasts, 2); // Exactly at the threshold is not a broadcast
+    assert_eq!(email::email_statistics(&[], 3).median_recipients, 0.0);
+
+    let build = |drop_broadcasts| {
+        let mut config = Config::default();
+        config.graph.drop_broadcasts = drop_broadcasts;
+        build_graph(&config, emails.clone(), Vec::new(), &HashMap::new(), &Logger::silent()).unwrap()
+    };
+    let full = build(None);
+    assert_eq!(full.edge_count(), 14); // boss reaches six people across both blasts
+    let trimmed = build(Some(4));
+    assert_eq!(trimmed.edge_count(), 8); // boss's two blasts are gone, hr's stays
+    assert!(!trimmed.adjacency_list.contains_key("boss"));
+    assert!(!trimmed.adjacency_list.contains_key("f"));
+    assert_eq!(build(Some(3)).edge_count(), 4);
+
+    let mut config = Config::default();
+    assert_eq!(config.graph.broadcast_threshold(), email::DEFAULT_BROADCAST_THRESHOLD);
+    config.graph.broadcast_threshold = Some(10);
+    config.graph.drop_broadcasts = Some(4);
+    assert_eq!(config.graph.broadcast_threshold(), 4);
+}
+
+#[test]
+fn test_read_csv_detailed_lists_skipped_rows() {
+    let report = email::read_csv_detailed("tests/fixtures/skipped_rows.csv").expect("fixture should open");
+
+    // Rows are numbered by line, so the first data row is row 2
+    assert_eq!(report.total_rows, 5);
+    assert_eq!(report.emails.len(), 3);
+    assert_eq!(
+        report.skipped,
+        vec![
+            email::SkippedRecord { row: 3, reason: SkipReason::MissingSender },
+            email::SkippedRecord { row: 5, reason: SkipReason::NoRecipients },
+        ]
+    );
+    assert_eq!(describe_skipped(&report.skipped), " (1 without sender, 1 without recipients)");
+    assert_eq!(describe_skipped(&[]), "");
+
+    let report = email::read_csv_detailed("tests/fixtures/broken_emails.csv").unwrap();
+    assert_eq!(report.skipped[0], email::SkippedRecord { row: 3, reason: SkipReason::DeserializeError });
+
+    // Rows dropped by a filter still count towards the total
+    let after = filter::EmailFilter { after: email::parse_date("2001-07-01"), ..Default::default() };
+    let report = email::ParseReport::from(email::read_csv_filtered("tests/fixtures/dated_emails.csv", &after).unwrap());
+    assert_eq!(report.total_rows, 4);
+    assert!(report.emails.len() < 4);
+    assert!(report.skipped.is_empty());
+
+    // The binary's summary is printed from the same report
+    let mut outcome = read_csv("tests/fixtures/skipped_rows.csv").unwrap();
+    let report = outcome.take_report();
+    assert!(outcome.emails.is_empty());
+    assert_eq!((report.emails.len(), outcome.failed_count()), (3, 2));
+    let logger = Logger::capturing(Verbosity::Normal);
+    report_parse_outcome(&report, &outcome, &logger);
+    let messages = logger.messages();
+    assert!(messages.contains(&"Read 5 rows; the parsed emails have 3 recipients in total.".to_string()));
+    assert!(messages.contains(&"Failed to parse 2 records (1 without sender, 1 without recipients); use -v to list them.".to_string()));
+}
+
+#[test]
+fn test_read_csv_missing_file() {
+    let result = read_csv("tests/fixtures/does_not_exist.csv");
+    assert!(matches!(result, Err(EmailError::Io { .. })));
+}
+
+#[test]
+fn test_config_deserializes_sample_file() {
+    let (config, unknown_keys) = Config::load(std::path::Path::new("tests/fixtures/analysis.toml"))
+        .expect("sample config should parse");
+
+    assert!(unknown_keys.is_empty());
+    assert_eq!(config.input.path.as_deref(), Some("exports/q3.csv"));
+    assert_eq!(config.input.schema.sender_column, "From");
+    assert_eq!(config.input.schema.recipient_columns, vec!["To".to_string()]);
+    assert_eq!(config.input.schema.date_column, "date"); // Unset columns keep their defaults
+    assert_eq!(config.graph.min_degree, 2);
+    assert!(config.graph.drop_self_loops);
+    assert_eq!(config.algorithms.lpa_seed, Some(42));
+    assert_eq!(config.algorithms.max_iterations, 100);
+    assert!(config.algorithms.enable_pagerank);
+    assert_eq!(config.algorithms.pagerank_variant, PagerankVariant::Both);
+    assert!(!config.algorithms.enable_betweenness);
+    assert_eq!(config.output.top_n, 5);
+    assert_eq!(config.output.node_csv, Some(std::path::PathBuf::from("nodes.csv")));
+    assert_eq!(config.output.graphml, None);
+}
+
+#[test]
+fn test_config_reports_unknown_keys() {
+    let text = "[graph]\nmin_degre = 3\n\n[extras]\nfoo = 1\n";
+    let (config, unknown_keys) = Config::from_toml_str(text).unwrap();
+
+    assert_eq!(config, Config::default());
+    assert_eq!(unknown_keys, vec!["graph.min_degre".to_string(), "extras".to_string()]);
+
+    // The warning lists the valid keys of the section the typo was in
+    let warning = unknown_key_warning("graph.min_degre");
+    assert!(warning.contains("graph.min_degree"));
+    assert!(warning.contains("graph.drop_self_loops"));
+    assert!(!warning.contains("output.top_n"));
+}
+
+#[test]
+fn test_cli_flags_override_config() {
+    let cli = Cli::try_parse_from([
+        "email_analysis",
+        "--config", "tests/fixtures/analysis.toml",
+        "--top-n", "20",
+        "--input", "other.csv",
+        "--betweenness",
+        "--pagerank-variant", "weighted",
+    ])
+    .unwrap();
+    let config = resolve_config(&cli, &Logger::silent()).unwrap();
+
+    // Values given on the command line win
+    assert_eq!(config.output.top_n, 20);
+    assert_eq!(config.input.path.as_deref(), Some("other.csv"));
+    assert!(config.algorithms.enable_betweenness);
+    assert_eq!(config.algorithms.pagerank_variant, PagerankVariant::Weighted);
+
+    // Values only present in the config file are kept
+    assert_eq!(config.graph.min_degree, 2);
+    assert_eq!(config.algorithms.lpa_seed, Some(42));
+    assert!(config.algorithms.enable_pagerank);
+}
+
+#[test]
+fn test_repl_dispatch_commands() {
+    let mut graph = Graph::new();
+    graph.add_edge("alice@example.com".to_string(), "bob@example.com".to_string());
+    graph.add_edge("alice@example.com".to_string(), "carol@example.com".to_string());
+    graph.add_edge("bob@example.com".to_string(), "dave@example.com".to_string());
+    graph.add_edge("carol@example.com".to_string(), "dave@example.com".to_string());
+    let labels: HashMap<String, String> = graph.adjacency_list
+        .keys()
+        .map(|node| (node.clone(), "team".to_string()))
+        .collect();
+    let session = repl::Session::new(graph, labels);
+    let text = |line: &str| match session.dispatch(line) {
+        repl::Reply::Text(text) => text,
+        repl::Reply::Quit => panic!("unexpected quit for '{}'", line),
+    };
+
+    assert_eq!(
+        text("neighbors alice@example.com"),
+        "bob@example.com\ncarol@example.com\n(2 addresses)"
+    );
+    assert_eq!(
+        text("in dave@example.com"),
+        "bob@example.com\ncarol@example.com\n(2 addresses)"
+    );
+    assert_eq!(
+        text("degree Dave@Example.com"),
+        "address           out_degree  in_degree\ndave@example.com  0           2"
+    );
+    assert_eq!(
+        text("community bob@example.com"),
+        "address          community  size\nbob@example.com  team       4"
+    );
+    assert_eq!(
+        text("path alice@example.com dave@example.com"),
+        "2 hop(s): alice@example.com -> bob@example.com -> dave@example.com"
+    );
+    assert_eq!(
+        text("path dave@example.com alice@example.com"),
+        "no path from dave@example.com to alice@example.com"
+    );
+    assert_eq!(
+        text("top senders 1"),
+        "rank  address            out_degree\n1     alice@example.com  2"
+    );
+    assert!(text("bogus").starts_with("unrecognized command 'bogus'"));
+    assert_eq!(session.dispatch("quit"), repl::Reply::Quit);
+}
+
+#[test]
+fn test_find_nodes_matches_each_mode_most_connected_first() {
+    use graph::{MatchMode, NodeLookup};
+
+    // jeff.skilling has 3 edges, jeff.dasovich 2, jeffrey.shankman 1
+    let graph = Graph::from_edges(&[
+        ("jeff.skilling@enron.com", "a@enron.com"),
+        ("b@enron.com", "jeff.skilling@enron.com"),
+        ("c@enron.com", "jeff.skilling@enron.com"),
+        ("jeff.dasovich@enron.com", "a@enron.com"),
+        ("jeff.dasovich@enron.com", "b@enron.com"),
+        ("jeffrey.shankman@enron.com", "c@enron.com"),
+    ]);
+    let find = |pattern: &str, mode| graph.find_nodes(pattern, mode).unwrap();
+
+    assert_eq!(find("JEFF", MatchMode::Substring), ["jeff.skilling@enron.com", "jeff.dasovich@enron.com", "jeffrey.shankman@enron.com"]);
+    assert_eq!(find("skill", MatchMode::Substring), ["jeff.skilling@enron.com"]);
+    assert_eq!(find("jeff.", MatchMode::Prefix), ["jeff.skilling@enron.com", "jeff.dasovich@enron.com"]);
+    assert!(find("skill", MatchMode::Prefix).is_empty());
+    assert_eq!(find("?@enron.com", MatchMode::Glob), ["a@enron.com", "b@enron.com", "c@enron.com"]); // Tied at 2 edges each
+    assert_eq!(find("jeff*", MatchMode::Glob).len(), 3);
+    assert!(find("jeff", MatchMode::Glob).is_empty()); // A glob must match the whole name
+    assert_eq!(find(r"^jeff\.(s|d)", MatchMode::Regex), ["jeff.skilling@enron.com", "jeff.dasovich@enron.com"]);
+    assert!(graph.find_nodes("(", MatchMode::Regex).is_err());
+    assert_eq!(graph.find_nodes_with_limit("jeff", MatchMode::Substring, 1).unwrap(), ["jeff.skilling@enron.com"]);
+
+    assert_eq!(graph.lookup_node("Jeff.Skilling@Enron.com", MatchMode::Substring, 5), Ok(NodeLookup::Found("jeff.skilling@enron.com".to_string())));
+    assert_eq!(graph.lookup_node("shank", MatchMode::Substring, 5), Ok(NodeLookup::Found("jeffrey.shankman@enron.com".to_string())));
+    assert_eq!(graph.lookup_node("jeff.", MatchMode::Prefix, 5), Ok(NodeLookup::Ambiguous(find("jeff.", MatchMode::Prefix))));
+    assert_eq!(graph.lookup_node("ken.lay", MatchMode::Substring, 5), Ok(NodeLookup::NotFound));
+
+    // The REPL lists the candidates instead of guessing
+    let session = repl::Session::new(graph.clone(), HashMap::new());
+    assert_eq!(
+        session.dispatch("path jeff.d a@enron.com"),
+        repl::Reply::Text("1 hop(s): jeff.dasovich@enron.com -> a@enron.com".to_string())
+    );
+    assert_eq!(
+        session.dispatch("path jeff a@enron.com"),
+        repl::Reply::Text(
+            "'jeff' matches 3 addresses: jeff.skilling@enron.com, jeff.dasovich@enron.com, jeffrey.shankman@enron.com".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_domain_statistics_counts_addresses_and_traffic() {
+    use graph::{DomainStats, INVALID_DOMAIN};
+
+    // Repeated pairs are separate emails
+    let graph = Graph::from_edges(&[
+        ("a@enron.com", "b@enron.com"),
+        ("a@enron.com", "b@enron.com"),
+        ("a@enron.com", "x@aol.com"),
+        ("b@enron.com", "x@aol.com"),
+        ("c@enron.com", "x@aol.com"),
+        ("x@aol.com", "a@enron.com"),
+        ("y@aol.com", "x@aol.com"),
+        ("postmaster", "y@aol.com"),
+    ]);
+    let stats = graph.domain_statistics();
+
+    let expected = [
+        ("enron.com", 3, 5, 3, Some("aol.com"), 4),
+        ("aol.com", 2, 2, 5, Some("enron.com"), 4),
+        (INVALID_DOMAIN, 1, 1, 0, Some("aol.com"), 1),
+    ];
+    let expected: Vec<DomainStats> = expected
+        .into_iter()
+        .map(|(domain, addresses, emails_sent, emails_received, top, top_external_emails)| DomainStats {
+            domain: domain.to_string(),
+            addresses,
+            emails_sent,
+            emails_received,
+            top_external_domain: top.map(str::to_string),
+            top_external_emails,
+        })
+        .collect();
+    assert_eq!(stats, expected);
+
+    // A domain override moves the address to its new domain
+    let mut graph = graph;
+    graph.set_node_attr("postmaster", graph::DOMAIN_ATTR, "enron.com".to_string());
+    let enron = graph.domain_statistics().into_iter().find(|stats| stats.domain == "enron.com").unwrap();
+    assert_eq!((enron.addresses, enron.emails_sent, enron.top_external_emails), (4, 6, 5));
+    assert!(graph.domain_statistics().iter().all(|stats| stats.domain != INVALID_DOMAIN));
+}
+
+#[test]
+fn test_resolve_node_normalizes_and_reports_collisions() {
+    use graph::NodeResolution;
+
+    let mut graph = Graph::from_edges(&[("alice@example.com", "bob@example.com")]);
+    assert_eq!(graph.resolve_node("Alice@Example.COM "), Some(&"alice@example.com".to_string()));
+    assert_eq!(graph.resolve_node(r#""Alice" <ALICE@example.com>"#), Some(&"alice@example.com".to_string()));
+    assert_eq!(graph.resolve_node("alice@other.com"), None);
+    assert_eq!(
+        graph.resolve_node_detailed("alice@other.com", 5),
+        NodeResolution::Unknown { suggestions: vec!["alice@example.com".to_string()] }
+    );
+
+    // Nodes added or removed after a lookup are seen by the next one
+    graph.add_edge("Carol@Example.com".to_string(), "carol@example.com".to_string());
+    assert_eq!(graph.resolve_node("Carol@Example.com"), Some(&"Carol@Example.com".to_string())); // Exact names win
+    assert_eq!(graph.resolve_node("CAROL@example.com"), None);
+    let both = vec!["Carol@Example.com".to_string(), "carol@example.com".to_string()];
+    assert_eq!(graph.resolve_node_detailed("CAROL@example.com", 5), NodeResolution::Ambiguous(both.clone()));
+    assert_eq!(graph.lookup_node("CAROL@example.com", graph::MatchMode::Substring, 5), Ok(graph::NodeLookup::Ambiguous(both)));
+    graph.remove_node("Carol@Example.com");
+    assert_eq!(graph.resolve_node("CAROL@example.com"), Some(&"carol@example.com".to_string()));
+    graph.remove_node("bob@example.com");
+    assert_eq!(graph.resolve_node("Bob@Example.com"), None);
+
+    // Commands say which it was
+    graph.add_edge("Dave@Example.com".to_string(), "dave@example.com".to_string());
+    let message = |address| resolve_address(&graph, address, 5).unwrap_err().to_string();
+    assert_eq!(message("DAVE@example.com"), "address 'DAVE@example.com' could be any of: Dave@Example.com, dave@example.com");
+    assert_eq!(message("alice@corp.com"), "address 'alice@corp.com' is not in the graph; did you mean alice@example.com?");
+    assert_eq!(message("zed@corp.com"), "address 'zed@corp.com' is not in the graph");
+    assert_eq!(graph.node_report("ALICE@example.com", None).unwrap().address, "alice@example.com");
+}
+
+#[test]
+fn test_subcommands_reject_unknown_addresses_with_suggestions() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = Config::default();
+    config.input.path = Some("tests/fixtures/dated_emails.csv".to_string());
+    let logger = Logger::silent();
+    let unknown = |result: Result<(), Error>| match result {
+        Err(Error::UnknownAddress { address, suggestions }) => (address, suggestions),
+        other => panic!("expected an unknown address, got {:?}", other),
+    };
+
+    // Nothing is written for a root or seed that is not in the graph
+    let tree = dir.path().join("tree.dot");
+    let (address, suggestions) = unknown(run_influence_tree(&config, &logger, "alice@corp.com", 2, false, &tree));
+    assert_eq!((address.as_str(), suggestions), ("alice@corp.com", vec!["alice@example.com".to_string()]));
+    assert!(!tree.exists());
+    let sample = dir.path().join("sample.csv");
+    unknown(run_sample(&config, &logger, &["Alice@Example.com".to_string(), "zed@corp.com".to_string()], 1, 10, 0, &sample));
+    assert!(!sample.exists());
+
+    let since = chrono::DateTime::UNIX_EPOCH;
+    let (address, _) = unknown(run_exposure(&config, &logger, "alise@example.com", since, None, None));
+    assert_eq!(address, "alise@example.com");
+    unknown(run_exposure(&config, &logger, "ALICE@example.com", since, None, Some("bobb@corp.com")));
+    assert!(run_exposure(&config, &logger, "ALICE@example.com", since, None, Some("Bob@Example.com")).is_ok());
+
+    // Path pairs report the suggestions in place of the candidates
+    let graph = load_graph(&config, &logger).unwrap();
+    let endpoint = paths::Endpoint::lookup(&graph, "alice@corp.com".to_string(), graph::MatchMode::Substring, 5).unwrap();
+    assert_eq!(endpoint.candidates(), ["alice@example.com".to_string()]);
+}
+
+#[test]
+fn test_repl_suggests_closest_address() {
+    let mut graph = Graph::new();
+    graph.add_edge("alice@example.com".to_string(), "bob@example.com".to_string());
+    let session = repl::Session::new(graph, HashMap::new());
+
+    // Part of a single address resolves to it
+    assert_eq!(session.dispatch("neighbors ali"), repl::Reply::Text("bob@example.com\n(1 addresses)".to_string()));
+    // Edit-distance match for a typo
+    assert_eq!(
+        session.dispatch("in bob@exmaple.com"),
+        repl::Reply::Text("unknown address 'bob@exmaple.com'; did you mean 'bob@example.com'?".to_string())
+    );
+    assert_eq!(repl::levenshtein("kitten", "sitting"), 3);
+}
+
+#[test]
+fn test_read_csv_merges_all_recipient_columns() {
+    let outcome = read_csv("tests/fixtures/multi_recipient.csv").expect("fixture should open");
+
+    // Empty optional columns are not failures
+    assert_eq!(outcome.failed_count(), 0);
+    assert_eq!(outcome.emails.len(), 4);
+
+    // Duplicates across columns collapse into a single recipient
+    assert_eq!(outcome.emails[2].to, vec!["dave@example.com".to_string()]);
+
+    let graph = Graph::build_from_emails(outcome.emails);
+    let out_degrees = graph.calculate_out_degrees();
+    assert_eq!(out_degrees["alice@example.com"], 3); // recipient1..3
+    assert_eq!(out_degrees["bob@example.com"], 3); // recipient1, cc, bcc
+    assert_eq!(out_degrees["carol@example.com"], 1);
+    assert_eq!(out_degrees["dave@example.com"], 1); // recipient3 only
+    assert_eq!(graph.node_count(), 6);
+}
+
+#[test]
+fn test_parse_date_formats() {
+    use chrono::TimeZone;
+    let expected = chrono::Utc.with_ymd_and_hms(2001, 10, 1, 15, 0, 0).unwrap();
+    assert_eq!(email::parse_date("Mon, 1 Oct 2001 08:00:00 -0700 (PDT)"), Some(expected));
+    assert_eq!(email::parse_date("2001-10-01T08:00:00-07:00"), Some(expected));
+    assert_eq!(email::parse_date("2001-10-01 08:00:00-07:00"), Some(expected));
+    assert_eq!(email::parse_date("2001-10-01 15:00:00"), Some(expected));
+    assert_eq!(
+        email::parse_date("2001-10-01"),
+        Some(chrono::Utc.with_ymd_and_hms(2001, 10, 1, 0, 0, 0).unwrap())
+    );
+    assert_eq!(email::parse_date("sometime in summer"), None);
+}
+
+#[test]
+fn test_read_csv_filtered_by_date_range() {
+    let q3 = filter::EmailFilter {
+        after: email::parse_date("2001-07-01"),
+        before: email::parse_date("2001-10-01"),
+        ..Default::default()
+    };
+    let outcome = email::read_csv_filtered("tests/fixtures/dated_emails.csv", &q3).unwrap();
+
+    // Only the August email is inside Q3
+    assert_eq!(outcome.emails.len(), 1);
+    assert_eq!(outcome.outside_date_range, 2);
+    assert_eq!(outcome.undated_excluded, 1);
+    let graph = Graph::build_from_emails(outcome.emails);
+    assert_eq!(graph.node_count(), 2);
+    assert!(graph.adjacency_list["bob@example.com"].contains("carol@example.com"));
+
+    // Undated records can be kept explicitly
+    let with_undated = filter::EmailFilter { include_undated: true, ..q3 };
+    let outcome = email::read_csv_filtered("tests/fixtures/dated_emails.csv", &with_undated).unwrap();
+    assert_eq!(outcome.emails.len(), 2);
+    assert_eq!(outcome.emails[1].from, "dave@example.com");
+    assert_eq!(outcome.emails[1].timestamp, None);
+
+    // Without a date range nothing is dropped
+    let outcome = email::read_csv_filtered("tests/fixtures/dated_emails.csv", &filter::EmailFilter::default()).unwrap();
+    assert_eq!(outcome.emails.len(), 4);
+}
+
+/// Builds one email per directed pair among `people`, all sent at `timestamp`.
+#[cfg(test)]
+fn all_pairs_emails(people: &[&str], timestamp: &str) -> Vec<ParsedEmail> {
+    people
+        .iter()
+        .map(|from| ParsedEmail {
+            from: from.to_string(),
+            to: people.iter().filter(|to| *to != from).map(|to| to.to_string()).collect(),
+            timestamp: email::parse_date(timestamp),
+        })
+        .collect()
+}
+
+#[test]
+fn test_temporal_reachability_respects_email_order() {
+    let email = |from: &str, to: &str, timestamp: &str| ParsedEmail {
+        from: from.to_string(),
+        to: vec![to.to_string()],
+        timestamp: email::parse_date(timestamp),
+    };
+    let emails = vec![
+        email("b", "c", "2001-05-01 09:00:00"), // Before b heard anything
+        email("x", "a", "2001-05-01 10:00:00"), // Before the start
+        email("a", "b", "2001-05-02 09:00:00"),
+        email("b", "d", "2001-05-02 09:00:00"), // Sent at the same moment b heard
+        email("b", "e", "2001-05-04 09:00:00"),
+        email("e", "f", "2001-05-04 12:00:00"),
+    ];
+    let start = email::parse_date("2001-05-02 00:00:00").unwrap();
+    let set = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<HashSet<String>>();
+
+    // a -> b -> c is a path in the static graph, but not in time
+    let graph = Graph::build_from_emails(emails.clone());
+    assert!(graph.adjacency_list["a"].contains("b") && graph.adjacency_list["b"].contains("c"));
+    assert_eq!(temporal::temporal_reachable_set(&emails, "a", start, None), set(&["b", "e", "f"]));
+    assert_eq!(temporal::temporal_path(&emails, "a", "c", start, None), None);
+    assert_eq!(temporal::temporal_path(&emails, "a", "a", start, None), Some(Vec::new()));
+
+    let path = temporal::temporal_path(&emails, "a", "f", start, None).unwrap();
+    let hops: Vec<(&str, &str, String)> = path
+        .iter()
+        .map(|hop| (hop.from.as_str(), hop.to.as_str(), hop.timestamp.format("%m-%d %H:%M").to_string()))
+        .collect();
+    assert_eq!(hops, vec![
+        ("a", "b", "05-02 09:00".to_string()),
+        ("b", "e", "05-04 09:00".to_string()),
+        ("e", "f", "05-04 12:00".to_string()),
+    ]);
+
+    // b waits two days before passing it on, too long for a one-day limit
+    let one_day = Some(chrono::Duration::days(1));
+    assert_eq!(temporal::temporal_reachable_set(&emails, "a", start, one_day), set(&["b"]));
+    assert_eq!(temporal::temporal_path(&emails, "a", "f", start, one_day), None);
+
+    // Learning it after a wrote to b reaches no one
+    let later = email::parse_date("2001-05-03 00:00:00").unwrap();
+    assert!(temporal::temporal_reachable_set(&emails, "a", later, None).is_empty());
+}
+
+#[test]
+fn test_temporal_snapshots_track_community_split() {
+    // One close-knit group for two days, then it splits into two pairs
+    let mut emails = all_pairs_emails(&["a", "b", "c", "d"], "2001-05-01 09:00:00");
+    emails.extend(all_pairs_emails(&["a", "b", "c", "d"], "2001-05-02 09:00:00"));
+    emails.extend(all_pairs_emails(&["a", "b"], "2001-05-03 09:00:00"));
+    emails.extend(all_pairs_emails(&["c", "d"], "2001-05-03 10:00:00"));
+
+    let snapshots = temporal::build_temporal_snapshots(&emails, chrono::Duration::days(1));
+    assert_eq!(snapshots.len(), 3);
+    assert_eq!(snapshots[0].1.node_count(), 4);
+    assert_eq!(snapshots[2].1.node_count(), 4);
+    assert_eq!(*snapshots[1].0.start(), email::parse_date("2001-05-02 09:00:00").unwrap());
+
+    let opts = graph::LpaOptions { seed: Some(7), ..Default::default() };
+    let shifts = temporal::community_stability(&snapshots, &opts);
+    assert_eq!(shifts.len(), 2);
+    assert_eq!(shifts[0].largest_jaccard, 1.0);
+    assert_eq!(shifts[1].communities_after, 2);
+    assert_eq!(shifts[1].largest_jaccard, 0.5);
+}
+
+#[test]
+fn test_temporal_snapshots_keep_empty_windows() {
+    let mut emails = all_pairs_emails(&["a", "b"], "2001-05-01 09:00:00");
+    emails.extend(all_pairs_emails(&["a", "b"], "2001-05-03 09:00:00"));
+
+    let snapshots = temporal::build_temporal_snapshots(&emails, chrono::Duration::days(1));
+    assert_eq!(snapshots.len(), 3);
+    assert_eq!(snapshots[1].1.node_count(), 0);
+    assert!(snapshots[1].1.adjacency_list.is_empty());
+
+    let shifts = temporal::community_stability(&snapshots, &graph::LpaOptions::default());
+    assert_eq!(shifts[0].communities_after, 0);
+    assert_eq!(shifts[0].largest_jaccard, 0.0);
+}
+
+#[test]
+fn test_recency_weighted_edges() {
+    let now = email::parse_date("2001-06-01 00:00:00").unwrap();
+    let half_life = chrono::Duration::days(30);
+    let emails = vec![
+        ParsedEmail {
+            from: "alice@example.com".to_string(),
+            to: vec!["bob@example.com".to_string()],
+            timestamp: Some(now), // Weight 1.0
+        },
+        ParsedEmail {
+            from: "alice@example.com".to_string(),
+            to: vec!["bob@example.com".to_string()],
+            timestamp: Some(now - half_life), // Weight 0.5
+        },
+        ParsedEmail {
+            from: "bob@example.com".to_string(),
+            to: vec!["carol@example.com".to_string()],
+            timestamp: None, // Default undated weight
+        },
+    ];
+
+    let graph = Graph::build_from_emails_weighted_by_recency(emails, now, half_life);
+    let weight = graph.edge_weight("alice@example.com", "bob@example.com").unwrap();
+    assert!((weight - 1.5).abs() < 1e-9, "expected 1.5, got {}", weight);
+    assert_eq!(
+        graph.edge_weight("bob@example.com", "carol@example.com"),
+        Some(graph::RecencyWeights::DEFAULT_UNDATED_WEIGHT)
+    );
+
+    // Weighted degrees sum the decayed weights
+    let out_weights = graph.calculate_weighted_out_degrees();
+    let in_weights = graph.calculate_weighted_in_degrees();
+    assert!((out_weights["alice@example.com"] - 1.5).abs() < 1e-9);
+    assert!((in_weights["bob@example.com"] - 1.5).abs() < 1e-9);
+    assert_eq!(in_weights["alice@example.com"], 0.0);
+
+    // A configurable weight for undated emails
+    let weights = graph::RecencyWeights { undated_weight: 0.25, ..graph::RecencyWeights::new(now, half_life) };
+    assert_eq!(weights.weight(None), 0.25);
+}
+
+#[test]
+fn test_read_csv_with_alternative_schema() {
+    let schema = email::CsvSchema {
+        sender_column: "From".to_string(),
+        recipient_columns: vec!["To".to_string()],
+        date_column: "Date".to_string(),
+        subject_column: "Subject".to_string(),
+        body_column: "Body".to_string(),
+        index_column: None,
+        delimiter: None,
+    };
+    let alternative = email::read_csv_with_schema("tests/fixtures/alt_headers.csv", &schema).unwrap();
+    let default = read_csv("tests/fixtures/dated_emails.csv").unwrap();
+
+    // Both header layouts describe the same emails
+    assert_eq!(alternative.failed_count(), 0);
+    assert_eq!(alternative.emails.len(), 4);
+    assert_eq!(alternative.emails, default.emails);
+}
+
+#[test]
+fn test_read_csv_reports_missing_schema_column() {
+    // The default schema expects a 'sender' column, which this file doesn't have
+    let result = read_csv("tests/fixtures/alt_headers.csv");
+    match result {
+        Err(err @ EmailError::MissingColumn { .. }) => {
+            let message = err.to_string();
+            assert!(message.contains("'sender'"), "unexpected message: {}", message);
+            assert!(message.contains("From, To, Date, Subject, Body"), "unexpected message: {}", message);
+        }
+        other => panic!("expected MissingColumn, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_recipients_mixed_separators() {
+    assert_eq!(
+        email::parse_recipients("a@corp.com; b@corp.com, C@Corp.com ;"),
+        vec!["a@corp.com", "b@corp.com", "c@corp.com"]
+    );
+}
+
+#[test]
+fn test_parse_recipients_quoted_display_names() {
+    let (addresses, dropped) =
+        email::parse_recipients_with_stats(r#""Doe, Jane" <jane@corp.com>; john@corp.com"#);
+    assert_eq!(addresses, vec!["jane@corp.com", "john@corp.com"]);
+    assert_eq!(dropped, 0);
+
+    assert_eq!(
+        email::parse_recipients(r#""Smith; Bob" <Bob.Smith@Corp.com>, Alice <alice@corp.com>"#),
+        vec!["bob.smith@corp.com", "alice@corp.com"]
+    );
+    assert_eq!(email::parse_recipients(r#" "jane@corp.com" "#), vec!["jane@corp.com"]);
+}
+
+#[test]
+fn test_parse_recipients_drops_entries_without_address() {
+    let (addresses, dropped) =
+        email::parse_recipients_with_stats("undisclosed-recipients, bob@corp.com, Jane Doe <>");
+    assert_eq!(addresses, vec!["bob@corp.com"]);
+    assert_eq!(dropped, 2);
+
+    // Empty entries are not counted as dropped
+    assert_eq!(email::parse_recipients_with_stats(" , ;"), (Vec::<String>::new(), 0));
+}
+
+#[test]
+fn test_sender_and_recipient_addresses_share_normalization() {
+    assert_eq!(email::normalize_address("  Alice@Example.COM "), "alice@example.com");
+    assert_eq!(email::normalize_address(r#""Smith, Alice" <Alice@Example.com>"#), "alice@example.com");
+
+    let outcome = read_csv("tests/fixtures/mixed_case.csv").unwrap();
+    let graph = Graph::build_from_emails(outcome.emails);
+
+    // All three spellings of alice collapse into a single node
+    assert_eq!(graph.node_count(), 3);
+    let out_degrees = graph.calculate_out_degrees();
+    let in_degrees = graph.calculate_in_degrees();
+    assert_eq!(out_degrees["alice@example.com"], 2); // bob and carol
+    assert_eq!(in_degrees["alice@example.com"], 1); // from bob
+    assert!(!graph.adjacency_list.contains_key("Alice@Example.com"));
+}
+
+#[test]
+fn test_decode_encoded_words() {
+    assert_eq!(maildir::decode_encoded_words("=?utf-8?B?SsO8cmdlbg==?= <j@corp.com>"), "Jürgen <j@corp.com>");
+    assert_eq!(maildir::decode_encoded_words("=?iso-8859-1?Q?Andr=E9_Smith?="), "André Smith");
+    // Whitespace between adjacent encoded words is dropped
+    assert_eq!(maildir::decode_encoded_words("=?utf-8?Q?a?= =?utf-8?Q?b?="), "ab");
+    // Malformed words are left alone
+    assert_eq!(maildir::decode_encoded_words("plain =?bogus"), "plain =?bogus");
+}
+
+#[test]
+fn test_read_maildir_headers() {
+    let dir = tempfile::tempdir().unwrap();
+    let cur = dir.path().join("cur");
+    let new = dir.path().join("new");
+    std::fs::create_dir(&cur).unwrap();
+    std::fs::create_dir(&new).unwrap();
+
+    std::fs::write(
+        cur.join("1"),
+        "From: =?utf-8?B?SsO8cmdlbg==?= <Juergen@Corp.com>\r\n\
+         To: bob@corp.com,\r\n\tCarol <carol@corp.com>\r\n\
+         Cc: bob@corp.com; dave@corp.com\r\n\
+         Date: Mon, 14 May 2001 16:39:00 -0700 (PDT)\r\n\
+         \r\n\
+         To: body@corp.com\r\n",
+    )
+    .unwrap();
+    std::fs::write(new.join("2"), "To: bob@corp.com\n\nNo sender\n").unwrap();
+    std::fs::write(new.join("3"), "From: bob@corp.com\nSubject: hi\n\n").unwrap();
+    // Files outside cur/ and new/ are not messages
+    std::fs::write(dir.path().join("notes.eml"), "From: x@corp.com\nTo: y@corp.com\n\n").unwrap();
+
+    let outcome = maildir::read_maildir(dir.path()).unwrap();
+    assert_eq!(
+        outcome.emails,
+        vec![ParsedEmail {
+            from: "juergen@corp.com".to_string(),
+            to: vec!["bob@corp.com".to_string(), "carol@corp.com".to_string(), "dave@corp.com".to_string()],
+            timestamp: email::parse_date("2001-05-14T23:39:00Z"),
+        }]
+    );
+    assert_eq!(outcome.row_errors.len(), 2);
+    assert!(matches!(outcome.row_errors[0], EmailError::MessageWithoutSender { .. }));
+    assert!(matches!(outcome.row_errors[1], EmailError::MessageWithoutRecipients { .. }));
+}
+
+#[test]
+fn test_read_maildir_eml_tree() {
+    let dir = tempfile::tempdir().unwrap();
+    let nested = dir.path().join("inbox");
+    std::fs::create_dir(&nested).unwrap();
+    std::fs::write(dir.path().join("a.eml"), "From: alice@corp.com\nTo: bob@corp.com\n\n").unwrap();
+    std::fs::write(nested.join("b.EML"), "From: bob@corp.com\nBcc: alice@corp.com\n\n").unwrap();
+    std::fs::write(nested.join("readme.txt"), "From: x@corp.com\nTo: y@corp.com\n\n").unwrap();
+
+    let outcome = maildir::read_maildir(dir.path()).unwrap();
+    let graph = Graph::build_from_emails(outcome.emails);
+    assert_eq!(graph.node_count(), 2);
+    assert!(graph.adjacency_list["alice@corp.com"].contains("bob@corp.com"));
+    assert!(graph.adjacency_list["bob@corp.com"].contains("alice@corp.com"));
+
+    assert!(matches!(
+        maildir::read_maildir(&dir.path().join("missing")),
+        Err(EmailError::Io { .. })
+    ));
+}
+
+#[test]
+fn test_read_csv_gzipped() {
+    use std::io::Write;
+
+    let dir = tempfile::tempdir().unwrap();
+    let csv_text = ",date,sender,recipient1,subject,text\n\
+                    0,2001-05-14,alice@corp.com,\"bob@corp.com, carol@corp.com\",hi,hello\n\
+                    1,2001-05-15,bob@corp.com,alice@corp.com,re: hi,thanks\n";
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(csv_text.as_bytes()).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    // Detected by extension
+    let gz_path = dir.path().join("emails.csv.gz");
+    std::fs::write(&gz_path, &compressed).unwrap();
+    let outcome = read_csv(gz_path.to_str().unwrap()).unwrap();
+    assert_eq!(outcome.emails.len(), 2);
+    assert_eq!(outcome.emails[0].to, vec!["bob@corp.com", "carol@corp.com"]);
+    assert!(outcome.row_errors.is_empty());
+
+    // Detected by magic bytes
+    let plain_name = dir.path().join("emails.csv");
+    std::fs::write(&plain_name, &compressed).unwrap();
+    assert_eq!(read_csv(plain_name.to_str().unwrap()).unwrap().emails.len(), 2);
+
+    // A truncated stream is a decode error, not a panic or a bad row
+    let truncated = dir.path().join("truncated.csv.gz");
+    std::fs::write(&truncated, &compressed[..compressed.len() / 2]).unwrap();
+    assert!(matches!(read_csv(truncated.to_str().unwrap()), Err(EmailError::Decode { .. })));
+}
+
+#[test]
+fn test_read_csv_filtered_by_subject_and_body() {
+    let filter = filter::EmailFilter {
+        subject_regex: Some("invoice".parse().unwrap()),
+        ..filter::EmailFilter::default()
+    };
+    let outcome = email::read_csv_filtered("tests/fixtures/subjects.csv", &filter).unwrap();
+    assert_eq!(outcome.pattern_excluded, 2);
+
+    // Matching is case-insensitive, so both "Invoice" and "INVOICE" rows count
+    let graph = Graph::build_from_emails(outcome.emails);
+    let mut edges: Vec<(&String, &String)> = graph.adjacency_list.iter()
+        .flat_map(|(from, tos)| tos.iter().map(move |to| (from, to)))
+        .collect();
+    edges.sort();
+    assert_eq!(edges, vec![
+        (&"alice@example.com".to_string(), &"bob@example.com".to_string()),
+        (&"carol@example.com".to_string(), &"dave@example.com".to_string()),
+    ]);
+
+    // Both patterns must match
+    let filter = filter::EmailFilter {
+        subject_regex: Some("invoice".parse().unwrap()),
+        body_regex: Some("^Forwarding".parse().unwrap()),
+        ..filter::EmailFilter::default()
+    };
+    let outcome = email::read_csv_filtered("tests/fixtures/subjects.csv", &filter).unwrap();
+    assert_eq!(outcome.emails.len(), 1);
+    assert_eq!(outcome.emails[0].from, "carol@example.com");
+}
+
+#[test]
+fn test_invalid_text_pattern_is_rejected() {
+    let err = Cli::try_parse_from(["email_analysis", "--subject-regex", "(invoice"]).unwrap_err();
+    assert!(err.to_string().contains("--subject-regex"));
+
+    assert!(Config::from_toml_str("[filter]\nbody_regex = \"[unclosed\"\n").is_err());
+    let (config, _) = Config::from_toml_str("[filter]\nsubject_regex = \"(?-i)Invoice\"\n").unwrap();
+    let pattern = config.filter.subject_regex.unwrap();
+    assert!(pattern.is_match("Invoice #1"));
+    assert!(!pattern.is_match("INVOICE #1"));
+}
+
+#[test]
+fn test_address_pattern_globs() {
+    let exact = filter::AddressPattern::new("Calendar@Example.com");
+    assert!(exact.matches("calendar@example.com"));
+    assert!(!exact.matches("calendar@example.com.au"));
+
+    let prefix = filter::AddressPattern::new("noreply@*");
+    assert!(prefix.matches("noreply@example.com"));
+    assert!(!prefix.matches("reply@example.com"));
+
+    let suffix = filter::AddressPattern::new("*@lists.example.com");
+    assert!(suffix.matches("digest@lists.example.com"));
+    assert!(!suffix.matches("digest@example.com"));
+
+    let middle = filter::AddressPattern::new("no*@*.example.com");
+    assert!(middle.matches("notify@mail.example.com"));
+    assert!(!middle.matches("notify@example.com"));
+
+    let single = filter::AddressPattern::new("user?@example.com");
+    assert!(single.matches("user1@example.com"));
+    assert!(!single.matches("user12@example.com"));
+
+    // The shared matcher backtracks when an early guess for `*` fails
+    assert!(filter::glob_match("*ab*c", "aabxabyc"));
+    assert!(filter::glob_match("a**", "a"));
+    assert!(!filter::glob_match("*a?", "ba"));
+    assert!(filter::glob_match("", ""));
+}
+
+#[test]
+fn test_blocked_addresses_never_reach_the_graph() {
+    let cli = Cli::try_parse_from(["email_analysis", "--blocklist", "tests/fixtures/blocklist.txt"]).unwrap();
+    let config = resolve_config(&cli, &Logger::silent()).unwrap();
+    assert_eq!(config.filter.blocklist.len(), 4);
+
+    let outcome = email::read_csv_filtered("tests/fixtures/automated.csv", &config.filter).unwrap();
+    assert_eq!(outcome.address_excluded, 3); // noreply and mailer-daemon senders, calendar-only row
+    assert_eq!(outcome.blocked_recipients, 3);
+
+    let graph = Graph::build_from_emails(outcome.emails);
+    let mut nodes: Vec<&String> = graph.adjacency_list.keys().collect();
+    nodes.sort();
+    assert_eq!(nodes, vec!["alice@example.com", "bob@example.com"]);
+
+    // The allowlist keeps only matching addresses
+    let allow_people = filter::EmailFilter {
+        allowlist: vec![filter::AddressPattern::new("alice@*"), filter::AddressPattern::new("bob@*")],
+        blocklist: vec![filter::AddressPattern::new("bob@example.com")],
+        ..filter::EmailFilter::default()
+    };
+    let outcome = email::read_csv_filtered("tests/fixtures/automated.csv", &allow_people).unwrap();
+    assert_eq!(outcome.emails.len(), 0); // alice only ever writes to bob, who is blocked
+
+    let missing = Cli::try_parse_from(["email_analysis", "--allowlist", "tests/fixtures/missing.txt"]).unwrap();
+    assert!(matches!(resolve_config(&missing, &Logger::silent()), Err(Error::AddressListRead { .. })));
+}
+
+#[test]
+fn test_read_delimited_variants_build_identical_graphs() {
+    let build = |path: &str, delimiter: Option<email::Delimiter>| {
+        let schema = email::CsvSchema { delimiter, ..email::CsvSchema::default() };
+        let outcome = email::read_csv_with_schema(path, &schema).unwrap();
+        assert!(outcome.row_errors.is_empty(), "{}: {:?}", path, outcome.row_errors);
+        Graph::build_from_emails(outcome.emails)
+    };
+
+    let reference = build("tests/fixtures/delimited.csv", Some(email::Delimiter::Comma));
+    assert_eq!(reference.node_count(), 4);
+    assert!(reference.adjacency_list["carol@example.com"].contains("alice@example.com"));
+
+    // Explicit and sniffed delimiters, including recipients split on the record delimiter's rivals
+    for (path, delimiter) in [
+        ("tests/fixtures/delimited.csv", None),
+        ("tests/fixtures/delimited.tsv", Some(email::Delimiter::Tab)),
+        ("tests/fixtures/delimited.tsv", None),
+        ("tests/fixtures/delimited_semicolon.csv", Some(email::Delimiter::Semicolon)),
+        ("tests/fixtures/delimited_semicolon.csv", None),
+    ] {
+        let graph = build(path, delimiter);
+        assert_eq!(graph.adjacency_list, reference.adjacency_list, "{} with {:?}", path, delimiter);
+        assert_eq!(graph.edge_weights, reference.edge_weights, "{} with {:?}", path, delimiter);
+    }
+}
+
+#[test]
+fn test_delimiter_parsing_and_sniffing() {
+    assert_eq!("\\t".parse(), Ok(email::Delimiter::Tab));
+    assert_eq!("\t".parse(), Ok(email::Delimiter::Tab));
+    assert_eq!("|".parse(), Ok(email::Delimiter::Pipe));
+    assert!(":".parse::<email::Delimiter>().is_err());
+
+    assert_eq!(email::Delimiter::sniff(b"a|b|c\n1,2|3|4"), email::Delimiter::Pipe);
+    assert_eq!(email::Delimiter::sniff(b"\"a;b\",c,d"), email::Delimiter::Comma); // Quoted separators don't count
+    assert_eq!(email::Delimiter::sniff(b"single"), email::Delimiter::Comma);
+
+    let cli = Cli::try_parse_from(["email_analysis", "--delimiter", "\\t"]).unwrap();
+    assert_eq!(resolve_config(&cli, &Logger::silent()).unwrap().input.schema.delimiter, Some(email::Delimiter::Tab));
+    assert!(Cli::try_parse_from(["email_analysis", "--delimiter", "::"]).is_err());
+}
+
+#[test]
+fn test_index_column_is_optional() {
+    let with_index = read_csv("tests/fixtures/with_index.csv").unwrap();
+    let without_index = read_csv("tests/fixtures/without_index.csv").unwrap();
+    assert_eq!(with_index.emails.len(), 2);
+    assert_eq!(with_index.emails, without_index.emails);
+
+    // Skipped rows are reported by line number, which doesn't depend on the index
+    let rows = |outcome: &ParseOutcome| outcome.row_errors.iter().map(EmailError::row).collect::<Vec<_>>();
+    assert_eq!(rows(&with_index), vec![Some(3)]);
+    assert_eq!(rows(&without_index), vec![Some(3)]);
+}
+
+#[test]
+fn test_address_mapper_is_stable() {
+    let mut mapper = AddressMapper::new(false);
+    assert_eq!(mapper.pseudonym("alice@example.com"), "user_0001");
+    assert_eq!(mapper.pseudonym("bob@corp.com"), "user_0002");
+    assert_eq!(mapper.pseudonym("alice@example.com"), "user_0001");
+    assert_eq!(mapper.original("user_0002"), Some("bob@corp.com"));
+
+    let mut with_domains = AddressMapper::new(true);
+    assert_eq!(with_domains.pseudonym("alice@example.com"), "user_0001@example.com");
+
+    // A saved mapping keeps old pseudonyms and numbers new addresses after them
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("address_map.csv");
+    mapper.save_csv(&path).unwrap();
+    let mut loaded = AddressMapper::load_csv(&path, false).unwrap();
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(loaded.pseudonym("bob@corp.com"), "user_0002");
+    assert_eq!(loaded.pseudonym("carol@corp.com"), "user_0003");
+}
+
+#[test]
+fn test_anonymized_graph_keeps_structure() {
+    let graph = Graph::build_from_emails(read_csv("tests/fixtures/delimited.csv").unwrap().emails);
+    let mut mapper = AddressMapper::new(true);
+    let anonymized = graph.anonymize(&mut mapper);
+
+    assert_eq!(anonymized.node_count(), graph.node_count());
+    assert!(anonymized.adjacency_list.keys().all(|node| node.starts_with("user_")));
+
+    // Every edge and degree carries over under the mapping
+    let out_degrees = graph.calculate_out_degrees();
+    let in_degrees = graph.calculate_in_degrees();
+    let anonymized_out = anonymized.calculate_out_degrees();
+    let anonymized_in = anonymized.calculate_in_degrees();
+    for (node, neighbors) in &graph.adjacency_list {
+        let pseudonym = mapper.pseudonym(node);
+        assert_eq!(anonymized_out[&pseudonym], out_degrees[node]);
+        assert_eq!(anonymized_in[&pseudonym], in_degrees[node]);
+        for neighbor in neighbors {
+            let to = mapper.pseudonym(neighbor);
+            assert_eq!(anonymized.edge_weight(&pseudonym, &to), graph.edge_weight(node, neighbor));
+        }
+    }
+    let path = graph.shortest_path("bob@example.com", "dave@example.com").unwrap();
+    let anonymized_path: Vec<String> = path.iter().map(|node| mapper.pseudonym(node)).collect();
+    assert_eq!(anonymized.shortest_path(&anonymized_path[0], &anonymized_path[path.len() - 1]), Some(anonymized_path));
+}
+
+#[test]
+fn test_node_and_edge_counts_stay_consistent() {
+    let check = |graph: &Graph| {
+        assert_eq!(graph.node_count(), graph.adjacency_list.len());
+        let edges: usize = graph.adjacency_list.values().map(HashSet::len).sum();
+        assert_eq!(graph.edge_count(), edges);
+    };
+
+    let mut graph = Graph::build_from_emails(read_csv("tests/fixtures/delimited.csv").unwrap().emails);
+    check(&graph);
+    assert_eq!((graph.node_count(), graph.edge_count()), (4, 5));
+
+    // A repeated edge adds weight but no new edge; a self-loop is one edge on one node
+    graph.add_edge("alice@example.com".to_string(), "bob@example.com".to_string());
+    check(&graph);
+    assert_eq!(graph.edge_count(), 5);
+    graph.add_edge("erin@example.com".to_string(), "erin@example.com".to_string());
+    check(&graph);
+    assert_eq!((graph.node_count(), graph.edge_count()), (5, 6));
+
+    check(&graph.prune_min_degree(3));
+    check(&graph.anonymize(&mut AddressMapper::new(false)));
+    check(&Graph::new());
+}
+
+#[test]
+fn test_top_lists_break_ties_by_name() {
+    let degrees: HashMap<String, usize> = [("dave", 3), ("carol", 2), ("erin", 2), ("alice", 2), ("bob", 5), ("frank", 1)]
+        .into_iter()
+        .map(|(name, degree)| (name.to_string(), degree))
+        .collect();
+
+    let expected: Vec<(String, usize)> = vec![("bob".to_string(), 5), ("dave".to_string(), 3), ("alice".to_string(), 2)];
+    for _ in 0..20 {
+        // Rebuild the map so its iteration order changes between rounds
+        let shuffled: HashMap<String, usize> = degrees.clone().into_iter().collect();
+        assert_eq!(identify_top_senders(&shuffled, 3), expected);
+        assert_eq!(identify_top_recipients(&shuffled, 3), expected);
+    }
+
+    let top = rank_top(&degrees, 3, false);
+    assert_eq!(top.omitted_ties, 2); // carol and erin also have degree 2
+    let with_ties = rank_top(&degrees, 3, true);
+    let names: Vec<&str> = with_ties.entries.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, vec!["bob", "dave", "alice", "carol", "erin"]);
+    assert_eq!(with_ties.omitted_ties, 0);
+
+    assert_eq!(rank_top(&degrees, 0, true), TopList { entries: Vec::new(), omitted_ties: 0 });
+    assert_eq!(rank_top(&degrees, 10, false).entries.len(), 6);
+}
+
+#[test]
+fn test_analysis_handles_empty_and_single_node_graphs() {
+    let empty = Graph::new();
+    assert_eq!(analyze_degree_distribution(&empty, 5), None);
+    assert_eq!(analyze_communities(&HashMap::new(), 0), None);
+    assert!(empty.label_propagation().is_empty());
+    assert!(identify_top_senders(&empty.calculate_out_degrees(), 10).is_empty());
+
+    let mut single = Graph::new();
+    single.add_edge("alice@example.com".to_string(), "alice@example.com".to_string());
+    let stats = analyze_degree_distribution(&single, 5).unwrap();
+    assert_eq!((stats.total_nodes, stats.average_out_degree, stats.average_in_degree), (1, 1.0, 1.0));
+
+    let mut community_map: HashMap<String, Vec<String>> = HashMap::new();
+    for (node, label) in single.label_propagation() {
+        community_map.entry(label).or_default().push(node);
+    }
+    let stats = analyze_communities(&community_map, 0).unwrap();
+    assert_eq!((stats.total_communities, stats.average_size), (1, 1.0));
+    assert!(!stats.average_size.is_nan());
+}
+
+#[test]
+fn test_no_valid_emails_is_an_error() {
+    let cli = Cli::try_parse_from(["email_analysis", "--input", "tests/fixtures/broken_emails.csv", "--subject-regex", "^no such subject$"])
+        .unwrap();
+    let config = resolve_config(&cli, &Logger::silent()).unwrap();
+    let err = load_emails(&config, &Logger::silent()).unwrap_err();
+    assert!(matches!(err, Error::NoValidEmails { .. }));
+    assert!(err.to_string().contains("no valid emails parsed"));
+}
+
+#[test]
+fn test_top_senders_by_volume_differ_from_distinct_contacts() {
+    let mut graph = Graph::new();
+    for _ in 0..100 {
+        graph.add_edge("bulk@example.com".to_string(), "list@example.com".to_string());
+    }
+    for i in 0..5 {
+        graph.add_edge("social@example.com".to_string(), format!("friend{}@example.com", i));
+    }
+
+    let by_contacts = identify_top_senders(&graph.calculate_out_degrees(), 2);
+    assert_eq!(by_contacts, vec![("social@example.com".to_string(), 5), ("bulk@example.com".to_string(), 1)]);
+    let by_volume = identify_top_senders_by_volume(&graph, 2);
+    assert_eq!(by_volume, vec![("bulk@example.com".to_string(), 100), ("social@example.com".to_string(), 5)]);
+
+    assert_eq!(identify_top_recipients_by_volume(&graph, 1), vec![("list@example.com".to_string(), 100)]);
+    assert_eq!(graph.edge_multiplicity("bulk@example.com", "list@example.com"), 100);
+
+    // Recency-weighted graphs still count whole emails
+    let emails = vec![ParsedEmail {
+        from: "a@example.com".to_string(),
+        to: vec!["b@example.com".to_string()],
+        timestamp: None,
+    }; 3];
+    let weights = graph::RecencyWeights { undated_weight: 0.25, ..graph::RecencyWeights::new(chrono::Utc::now(), chrono::Duration::days(30)) };
+    let weighted = Graph::build_from_emails_weighted(emails, &weights);
+    assert_eq!(weighted.edge_weight("a@example.com", "b@example.com"), Some(0.75));
+    assert_eq!(weighted.edge_multiplicity("a@example.com", "b@example.com"), 3);
+    assert_eq!(weighted.prune_min_degree(1).edge_multiplicity("a@example.com", "b@example.com"), 3);
+}
+
+#[test]
+fn test_top_members_per_community_count_internal_edges_only() {
+    let mut graph = Graph::new();
+    let mut labels: HashMap<String, String> = HashMap::new();
+    // Community "a": hub talks to everyone, the others only to the hub
+    for member in ["a1", "a2", "a3", "a4"] {
+        graph.add_edge("hub".to_string(), member.to_string());
+        graph.add_edge(member.to_string(), "hub".to_string());
+        labels.insert(member.to_string(), "a".to_string());
+    }
+    labels.insert("hub".to_string(), "a".to_string());
+    // Community "b": a triangle, plus many edges from the hub that must not count there
+    for (from, to) in [("b1", "b2"), ("b2", "b3"), ("b3", "b1")] {
+        graph.add_edge(from.to_string(), to.to_string());
+        labels.insert(from.to_string(), "b".to_string());
+    }
+    for member in ["b1", "b2", "b3"] {
+        graph.add_edge("hub".to_string(), member.to_string());
+    }
+
+    let leaders = top_members_per_community(&graph, &labels, 3);
+    assert_eq!(leaders["a"][0], ("hub".to_string(), 8));
+    assert_eq!(leaders["a"][1], ("a1".to_string(), 2));
+    assert_eq!(
+        leaders["b"],
+        vec![("b1".to_string(), 2), ("b2".to_string(), 2), ("b3".to_string(), 2)]
+    );
+    assert!(leaders["b"].iter().all(|(member, _)| member != "hub"));
+}
+
+#[test]
+fn test_nodes_by_community_sorted_breaks_ties_deterministically() {
+    let graph = Graph::build_from_emails(read_csv("tests/fixtures/community_ties.csv").unwrap().emails);
+    let labels: HashMap<String, String> = [
+        ("x1", "c"), ("x2", "c"), ("x3", "c"),
+        ("y1", "b"), ("y2", "b"),
+        ("z1", "a"), ("q1", "a"),
+        ("nobody", "d"), // Not in the graph
+    ]
+    .iter()
+    .map(|(node, label)| (format!("{}@example.com", node), label.to_string()))
+    .collect();
+
+    let listing = graph.nodes_by_community_sorted(&labels);
+    let expected: Vec<(&str, Vec<(&str, usize)>)> = vec![
+        ("c", vec![("x1", 4), ("x2", 2), ("x3", 2)]),
+        ("a", vec![("z1", 2), ("q1", 1)]), // Same size as "b", smaller label
+        ("b", vec![("y1", 3), ("y2", 2)]),
+    ];
+    let expected: Vec<(String, Vec<(String, usize)>)> = expected
+        .into_iter()
+        .map(|(label, members)| {
+            let members = members.into_iter().map(|(node, degree)| (format!("{}@example.com", node), degree)).collect();
+            (label.to_string(), members)
+        })
+        .collect();
+    assert_eq!(listing, expected);
+
+    let dir = tempfile::tempdir().unwrap();
+    let text_path = dir.path().join("communities.txt");
+    export::write_community_report(&graph, &labels, &text_path).unwrap();
+    let text = std::fs::read_to_string(&text_path).unwrap();
+    assert!(text.starts_with("Community c (3 members)\n  x1@example.com 4\n  x2@example.com 2\n  x3@example.com 2\n\nCommunity a"));
+
+    let markdown_path = dir.path().join("communities.md");
+    export::write_community_report(&graph, &labels, &markdown_path).unwrap();
+    let markdown = std::fs::read_to_string(&markdown_path).unwrap();
+    assert!(markdown.contains("\n## c (3 members)\n\n| Member | Degree |\n| --- | ---: |\n| x1@example.com | 4 |\n"));
+    assert!(markdown.find("## a (").unwrap() < markdown.find("## b (").unwrap());
+}
+
+#[test]
+fn test_community_size_histogram_buckets() {
+    let sizes = [1, 1, 1, 2, 5, 6, 20, 21, 100, 101, 5000];
+    let histogram = community_size_histogram(&sizes);
+    let expected: Vec<(String, usize)> = [("1", 3), ("2-5", 2), ("6-20", 2), ("21-100", 2), ("101+", 2)]
+        .into_iter()
+        .map(|(bucket, count)| (bucket.to_string(), count))
+        .collect();
+    assert_eq!(histogram, expected);
+    assert!(community_size_histogram(&[]).iter().all(|(_, count)| *count == 0));
+
+    let community_map: HashMap<String, Vec<String>> = [
+        ("x", vec!["a", "b", "c"]),
+        ("y", vec!["d"]),
+        ("w", vec!["e"]),
+    ]
+    .into_iter()
+    .map(|(label, members)| (label.to_string(), members.into_iter().map(str::to_string).collect()))
+    .collect();
+    assert_eq!(community_size_distribution(&community_map), vec![3, 1, 1]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("community_sizes.csv");
+    export::write_community_sizes(&community_map, &path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "label,size\nx,3\nw,1\ny,1\n");
+}
+
+#[test]
+fn test_reachable_set_sizes_on_chain() {
+    let mut graph = Graph::from_edges(&[("A", "B"), ("B", "C"), ("C", "D")]);
+    let sizes: Vec<usize> = ["A", "B", "C", "D"].iter().map(|node| graph.reachable_set_size(node)).collect();
+    assert_eq!(sizes, vec![3, 2, 1, 0]);
+    assert_eq!(graph.reachable_set_size("Z"), 0);
+
+    // A cycle back to the start doesn't count the start itself
+    graph.add_edge("D".to_string(), "A".to_string());
+    assert_eq!(graph.reachable_set_size("C"), 3);
+
+    let all = graph.reachability_distribution(None, 0);
+    assert_eq!(all.len(), 4);
+    let sample = graph.reachability_distribution(Some(2), 7);
+    assert_eq!(sample.len(), 2);
+    assert!(sample.iter().all(|(node, size)| all[node] == *size));
+
+    // The same seed picks the same people
+    for seed in 0..5 {
+        assert_eq!(graph.reachability_distribution(Some(2), seed), graph.reachability_distribution(Some(2), seed));
+    }
+}
+
+#[test]
+fn test_path_report_for_pairs_file() {
+    let graph = Graph::build_from_emails(read_csv("tests/fixtures/delimited.csv").unwrap().emails);
+    let dir = tempfile::tempdir().unwrap();
+    let pairs_path = dir.path().join("pairs.csv");
+    std::fs::write(
+        &pairs_path,
+        "from,to\n\
+         bob@example.com,dave@example.com\n\
+         Dave <DAVE@example.com>,bob@example.com\n\
+         bob@example.com,nobody@example.com\n\
+         bob,car\n\
+         bob,example.com\n",
+    )
+    .unwrap();
+
+    let pairs = paths::read_pairs(&pairs_path).unwrap();
+    assert_eq!(pairs.len(), 5);
+    let pairs: Vec<(paths::Endpoint, paths::Endpoint)> = pairs
+        .into_iter()
+        .map(|(from, to)| {
+            let lookup = |query| paths::Endpoint::lookup(&graph, query, graph::MatchMode::Substring, 10).unwrap();
+            (lookup(from), lookup(to))
+        })
+        .collect();
+    let mut out = Vec::new();
+    paths::write_path_report(&graph, &pairs, &mut out).unwrap();
+
+    let rows: Vec<&str> = std::str::from_utf8(&out).unwrap().lines().collect();
+    assert_eq!(rows[0], "from,to,status,directed_hops,directed_path,undirected_hops,undirected_path,from_candidates,to_candidates");
+    assert_eq!(rows[1..4], [
+        "bob@example.com,dave@example.com,ok,3,bob@example.com->alice@example.com->carol@example.com->dave@example.com,\
+         3,bob@example.com->alice@example.com->carol@example.com->dave@example.com,,",
+        // dave never sends, so only the undirected path exists
+        "dave@example.com,bob@example.com,ok,,,3,dave@example.com->carol@example.com->alice@example.com->bob@example.com,,",
+        "bob@example.com,nobody@example.com,unknown_node,,,,,,",
+    ]);
+    // Partial addresses that match one node each are resolved
+    assert_eq!(rows[4], "bob@example.com,carol@example.com,ok,2,bob@example.com->alice@example.com->carol@example.com,\
+         2,bob@example.com->alice@example.com->carol@example.com,,");
+
+    // A query matching several nodes lists them instead
+    let ambiguous: Vec<&str> = rows[5].split(',').collect();
+    assert_eq!(ambiguous[..3], ["bob@example.com", "example.com", "ambiguous"]);
+    let mut candidates: Vec<&str> = ambiguous[8].split(';').collect();
+    candidates.sort();
+    assert_eq!(candidates, nodes_of(&graph));
+}
+
+#[test]
+fn test_all_shortest_paths_in_diamond() {
+    let graph = Graph::from_edges(&[("A", "B"), ("B", "D"), ("A", "C"), ("C", "D"), ("D", "E")]);
+
+    let paths = graph.all_shortest_paths("A", "D");
+    assert_eq!(paths, vec![vec!["A", "B", "D"], vec!["A", "C", "D"]]);
+    assert_eq!(graph.all_shortest_paths("A", "A"), vec![vec!["A"]]);
+    assert!(graph.all_shortest_paths("D", "A").is_empty());
+    assert!(graph.all_shortest_paths("A", "Z").is_empty());
+
+    // Truncated enumeration still reports the full count
+    let limited = graph.shortest_paths_with_limit("A", "E", 1);
+    assert_eq!(limited.count, 2);
+    assert_eq!(limited.paths.len(), 1);
+    assert!(limited.is_truncated());
+
+    // A chain of 20 diamonds has 2^20 shortest paths
+    let mut chain = Graph::new();
+    for i in 0..20 {
+        for via in ["x", "y"] {
+            chain.add_edge(format!("n{}", i), format!("{}{}", via, i));
+            chain.add_edge(format!("{}{}", via, i), format!("n{}", i + 1));
+        }
+    }
+    let limited = chain.shortest_paths_with_limit("n0", "n20", 5);
+    assert_eq!(limited.count, 1 << 20);
+    assert_eq!(limited.paths.len(), 5);
+    assert!(limited.paths.iter().all(|path| path.len() == 41));
+}
+
+#[test]
+fn test_average_neighbor_degree_on_star() {
+    let mut graph = Graph::new();
+    let leaves = ["l1", "l2", "l3", "l4"];
+    for leaf in leaves {
+        graph.add_edge("center".to_string(), leaf.to_string());
+        graph.add_edge(leaf.to_string(), "center".to_string());
+    }
+    graph.add_edge("isolated".to_string(), "isolated".to_string());
+    graph.add_edge("lonely".to_string(), "l1".to_string());
+
+    // Every leaf has total degree 2 (one edge each way) except l1, which lonely also emails
+    let averages = graph.average_neighbor_degree();
+    assert_eq!(averages["center"], (3.0 + 2.0 * 3.0) / 4.0);
+    for leaf in leaves {
+        assert_eq!(averages[leaf], 8.0); // The center: 4 out + 4 in
+    }
+    assert_eq!(averages["isolated"], 2.0); // A self-loop counts in both directions
+
+    let in_averages = graph.average_in_neighbor_degree();
+    assert_eq!(in_averages["lonely"], 0.0);
+    assert_eq!(in_averages["l1"], (8.0 + 1.0) / 2.0);
+    assert_eq!(graph.in_neighbors("l1"), vec!["center", "lonely"]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nodes.csv");
+    export::write_node_csv(&graph, None, None, &HashSet::new(), &path).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.starts_with("address,out_degree,in_degree,avg_neighbor_degree,harmonic_centrality,community,embeddedness,likely_list,domain\n"));
+    assert!(text.contains("\nl2,1,1,8.0000,,,,false,\n"));
+}
+
+#[test]
+fn test_neo4j_export_writes_nodes_and_relationships() {
+    let graph = Graph::from_edges(&[("b@x.com", "a@x.com"), ("a@x.com", "b@x.com"), ("a@x.com", "b@x.com"), ("c,d@x.com", "c,d@x.com")]);
+    let dir = tempfile::tempdir().unwrap();
+    let (nodes_path, rels_path) = (dir.path().join("nodes.csv"), dir.path().join("rels.csv"));
+
+    export::export_neo4j(&graph, None, &nodes_path, &rels_path).unwrap();
+    let nodes = std::fs::read_to_string(&nodes_path).unwrap();
+    assert_eq!(nodes, "emailAddress:ID,:LABEL\na@x.com,Person\nb@x.com,Person\n\"c,d@x.com\",Person\n");
+    let rels = std::fs::read_to_string(&rels_path).unwrap();
+    let lines: Vec<&str> = rels.lines().collect();
+    assert_eq!(lines[0], ":START_ID,:END_ID,:TYPE,weight:int");
+    assert_eq!(lines.len(), 1 + graph.edge_count());
+    assert_eq!(lines[1], "a@x.com,b@x.com,EMAILED,2");
+    // The self-loop is one relationship
+    assert_eq!(lines[3], "\"c,d@x.com\",\"c,d@x.com\",EMAILED,1");
+
+    let labels: HashMap<String, String> = [("a@x.com", "a"), ("b@x.com", "a")]
+        .iter()
+        .map(|(node, label)| (node.to_string(), label.to_string()))
+        .collect();
+    export::export_neo4j(&graph, Some(&labels), &nodes_path, &rels_path).unwrap();
+    let nodes = std::fs::read_to_string(&nodes_path).unwrap();
+    let lines: Vec<&str> = nodes.lines().collect();
+    assert_eq!(lines[0], "emailAddress:ID,:LABEL,community");
+    assert_eq!(lines.len(), 1 + graph.node_count());
+    assert_eq!(lines[1], "a@x.com,Person,a");
+    assert_eq!(lines[3], "\"c,d@x.com\",Person,");
+}
+
+#[test]
+fn test_percentile_hand_computed_values() {
+    assert_eq!(percentile(&[], 0.5), None);
+    assert_eq!(percentile(&[7], 0.0), Some(7.0));
+    assert_eq!(percentile(&[7], 0.99), Some(7.0));
+
+    // Unsorted input; sorted it is [1, 2, 3, 4, 10, 20]
+    let values = [10, 1, 4, 20, 3, 2];
+    assert_eq!(percentile(&values, 0.0), Some(1.0));
+    assert_eq!(percentile(&values, 1.0), Some(20.0));
+    assert_eq!(percentile(&values, 0.5), Some(3.5)); // Mean of the middle two
+    assert_eq!(percentile(&values, 0.9), Some(15.0)); // Rank 4.5, between 10 and 20
+    assert_eq!(percentile(&values, 0.2), Some(2.0)); // Rank 1.0 exactly
+    assert_eq!(percentile(&values, -1.0), Some(1.0));
+    assert_eq!(percentile(&values, 2.0), Some(20.0));
+
+    // Ties around the selected rank
+    assert_eq!(percentile(&[5, 5, 5, 9], 0.5), Some(5.0));
+
+    let percentiles = DegreePercentiles::of(&(1..=101).collect::<Vec<usize>>()).unwrap();
+    assert_eq!(percentiles, DegreePercentiles { median: 51.0, p90: 91.0, p95: 96.0, p99: 100.0 });
+}
+
+#[test]
+fn test_lpa_merges_small_communities() {
+    let graph = Graph::build_from_emails(read_csv("tests/fixtures/singleton_community.csv").unwrap().emails);
+    let options = graph::LpaOptions { seed: Some(7), ..graph::LpaOptions::default() };
+
+    // zed never sends, so plain directed propagation leaves it on its own
+    let labels = graph.label_propagation_with_options(&options);
+    assert_eq!(labels["zed@example.com"], "zed@example.com");
+    assert_eq!(labels["hermit@example.com"], "hermit@example.com");
+
+    let merged = graph.label_propagation_with_options(&graph::LpaOptions { min_community_size: 2, ..options.clone() });
+    assert_eq!(merged["zed@example.com"], merged["a1@example.com"]);
+    assert_ne!(merged["a1@example.com"], merged["b1@example.com"]);
+    assert_eq!(merged["hermit@example.com"], graph::UNASSIGNED_LABEL); // No edges to another community
+
+    // In undirected mode zed hears from a1 and joins without any merging
+    let undirected = graph.label_propagation_with_options(&graph::LpaOptions { use_undirected_neighbors: true, ..options });
+    assert_eq!(undirected["zed@example.com"], undirected["a1@example.com"]);
+}
+
+/// Writes a synthetic CSV with `rows` emails among a few hundred people, in
+/// the default column layout. Every 97th row has no sender.
+#[cfg(all(test, feature = "parallel"))]
+fn write_synthetic_csv(path: &std::path::Path, rows: usize) {
+    use std::io::Write;
+
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
+    writeln!(out, ",date,sender,recipient1,subject,text").unwrap();
+    for i in 0..rows {
+        let sender = if i % 97 == 0 { String::new() } else { format!("User{}@Example.com", i % 331) };
+        writeln!(
+            out,
+            "{},2001-{:02}-{:02} 09:00:00,{},\"Person <p{}@example.com>, p{}@example.com; P{}@EXAMPLE.COM\",subject {},body {}",
+            i, i % 12 + 1, i % 28 + 1, sender, i % 211, i % 157, i % 89, i, i
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_parallel_reader_matches_serial_reader() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("synthetic.csv");
+    write_synthetic_csv(&path, 50_000);
+    let path = path.to_str().unwrap();
+
+    let filter = filter::EmailFilter {
+        after: email::parse_date("2001-03-01"),
+        blocklist: vec![filter::AddressPattern::new("p1*@example.com")],
+        ..filter::EmailFilter::default()
+    };
+    let schema = email::CsvSchema::default();
+    let serial = email::read_csv_with(path, &schema, &filter, &Logger::silent()).unwrap();
+    let parallel = email::read_csv_parallel(path, &schema, &filter, &Logger::silent()).unwrap();
+
+    assert_eq!(parallel.emails, serial.emails);
+    assert_eq!(parallel.row_errors.iter().map(EmailError::row).collect::<Vec<_>>(),
+               serial.row_errors.iter().map(EmailError::row).collect::<Vec<_>>());
+    assert_eq!(parallel.outside_date_range, serial.outside_date_range);
+    assert_eq!(parallel.blocked_recipients, serial.blocked_recipients);
+    assert_eq!(parallel.address_excluded, serial.address_excluded);
+    assert_eq!(parallel.rejected_recipients, serial.rejected_recipients);
+}
+
+/// Times both readers on a million rows; run with
+/// `cargo test --release --features parallel -- --ignored --nocapture`.
+#[cfg(feature = "parallel")]
+#[test]
+#[ignore]
+fn bench_parallel_reader_on_a_million_rows() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("synthetic.csv");
+    write_synthetic_csv(&path, 1_000_000);
+    let path = path.to_str().unwrap();
+    let (schema, filter) = (email::CsvSchema::default(), filter::EmailFilter::default());
+
+    let start = std::time::Instant::now();
+    let serial = email::read_csv_with(path, &schema, &filter, &Logger::silent()).unwrap();
+    let serial_time = start.elapsed();
+    let start = std::time::Instant::now();
+    let parallel = email::read_csv_parallel(path, &schema, &filter, &Logger::silent()).unwrap();
+    let parallel_time = start.elapsed();
+
+    println!("serial: {:?}, parallel: {:?} ({} threads)", serial_time, parallel_time, rayon::current_num_threads());
+    assert_eq!(parallel.emails.len(), serial.emails.len());
+}
+
+#[test]
+fn test_approximate_betweenness_with_all_sources_is_exact() {
+    let graph = Graph::from_edges(&[("a", "b"), ("b", "c"), ("c", "d"), ("a", "c"), ("d", "a"), ("b", "e"), ("e", "d")]);
+
+    let exact = graph.betweenness_centrality();
+    let approximate = graph.approximate_betweenness(graph.node_count(), 7);
+    assert_eq!(approximate.len(), exact.len());
+    for (node, score) in &exact {
+        assert!((approximate[node] - score).abs() < 1e-9, "{}: {} vs {}", node, approximate[node], score);
+    }
+
+    // The same seed picks the same sources
+    assert_eq!(graph.approximate_betweenness(2, 42), graph.approximate_betweenness(2, 42));
+    assert!(graph.approximate_betweenness(0, 42).values().all(|&score| score == 0.0));
+}
+
+#[test]
+fn test_edge_betweenness_ranks_barbell_bridge_highest() {
+    use graph::Direction;
+
+    // Two 4-cliques, emailing both ways, joined by a two-way bridge a3 <-> b0
+    let mut graph = Graph::new();
+    for side in ["a", "b"] {
+        for i in 0..4 {
+            for j in 0..4 {
+                if i != j {
+                    graph.add_edge(format!("{}{}", side, i), format!("{}{}", side, j));
+                }
+            }
+        }
+    }
+    graph.add_edge("a3".to_string(), "b0".to_string());
+    graph.add_edge("b0".to_string(), "a3".to_string());
+
+    let scores = graph.edge_betweenness();
+    assert_eq!(scores.len(), graph.edge_count());
+    // 4 senders on one side times 4 recipients on the other
+    assert!((scores[&("a3".to_string(), "b0".to_string())] - 16.0).abs() < 1e-9);
+
+    let top = graph.top_edges_by_betweenness(3);
+    assert_eq!(top[0].0, ("a3".to_string(), "b0".to_string()));
+    assert_eq!(top[1].0, ("b0".to_string(), "a3".to_string()));
+    assert!(top[1].1 > 3.0 * top[2].1, "{:?}", top);
+
+    // Summed over edges, every shortest path counts once per hop
+    let total_hops: f64 = graph.adjacency_list.keys()
+        .map(|node| graph.bfs_distances(node, Direction::Out).values().sum::<usize>() as f64)
+        .sum();
+    assert!((scores.values().sum::<f64>() - total_hops).abs() < 1e-9);
+}
+
+#[test]
+fn test_maximum_spanning_tree_keeps_heaviest_edges() {
+    let mut graph = Graph::new();
+    let mut send = |from: &str, to: &str, emails: usize| {
+        for _ in 0..emails {
+            graph.add_edge(from.to_string(), to.to_string());
+        }
+    };
+    // Triangle a-b-c: a<->b is heavy both ways, b-c light, a-c medium
+    send("a", "b", 3);
+    send("b", "a", 2);
+    send("b", "c", 1);
+    send("c", "a", 4);
+    // A separate pair and a loner with only a self-loop
+    send("d", "e", 1);
+    send("f", "f", 9);
+
+    let tree = graph.maximum_spanning_tree();
+    let components = graph.weakly_connected_components().len();
+    assert_eq!(tree.node_count(), graph.node_count());
+    assert_eq!(tree.edge_count(), graph.node_count() - components);
+    assert_eq!(tree.weakly_connected_components().len(), components);
+
+    // a-b (5 emails) and c-a (4) beat b-c (1); a-b points the way most mail went
+    assert_eq!(tree.edge_weight("a", "b"), Some(5.0));
+    assert_eq!(tree.edge_multiplicity("a", "b"), 5);
+    assert_eq!(tree.edge_weight("b", "a"), None);
+    assert_eq!(tree.edge_weight("c", "a"), Some(4.0));
+    assert!(tree.edge_weight("b", "c").is_none() && tree.edge_weight("c", "b").is_none());
+    assert_eq!(tree.edge_weight("d", "e"), Some(1.0));
+    assert!(tree.get_neighbors(&"f".to_string()).unwrap().is_empty());
+
+    // The backbone goes through the GraphML writer like any other graph
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("backbone.graphml");
+    export::write_graphml(&tree, None, false, &path).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap().matches("<edge ").count(), 3);
+}
+
+#[test]
+fn test_generators_are_deterministic() {
+    let er = Graph::erdos_renyi(60, 0.1, 3);
+    assert_graph_eq!(er, Graph::erdos_renyi(60, 0.1, 3));
+    assert_ne!(er, Graph::erdos_renyi(60, 0.1, 4));
+    assert_eq!(er.node_count(), 60);
+
+    let ba = Graph::barabasi_albert(200, 3, 3);
+    assert_graph_eq!(ba, Graph::barabasi_albert(200, 3, 3));
+    assert_eq!(ba.node_count(), 200);
+    assert_eq!(ba.edge_count(), (200 - 3) * 3);
+    assert!(ba.calculate_out_degrees().values().all(|&degree| degree == 0 || degree == 3));
+
+    let pp = Graph::planted_partition(4, 20, 0.3, 0.01, 3);
+    assert_graph_eq!(pp, Graph::planted_partition(4, 20, 0.3, 0.01, 3));
+    assert_eq!(pp.node_count(), 80);
+}
+
+#[test]
+fn test_lpa_recovers_planted_partition() {
+    let (communities, size) = (4, 30);
+    let graph = Graph::planted_partition(communities, size, 0.4, 0.005, 11);
+    let options = graph::LpaOptions { seed: Some(5), use_undirected_neighbors: true, ..graph::LpaOptions::default() };
+    let labels = graph.label_propagation_with_options(&options);
+
+    // Each planted group should be (almost) entirely one community of its own
+    let mut majority_labels = HashSet::new();
+    for group in 0..communities {
+        let mut counts: HashMap<&String, usize> = HashMap::new();
+        for node in group * size..(group + 1) * size {
+            *counts.entry(&labels[&format!("n{}", node)]).or_insert(0) += 1;
+        }
+        let (label, count) = counts.into_iter().max_by_key(|&(_, count)| count).unwrap();
+        assert!(count >= size * 9 / 10, "group {} split: largest label covers {}", group, count);
+        majority_labels.insert(label.clone());
+    }
+    assert_eq!(majority_labels.len(), communities);
+}
+
+#[test]
+fn test_community_embeddedness_counts_edges_inside_the_community() {
+    // m has two edges to and one from its own community, and one to the other
+    let mut graph = Graph::from_edges(&[("m", "a1"), ("m", "a2"), ("a3", "m"), ("m", "b1"), ("b1", "b2")]);
+    graph.insert_node("loner".to_string());
+    let mut labels: HashMap<String, String> = [("m", "A"), ("a1", "A"), ("a2", "A"), ("a3", "A"), ("b1", "B"), ("b2", "B")]
+        .into_iter()
+        .map(|(node, label)| (node.to_string(), label.to_string()))
+        .collect();
+    labels.insert("loner".to_string(), "C".to_string());
+
+    let scores = graph.community_embeddedness(&labels);
+    assert_eq!(scores["m"], 0.75);
+    assert_eq!(scores["a1"], 1.0);
+    assert_eq!(scores["b1"], 0.5);
+    assert_eq!(scores["loner"], 0.0);
+
+    // Heavier edges count for more
+    graph.add_weighted_edge("m".to_string(), "b1".to_string(), 2.0);
+    assert_eq!(graph.community_embeddedness(&labels)["m"], 0.5);
+}
+
+#[test]
+fn test_weighted_label_propagation_follows_heavy_edges() {
+    // x emails a1 ten times and each of b1, b2 and b3 once
+    let mut graph = Graph::from_edges(&[("a1", "a2"), ("b1", "b2"), ("b2", "b3"), ("x", "b1"), ("x", "b2"), ("x", "b3")]);
+    graph.add_weighted_edge("x".to_string(), "a1".to_string(), 10.0);
+    let fixed: HashMap<String, String> = [("a1", "A"), ("a2", "A"), ("b1", "B"), ("b2", "B"), ("b3", "B")]
+        .into_iter()
+        .map(|(node, label)| (node.to_string(), label.to_string()))
+        .collect();
+
+    for seed in 0..5 {
+        let unweighted = graph::LpaOptions { seed: Some(seed), ..graph::LpaOptions::default() };
+        assert_eq!(graph.label_propagation_seeded(&fixed, &unweighted)["x"], "B");
+        let weighted = graph::LpaOptions { weighted: true, ..unweighted };
+        assert_eq!(graph.label_propagation_seeded(&fixed, &weighted)["x"], "A");
+    }
+}
+
+#[test]
+fn test_consensus_communities_are_stable_inside_planted_groups() {
+    let (communities, size) = (4, 30);
+    let graph = Graph::planted_partition(communities, size, 0.4, 0.005, 11);
+    let options = graph::LpaOptions { use_undirected_neighbors: true, ..graph::LpaOptions::default() };
+    let consensus = graph.consensus_communities_with_options(10, 3, &options);
+
+    assert_eq!(consensus.runs, 10);
+    assert_eq!(consensus.stability.len(), communities * size);
+    assert!(consensus.average_stability() > 0.95, "average stability {}", consensus.average_stability());
+    for group in 0..communities {
+        let members: Vec<String> = (group * size..(group + 1) * size).map(|node| format!("n{}", node)).collect();
+        let mean = members.iter().map(|node| consensus.stability[node]).sum::<f64>() / size as f64;
+        assert!(mean > 0.95, "group {} mean stability {}", group, mean);
+        // Almost the whole group shares one consensus community, named after a member
+        let mut counts: HashMap<&String, usize> = HashMap::new();
+        for node in &members {
+            *counts.entry(&consensus.labels[node]).or_insert(0) += 1;
+        }
+        let (label, count) = counts.into_iter().max_by_key(|&(_, count)| count).unwrap();
+        assert!(count >= size * 9 / 10, "group {} split: largest consensus community covers {}", group, count);
+        assert!(members.contains(label));
+    }
+    assert!(consensus.co_assignment.values().all(|fraction| (0.0..=1.0).contains(fraction)));
+
+    // The same seeds give the same consensus
+    let again = graph.consensus_communities_with_options(10, 3, &options);
+    assert_eq!(again.labels, consensus.labels);
+    assert_eq!(again.stability, consensus.stability);
+}
+
+#[test]
+fn test_sample_edges_is_reproducible_and_stratified_keeps_every_node() {
+    let mut graph = Graph::planted_partition(3, 20, 0.3, 0.02, 4);
+    graph.add_weighted_edge("n0".to_string(), "n1".to_string(), 5.0);
+    graph.insert_node("loner".to_string());
+
+    let sampled = graph.sample_edges(0.2, 7);
+    debug_assert_valid!(sampled);
+    assert_graph_eq!(sampled, graph.sample_edges(0.2, 7));
+    assert!(sampled != graph.sample_edges(0.2, 8));
+    let kept = sampled.edge_count() as f64 / graph.edge_count() as f64;
+    assert!((0.1..0.3).contains(&kept), "kept {} of the edges", kept);
+    for (from, recipients) in &sampled.adjacency_list {
+        for to in recipients {
+            assert_eq!(sampled.edge_weight(from, to), graph.edge_weight(from, to));
+        }
+    }
+    assert!(!sampled.adjacency_list.contains_key("loner"));
+    assert_graph_eq!(graph.sample_edges(1.0, 7), graph.without_isolated_nodes());
+    assert_graph_eq!(graph.sample_edges(0.0, 7), Graph::new());
+
+    // Even with almost every edge dropped, everyone who had an edge keeps one
+    let stratified = graph.sample_edges_stratified(0.01, 7);
+    debug_assert_valid!(stratified);
+    assert_graph_eq!(stratified, graph.sample_edges_stratified(0.01, 7));
+    assert_eq!(stratified.node_count(), graph.node_count());
+    let out_degrees = stratified.calculate_out_degrees();
+    let in_degrees = stratified.calculate_in_degrees();
+    for node in graph.adjacency_list.keys().filter(|node| *node != "loner") {
+        assert!(out_degrees[node] + in_degrees[node] >= 1, "{} lost all its edges", node);
+    }
+    assert!(stratified.edge_count() < graph.edge_count() / 2);
+}
+
+#[test]
+fn test_snowball_sample_respects_depth() {
+    // A chain a -> b <- c -> d -> e, expanded ignoring direction
+    let graph = Graph::from_edges(&[("a", "b"), ("c", "b"), ("c", "d"), ("d", "e")]);
+
+    let seeds = ["b".to_string()];
+    assert_eq!(nodes_of(&graph.snowball_sample(&seeds, 0, 10, 1)), ["b"]);
+    assert_eq!(nodes_of(&graph.snowball_sample(&seeds, 1, 10, 1)), ["a", "b", "c"]);
+    let two_steps = graph.snowball_sample(&seeds, 2, 10, 1);
+    assert_eq!(nodes_of(&two_steps), ["a", "b", "c", "d"]);
+    assert_eq!(two_steps.edge_count(), 3);
+    assert_graph_eq!(two_steps, Graph::from_edges(&[("a", "b"), ("c", "b"), ("c", "d")]));
+    assert!(graph.snowball_sample(&["nobody".to_string()], 3, 10, 1).adjacency_list.is_empty());
+}
+
+#[test]
+fn test_influence_tree_draws_one_solid_edge_per_reached_person() {
+    // b and c both lead to d, c also emails b and d replies to a; e is 3 steps out
+    let graph = Graph::from_edges(&[("a", "b"), ("a", "c"), ("b", "d"), ("c", "d"), ("c", "b"), ("d", "a"), ("d", "e")]);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tree.dot");
+    let edges = |dot: &str, dashed: bool| dot.lines().filter(|line| line.contains("->") && line.contains("dashed") == dashed).count();
+
+    let drawn = export::export_influence_tree(&graph, "a", 2, false, &path).unwrap();
+    let dot = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(drawn, 4);
+    assert_eq!(edges(&dot, false), drawn - 1);
+    assert_eq!(edges(&dot, true), 0);
+    assert!(dot.contains(r#"{ rank=same; "b"; "c"; } // layer 1"#));
+    assert!(dot.contains(r#""b" -> "d";"#) && !dot.contains(r#""e""#));
+
+    // The other emails among a, b, c and d come back dashed without changing the tree
+    let drawn = export::export_influence_tree(&graph, "a", 2, true, &path).unwrap();
+    let dot = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(edges(&dot, false), drawn - 1);
+    assert_eq!(edges(&dot, true), 3);
+
+    assert_eq!(export::export_influence_tree(&graph, "nobody", 2, true, &path).unwrap(), 0);
+}
+
+#[test]
+fn test_snowball_sample_caps_each_level() {
+    // A hub with 20 spokes, each spoke with 5 leaves of its own
+    let mut graph = Graph::new();
+    for spoke in 0..20 {
+        graph.add_edge("hub".to_string(), format!("s{}", spoke));
+        for leaf in 0..5 {
+            graph.add_edge(format!("s{}", spoke), format!("s{}_l{}", spoke, leaf));
+        }
+    }
+
+    let sample = graph.snowball_sample(&["hub".to_string()], 2, 4, 9);
+    let spokes = sample.adjacency_list.keys().filter(|node| node.starts_with('s') && !node.contains('_')).count();
+    let leaves = sample.adjacency_list.keys().filter(|node| node.contains('_')).count();
+    assert_eq!((spokes, leaves), (4, 4));
+    assert_eq!(sample.node_count(), 9);
+    assert_eq!(nodes_of(&sample), nodes_of(&graph.snowball_sample(&["hub".to_string()], 2, 4, 9)));
+}
+
+/// Returns the graph's nodes in sorted order.
+#[cfg(test)]
+fn nodes_of(graph: &Graph) -> Vec<String> {
+    let mut nodes: Vec<String> = graph.adjacency_list.keys().cloned().collect();
+    nodes.sort();
+    nodes
+}
+
+#[test]
+fn test_html_visualization_embeds_graph_json() {
+    let mut graph = Graph::new();
+    graph.add_edge("alice@example.com".to_string(), "bob@example.com".to_string());
+    graph.add_edge("bob@example.com".to_string(), "</script><b>@example.com".to_string());
+    let labels: HashMap<String, String> = graph.adjacency_list.keys().map(|node| (node.clone(), "c1".to_string())).collect();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("graph.html");
+    assert_eq!(export::export_html_visualization(&graph, &labels, &path).unwrap(), 3);
+
+    let html = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(html.matches("</script>").count(), 2, "address must not close the data script");
+    let start = html.find(r#"type="application/json">"#).unwrap() + r#"type="application/json">"#.len();
+    let end = start + html[start..].find("</script>").unwrap();
+    let data: serde_json::Value = serde_json::from_str(&html[start..end]).unwrap();
+    assert_eq!(data["nodes"].as_array().unwrap().len(), 3);
+    assert_eq!(data["edges"].as_array().unwrap().len(), 2);
+    assert_eq!(data["nodes"][0]["id"], "</script><b>@example.com");
+    assert_eq!(data["nodes"][2]["degree"], 2);
+
+    // Over the limit, only the highest-degree people are drawn
+    assert_eq!(export::export_html_visualization_with_limit(&graph, &labels, &path, 1).unwrap(), 1);
+    let html = std::fs::read_to_string(&path).unwrap();
+    assert!(html.contains(r#""id":"bob@example.com""#) && html.contains(r#""truncated":true"#));
+}
+
+#[test]
+fn test_matrix_market_indices_match_node_index() {
+    let mut graph = Graph::new();
+    graph.add_weighted_edge("carol@example.com".to_string(), "alice@example.com".to_string(), 2.5);
+    graph.add_edge("alice@example.com".to_string(), "bob@example.com".to_string());
+    graph.add_edge("bob@example.com".to_string(), "bob@example.com".to_string());
+    graph.add_edge("bob@example.com".to_string(), "bob@example.com".to_string());
+
+    let mut matrix = Vec::new();
+    graph.to_matrix_market(&mut matrix).unwrap();
+    let matrix = String::from_utf8(matrix).unwrap();
+    let mut lines = matrix.lines().filter(|line| !line.starts_with('%'));
+    assert_eq!(lines.next(), Some("3 3 3"));
+    let entries: Vec<(usize, usize, f64)> = lines
+        .map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            (fields[0].parse().unwrap(), fields[1].parse().unwrap(), fields[2].parse().unwrap())
+        })
+        .collect();
+
+    let dir = tempfile::tempdir().unwrap();
+    let index_path = dir.path().join("node_index.csv");
+    export::write_node_index(&graph, &index_path).unwrap();
+    let mut reader = csv::Reader::from_path(&index_path).unwrap();
+    let addresses: HashMap<usize, String> = reader
+        .records()
+        .map(|record| {
+            let record = record.unwrap();
+            (record[0].parse().unwrap(), record[1].to_string())
+        })
+        .collect();
+
+    // Every entry maps back to an edge with the same weight
+    for &(i, j, weight) in &entries {
+        assert_eq!(graph.edge_weight(&addresses[&i], &addresses[&j]), Some(weight));
+    }
+    let bob = addresses.iter().find(|(_, address)| *address == "bob@example.com").unwrap().0;
+    assert!(entries.contains(&(*bob, *bob, 2.0)));
+
+    let (nodes, counts) = graph.to_dense_counts().unwrap();
+    assert_eq!(nodes, ["alice@example.com", "bob@example.com", "carol@example.com"]);
+    assert_eq!(counts, vec![vec![0, 1, 0], vec![0, 2, 0], vec![1, 0, 0]]);
+    let mut chain = Graph::new();
+    for i in 0..graph::DENSE_MATRIX_MAX_NODES {
+        chain.add_edge(format!("n{}", i), format!("n{}", i + 1));
+    }
+    assert!(chain.to_dense_counts().is_none());
+}
+
+#[test]
+fn test_node_report_for_fixture_emails() {
+    let emails = vec![
+        ParsedEmail { from: "alice@example.com".to_string(), to: vec!["bob@example.com".to_string(), "carol@example.com".to_string()], timestamp: None },
+        ParsedEmail { from: "bob@example.com".to_string(), to: vec!["dave@example.com".to_string()], timestamp: None },
+        ParsedEmail {
+            from: "carol@example.com".to_string(),
+            to: vec!["dave@example.com".to_string(), "eve@example.com".to_string(), "frank@example.com".to_string()],
+            timestamp: None,
+        },
+        ParsedEmail { from: "alice@example.com".to_string(), to: vec!["dave@example.com".to_string()], timestamp: None },
+    ];
+    let graph = Graph::build_from_emails(emails);
+    let labels: HashMap<String, String> = graph.adjacency_list.keys().map(|node| (node.clone(), "c1".to_string())).collect();
+
+    let report = graph.node_report("  Dave@Example.COM ", Some(&labels)).unwrap();
+    assert_eq!(report.address, "dave@example.com");
+    assert_eq!(report.in_degree, 3);
+    assert_eq!(report.in_neighbors, ["alice@example.com", "bob@example.com", "carol@example.com"]);
+    assert_eq!(report.out_degree, 0);
+    assert!(report.out_neighbors.is_empty());
+    assert_eq!(report.weighted_in_degree, 3.0);
+    assert_eq!(report.in_degree_rank, 1);
+    assert_eq!(report.out_degree_rank, 4); // After alice, carol (3 each) and bob (1); dave ties first among the 0s
+    assert_eq!(report.community.as_deref(), Some("c1"));
+
+    assert!(graph.node_report("nobody@example.com", None).is_none());
+    assert_eq!(graph.node_report("alice@example.com", None).unwrap().community, None);
+}
+
+#[test]
+fn test_mutual_graph_keeps_only_reciprocated_pairs() {
+    let graph = Graph::from_edges(&[("a", "b"), ("b", "a"), ("a", "b"), ("b", "c"), ("c", "d"), ("d", "c"), ("e", "e"), ("e", "a")]);
+
+    let mutual = graph.mutual_graph();
+    assert_graph_eq!(mutual, Graph::from_edges(&[("a", "b"), ("a", "b"), ("b", "a"), ("c", "d"), ("d", "c")]));
+    assert_eq!(nodes_of(&mutual), ["a", "b", "c", "d"]);
+    assert_eq!(mutual.edge_count(), 4);
+    assert!(mutual.adjacency_list["b"].contains("a") && mutual.adjacency_list["a"].contains("b"));
+    assert!(!mutual.adjacency_list["b"].contains("c"));
+    assert_eq!(mutual.edge_multiplicity("a", "b"), 2);
+    assert_eq!(mutual.edge_multiplicity("b", "a"), 1);
+    assert!(Graph::new().mutual_graph().adjacency_list.is_empty());
+}
+
+#[test]
+fn test_build_option_drops_self_loops() {
+    let emails = vec![
+        ParsedEmail { from: "alice@example.com".to_string(), to: vec!["bob@example.com".to_string()], timestamp: None },
+        ParsedEmail { from: "bob@example.com".to_string(), to: vec!["alice@example.com".to_string()], timestamp: None },
+        ParsedEmail { from: "carol@example.com".to_string(), to: vec!["carol@example.com".to_string()], timestamp: None },
+    ];
+    let opts = graph::GraphBuildOptions { drop_self_loops: true };
+    let (graph, stats) = Graph::build_from_emails_with_options(emails.clone(), &opts);
+
+    assert_eq!(stats.self_loops_skipped, 1);
+    let mut expected = Graph::from_edges(&[("alice@example.com", "bob@example.com"), ("bob@example.com", "alice@example.com")]);
+    expected.insert_node("carol@example.com".to_string());
+    assert_graph_eq!(graph, expected);
+    assert_eq!(graph.node_count(), 3);
+    assert_eq!(graph.edge_count(), 2);
+    assert_eq!(graph.calculate_out_degrees()["carol@example.com"], 0);
+    assert_eq!(graph.calculate_in_degrees()["carol@example.com"], 0);
+
+    // The default keeps the loop
+    let (graph, stats) = Graph::build_from_emails_with_options(emails, &graph::GraphBuildOptions::default());
+    assert_eq!(stats.self_loops_skipped, 0);
+    assert_eq!(graph.calculate_out_degrees()["carol@example.com"], 1);
+}
+
+#[test]
+fn test_filter_by_weight_keeps_heavy_edges() {
+    let heavy = [("b", "c"), ("b", "c"), ("c", "a"), ("c", "a"), ("c", "a"), ("c", "a"), ("c", "a")];
+    let graph = Graph::from_edges(&[&heavy[..], &[("a", "b"), ("d", "a")]].concat());
+
+    let filtered = graph.filter_by_weight(2);
+    let mut expected = Graph::from_edges(&heavy);
+    expected.insert_node("d".to_string()); // Kept without its edge
+    assert_graph_eq!(filtered, expected);
+    assert_eq!(filtered.node_count(), 4);
+    assert_eq!(filtered.edge_count(), 2);
+    assert_eq!(filtered.edge_multiplicity("b", "c"), 2);
+    assert_eq!(filtered.edge_multiplicity("c", "a"), 5);
+    assert_eq!(filtered.edge_weight("c", "a"), Some(5.0));
+    assert_eq!(filtered.edge_weight("a", "b"), None);
+    assert!(filtered.in_neighbors("b").is_empty());
+    assert_eq!(filtered.calculate_out_degrees()["d"], 0);
+
+    let connected = filtered.without_isolated_nodes();
+    assert_eq!(nodes_of(&connected), ["a", "b", "c"]);
+    assert_eq!(connected.edge_count(), 2);
+    assert_graph_eq!(connected, Graph::from_edges(&heavy));
+}
+
+#[test]
+fn test_canonicalize_labels_orders_by_size() {
+    let partition = [
+        ("a", "x"), ("b", "x"),
+        ("c", "y"), ("d", "y"), ("e", "y"),
+        ("f", "z"), ("g", "z"),
+        ("h", graph::UNASSIGNED_LABEL),
+    ];
+    let labels: HashMap<String, String> = partition.iter().map(|(node, label)| (node.to_string(), label.to_string())).collect();
+
+    let (canonical, originals) = graph::canonicalize_labels(&labels);
+    assert_eq!(canonical["c"], "C0"); // Largest
+    assert_eq!(canonical["a"], "C1"); // Tied with z, but a < f
+    assert_eq!(canonical["g"], "C2");
+    assert_eq!(canonical["h"], graph::UNASSIGNED_LABEL);
+    assert_eq!(originals["C0"], "y");
+    assert_eq!(originals["C1"], "x");
+    assert_eq!(originals.len(), 3);
+
+    // Renaming the original labels changes nothing
+    let relabeled: HashMap<String, String> = labels.iter().map(|(node, label)| (node.clone(), format!("{}-run2", label))).collect();
+    let (again, _) = graph::canonicalize_labels(&relabeled);
+    assert_eq!(again.get("a"), canonical.get("a"));
+    assert_eq!(again.get("e"), canonical.get("e"));
+    assert_eq!(again.get("f"), canonical.get("f"));
+}
+
+#[test]
+fn test_merge_small_communities_absorbs_singleton() {
+    // A triangle, a singleton hanging off it, and a singleton on its own
+    let mut graph = Graph::from_edges(&[("a", "b"), ("b", "c"), ("c", "a"), ("d", "a"), ("d", "b")]);
+    graph.insert_node("loner".to_string());
+    let labels: HashMap<String, String> = [("a", "t"), ("b", "t"), ("c", "t"), ("d", "d"), ("loner", "loner")]
+        .iter()
+        .map(|(node, label)| (node.to_string(), label.to_string()))
+        .collect();
+
+    let merged = graph.merge_small_communities(&labels, 2);
+    assert_eq!(merged["d"], "t");
+    assert_eq!(merged["a"], "t");
+    assert_eq!(merged["loner"], graph::UNASSIGNED_LABEL);
+    assert_eq!(graph.merge_small_communities(&labels, 1), labels);
+}
+
+#[test]
+fn test_activity_series_spans_three_buckets() {
+    use chrono::TimeZone;
+    let at = |day: u32| Some(chrono::Utc.with_ymd_and_hms(2001, 5, day, 9, 0, 0).unwrap());
+    let email = |from: &str, day: u32| ParsedEmail { from: from.to_string(), to: vec!["x@example.com".to_string()], timestamp: at(day) };
+    let emails = vec![
+        email("alice@example.com", 1),
+        email("alice@example.com", 2),
+        email("bob@example.com", 3),
+        email("alice@example.com", 15), // Nothing in the middle week
+        ParsedEmail { timestamp: None, ..email("bob@example.com", 1) },
+    ];
+
+    let week = chrono::Duration::days(7);
+    let total = temporal::total_activity_series(&emails, week);
+    assert_eq!(total, vec![(at(1).unwrap(), 3), (at(8).unwrap(), 0), (at(15).unwrap(), 1)]);
+
+    let series = temporal::sender_activity_series(&emails, week);
+    assert_eq!(series.len(), 2);
+    let counts = |sender: &str| series[sender].iter().map(|(_, count)| *count).collect::<Vec<_>>();
+    assert_eq!(counts("alice@example.com"), [2, 0, 1]);
+    assert_eq!(counts("bob@example.com"), [1, 0, 0]);
+    assert_eq!(series["bob@example.com"][2].0, at(15).unwrap());
+
+    assert!(temporal::sender_activity_series(&emails, chrono::Duration::zero()).is_empty());
+}
+
+#[test]
+fn test_detect_bursts_reports_only_the_spike() {
+    use chrono::TimeZone;
+    let start = chrono::Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0).unwrap();
+    let series_of = |counts: &[usize]| -> temporal::ActivitySeries {
+        counts.iter().enumerate().map(|(i, &count)| (start + chrono::Duration::days(7 * i as i64), count)).collect()
+    };
+
+    let mut series = HashMap::new();
+    series.insert("spiky@example.com".to_string(), series_of(&[1, 2, 1, 1, 30, 28, 1, 2, 1, 1, 2, 1]));
+    series.insert("flat@example.com".to_string(), series_of(&[4; 12]));
+    series.insert("short@example.com".to_string(), series_of(&[0, 50]));
+
+    let bursts = temporal::detect_bursts(&series, 1.5);
+    assert_eq!(bursts.len(), 1);
+    assert_eq!(bursts[0].sender, "spiky@example.com");
+    assert_eq!(bursts[0].start, series["spiky@example.com"][4].0);
+    assert_eq!(bursts[0].end, series["spiky@example.com"][5].0);
+    assert_eq!(bursts[0].peak, 30);
+}
+
+#[test]
+fn test_threads_group_replies_with_original() {
+    assert_eq!(threads::normalize_subject("  Re: RE:fwd: FW:  Budget "), "budget");
+    assert_eq!(threads::normalize_subject("Regarding budget"), "regarding budget");
+
+    let email = |from: &str, subject: &str| email::ParsedEmailFull {
+        email: ParsedEmail { from: from.to_string(), to: vec!["carol@example.com".to_string()], timestamp: None },
+        subject: subject.to_string(),
+    };
+    let emails = vec![
+        email("alice@example.com", "budget"),
+        email("bob@example.com", "Re: Re: budget"),
+        email("carol@example.com", "Fwd: lunch"),
+        email("dave@example.com", "Re: "),
+    ];
+
+    let threads = threads::group_into_threads(&emails);
+    assert_eq!(threads.len(), 2);
+    assert_eq!(threads["budget"].message_count, 2);
+    assert_eq!(
+        threads["budget"].participants.iter().collect::<Vec<_>>(),
+        ["alice@example.com", "bob@example.com", "carol@example.com"]
+    );
+    assert_eq!(threads["budget"].span(), None);
+    assert_eq!(threads::longest_threads(&threads, 1)[0].0, "budget");
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn test_sqlite_round_trips_the_graph() {
+    let emails = read_csv("tests/fixtures/subjects.csv").unwrap().into_full_emails();
+    let mut graph = Graph::build_from_emails(emails.iter().map(|full| full.email.clone()).collect());
+    graph.add_weighted_edge("carol@example.com".to_string(), "dave@example.com".to_string(), 2.5);
+    graph.insert_node("loner@example.com".to_string());
+    let labels: HashMap<String, String> = [("carol@example.com".to_string(), "c".to_string())].into_iter().collect();
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("emails.db");
+    sqlite::persist_to_sqlite(&path, &emails, &graph, Some(&labels)).unwrap();
+    let loaded = Graph::load_from_sqlite(&path).unwrap();
+    assert_graph_eq!(loaded, graph);
+    assert_eq!(loaded.edge_info, graph.edge_info);
+
+    // Writing again replaces the tables rather than appending
+    sqlite::persist_to_sqlite(&path, &emails, &graph, None).unwrap();
+    let connection = rusqlite::Connection::open(&path).unwrap();
+    let count = |table: &str| -> usize {
+        connection.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0)).unwrap()
+    };
+    let recipients: usize = emails.iter().map(|full| full.email.to.len()).sum();
+    assert_eq!(count("emails"), recipients);
+    assert_eq!(count("nodes"), graph.node_count());
+    assert_eq!(count("edges"), graph.edge_count());
+    let (in_degree, out_degree): (usize, usize) = connection
+        .query_row("SELECT in_degree, out_degree FROM nodes WHERE address = 'dave@example.com'", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap();
+    assert_eq!((in_degree, out_degree), (graph.in_neighbors("dave@example.com").len(), graph.adjacency_list["dave@example.com"].len()));
+}
+
+/// Copies a CSV fixture into a Parquet file of text columns, empty fields becoming nulls.
+#[cfg(all(test, feature = "parquet"))]
+fn write_parquet_copy(csv_path: &str, parquet_path: &std::path::Path) {
+    use arrow_array::{ArrayRef, RecordBatch, StringArray};
+    use std::sync::Arc;
+
+    let mut reader = csv::Reader::from_path(csv_path).unwrap();
+    let headers = reader.headers().unwrap().clone();
+    let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    let columns: Vec<(String, ArrayRef)> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            let values: StringArray = rows.iter().map(|row| Some(&row[i]).filter(|value| !value.is_empty())).collect();
+            (header.to_string(), Arc::new(values) as ArrayRef)
+        })
+        .collect();
+    let batch = RecordBatch::try_from_iter(columns).unwrap();
+    let mut writer = parquet::arrow::ArrowWriter::try_new(std::fs::File::create(parquet_path).unwrap(), batch.schema(), None).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_parquet_reader_matches_csv_reader() {
+    let dir = tempfile::tempdir().unwrap();
+    for fixture in ["multi_recipient.csv", "with_index.csv", "dated_emails.csv", "skipped_rows.csv"] {
+        let csv_path = format!("tests/fixtures/{}", fixture);
+        let parquet_path = dir.path().join(fixture).with_extension("parquet");
+        write_parquet_copy(&csv_path, &parquet_path);
+
+        let schema = email::CsvSchema::default();
+        let from_csv = read_csv(&csv_path).unwrap();
+        let from_parquet = email::read_parquet_with(parquet_path.to_str().unwrap(), &schema, &filter::EmailFilter::default()).unwrap();
+        assert_eq!(from_parquet.emails, from_csv.emails, "{}", fixture);
+        assert_eq!(from_parquet.failed_count(), from_csv.failed_count(), "{}", fixture);
+        assert_eq!(from_parquet.rejected_recipients, from_csv.rejected_recipients, "{}", fixture);
+        assert_eq!(email::read_parquet(parquet_path.to_str().unwrap(), &schema).unwrap(), from_csv.emails);
+
+        // Skipped rows carry the same row numbers in both formats
+        let rows = |outcome: email::ParseOutcome| ParseReport::from(outcome).skipped;
+        assert_eq!(rows(from_parquet), rows(from_csv), "{}", fixture);
+    }
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_parquet_reader_accepts_metadata_only_files_with_timestamps() {
+    use arrow_array::{ArrayRef, RecordBatch, StringArray, TimestampSecondArray};
+    use std::sync::Arc;
+
+    // No subject or body columns, and a real timestamp column
+    let sent = chrono::DateTime::parse_from_rfc3339("2001-05-14T09:00:00Z").unwrap().timestamp();
+    let batch = RecordBatch::try_from_iter([
+        ("sender", Arc::new(StringArray::from(vec!["Alice@Example.com", "bob@example.com"])) as ArrayRef),
+        ("recipient1", Arc::new(StringArray::from(vec!["\"Doe, Jane\" <jane@example.com>; bob@example.com", "alice@example.com"])) as ArrayRef),
+        ("date", Arc::new(TimestampSecondArray::from(vec![Some(sent), None])) as ArrayRef),
+    ])
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("metadata.parquet");
+    let mut writer = parquet::arrow::ArrowWriter::try_new(std::fs::File::create(&path).unwrap(), batch.schema(), None).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+
+    let emails = email::read_parquet(path.to_str().unwrap(), &email::CsvSchema::default()).unwrap();
+    assert_eq!(emails.len(), 2);
+    assert_eq!(emails[0].from, "alice@example.com");
+    assert_eq!(emails[0].to, vec!["jane@example.com", "bob@example.com"]);
+    assert_eq!(emails[0].timestamp.unwrap().timestamp(), sent);
+    assert_eq!(emails[1].timestamp, None);
+
+    // Text patterns need the subject and body, which this file doesn't have
+    let filter = filter::EmailFilter { subject_regex: Some(filter::TextPattern::new("plan").unwrap()), ..Default::default() };
+    let err = email::read_parquet_with(path.to_str().unwrap(), &email::CsvSchema::default(), &filter).unwrap_err();
+    assert!(matches!(err, EmailError::MissingColumn { .. }), "{:?}", err);
+}
+
+#[test]
+fn test_input_pattern_reads_every_matching_file() {
+    let header = ",date,sender,recipient1,subject,text\n";
+    let first = "0,2001-05-14,alice@example.com,\"bob@example.com, carol@example.com\",plan,draft\n\
+                 1,2001-05-14,,bob@example.com,no sender,dropped\n";
+    let second = "0,2001-05-15,bob@example.com,alice@example.com,re: plan,ok\n\
+                  1,2001-05-16,alice@example.com,bob@example.com,re: plan,thanks\n";
+    let dir = tempfile::tempdir().unwrap();
+    let shards = dir.path().join("shards");
+    std::fs::create_dir(&shards).unwrap();
+    // Written out of order; the reader sorts by name
+    std::fs::write(shards.join("emails_1.csv"), format!("{}{}", header, second)).unwrap();
+    std::fs::write(shards.join("emails_0.csv"), format!("{}{}", header, first)).unwrap();
+    std::fs::write(shards.join("notes.txt"), "not an export").unwrap();
+    let combined = dir.path().join("combined.csv");
+    std::fs::write(&combined, format!("{}{}{}", header, first, second)).unwrap();
+
+    let mut config = Config::default();
+    config.input.path = Some(shards.join("emails_?.csv").display().to_string());
+    let outcome = load_parse_outcome(&config, &Logger::silent()).unwrap();
+    assert_eq!(outcome.emails.len(), 3);
+    assert_eq!(outcome.failed_count(), 1);
+    assert_eq!(outcome.subjects, ["plan", "re: plan", "re: plan"]);
+    let from_shards = load_graph(&config, &Logger::silent()).unwrap();
+
+    config.input.path = Some(combined.display().to_string());
+    assert_eq!(from_shards, load_graph(&config, &Logger::silent()).unwrap());
+
+    // A shard that can't be read is skipped, unless strict
+    std::fs::write(shards.join("emails_2.csv"), "from,to\na@example.com,b@example.com\n").unwrap();
+    config.input.path = Some(shards.join("emails_*.csv").display().to_string());
+    assert_eq!(load_graph(&config, &Logger::silent()).unwrap(), from_shards);
+    config.input.strict = true;
+    assert!(matches!(load_graph(&config, &Logger::silent()), Err(Error::Email(EmailError::MissingColumn { .. }))));
+
+    config.input.path = Some(shards.join("*.tsv").display().to_string());
+    assert!(matches!(load_graph(&config, &Logger::silent()), Err(Error::NoInputFiles { .. })));
+}
+
+#[test]
+fn test_parsed_emails_are_written_one_row_per_recipient() {
+    let sent = email::parse_date("2001-05-14 09:00:00");
+    let emails = vec![
+        ParsedEmail {
+            from: "alice@example.com".to_string(),
+            to: vec!["bob@example.com".to_string(), "\"doe, jane\"@example.com".to_string()],
+            timestamp: sent,
+        },
+        ParsedEmail { from: "bob@example.com".to_string(), to: vec!["alice@example.com".to_string()], timestamp: None },
+    ];
+    let mut out = Vec::new();
+    export::write_parsed_emails(&emails, &mut out).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert_eq!(
+        text,
+        "from,to,timestamp\n\
+         alice@example.com,bob@example.com,2001-05-14T09:00:00+00:00\n\
+         alice@example.com,\"\"\"doe, jane\"\"@example.com\",2001-05-14T09:00:00+00:00\n\
+         bob@example.com,alice@example.com,\n"
+    );
+
+    // Reading it back with a CSV parser recovers the addresses
+    let mut reader = csv::Reader::from_reader(text.as_bytes());
+    let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(&rows[1][1], "\"doe, jane\"@example.com");
+}
+
+#[test]
+fn test_clean_dedupes_and_explodes_recipients() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("emails.csv");
+    std::fs::write(
+        &input,
+        ",date,sender,recipient1,subject,text\n\
+         0,2001-05-14,alice@example.com,\"bob@example.com, carol@example.com\",plan,draft\n\
+         1,2001-05-14,Alice@Example.com,\"Bob@example.com; carol@example.com\",plan,same email filed twice\n\
+         2,2001-05-15,bob@example.com,alice@example.com,re: plan,ok\n",
+    )
+    .unwrap();
+    let mut config = Config::default();
+    config.input.path = Some(input.display().to_string());
+    let output = dir.path().join("clean.csv");
+    run_clean(&config, &Logger::silent(), &output).unwrap();
+
+    let text = std::fs::read_to_string(&output).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 1 + 3); // Two emails left, to two and one people
+    assert_eq!(lines[1], "alice@example.com,bob@example.com,2001-05-14T00:00:00+00:00");
+}
+
+#[test]
+fn test_memory_estimate_grows_with_nodes_and_edges() {
+    let mut graph = Graph::new();
+    assert_eq!(graph.estimate_memory_bytes(), 0);
+
+    let mut previous = 0;
+    for i in 0..50 {
+        graph.add_edge(format!("sender{}@example.com", i), format!("recipient{}@example.com", i));
+        let estimate = graph.estimate_memory_bytes();
+        assert!(estimate > previous, "new nodes: {} <= {}", estimate, previous);
+        previous = estimate;
+    }
+    for i in 1..50 {
+        // Edges between existing people only
+        graph.add_edge(format!("sender{}@example.com", i), format!("recipient{}@example.com", i - 1));
+        let estimate = graph.estimate_memory_bytes();
+        assert!(estimate > previous, "new edges: {} <= {}", estimate, previous);
+        previous = estimate;
+    }
+    // Repeating an email only adds weight
+    graph.add_edge("sender1@example.com".to_string(), "recipient1@example.com".to_string());
+    assert_eq!(graph.estimate_memory_bytes(), previous);
+    // At least the 100 addresses, each stored several times
+    assert!(previous > 100 * "sender00@example.com".len() * 4);
+
+    let outcome = read_csv("tests/fixtures/multi_recipient.csv").unwrap();
+    assert_eq!(outcome.row_count(), outcome.emails.len() + outcome.failed_count());
+    assert_eq!(outcome.recipient_count(), outcome.emails.iter().map(|email| email.to.len()).sum::<usize>());
+    assert!(outcome.estimate_memory_bytes() > 0);
+    assert_eq!(format_bytes(512), "512 B");
+    assert_eq!(format_bytes(1536 * 1024), "1.5 MiB");
+}
+
+#[test]
+fn test_giant_component_only_keeps_the_triangle() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("emails.csv");
+    std::fs::write(
+        &input,
+        ",date,sender,recipient1,subject,text\n\
+         0,2001-05-14,a@example.com,b@example.com,x,x\n\
+         1,2001-05-14,b@example.com,c@example.com,x,x\n\
+         2,2001-05-14,c@example.com,a@example.com,x,x\n\
+         3,2001-05-14,x@example.com,y@example.com,x,x\n",
+    )
+    .unwrap();
+    let mut config = Config::default();
+    config.input.path = Some(input.display().to_string());
+    config.graph.giant_component_only = true;
+
+    let graph = load_graph(&config, &Logger::silent()).unwrap();
+    assert_eq!(graph.node_count(), 3);
+    assert_eq!(graph.edge_count(), 3);
+    assert!(!graph.adjacency_list.contains_key("x@example.com"));
+    assert_graph_eq!(graph, graph.largest_component_subgraph());
+    assert_graph_eq!(Graph::new().largest_component_subgraph(), Graph::new());
+
+    // The JSON report records the restriction
+    let report_path = dir.path().join("report.json");
+    config.output.report_json = Some(report_path.clone());
+    run(&config, &Logger::silent()).unwrap();
+    let report: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+    assert_eq!(report["giant_component_only"], true);
+    assert_eq!(report["node_count"], 3);
+}
+
+#[test]
+fn test_distribution_list_flagged_but_real_hub_is_not() {
+    let mut graph = Graph::new();
+    for i in 0..6 {
+        let person = format!("p{}@example.com", i);
+        for _ in 0..5 {
+            graph.add_edge(person.clone(), "list@example.com".to_string());
+            graph.add_edge(person.clone(), "hub@example.com".to_string());
+        }
+        // The hub answers everyone; the list never writes back
+        graph.add_edge("hub@example.com".to_string(), person.clone());
+        graph.add_edge("hub@example.com".to_string(), person);
+    }
+    graph.add_edge("list@example.com".to_string(), "list@example.com".to_string()); // Ignored
+
+    let lists = graph.likely_distribution_lists(&graph::DistributionListOptions::default());
+    assert_eq!(lists, HashSet::from(["list@example.com".to_string()]));
+
+    // A quiet address with few senders isn't flagged however little it sends
+    let strict = graph::DistributionListOptions { min_senders: 7, ..Default::default() };
+    assert!(graph.likely_distribution_lists(&strict).is_empty());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nodes.csv");
+    export::write_node_csv(&graph, None, None, &lists, &path).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.contains("\nlist@example.com,1,7,"));
+    assert!(text.lines().any(|line| line.starts_with("list@example.com,") && line.ends_with(",true,example.com")));
+    assert!(text.lines().any(|line| line.starts_with("hub@example.com,") && line.ends_with(",false,example.com")));
+}
+
+#[test]
+fn test_edge_info_tracks_first_last_and_active_days() {
+    let graph = Graph::build_from_emails(read_csv("tests/fixtures/edge_activity.csv").unwrap().emails);
+    let info = graph.edge_info("alice@example.com", "bob@example.com").unwrap();
+    assert_eq!(info.count, 3);
+    assert_eq!(info.first_seen, email::parse_date("2001-05-14 17:30:00"));
+    assert_eq!(info.last_seen, email::parse_date("2001-05-15 16:45:00"));
+    assert_eq!(info.active_days, 2); // Two emails on the 15th make one day
+    let info = graph.edge_info("alice@example.com", "carol@example.com").unwrap();
+    assert_eq!((info.count, info.active_days), (1, 1));
+    assert_eq!(info.first_seen, info.last_seen);
+    assert!(graph.edge_info("bob@example.com", "alice@example.com").is_none());
+
+    // Derived graphs keep the activity; undated emails only count
+    let mut copy = graph.filter_by_weight(2);
+    assert_eq!(copy.edge_info("alice@example.com", "bob@example.com"), graph.edge_info("alice@example.com", "bob@example.com"));
+    copy.add_edge("alice@example.com".to_string(), "bob@example.com".to_string());
+    let info = copy.edge_info("alice@example.com", "bob@example.com").unwrap();
+    assert_eq!((info.count, info.active_days), (4, 2));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("edges.csv");
+    export::write_edge_list(&graph, true, &path).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(text.lines().next(), Some("from,to,weight,emails,first_seen,last_seen,active_days"));
+    assert!(text.contains("\nalice@example.com,bob@example.com,3,3,2001-05-14T17:30:00+00:00,2001-05-15T16:45:00+00:00,2\n"));
+    export::write_edge_list(&graph, false, &path).unwrap();
+    assert!(std::fs::read_to_string(&path).unwrap().starts_with("from,to,weight,emails\n"));
+
+    let path = dir.path().join("graph.graphml");
+    export::write_graphml(&graph, None, true, &path).unwrap();
+    let xml = std::fs::read_to_string(&path).unwrap();
+    assert!(xml.contains(r#"<key id="active_days" for="edge" attr.name="active_days" attr.type="int"/>"#));
+    assert!(xml.contains(concat!(
+        r#"    <edge source="alice@example.com" target="bob@example.com">"#, "\n",
+        r#"      <data key="emails">3</data>"#, "\n",
+        r#"      <data key="first_seen">2001-05-14T17:30:00+00:00</data>"#, "\n",
+        r#"      <data key="last_seen">2001-05-15T16:45:00+00:00</data>"#, "\n",
+        r#"      <data key="active_days">2</data>"#, "\n",
+    )));
+}
+
+/// Reads the node data of a GraphML file written by `write_graphml` back
+/// into attribute name -> value maps, by node.
+#[cfg(test)]
+fn read_graphml_node_data(xml: &str) -> HashMap<String, BTreeMap<String, String>> {
+    let unescape = |text: &str| {
+        text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+    };
+    let between = |line: &str, start: &str, end: &str| -> Option<String> {
+        let from = line.find(start)? + start.len();
+        Some(unescape(&line[from..from + line[from..].find(end)?]))
+    };
+
+    let mut names = HashMap::new();
+    let mut nodes: HashMap<String, BTreeMap<String, String>> = HashMap::new();
+    let mut current = None;
+    for line in xml.lines().map(str::trim) {
+        if line.starts_with("<key ") {
+            names.insert(between(line, "id=\"", "\"").unwrap(), between(line, "attr.name=\"", "\"").unwrap());
+        } else if line.starts_with("<node ") {
+            let id = between(line, "id=\"", "\"").unwrap();
+            nodes.entry(id.clone()).or_default();
+            current = (!line.ends_with("/>")).then_some(id);
+        } else if let (Some(node), true) = (&current, line.starts_with("<data ")) {
+            let key = between(line, "key=\"", "\"").unwrap();
+            let value = between(line, ">", "</data>").unwrap();
+            nodes.get_mut(node).unwrap().insert(names[&key].clone(), value);
+        } else if line == "</node>" {
+            current = None;
+        }
+    }
+    nodes
+}
+
+#[test]
+fn test_node_attributes_round_trip_through_graphml() {
+    let outcome = read_csv("tests/fixtures/display_names.csv").unwrap();
+    // The first name seen for an address wins; plain addresses have none
+    let expected: HashMap<String, String> = [("jane@corp.com", "Doe, Jane"), ("bob@corp.com", "Bob Smith")]
+        .iter()
+        .map(|(address, name)| (address.to_string(), name.to_string()))
+        .collect();
+    assert_eq!(outcome.display_names, expected);
+    assert_eq!(email::display_name("'Jane D.' <jane@corp.com>").as_deref(), Some("Jane D."));
+    assert_eq!(email::display_name("<jane@corp.com>"), None);
+
+    let mut graph = Graph::build_from_emails(outcome.emails);
+    for (address, name) in &outcome.display_names {
+        assert!(graph.set_node_attr(address, graph::DISPLAY_NAME_ATTR, name.clone()));
+    }
+    assert!(!graph.set_node_attr("nobody@corp.com", "department", "Ghost".to_string()));
+    assert_eq!(graph.load_node_tags(std::path::Path::new("tests/fixtures/node_tags.csv")).unwrap(), 3);
+
+    assert_eq!(graph.get_node_attr("bob@corp.com", "department"), Some("Trading"));
+    assert_eq!(graph.get_node_attr("carol@corp.com", graph::DOMAIN_ATTR), Some("corp.com"));
+    assert_eq!(graph.get_node_attr("carol@corp.com", graph::DISPLAY_NAME_ATTR), None);
+    assert_eq!(graph.node_attribute_keys(), vec!["display_name", "domain", "department", "title"]);
+    let report = graph.node_report("Jane@Corp.com", None).unwrap();
+    assert_eq!(report.attributes, vec![
+        ("display_name".to_string(), "Doe, Jane".to_string()),
+        ("domain".to_string(), "corp.com".to_string()),
+        ("department".to_string(), "Legal".to_string()),
+        ("title".to_string(), "VP & <Counsel>".to_string()),
+    ]);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("graph.graphml");
+    export::write_graphml(&graph, None, false, &path).unwrap();
+    let exported = read_graphml_node_data(&std::fs::read_to_string(&path).unwrap());
+    assert_eq!(exported.len(), graph.node_count());
+    for (node, data) in &exported {
+        let attrs: BTreeMap<String, String> = graph
+            .node_attrs(node)
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        assert_eq!(*data, attrs, "{}", node);
+    }
+
+    // Pseudonyms keep the tags but not the names
+    let anonymized = graph.anonymize(&mut AddressMapper::new(false));
+    let tagged: Vec<&graph::NodeMeta> = anonymized.node_attributes.values().collect();
+    assert_eq!(tagged.len(), 2);
+    assert!(tagged.iter().all(|meta| meta.display_name.is_none() && meta.tags.contains_key("department")));
+}
+
+#[test]
+fn test_distance_distribution_matches_exact_counts() {
+    // A directed path a -> b -> c -> d, plus a pair outside the largest component
+    let graph = Graph::from_edges(&[("a", "b"), ("b", "c"), ("c", "d"), ("x", "y")]);
+
+    // Asking for every ordered pair measures all 12 exactly
+    let exact = graph.sample_distances(100, 1);
+    assert_eq!(exact.pairs, 12);
+    assert_eq!(exact.hops, BTreeMap::from([(1, 6), (2, 4), (3, 2)]));
+    assert_eq!(exact.unreachable, 6); // Every pair pointing back up the path
+    assert_eq!(exact.fraction_within(2), 10.0 / 12.0);
+    assert_eq!(graph.distance_distribution(100, 1), exact.hops);
+
+    // A smaller sample has distinct pairs, is reproducible and never exceeds the exact counts
+    let sampled = graph.sample_distances(5, 9);
+    assert_eq!(sampled, graph.sample_distances(5, 9));
+    assert_eq!(sampled.hops.values().sum::<usize>(), 5);
+    for (hops, count) in &sampled.hops {
+        assert!(*count <= exact.hops[hops]);
+    }
+    assert_eq!(Graph::new().sample_distances(10, 1), graph::DistanceSample::default());
+}
+
+#[test]
+fn test_combined_rankings_merge_conflicting_orders() {
+    let counts = |values: [usize; 3]| -> HashMap<String, usize> {
+        ["a", "b", "c"].iter().map(|node| node.to_string()).zip(values).collect()
+    };
+    let degree = counts([3, 2, 1]);
+    let weighted_degree = counts([1, 3, 2]);
+    let pagerank: HashMap<String, f64> = [("a", 0.2), ("b", 0.3), ("c", 0.5)]
+        .into_iter()
+        .map(|(node, score)| (node.to_string(), score))
+        .collect();
+    let metrics = CentralityMetrics { degree: &degree, weighted_degree: &weighted_degree, pagerank: Some(&pagerank), betweenness: None };
+
+    // Each node leads one measure, so all three make a top 1
+    let rows = combined_rankings(&metrics, 1);
+    let rank = |node: &str, degree, weighted_degree, pagerank| CombinedRank {
+        node: node.to_string(),
+        degree: Some(degree),
+        weighted_degree: Some(weighted_degree),
+        pagerank: Some(pagerank),
+        betweenness: None,
+    };
+    assert_eq!(rows, [rank("a", 1, 3, 3), rank("b", 2, 1, 2), rank("c", 3, 2, 1)]);
+
+    // Without PageRank, c is nobody's top 1
+    let volume_only = CentralityMetrics { pagerank: None, ..metrics };
+    let nodes: Vec<String> = combined_rankings(&volume_only, 1).into_iter().map(|row| row.node).collect();
+    assert_eq!(nodes, ["a", "b"]);
+}
+
+#[test]
+fn test_skipped_rows_are_logged_only_when_verbose() {
+    let path = "tests/fixtures/broken_emails.csv";
+    let (schema, filter) = (email::CsvSchema::default(), filter::EmailFilter::default());
+
+    let verbose = Logger::capturing(Verbosity::Verbose);
+    let mut outcome = email::read_csv_with(path, &schema, &filter, &verbose).unwrap();
+    let messages = verbose.messages();
+    assert_eq!(messages.len(), outcome.failed_count());
+    assert!(messages.iter().all(|message| message.starts_with("tests/fixtures/broken_emails.csv: skipped row")));
+    assert!(messages.iter().any(|message| message.contains("missing sender")));
+
+    // By default only the count is reported, and quiet says nothing at all
+    let normal = Logger::capturing(Verbosity::Normal);
+    email::read_csv_with(path, &schema, &filter, &normal).unwrap();
+    assert!(normal.messages().is_empty());
+    let report = outcome.take_report();
+    report_parse_outcome(&report, &outcome, &normal);
+    assert!(normal.messages().contains(&"Failed to parse 3 records (1 malformed, 1 without sender, 1 without recipients); use -v to list them.".to_string()));
+    let quiet = Logger::capturing(Verbosity::Quiet);
+    report_parse_outcome(&report, &outcome, &quiet);
+    quiet.warn("ignored");
+    assert!(quiet.messages().is_empty());
+
+    let parse = |args: &[&str]| Cli::try_parse_from([&["email_analysis"], args].concat()).map(|cli| cli.verbosity());
+    assert_eq!(parse(&[]).unwrap(), None);
+    assert_eq!(parse(&["-v"]).unwrap(), Some(Verbosity::Verbose));
+    assert_eq!(parse(&["-vv"]).unwrap(), Some(Verbosity::Debug));
+    assert_eq!(parse(&["--quiet"]).unwrap(), Some(Verbosity::Quiet));
+    assert!(parse(&["-q", "-v"]).is_err());
+}
+
+#[test]
+fn test_metric_rank_correlation_uses_average_ranks() {
+    let metric = |values: &[(&str, f64)]| -> HashMap<String, f64> {
+        values.iter().map(|(node, value)| (node.to_string(), *value)).collect()
+    };
+    let metrics: HashMap<String, HashMap<String, f64>> = HashMap::from([
+        ("a".to_string(), metric(&[("n1", 1.0), ("n2", 2.0), ("n3", 3.0), ("n4", 4.0)])),
+        // Same order on a different scale
+        ("b".to_string(), metric(&[("n1", 10.0), ("n2", 20.0), ("n3", 30.0), ("n4", 40.0)])),
+        // The top two swapped: 1 - 6 * 2 / (4 * 15) = 0.8
+        ("c".to_string(), metric(&[("n1", 1.0), ("n2", 2.0), ("n3", 4.0), ("n4", 3.0)])),
+        // A tie on n1 and n2 (ranks 1.5 and 1.5), and no score for n4
+        ("d".to_string(), metric(&[("n1", 5.0), ("n2", 5.0), ("n3", 7.0)])),
+    ]);
+
+    let correlations: HashMap<(String, String), f64> = graph::metric_rank_correlation(&metrics)
+        .into_iter()
+        .map(|(first, second, rho)| ((first, second), rho))
+        .collect();
+    let rho = |first: &str, second: &str| correlations[&(first.to_string(), second.to_string())];
+    assert_eq!(correlations.len(), 6);
+    assert!((rho("a", "b") - 1.0).abs() < 1e-12);
+    assert!((rho("a", "c") - 0.8).abs() < 1e-12);
+    // Over n1..n3: ranks (1.5, 1.5, 3) against (1, 2, 3) give 1.5 / sqrt(1.5 * 2)
+    assert!((rho("a", "d") - 1.5 / 3.0f64.sqrt()).abs() < 1e-12);
+    assert!(graph::metric_rank_correlation(&HashMap::new()).is_empty());
+}
+
+#[test]
+fn test_degree_outliers_flag_only_the_broadcaster() {
+    // Everyone in a ring writes to the next two people; one broadcaster writes to all of them
+    let mut graph = Graph::new();
+    for i in 0..30 {
+        for step in 1..=2 {
+            graph.add_edge(format!("p{}", i), format!("p{}", (i + step) % 30));
+        }
+        graph.add_edge("broadcast".to_string(), format!("p{}", i));
+    }
+    graph.add_edge("p0".to_string(), "broadcast".to_string());
+
+    let outliers = graph.degree_outliers(3.0);
+    assert_eq!(outliers.len(), 1);
+    let broadcaster = &outliers[0];
+    assert_eq!(broadcaster.node, "broadcast");
+    assert_eq!((broadcaster.out_degree, broadcaster.in_degree), (30, 1));
+    assert!(broadcaster.out_z > 3.0);
+    assert!(broadcaster.in_z < -3.0); // Hears from far fewer people than anyone else
+    assert_eq!(broadcaster.max_abs_z(), broadcaster.out_z.abs().max(broadcaster.in_z.abs()));
+
+    // A looser threshold doesn't drag in the regular people, whose degrees barely vary
+    assert_eq!(graph.degree_outliers(1.0).len(), 1);
+    assert!(Graph::from_edges(&[("a", "b"), ("b", "a")]).degree_outliers(0.0).is_empty());
+}
+
+#[test]
+fn test_read_csv_from_reader_matches_file_reader() {
+    use std::io::Cursor;
+
+    // Every fixture reads the same from memory as from disk, bad rows and sniffed delimiters included
+    let schema = email::CsvSchema::default();
+    for fixture in ["multi_recipient.csv", "broken_emails.csv", "delimited.tsv", "delimited_semicolon.csv"] {
+        let path = format!("tests/fixtures/{}", fixture);
+        let bytes = std::fs::read(&path).unwrap();
+        let from_reader = email::read_csv_from_reader(Cursor::new(bytes), &schema).unwrap();
+        let from_file = read_csv(&path).unwrap();
+        assert_eq!(from_reader.emails, from_file.emails, "{}", fixture);
+        assert_eq!(from_reader.subjects, from_file.subjects, "{}", fixture);
+        assert_eq!(from_reader.failed_count(), from_file.failed_count(), "{}", fixture);
+    }
+
+    let schema = email::CsvSchema { sender_column: "From".to_string(), ..email::CsvSchema::default() };
+    let input = "From,recipient1,date,subject,text\nAlice@Example.com,bob@example.com,2001-05-14,hi,hello\n";
+    let outcome = email::read_csv_from_reader(input.as_bytes(), &schema).unwrap();
+    assert_eq!(outcome.emails.len(), 1);
+    assert_eq!(outcome.emails[0].from, "alice@example.com");
+
+    let err = email::read_csv_from_reader(Cursor::new(b"from,to\n"), &email::CsvSchema::default()).unwrap_err();
+    assert!(matches!(err, EmailError::MissingColumn { ref column, .. } if column == "sender"));
+}
+
+#[test]
+fn test_diff_refuses_to_read_stdin_twice() {
+    let err = run_diff(&Config::default(), &Logger::silent(), "-", "-").unwrap_err();
+    assert!(matches!(err, Error::StdinReadTwice));
+}
+
+#[test]
+fn test_reader_keeps_subjects_with_emails() {
+    let outcome = read_csv("tests/fixtures/subjects.csv").unwrap();
+    assert_eq!(outcome.subjects.len(), outcome.emails.len());
+    let full = outcome.into_full_emails();
+    assert_eq!(full[0].subject, "Invoice #1042");
+    assert_eq!(full[2].email.from, "carol@example.com");
+
+    let threads = threads::group_into_threads(&full);
+    assert_eq!(threads["invoice #1042"].message_count, 2);
+    assert_eq!(threads["invoice #1042"].span(), Some(chrono::Duration::days(2)));
+}
+
+#[test]
+fn test_community_keywords_reflect_planted_vocabularies() {
+    assert_eq!(keywords::tokenize("Re: The Q3 budget, revised!"), ["q3", "budget", "revised"]);
+
+    let email = |from: &str, subject: &str| email::ParsedEmailFull {
+        email: ParsedEmail { from: from.to_string(), to: vec!["x@example.com".to_string()], timestamp: None },
+        subject: subject.to_string(),
+    };
+    let emails = vec![
+        email("trader1@example.com", "gas trading desk positions"),
+        email("trader2@example.com", "Re: trading positions for the gas desk"),
+        email("trader1@example.com", "gas prices update"),
+        email("lawyer1@example.com", "contract review for the merger"),
+        email("lawyer2@example.com", "merger contract draft update"),
+        email("outsider@example.com", "trading contract"), // No community
+    ];
+    let labels: HashMap<String, String> = [
+        ("trader1@example.com", "C0"),
+        ("trader2@example.com", "C0"),
+        ("lawyer1@example.com", "C1"),
+        ("lawyer2@example.com", "C1"),
+    ]
+    .iter()
+    .map(|(node, label)| (node.to_string(), label.to_string()))
+    .collect();
+
+    let profiles = keywords::community_keywords(&emails, &labels, 3);
+    let words = |label: &str| profiles[label].iter().map(|(word, _)| word.as_str()).collect::<Vec<_>>();
+    assert_eq!(words("C0"), ["gas", "desk", "positions"]);
+    assert_eq!(words("C1"), ["contract", "merger", "draft"]);
+    assert!(profiles.values().flatten().all(|(word, score)| word != "update" && *score > 0.0));
+}
+
+#[test]
+fn test_graph_diff_reports_exact_changes() {
+    let build = |edges: &[(&str, &str)]| {
+        let mut graph = Graph::new();
+        for (from, to) in edges {
+            graph.add_edge(from.to_string(), to.to_string());
+        }
+        graph
+    };
+    let january = build(&[("a", "b"), ("b", "c"), ("c", "d")]);
+    let february = build(&[("a", "b"), ("a", "b"), ("b", "c"), ("a", "e"), ("e", "b")]);
+
+    let diff = january.diff(&february);
+    let pair = |from: &str, to: &str| (from.to_string(), to.to_string());
+    assert_eq!(diff.added_nodes, ["e"]);
+    assert_eq!(diff.removed_nodes, ["d"]);
+    assert_eq!(diff.added_edges, [pair("a", "e"), pair("e", "b")]);
+    assert_eq!(diff.removed_edges, [pair("c", "d")]);
+    assert_eq!(diff.weight_changes, [graph::WeightChange { from: "a".to_string(), to: "b".to_string(), before: 1.0, after: 2.0 }]);
+    assert_eq!(diff.degree_deltas["a"], 1);
+    assert_eq!(diff.degree_deltas["b"], 1);
+    assert_eq!(diff.degree_deltas["c"], -1);
+    assert_eq!(diff.degree_deltas["d"], -1);
+    assert_eq!(diff.degree_deltas["e"], 2);
+    assert_eq!(diff.degree_deltas.len(), 5);
+
+    let unchanged = january.diff(&january);
+    assert!(unchanged.added_edges.is_empty() && unchanged.removed_edges.is_empty() && unchanged.weight_changes.is_empty());
+}
+
+#[test]
+fn test_cli_definition_is_consistent() {
+    use clap::CommandFactory;
+    Cli::command().debug_assert();
+}
+
+#[test]
+fn test_incremental_build_matches_batch_build() {
+    let emails = read_csv("tests/fixtures/multi_recipient.csv").unwrap().emails;
+    let (first, second) = emails.split_at(emails.len() / 2);
+
+    let mut incremental = Graph::build_from_emails(first.to_vec());
+    debug_assert_valid!(incremental);
+    let stats = incremental.add_emails(second.to_vec());
+    debug_assert_valid!(incremental);
+    let batch = Graph::build_from_emails(emails.clone());
+    assert_eq!(incremental, batch);
+    assert_eq!(incremental.edge_count(), batch.edge_count());
+    assert_eq!(stats.new_nodes + Graph::build_from_emails(first.to_vec()).node_count(), batch.node_count());
+
+    // Adding the same emails again only adds weight
+    let again = incremental.add_emails(emails.clone());
+    debug_assert_valid!(incremental);
+    let recipients: usize = emails.iter().map(|email| email.to.len()).sum();
+    assert_eq!(again, graph::AddStats { new_nodes: 0, new_edges: 0, duplicate_edges: recipients, self_loops_skipped: 0 });
+    assert_eq!(incremental.edge_count(), batch.edge_count());
+    for (from, to) in batch.edge_weights.iter().flat_map(|(from, weights)| weights.keys().map(move |to| (from, to))) {
+        assert_eq!(incremental.edge_multiplicity(from, to), 2 * batch.edge_multiplicity(from, to));
+    }
+}
+
+#[test]
+fn test_strength_statistics_separate_from_degree() {
+    let outcome = read_csv("tests/fixtures/strength.csv").expect("fixture should open");
+    let graph = Graph::build_from_emails(outcome.emails);
+
+    // chatty has the lowest non-zero out-degree but sends the most emails
+    assert_eq!(graph.calculate_out_degrees()["chatty@example.com"], 1);
+    assert_eq!(graph.calculate_out_strengths()["chatty@example.com"], 6);
+    assert_eq!(graph.calculate_in_strengths()["boss@example.com"], 6);
+    assert_eq!(graph.calculate_in_strengths()["chatty@example.com"], 0);
+
+    let stats = degree_statistics(&graph).unwrap();
+    assert_eq!(stats.total_nodes, 6);
+    assert_eq!(stats.total_out_degree, 4);
+    assert_eq!(stats.max_out_degree, 3); // hub
+    assert_eq!(stats.max_in_degree, 1);
+    assert!((stats.average_out_degree - 4.0 / 6.0).abs() < 1e-9);
+
+    assert_eq!(stats.strength, StrengthStats {
+        total_emails: 9,
+        average_out_strength: 1.5,
+        max_out_strength: 6,
+        min_out_strength: 0,
+        average_in_strength: 1.5,
+        max_in_strength: 6,
+        min_in_strength: 0,
+    });
+}
+
+#[test]
+fn test_weighted_pagerank_matches_unweighted_on_equal_weights() {
+    // Every generated edge carries weight 1.0, and dangling nodes are likely at this density
+    let graph = Graph::erdos_renyi(60, 0.05, 3);
+    let plain = graph.pagerank(0.85, 1e-10, 200);
+    let weighted = graph.weighted_pagerank(0.85, 1e-10, 200);
+
+    assert_eq!(plain.len(), 60);
+    for (node, score) in &plain {
+        assert!((score - weighted[node]).abs() < 1e-9, "{} differs", node);
+    }
+    let total: f64 = weighted.values().sum();
+    assert!((total - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_weighted_pagerank_follows_heavy_edges() {
+    // hub emails heavy 9 times and light once; unweighted they look the same
+    let mut graph = Graph::new();
+    for _ in 0..9 {
+        graph.add_edge("hub".to_string(), "heavy".to_string());
+    }
+    graph.add_edge("hub".to_string(), "light".to_string());
+
+    let plain = graph.pagerank(0.85, 1e-10, 200);
+    assert!((plain["heavy"] - plain["light"]).abs() < 1e-9);
+
+    let weighted = graph.weighted_pagerank(0.85, 1e-10, 200);
+    assert!(weighted["heavy"] > weighted["light"]);
+    let total: f64 = weighted.values().sum();
+    assert!((total - 1.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_modularity_hand_computed() {
+    // Two separate pairs: a <-> b and c -> d
+    let mut graph = Graph::new();
+    graph.add_edge("a".to_string(), "b".to_string());
+    graph.add_edge("b".to_string(), "a".to_string());
+    graph.add_edge("c".to_string(), "d".to_string());
+    let label = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs.iter().map(|(node, label)| (node.to_string(), label.to_string())).collect()
+    };
+
+    // Strengths a=2, b=2, c=1, d=1 out of 6
+    let pairs = label(&[("a", "x"), ("b", "x"), ("c", "y"), ("d", "y")]);
+    let expected = (4.0 / 6.0 - (4.0f64 / 6.0).powi(2)) + (2.0 / 6.0 - (2.0f64 / 6.0).powi(2));
+    assert!((graph.modularity(&pairs) - expected).abs() < 1e-12);
+
+    let together = label(&[("a", "x"), ("b", "x"), ("c", "x"), ("d", "x")]);
+    assert!(graph.modularity(&together).abs() < 1e-12);
+
+    // Missing labels count as singletons
+    let singletons = -(2.0 * (2.0f64 / 6.0).powi(2) + 2.0 * (1.0f64 / 6.0).powi(2));
+    assert!((graph.modularity(&HashMap::new()) - singletons).abs() < 1e-12);
+    assert_eq!(Graph::new().modularity(&HashMap::new()), 0.0);
+}
+
+#[test]
+fn test_label_propagation_reports_convergence() {
+    let graph = Graph::from_edges(&[("a", "b"), ("b", "c"), ("c", "a"), ("x", "y"), ("y", "z"), ("z", "x")]);
+    let opts = graph::LpaOptions { seed: Some(3), ..Default::default() };
+
+    let result = graph.label_propagation_detailed(&opts);
+    assert!(result.converged);
+    assert!(result.iterations_run <= 10, "took {} iterations", result.iterations_run);
+    assert_eq!(result.changes_per_iteration.len(), result.iterations_run);
+    assert_eq!(result.changes_per_iteration.last(), Some(&0));
+    assert_eq!(result.labels, graph.label_propagation_with_options(&opts));
+
+    // One pass is not enough for anyone to settle
+    let capped = graph.label_propagation_detailed(&graph::LpaOptions { max_iterations: 1, ..opts });
+    assert!(!capped.converged);
+    assert_eq!(capped.iterations_run, 1);
+    assert!(capped.changes_per_iteration[0] > 0);
+}
+
+#[test]
+fn test_refine_partition_splits_disconnected_community() {
+    // Two triangles with no edge between them, wrongly put in one community
+    let graph = Graph::from_edges(&[("a", "b"), ("b", "c"), ("c", "a"), ("x", "y"), ("y", "z"), ("z", "x")]);
+    let labels: HashMap<String, String> = nodes_of(&graph)
+        .into_iter()
+        .map(|node| (node, "all".to_string()))
+        .collect();
+    assert!(!graph.communities_are_connected(&labels));
+
+    let refined = graph.refine_partition(&labels, 1);
+    assert!(graph.communities_are_connected(&refined));
+    assert_eq!(refined["a"], refined["b"]);
+    assert_eq!(refined["a"], refined["c"]);
+    assert_eq!(refined["x"], refined["y"]);
+    assert_eq!(refined["x"], refined["z"]);
+    assert_ne!(refined["a"], refined["x"]);
+    assert!(graph.modularity(&refined) > graph.modularity(&labels));
+}
+
+#[test]
+fn test_compare_communities_agree_on_planted_partition() {
+    let graph = Graph::planted_partition(2, 30, 0.4, 0.005, 11);
+    let options = graph::LpaOptions::default();
+    let comparison = graph.compare_communities(&[1, 2], 5, &options);
+
+    let methods: Vec<&str> = comparison.partitions.iter().map(|partition| partition.method.as_str()).collect();
+    assert_eq!(methods, ["lpa (seed 1)", "lpa (seed 2)", "louvain", "leiden"]);
+    for (i, partition) in comparison.partitions.iter().enumerate() {
+        assert_eq!(partition.communities, 2, "{}", partition.method);
+        assert_eq!(partition.largest, 30);
+        assert_eq!(partition.singletons, 0);
+        assert!((partition.modularity - graph.modularity(&partition.labels)).abs() < 1e-12);
+        assert!((comparison.nmi[i][i] - 1.0).abs() < 1e-12);
+        assert!(comparison.nmi[i].iter().all(|&nmi| nmi > 0.95), "{:?}", comparison.nmi);
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("comparison.csv");
+    export::write_community_comparison_csv(&comparison, &path).unwrap();
+    let csv = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines[0], "method,communities,largest,singletons,modularity,runtime_ms,lpa (seed 1),lpa (seed 2),louvain,leiden");
+    assert_eq!(lines.len(), 5);
+    assert!(lines[3].starts_with("louvain,2,30,0,"));
+}
+
+#[test]
+fn test_normalized_mutual_information() {
+    let partition = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs.iter().map(|(node, label)| (node.to_string(), label.to_string())).collect()
+    };
+    let a = partition(&[("a", "x"), ("b", "x"), ("c", "y"), ("d", "y")]);
+    let renamed = partition(&[("a", "1"), ("b", "1"), ("c", "2"), ("d", "2")]);
+    let crossed = partition(&[("a", "1"), ("b", "2"), ("c", "1"), ("d", "2")]);
+    let one = partition(&[("a", "1"), ("b", "1"), ("c", "1"), ("d", "1")]);
+
+    assert!((community::normalized_mutual_information(&a, &renamed) - 1.0).abs() < 1e-12);
+    assert!(community::normalized_mutual_information(&a, &crossed).abs() < 1e-12);
+    assert!(community::normalized_mutual_information(&a, &one).abs() < 1e-12);
+    assert_eq!(community::normalized_mutual_information(&one, &one), 1.0);
+}
+
+#[test]
+fn test_louvain_and_leiden_recover_planted_partition() {
+    let (communities, size) = (4, 30);
+    let graph = Graph::planted_partition(communities, size, 0.4, 0.005, 11);
+
+    for labels in [graph.louvain(5), graph.leiden(5)] {
+        let mut block_labels = HashSet::new();
+        for group in 0..communities {
+            let members: HashSet<&String> = (group * size..(group + 1) * size)
+                .map(|node| &labels[&format!("n{}", node)])
+                .collect();
+            assert_eq!(members.len(), 1, "group {} split", group);
+            block_labels.extend(members);
+        }
+        assert_eq!(block_labels.len(), communities);
+    }
+
+    // The refinement keeps communities connected and never loses modularity
+    let louvain = graph.louvain(5);
+    let leiden = graph.leiden(5);
+    assert!(graph.communities_are_connected(&leiden));
+    assert!(graph.modularity(&leiden) >= graph.modularity(&louvain) - 1e-12);
+    assert!(graph.modularity(&leiden) > 0.6);
+    assert_eq!(leiden, graph.leiden(5));
+}
+
+#[test]
+fn test_seeded_label_propagation_on_barbell() {
+    // Two 5-cliques joined by a single two-way bridge between n4 and n5
+    let mut graph = Graph::new();
+    for half in [0..5, 5..10] {
+        for from in half.clone() {
+            for to in half.clone() {
+                if from != to {
+                    graph.add_edge(format!("n{}", from), format!("n{}", to));
+                }
+            }
+        }
+    }
+    graph.add_edge("n4".to_string(), "n5".to_string());
+    graph.add_edge("n5".to_string(), "n4".to_string());
+    graph.insert_node("loner".to_string());
+
+    let fixed: HashMap<String, String> = [("n0", "A"), ("n9", "B"), ("absent", "C")]
+        .iter()
+        .map(|(node, label)| (node.to_string(), label.to_string()))
+        .collect();
+    let options = graph::LpaOptions { seed: Some(3), ..graph::LpaOptions::default() };
+    let labels = graph.label_propagation_seeded(&fixed, &options);
+
+    for node in 0..5 {
+        assert_eq!(labels[&format!("n{}", node)], "A");
+        assert_eq!(labels[&format!("n{}", node + 5)], "B");
+    }
+    assert!(!labels.contains_key("absent"));
+
+    // No seed reaches the loner: it keeps its own name unless marked
+    assert_eq!(labels["loner"], "loner");
+    let marked = graph.label_propagation_seeded(&fixed, &graph::LpaOptions { mark_unlabeled: true, ..options });
+    assert_eq!(marked["loner"], graph::UNLABELED_LABEL);
+    assert_eq!(marked["n3"], "A");
+}
+
+#[test]
+fn test_read_seed_labels_and_cli_flag() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("seeds.csv");
+    std::fs::write(&path, "address,label\nAlice@Example.com,sales\n,orphan\nbob@example.com,\ncarol@example.com,legal\n").unwrap();
+
+    let seeds = community::read_seed_labels(&path).unwrap();
+    assert_eq!(seeds.len(), 2);
+    assert_eq!(seeds["alice@example.com"], "sales");
+    assert_eq!(seeds["carol@example.com"], "legal");
+
+    let cli = Cli::try_parse_from([
+        "email_analysis",
+        "--seed-labels", path.to_str().unwrap(),
+        "--mark-unlabeled",
+    ])
+    .unwrap();
+    let config = resolve_config(&cli, &Logger::silent()).unwrap();
+    assert_eq!(config.algorithms.seed_labels, seeds);
+    assert!(config.algorithms.lpa_options().mark_unlabeled);
+}
+
+#[test]
+fn test_partition_flow_follows_a_clean_split() {
+    let partition = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs.iter().map(|(node, label)| (node.to_string(), label.to_string())).collect()
+    };
+    // C0 splits into L1 and L2; f leaves and g joins
+    let earlier = partition(&[("a", "C0"), ("b", "C0"), ("c", "C0"), ("d", "C0"), ("e", "C0"), ("f", "C1")]);
+    let later = partition(&[("a", "L1"), ("b", "L1"), ("c", "L1"), ("d", "L2"), ("e", "L2"), ("g", "L2")]);
+
+    let flows = community::partition_flow(&earlier, &later);
+    let flow = |from: &str, to: &str, nodes| community::FlowEdge { from: from.to_string(), to: to.to_string(), nodes };
+    assert_eq!(
+        flows,
+        [
+            flow("C0", "L1", 3),
+            flow("C0", "L2", 2),
+            flow("(new)", "L2", 1),
+            flow("C1", "(gone)", 1),
+        ]
+    );
+    let total: usize = flows.iter().map(|flow| flow.nodes).sum();
+    assert_eq!(total, 7);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("flow.csv");
+    export::write_partition_flow_csv(&flows, &path).unwrap();
+    let csv = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(csv.lines().take(3).collect::<Vec<_>>(), ["from,to,nodes", "C0,L1,3", "C0,L2,2"]);
+}
+
+#[test]
+fn test_partition_round_trip_and_missing_nodes() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("communities.csv");
+    let labels: HashMap<String, String> = [("a", "C0"), ("b", "C0"), ("c", "C1")]
+        .into_iter()
+        .map(|(node, label)| (node.to_string(), label.to_string()))
+        .collect();
+    community::write_partition(&path, &labels).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "address,label\na,C0\nb,C0\nc,C1\n");
+    assert_eq!(community::read_partition(&path).unwrap(), labels);
+
+    // d is new in the graph and c has left it
+    let graph = Graph::from_edges(&[("a", "b"), ("b", "d")]);
+    let fitted = community::fit_partition(&graph, labels.clone());
+    assert_eq!(fitted.missing, ["d"]);
+    assert_eq!(fitted.ignored, 1);
+    assert_eq!(fitted.labels.len(), 3);
+    assert_eq!((fitted.labels["a"].as_str(), fitted.labels["b"].as_str()), ("C0", "C0"));
+    assert_eq!(fitted.labels["d"], "d");
+
+    // A fresh label never joins an existing community
+    let clash = HashMap::from([("a".to_string(), "d".to_string())]);
+    let fitted = community::fit_partition(&graph, clash);
+    assert_eq!(fitted.labels["d"], "d#2");
+    assert_eq!(fitted.labels["b"], "b");
+
+    // --communities-file replaces detection
+    let cli = Cli::try_parse_from(["email_analysis", "--communities-file", path.to_str().unwrap()]).unwrap();
+    let config = resolve_config(&cli, &Logger::silent()).unwrap();
+    let communities = communities_for(&graph, &config, &Logger::silent()).unwrap();
+    assert_eq!(communities.labels, community::fit_partition(&graph, labels).labels);
+    let cli = Cli::try_parse_from(["email_analysis", "--partition-csv", "out.csv"]).unwrap();
+    assert_eq!(resolve_config(&cli, &Logger::silent()).unwrap().output.partition_csv, Some("out.csv".into()));
+}
+
+#[test]
+fn test_classify_nodes_covers_every_class() {
+    let outcome = read_csv("tests/fixtures/node_classes.csv").expect("fixture should open");
+    let graph = Graph::build_from_emails(outcome.emails);
+    let classes = graph.classify_nodes();
+
+    assert_eq!(classes["alice@example.com"], NodeClass::Connector);
+    assert_eq!(classes["bob@example.com"], NodeClass::Connector);
+    assert_eq!(classes["robot@example.com"], NodeClass::Source);
+    assert_eq!(classes["list@example.com"], NodeClass::Sink);
+    // Self-loops are ignored: carol still only sends to others, narcissus to nobody
+    assert_eq!(classes["carol@example.com"], NodeClass::Source);
+    assert_eq!(classes["narcissus@example.com"], NodeClass::Isolated);
+
+    let stats = degree_statistics(&graph).unwrap();
+    assert_eq!(stats.node_classes, NodeClassCounts { sources: 2, sinks: 1, connectors: 2, isolated: 1 });
+
+    // Excluded classes get no community label
+    let mut config = Config::default();
+    config.algorithms.lpa_seed = Some(1);
+    config.algorithms.exclude_sources = true;
+    config.algorithms.exclude_sinks = true;
+    let labels = detect_communities(&graph, &config, &Logger::silent()).labels;
+    let mut labeled: Vec<&String> = labels.keys().collect();
+    labeled.sort();
+    assert_eq!(labeled, ["alice@example.com", "bob@example.com", "narcissus@example.com"]);
+}
+
+#[test]
+fn test_degree_correlation_and_asymmetry_hand_computed() {
+    // Out-degrees a=3, b=1, c=1, d=0; in-degrees a=1, b=1, c=1, d=2
+    let graph = Graph::from_edges(&[("a", "b"), ("a", "c"), ("a", "d"), ("b", "a"), ("c", "d")]);
+    // Covariance -1.25, variances 0.75 (in) and 4.75 (out)
+    let expected = -1.25 / (0.75f64 * 4.75).sqrt();
+    assert!((graph.degree_correlation() - expected).abs() < 1e-12);
+    assert!((degree_statistics(&graph).unwrap().degree_correlation - expected).abs() < 1e-12);
+
+    assert_eq!(graph.most_asymmetric_nodes(5), vec![("a".to_string(), 2), ("d".to_string(), -2)]);
+    assert_eq!(graph.most_asymmetric_nodes(1), vec![("a".to_string(), 2)]);
+
+    // Equal degrees everywhere have no variance to correlate
+    let cycle = Graph::from_edges(&[("a", "b"), ("b", "c"), ("c", "a")]);
+    assert_eq!(cycle.degree_correlation(), 0.0);
+    assert!(cycle.most_asymmetric_nodes(5).is_empty());
+    assert_eq!(Graph::new().degree_correlation(), 0.0);
+}
+
+#[test]
+fn test_eccentricities_on_path_graph() {
+    // P5 a - b - c - d - e, with mixed edge directions, plus a separate pair
+    let graph = Graph::from_edges(&[("a", "b"), ("c", "b"), ("c", "d"), ("d", "e"), ("x", "y")]);
+
+    let components = graph.weakly_connected_components();
+    assert_eq!(components.len(), 2);
+    assert_eq!(components[0], ["a", "b", "c", "d", "e"]);
+    assert_eq!(components[1], ["x", "y"]);
+
+    let eccentricities = graph.eccentricities(None, 0);
+    let expected: HashMap<String, usize> = [("a", 4), ("b", 3), ("c", 2), ("d", 3), ("e", 4)]
+        .iter()
+        .map(|(node, eccentricity)| (node.to_string(), *eccentricity))
+        .collect();
+    assert_eq!(eccentricities, expected);
+    assert_eq!(graph::radius(&eccentricities), Some(2));
+    assert_eq!(graph::diameter(&eccentricities), Some(4));
+    assert_eq!(graph::center(&eccentricities), ["c"]);
+    assert_eq!(graph::periphery(&eccentricities), ["a", "e"]);
+
+    // A sample measures fewer nodes, each exactly
+    let sampled = graph.eccentricities(Some(2), 3);
+    assert_eq!(sampled.len(), 2);
+    for (node, eccentricity) in &sampled {
+        assert_eq!(expected[node], *eccentricity);
+    }
+    assert_eq!(graph.eccentricities(Some(2), 3), sampled);
+    assert!(Graph::new().eccentricities(None, 0).is_empty());
+    assert_eq!(graph::radius(&HashMap::new()), None);
+}
+
+#[test]
+fn test_harmonic_centrality_on_disconnected_graph() {
+    // Chain a -> b -> c, and a separate pair x -> y; closeness is undefined
+    // here because most pairs are unreachable
+    let graph = Graph::from_edges(&[("a", "b"), ("b", "c"), ("x", "y")]);
+
+    let harmonic = graph.harmonic_centrality(None);
+    assert_eq!(harmonic["a"], 0.0); // Nobody reaches a
+    assert_eq!(harmonic["b"], 1.0);
+    assert_eq!(harmonic["c"], 1.5); // b at 1 step, a at 2
+    assert_eq!(harmonic["x"], 0.0);
+    assert_eq!(harmonic["y"], 1.0);
+
+    // Sampling every node is exact, sampling none gives zeros
+    assert_eq!(graph.harmonic_centrality(Some(5)), harmonic);
+    assert!(graph.harmonic_centrality(Some(0)).values().all(|&score| score == 0.0));
+    assert_eq!(graph.harmonic_centrality(Some(2)).len(), 5);
+}
+
+#[test]
+fn test_vote_rank_spreads_picks_beyond_clustered_hubs() {
+    // Hubs h1 and h2 hear from the same six people; m hears from four others
+    let mut graph = Graph::new();
+    for i in 0..6 {
+        graph.add_edge(format!("p{}", i), "h1".to_string());
+        graph.add_edge(format!("p{}", i), "h2".to_string());
+    }
+    for i in 0..4 {
+        graph.add_edge(format!("q{}", i), "m".to_string());
+    }
+
+    let by_degree: Vec<String> = identify_top_recipients(&graph.calculate_in_degrees(), 2)
+        .into_iter()
+        .map(|(node, _)| node)
+        .collect();
+    assert_eq!(by_degree, ["h1", "h2"]);
+
+    // After h1 is picked its voters are dampened, so m outranks h2
+    assert_eq!(graph.vote_rank(2), ["h1", "m"]);
+    assert_eq!(graph.vote_rank(3), ["h1", "m", "h2"]);
+
+    // Nobody is left with votes after the three receivers
+    assert_eq!(graph.vote_rank(10).len(), 3);
+    assert!(Graph::new().vote_rank(3).is_empty());
+}
+
+#[test]
+fn test_remove_node_drops_its_edges() {
+    let mut graph = Graph::from_edges(&[("a", "b"), ("b", "a"), ("b", "c"), ("b", "b"), ("c", "a")]);
+    assert_eq!(graph.edge_count(), 5);
+    debug_assert_valid!(graph);
+
+    assert!(graph.remove_node("b"));
+    debug_assert_valid!(graph);
+    assert!(!graph.remove_node("b"));
+    debug_assert_valid!(graph);
+    assert_graph_eq!(graph, Graph::from_edges(&[("c", "a")]));
+    assert_eq!(nodes_of(&graph), ["a", "c"]);
+    assert_eq!(graph.edge_count(), 1); // Only c -> a is left
+    assert!(graph.get_neighbors(&"a".to_string()).unwrap().is_empty());
+    assert_eq!(graph.in_neighbors("c"), Vec::<&String>::new());
+    assert_eq!(graph.edge_weight("a", "b"), None);
+    assert_eq!(graph.edge_multiplicity("c", "a"), 1);
+}
+
+#[test]
+fn test_validate_reports_every_broken_invariant() {
+    let mut graph = Graph::from_edges(&[("a", "b"), ("b", "c"), ("c", "a")]);
+    graph.add_weighted_edge("a".to_string(), "a".to_string(), 2.0);
+    debug_assert_valid!(graph);
+    assert_eq!(Graph::new().validate(), Ok(()));
+
+    // Point an edge at a node that doesn't exist, behind the graph's back
+    let mut corrupted = graph.clone();
+    corrupted.adjacency_list.get_mut("a").unwrap().insert("ghost".to_string());
+    assert_eq!(
+        corrupted.validate(),
+        Err(vec![
+            GraphInvariantViolation::DanglingNeighbor { from: "a".to_string(), to: "ghost".to_string() },
+            GraphInvariantViolation::MissingReverseEdge { from: "a".to_string(), to: "ghost".to_string() },
+            GraphInvariantViolation::MissingWeight { from: "a".to_string(), to: "ghost".to_string() },
+            GraphInvariantViolation::EdgeCountMismatch { recorded: 4, actual: 5 },
+        ])
+    );
+
+    // Dropping an edge from the public maps leaves its reverse entry and weight behind
+    let mut corrupted = graph.clone();
+    corrupted.adjacency_list.get_mut("b").unwrap().remove("c");
+    let violations = corrupted.validate().unwrap_err();
+    assert_eq!(
+        violations,
+        [
+            GraphInvariantViolation::StrayWeight { from: "b".to_string(), to: "c".to_string() },
+            GraphInvariantViolation::ExtraReverseEdge { from: "b".to_string(), to: "c".to_string() },
+            GraphInvariantViolation::EdgeCountMismatch { recorded: 4, actual: 3 },
+        ]
+    );
+    assert_eq!(violations[1].to_string(), "reverse adjacency records b -> c, which is not an edge");
+
+    let mut zero = Graph::new();
+    zero.add_weighted_edge("a".to_string(), "b".to_string(), 0.0);
+    assert_eq!(zero.validate(), Err(vec![GraphInvariantViolation::MissingWeight { from: "a".to_string(), to: "b".to_string() }]));
+}
+
+#[test]
+fn test_robustness_profile_on_star() {
+    // Center c sends to nine leaves
+    let mut graph = Graph::new();
+    for i in 0..9 {
+        graph.add_edge("c".to_string(), format!("leaf{}", i));
+    }
+    let before = graph.clone();
+
+    let targeted = graph.robustness_profile(graph::RemovalStrategy::HighestDegree, 10);
+    assert_eq!(targeted.len(), 11);
+    assert_eq!(targeted[0].largest_component_fraction, 1.0);
+    assert!((targeted[1].fraction_removed - 0.1).abs() < 1e-12);
+    assert!((targeted[1].largest_component_fraction - 0.1).abs() < 1e-12); // Only isolated leaves remain
+    assert_eq!(targeted[10].largest_component_fraction, 0.0);
+
+    // Random removal usually hits a leaf first and barely dents the component
+    let random = graph.robustness_profile(graph::RemovalStrategy::Random { seed: 7 }, 10);
+    assert!((random[1].largest_component_fraction - 0.9).abs() < 1e-12);
+    let mean: f64 = (0..20)
+        .map(|seed| graph.robustness_profile(graph::RemovalStrategy::Random { seed }, 10)[1].largest_component_fraction)
+        .sum::<f64>() / 20.0;
+    assert!(mean > 0.5, "mean after one random removal was {}", mean);
+    assert_graph_eq!(graph, before); // The original graph is untouched
+}
+
+#[test]
+fn test_edge_percolation_on_cycle_degrades_gracefully() {
+    // A directed cycle of 20 people
+    let mut graph = Graph::new();
+    for i in 0..20 {
+        graph.add_edge(format!("n{}", i), format!("n{}", (i + 1) % 20));
+    }
+    let before = graph.clone();
+
+    let curve = graph.edge_percolation_curve(20, 50, 3);
+    assert_eq!(curve.len(), 21);
+    assert_eq!(curve[0], (1.0, 1.0));
+    assert_eq!(curve[1], (0.95, 1.0)); // One cut leaves a path through everyone
+    assert!(curve[2].1 >= 0.5); // Two cuts leave two arcs, the larger at least half
+    assert_eq!(curve[20].0, 0.0);
+    assert!((curve[20].1 - 0.05).abs() < 1e-12); // Only isolated people remain
+    for pair in curve.windows(2) {
+        assert!(pair[1].1 <= pair[0].1 + 1e-12, "curve rose at {:?}", pair);
+    }
+    // No sudden collapse: losing a quarter of the edges still leaves a sizeable group
+    let quarter_lost = curve.iter().find(|(retained, _)| (retained - 0.75).abs() < 1e-12).unwrap();
+    assert!(quarter_lost.1 > 0.2, "largest component at 75% of edges was {}", quarter_lost.1);
+
+    assert_eq!(graph.edge_percolation_curve(20, 50, 3), curve); // Reproducible for a seed
+    assert_graph_eq!(graph, before);
+}
+
+#[test]
+fn test_bench_rebuilds_the_generated_graph() {
+    let cli = Cli::try_parse_from(["email_analysis", "bench", "--nodes", "300", "--edges-per-node", "3", "--seed", "4"]).unwrap();
+    assert!(matches!(cli.command, Some(Command::Bench { nodes: 300, edges_per_node: 3, seed: 4 })));
+    assert!(run_bench(&Config::default(), &Logger::silent(), 300, 3, 4).is_ok());
+}
+
+#[test]
+fn test_csr_graph_agrees_with_graph() {
+    let mut emails = emails_along_edges(&Graph::planted_partition(3, 15, 0.3, 0.02, 7));
+    emails.push(ParsedEmail { from: "n0".to_string(), to: vec!["n1".to_string(), "n1".to_string()], timestamp: None });
+    emails.push(ParsedEmail { from: "lonely".to_string(), to: vec![], timestamp: None });
+    let graph = Graph::build_from_emails(emails.clone());
+    let csr = CsrGraph::build_from_emails(&emails);
+
+    assert!(csr.matches(&graph));
+    assert_eq!(csr.id("lonely"), None);
+    let (n0, n1) = (csr.id("n0").unwrap(), csr.id("n1").unwrap());
+    assert_eq!(csr.email_count(n0, n1), graph.edge_weight("n0", "n1").map(|weight| weight as usize));
+    assert!(csr.email_count(n0, n1).unwrap() >= 2);
+
+    // Ids follow the address order
+    let names: Vec<&str> = (0..csr.node_count() as u32).map(|id| csr.name(id)).collect();
+    assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let out_degrees = graph.calculate_out_degrees();
+    let in_degrees = graph.calculate_in_degrees();
+    for (id, (out_degree, in_degree)) in csr.out_degrees().into_iter().zip(csr.in_degrees()).enumerate() {
+        assert_eq!(out_degrees[csr.name(id as u32)], out_degree);
+        assert_eq!(in_degrees[csr.name(id as u32)], in_degree);
+    }
+
+    for direction in [graph::Direction::Out, graph::Direction::In, graph::Direction::Undirected] {
+        let layers: Vec<Vec<&str>> = csr.bfs_layers(n0, direction).iter().map(|layer| csr.names_of(layer)).collect();
+        assert_eq!(layers, graph.bfs_layers("n0", direction));
+    }
+
+    for use_undirected_neighbors in [false, true] {
+        let options = graph::LpaOptions { seed: Some(3), use_undirected_neighbors, ..Default::default() };
+        let expected = graph.label_propagation_detailed(&options);
+        let result = csr.label_propagation(&options);
+        assert_eq!(result.iterations_run, expected.iterations_run);
+        assert_eq!(result.converged, expected.converged);
+        for (id, &label) in result.labels.iter().enumerate() {
+            assert_eq!(expected.labels[csr.name(id as u32)], csr.name(label));
+        }
+    }
+}
+
+#[test]
+fn test_rewiring_preserves_every_degree() {
+    // Two dense groups of six joined by one edge
+    let mut graph = Graph::new();
+    for group in ["a", "b"] {
+        for i in 0..6 {
+            for j in 0..6 {
+                if i != j && (i + j) % 3 != 0 {
+                    graph.add_edge(format!("{}{}", group, i), format!("{}{}", group, j));
+                }
+            }
+        }
+    }
+    graph.add_edge("a0".to_string(), "b0".to_string());
+
+    let rewired = graph.rewire_preserving_degrees(10 * graph.edge_count(), 5);
+    debug_assert_valid!(rewired);
+    assert_eq!(rewired.calculate_out_degrees(), graph.calculate_out_degrees());
+    assert_eq!(rewired.calculate_in_degrees(), graph.calculate_in_degrees());
+    assert_eq!(rewired.edge_count(), graph.edge_count()); // No edge was merged into a duplicate
+    assert!(rewired.adjacency_list.iter().all(|(from, recipients)| !recipients.contains(from)));
+    assert_ne!(rewired.adjacency_list, graph.adjacency_list);
+    assert_eq!(graph.rewire_preserving_degrees(10 * graph.edge_count(), 5).adjacency_list, rewired.adjacency_list);
+
+    // Allowing self-loops still keeps the degrees
+    let looped = graph.rewire_preserving_degrees_with_options(10 * graph.edge_count(), 5, true);
+    assert_eq!(looped.calculate_out_degrees(), graph.calculate_out_degrees());
+    assert_eq!(looped.calculate_in_degrees(), graph.calculate_in_degrees());
+
+    // The rewired groups mix, so the original is more clustered
+    assert!(graph.average_clustering() > rewired.average_clustering());
+    assert_eq!(Graph::from_edges(&[("a", "b"), ("b", "c"), ("c", "a")]).average_clustering(), 1.0);
+    assert_eq!(Graph::from_edges(&[("a", "b"), ("b", "c")]).average_clustering(), 0.0);
+
+    assert_eq!(graph::metric_zscore(3.0, &[1.0, 2.0, 3.0]), 1.0);
+    assert_eq!(graph::metric_zscore(5.0, &[2.0, 2.0]), 0.0); // No spread to measure against
+    assert_eq!(graph::metric_zscore(5.0, &[2.0]), 0.0);
+}
+
+#[test]
+fn test_spread_on_chain() {
+    // a -> b -> c -> d, started from b: everything downstream is infected
+    let graph = Graph::from_edges(&[("a", "b"), ("b", "c"), ("c", "d")]);
+    let seeds = ["b".to_string(), "nobody".to_string()];
+
+    let certain = spread::SpreadModel::IndependentCascade { p: 1.0, weighted: false };
+    let result = graph.simulate_spread(&seeds, certain, 20, 1);
+    assert_eq!(result.mean_reach, 3.0);
+    assert_eq!(result.median_reach, 3.0);
+    for node in ["b", "c", "d"] {
+        assert_eq!(result.infection_frequency[node], 1.0);
+    }
+    assert!(!result.infection_frequency.contains_key("a"));
+
+    // SIR that never recovers behaves the same
+    let sir = spread::SpreadModel::Sir { infection: 1.0, recovery: 0.0 };
+    assert_eq!(graph.simulate_spread(&seeds, sir, 5, 1).mean_reach, 3.0);
+
+    // Uncertain spread is reproducible for a seed and stays between the extremes
+    let maybe = spread::SpreadModel::IndependentCascade { p: 0.5, weighted: false };
+    let first = graph.simulate_spread(&seeds, maybe, 200, 9);
+    assert_eq!(first, graph.simulate_spread(&seeds, maybe, 200, 9));
+    assert!(first.mean_reach > 1.0 && first.mean_reach < 3.0);
+    assert!(first.infection_frequency["c"] > first.infection_frequency["d"]);
+}
+
+#[test]
+fn test_bfs_layers_in_every_direction() {
+    use graph::Direction;
+
+    // a -> b, a -> c, b -> d, c -> d, d -> e
+    let graph = Graph::from_edges(&[("a", "b"), ("a", "c"), ("b", "d"), ("c", "d"), ("d", "e")]);
+
+    assert_eq!(graph.bfs_layers("a", Direction::Out), vec![vec!["a"], vec!["b", "c"], vec!["d"], vec!["e"]]);
+    assert_eq!(graph.bfs_layers("d", Direction::In), vec![vec!["d"], vec!["b", "c"], vec!["a"]]);
+    assert_eq!(graph.bfs_layers("b", Direction::Undirected), vec![vec!["b"], vec!["a", "d"], vec!["c", "e"]]);
+    assert_eq!(graph.bfs_layers("e", Direction::Out), vec![vec!["e"]]);
+
+    let distances = graph.bfs_distances("d", Direction::In);
+    assert_eq!(distances.len(), 4);
+    assert_eq!(distances["a"], 2);
+    assert!(!distances.contains_key("e"));
+
+    assert!(graph.bfs_distances("nobody", Direction::Out).is_empty());
+    assert!(graph.bfs_layers("nobody", Direction::Undirected).is_empty());
+}
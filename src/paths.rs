@@ -0,0 +1,132 @@
+use csv::{ReaderBuilder, Writer};
+use std::io::{self, Write};
+use std::path::Path;
+use crate::email::normalize_address;
+use crate::graph::{Graph, MatchMode, NodeLookup, NodeResolution};
+
+/// Reads `(from, to)` address pairs from a two-column CSV file.
+/// A leading `from,to` header row is skipped if present.
+pub fn read_pairs(path: &Path) -> Result<Vec<(String, String)>, csv::Error> {
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_path(path)?;
+
+    let mut pairs = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        let record = record?;
+        let from = record.get(0).unwrap_or_default();
+        let to = record.get(1).unwrap_or_default();
+        if i == 0 && from.trim().eq_ignore_ascii_case("from") && to.trim().eq_ignore_ascii_case("to") {
+            continue;
+        }
+        pairs.push((normalize_address(from), normalize_address(to)));
+    }
+    Ok(pairs)
+}
+
+/// One address of a pair after looking it up in the graph.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    /// The node the address resolved to
+    Node(String),
+    /// Several nodes match the address, most connected first
+    Ambiguous { query: String, candidates: Vec<String> },
+    /// No node matches the address; the suggestions are the nearest matches
+    Unknown { query: String, suggestions: Vec<String> },
+}
+
+impl Endpoint {
+    /// Looks `query` up with `Graph::lookup_node`, so it may name a node
+    /// exactly or be a pattern matched with `mode` against up to `limit`
+    /// nodes. An unknown address gets up to `limit` suggestions from
+    /// `Graph::resolve_node_detailed`.
+    pub fn lookup(graph: &Graph, query: String, mode: MatchMode, limit: usize) -> Result<Self, regex::Error> {
+        Ok(match graph.lookup_node(&query, mode, limit)? {
+            NodeLookup::Found(node) => Endpoint::Node(node),
+            NodeLookup::Ambiguous(candidates) => Endpoint::Ambiguous { query, candidates },
+            NodeLookup::NotFound => {
+                let suggestions = match graph.resolve_node_detailed(&query, limit) {
+                    NodeResolution::Unknown { suggestions } => suggestions,
+                    _ => Vec::new(),
+                };
+                Endpoint::Unknown { query, suggestions }
+            }
+        })
+    }
+
+    /// The node, or the address as given if it did not resolve to one.
+    pub fn name(&self) -> &str {
+        match self {
+            Endpoint::Node(node) => node,
+            Endpoint::Ambiguous { query, .. } | Endpoint::Unknown { query, .. } => query,
+        }
+    }
+
+    /// The nodes an ambiguous address matches or the suggestions for an
+    /// unknown one, empty for a resolved address.
+    pub fn candidates(&self) -> &[String] {
+        match self {
+            Endpoint::Node(_) => &[],
+            Endpoint::Ambiguous { candidates, .. } => candidates,
+            Endpoint::Unknown { suggestions, .. } => suggestions,
+        }
+    }
+}
+
+/// Writes one CSV row per pair with its shortest directed and undirected paths.
+/// Columns: from,to,status,directed_hops,directed_path,undirected_hops,undirected_path,from_candidates,to_candidates
+///
+/// `status` is `ok`, `unknown_node` if either address matches no node, or
+/// `ambiguous` if either matches several; the candidates columns then list
+/// the matching nodes of that address, or the suggestions for an unknown
+/// one, separated by `;`. Hops and path are
+/// left empty when no path exists; paths are joined by `->`.
+pub fn write_path_report<W: Write>(graph: &Graph, pairs: &[(Endpoint, Endpoint)], out: W) -> io::Result<()> {
+    let mut writer = Writer::from_writer(out);
+    writer.write_record([
+        "from",
+        "to",
+        "status",
+        "directed_hops",
+        "directed_path",
+        "undirected_hops",
+        "undirected_path",
+        "from_candidates",
+        "to_candidates",
+    ])?;
+
+    let columns = |path: Option<Vec<String>>| match path {
+        Some(path) => ((path.len() - 1).to_string(), path.join("->")),
+        None => (String::new(), String::new()),
+    };
+
+    for (from_endpoint, to_endpoint) in pairs {
+        let (from, to) = (from_endpoint.name(), to_endpoint.name());
+        let (from_candidates, to_candidates) = (from_endpoint.candidates().join(";"), to_endpoint.candidates().join(";"));
+        let status = match (from_endpoint, to_endpoint) {
+            (Endpoint::Unknown { .. }, _) | (_, Endpoint::Unknown { .. }) => Some("unknown_node"),
+            (Endpoint::Ambiguous { .. }, _) | (_, Endpoint::Ambiguous { .. }) => Some("ambiguous"),
+            _ => None,
+        };
+        if let Some(status) = status {
+            writer.write_record([from, to, status, "", "", "", "", &from_candidates, &to_candidates])?;
+            continue;
+        }
+
+        let (directed_hops, directed_path) = columns(graph.shortest_path(from, to));
+        let (undirected_hops, undirected_path) = columns(graph.shortest_undirected_path(from, to));
+        writer.write_record([
+            from,
+            to,
+            "ok",
+            &directed_hops,
+            &directed_path,
+            &undirected_hops,
+            &undirected_path,
+            "",
+            "",
+        ])?;
+    }
+    writer.flush()
+}
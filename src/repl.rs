@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use crate::email::normalize_address;
+use crate::graph::{Graph, MatchMode, NodeLookup, DEFAULT_FIND_LIMIT};
+use crate::identify_top_senders;
+
+/// Help text listing the commands understood by the interactive mode
+const HELP: &str = "\
+commands:
+  neighbors <addr>   addresses <addr> has emailed
+  in <addr>          addresses that have emailed <addr>
+  degree <addr>      in- and out-degree of <addr>
+  community <addr>   community label of <addr> and its size
+  path <a> <b>       shortest directed path from <a> to <b>
+  top senders <n>    the <n> addresses with the most distinct recipients
+  help               show this message
+  quit               leave the interactive mode";
+
+/// Result of dispatching one line of input.
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Text(String),
+    Quit,
+}
+
+/// A loaded graph plus the analysis results queries are answered from.
+pub struct Session {
+    pub graph: Graph,
+    pub labels: HashMap<String, String>,
+    pub find_limit: usize, // Most candidates listed when an address matches several nodes
+    out_degrees: HashMap<String, usize>,
+    in_degrees: HashMap<String, usize>,
+}
+
+impl Session {
+    /// Precomputes degrees so each query is answered without rescanning the graph.
+    pub fn new(graph: Graph, labels: HashMap<String, String>) -> Self {
+        let out_degrees = graph.calculate_out_degrees();
+        let in_degrees = graph.calculate_in_degrees();
+        Session { graph, labels, find_limit: DEFAULT_FIND_LIMIT, out_degrees, in_degrees }
+    }
+
+    /// Parses and runs a single command line, returning the text to print.
+    pub fn dispatch(&self, line: &str) -> Reply {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let text = match words.as_slice() {
+            [] => String::new(),
+            ["quit"] | ["exit"] => return Reply::Quit,
+            ["help"] => HELP.to_string(),
+            ["neighbors", addr] => self.with_node(addr, |node| {
+                let mut neighbors: Vec<&String> = self.graph.adjacency_list.get(node).into_iter().flatten().collect();
+                neighbors.sort();
+                format_address_list(&neighbors)
+            }),
+            ["in", addr] => self.with_node(addr, |node| format_address_list(&self.graph.in_neighbors(node))),
+            ["degree", addr] => self.with_node(addr, |node| {
+                format_table(
+                    &["address", "out_degree", "in_degree"],
+                    &[vec![node.to_string(), self.out_degrees[node].to_string(), self.in_degrees[node].to_string()]],
+                )
+            }),
+            ["community", addr] => self.with_node(addr, |node| match self.labels.get(node) {
+                Some(label) => {
+                    let size = self.labels.values().filter(|other| *other == label).count();
+                    format_table(&["address", "community", "size"], &[vec![node.to_string(), label.clone(), size.to_string()]])
+                }
+                None => format!("{} has no community label", node),
+            }),
+            ["path", from, to] => self.with_node(from, |from| {
+                self.with_node(to, |to| match self.graph.shortest_path(from, to) {
+                    Some(path) => format!("{} hop(s): {}", path.len() - 1, path.join(" -> ")),
+                    None => format!("no path from {} to {}", from, to),
+                })
+            }),
+            ["top", "senders", n] => match n.parse::<usize>() {
+                Ok(n) => {
+                    let rows: Vec<Vec<String>> = identify_top_senders(&self.out_degrees, n)
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, (node, degree))| vec![(i + 1).to_string(), node, degree.to_string()])
+                        .collect();
+                    format_table(&["rank", "address", "out_degree"], &rows)
+                }
+                Err(_) => format!("'{}' is not a number", n),
+            },
+            _ => format!("unrecognized command '{}'; type 'help' for a list of commands", line.trim()),
+        };
+        Reply::Text(text)
+    }
+
+    /// Resolves an address to a node and runs `f` on it, or explains why it
+    /// couldn't. An address that is part of exactly one node's name resolves
+    /// to that node; one that is part of several lists them.
+    fn with_node<F: FnOnce(&str) -> String>(&self, addr: &str, f: F) -> String {
+        let lookup = self.graph
+            .lookup_node(addr, MatchMode::Substring, self.find_limit)
+            .expect("substring patterns always compile");
+        match lookup {
+            NodeLookup::Found(node) => f(&node),
+            NodeLookup::Ambiguous(candidates) => {
+                let more = if candidates.len() == self.find_limit { " or more" } else { "" };
+                format!("'{}' matches {}{} addresses: {}", addr, candidates.len(), more, candidates.join(", "))
+            }
+            NodeLookup::NotFound => match self.closest_match(addr) {
+                Some(suggestion) => format!("unknown address '{}'; did you mean '{}'?", addr, suggestion),
+                None => format!("unknown address '{}'", addr),
+            },
+        }
+    }
+
+    /// Suggests the node closest to an address that matches none: the node
+    /// with the smallest edit distance.
+    fn closest_match(&self, addr: &str) -> Option<&String> {
+        let query = normalize_address(addr);
+        self.graph.adjacency_list.keys().min_by(|a, b| {
+            levenshtein(&query, a)
+                .cmp(&levenshtein(&query, b))
+                .then_with(|| a.cmp(b))
+        })
+    }
+}
+
+/// Reads commands from `input` until `quit` or end of input, writing replies to `output`.
+pub fn run<R: BufRead, W: Write>(session: &Session, input: R, mut output: W) -> io::Result<()> {
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        match session.dispatch(&line?) {
+            Reply::Quit => break,
+            Reply::Text(text) => {
+                if !text.is_empty() {
+                    writeln!(output, "{}", text)?;
+                }
+            }
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
+
+/// Formats a list of addresses one per line, followed by a count.
+fn format_address_list(addresses: &[&String]) -> String {
+    let mut lines: Vec<String> = addresses.iter().map(|addr| addr.to_string()).collect();
+    lines.push(format!("({} addresses)", addresses.len()));
+    lines.join("\n")
+}
+
+/// Formats rows as a left-aligned table with a header row.
+fn format_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut lines = vec![format_row(headers.to_vec())];
+    for row in rows {
+        lines.push(format_row(row.iter().map(String::as_str).collect()));
+    }
+    lines.join("\n")
+}
+
+/// Computes the Levenshtein edit distance between two strings.
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b_chars.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b_chars.len()]
+}
@@ -0,0 +1,135 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use crate::graph::Graph;
+use crate::percentile;
+
+/// Upper bound on SIR rounds, in case nobody ever recovers
+const MAX_SIR_ROUNDS: usize = 10_000;
+
+/// How infection passes along the email edges in `Graph::simulate_spread`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpreadModel {
+    /// Independent cascade: a newly infected person gets one chance to infect
+    /// each recipient, succeeding with probability `p`. With `weighted`, every
+    /// email on the edge is a separate chance, so the edge succeeds with
+    /// probability `1 - (1 - p)^weight`.
+    IndependentCascade { p: f64, weighted: bool },
+    /// SIR: every round each infected person infects each susceptible
+    /// recipient with probability `infection`, then recovers (and can no
+    /// longer infect or be infected) with probability `recovery`.
+    Sir { infection: f64, recovery: f64 },
+}
+
+/// Outcome of `Graph::simulate_spread` over all trials.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpreadResult {
+    pub trials: usize,
+    /// Mean number of people infected by the end of a trial, seeds included
+    pub mean_reach: f64,
+    pub median_reach: f64,
+    /// Fraction of trials in which each person was infected; people never infected are left out
+    pub infection_frequency: HashMap<String, f64>,
+}
+
+impl Graph {
+    /// Runs `trials` independent spreading simulations starting from
+    /// `seeds` (addresses not in the graph are ignored) and summarizes how
+    /// far the infection got. Infection follows edges from sender to
+    /// recipient. The same `rng_seed` always gives the same result.
+    pub fn simulate_spread(&self, seeds: &[String], model: SpreadModel, trials: usize, rng_seed: u64) -> SpreadResult {
+        // Sorted recipients, so the random draws happen in a fixed order
+        let mut nodes: Vec<&String> = self.adjacency_list.keys().collect();
+        nodes.sort();
+        let index: HashMap<&String, usize> = nodes.iter().enumerate().map(|(i, node)| (*node, i)).collect();
+        let recipients: Vec<Vec<(usize, f64)>> = nodes
+            .iter()
+            .map(|node| {
+                let mut targets: Vec<(usize, f64)> = self.adjacency_list[*node]
+                    .iter()
+                    .filter(|target| *target != *node)
+                    .map(|target| (index[target], self.edge_weight(node, target).unwrap_or(1.0)))
+                    .collect();
+                targets.sort_by_key(|&(target, _)| target);
+                targets
+            })
+            .collect();
+
+        let mut starts: Vec<usize> = seeds.iter().filter_map(|seed| index.get(seed).copied()).collect();
+        starts.sort();
+        starts.dedup();
+
+        let mut rng = StdRng::seed_from_u64(rng_seed);
+        let mut reaches: Vec<usize> = Vec::with_capacity(trials);
+        let mut infected_counts = vec![0usize; nodes.len()];
+        for _ in 0..trials {
+            let infected = match model {
+                SpreadModel::IndependentCascade { p, weighted } => cascade(&recipients, &starts, p, weighted, &mut rng),
+                SpreadModel::Sir { infection, recovery } => sir(&recipients, &starts, infection, recovery, &mut rng),
+            };
+            reaches.push(infected.len());
+            for node in infected {
+                infected_counts[node] += 1;
+            }
+        }
+
+        let infection_frequency = infected_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(i, &count)| (nodes[i].clone(), count as f64 / trials as f64))
+            .collect();
+        SpreadResult {
+            trials,
+            mean_reach: if trials == 0 { 0.0 } else { reaches.iter().sum::<usize>() as f64 / trials as f64 },
+            median_reach: percentile(&reaches, 0.5).unwrap_or(0.0),
+            infection_frequency,
+        }
+    }
+}
+
+/// One independent-cascade run; returns everyone infected.
+fn cascade(recipients: &[Vec<(usize, f64)>], starts: &[usize], p: f64, weighted: bool, rng: &mut StdRng) -> HashSet<usize> {
+    let mut infected: HashSet<usize> = starts.iter().copied().collect();
+    let mut frontier: Vec<usize> = starts.to_vec();
+    while !frontier.is_empty() {
+        let mut next = Vec::new();
+        for node in frontier {
+            for &(target, weight) in &recipients[node] {
+                if infected.contains(&target) {
+                    continue;
+                }
+                let chance = if weighted { 1.0 - (1.0 - p).powf(weight) } else { p };
+                if rng.gen_bool(chance.clamp(0.0, 1.0)) {
+                    infected.insert(target);
+                    next.push(target);
+                }
+            }
+        }
+        frontier = next;
+    }
+    infected
+}
+
+/// One SIR run; returns everyone who was ever infected.
+fn sir(recipients: &[Vec<(usize, f64)>], starts: &[usize], infection: f64, recovery: f64, rng: &mut StdRng) -> HashSet<usize> {
+    let mut ever_infected: HashSet<usize> = starts.iter().copied().collect();
+    let mut infectious: Vec<usize> = starts.to_vec();
+    for _ in 0..MAX_SIR_ROUNDS {
+        if infectious.is_empty() {
+            break;
+        }
+        let mut newly_infected = Vec::new();
+        for &node in &infectious {
+            for &(target, _) in &recipients[node] {
+                if !ever_infected.contains(&target) && rng.gen_bool(infection.clamp(0.0, 1.0)) {
+                    ever_infected.insert(target);
+                    newly_infected.push(target);
+                }
+            }
+        }
+        infectious.retain(|_| !rng.gen_bool(recovery.clamp(0.0, 1.0)));
+        infectious.extend(newly_infected);
+    }
+    ever_infected
+}
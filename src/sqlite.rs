@@ -0,0 +1,126 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::{params, Connection};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::email::ParsedEmailFull;
+use crate::graph::{EdgeInfo, Graph};
+
+const SCHEMA: &str = "
+    DROP TABLE IF EXISTS emails;
+    DROP TABLE IF EXISTS nodes;
+    DROP TABLE IF EXISTS edges;
+    DROP TABLE IF EXISTS edge_days;
+    CREATE TABLE emails (sender TEXT NOT NULL, recipient TEXT NOT NULL, ts TEXT, subject_hash INTEGER NOT NULL);
+    CREATE TABLE nodes (address TEXT PRIMARY KEY, in_degree INTEGER NOT NULL, out_degree INTEGER NOT NULL, community TEXT);
+    CREATE TABLE edges (src TEXT NOT NULL, dst TEXT NOT NULL, weight REAL NOT NULL, emails INTEGER NOT NULL, first_seen TEXT, last_seen TEXT, PRIMARY KEY (src, dst));
+    CREATE TABLE edge_days (src TEXT NOT NULL, dst TEXT NOT NULL, day TEXT NOT NULL, PRIMARY KEY (src, dst, day));
+";
+
+/// Writes the emails and the graph to the SQLite database at `path`,
+/// replacing the `emails`, `nodes`, `edges` and `edge_days` tables if they exist:
+/// - `emails(sender, recipient, ts, subject_hash)`: one row per recipient
+///   of every email, with the timestamp as RFC 3339 (NULL if unknown) and
+///   a stable 64-bit FNV-1a hash of the subject instead of its text
+/// - `nodes(address, in_degree, out_degree, community)`: community is NULL
+///   for people without a label or when `labels` is `None`
+/// - `edges(src, dst, weight, emails, first_seen, last_seen)`: one row per
+///   directed edge, with its first and last email as RFC 3339 (NULL if unknown)
+/// - `edge_days(src, dst, day)`: one row per edge and day (YYYY-MM-DD) it was active
+///
+/// Everything is written in a single transaction.
+pub fn persist_to_sqlite(
+    path: &Path,
+    emails: &[ParsedEmailFull],
+    graph: &Graph,
+    labels: Option<&HashMap<String, String>>,
+) -> rusqlite::Result<()> {
+    let mut connection = Connection::open(path)?;
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(SCHEMA)?;
+    {
+        let mut insert = transaction.prepare("INSERT INTO emails VALUES (?1, ?2, ?3, ?4)")?;
+        for full in emails {
+            let ts = full.email.timestamp.map(|ts| ts.to_rfc3339());
+            let subject_hash = subject_hash(&full.subject);
+            for recipient in &full.email.to {
+                insert.execute(params![full.email.from, recipient, ts, subject_hash])?;
+            }
+        }
+
+        let out_degrees = graph.calculate_out_degrees();
+        let in_degrees = graph.calculate_in_degrees();
+        let mut insert = transaction.prepare("INSERT INTO nodes VALUES (?1, ?2, ?3, ?4)")?;
+        for node in graph.adjacency_list.keys() {
+            let community = labels.and_then(|labels| labels.get(node));
+            insert.execute(params![node, in_degrees[node] as i64, out_degrees[node] as i64, community])?;
+        }
+
+        let mut insert = transaction.prepare("INSERT INTO edges VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+        let mut insert_day = transaction.prepare("INSERT INTO edge_days VALUES (?1, ?2, ?3)")?;
+        for (from, recipients) in &graph.adjacency_list {
+            for to in recipients {
+                let weight = graph.edge_weight(from, to).unwrap_or(0.0);
+                let info = graph.edge_info(from, to).cloned().unwrap_or_default();
+                let (first_seen, last_seen) = (info.first_seen.map(|ts| ts.to_rfc3339()), info.last_seen.map(|ts| ts.to_rfc3339()));
+                insert.execute(params![from, to, weight, info.count as i64, first_seen, last_seen])?;
+                for day in info.days() {
+                    insert_day.execute(params![from, to, day.format("%Y-%m-%d").to_string()])?;
+                }
+            }
+        }
+    }
+    transaction.commit()
+}
+
+impl Graph {
+    /// Rebuilds a graph written by `persist_to_sqlite`, with the same
+    /// nodes (isolated ones included), edge weights and edge activity.
+    pub fn load_from_sqlite(path: &Path) -> rusqlite::Result<Graph> {
+        let connection = Connection::open(path)?;
+        let mut graph = Graph::new();
+
+        let mut nodes = connection.prepare("SELECT address FROM nodes")?;
+        for address in nodes.query_map([], |row| row.get::<_, String>(0))? {
+            graph.insert_node(address?);
+        }
+
+        let mut days: HashMap<(String, String), HashSet<NaiveDate>> = HashMap::new();
+        let mut edge_days = connection.prepare("SELECT src, dst, day FROM edge_days")?;
+        let rows = edge_days.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?;
+        for row in rows {
+            let (from, to, day) = row?;
+            if let Ok(day) = NaiveDate::parse_from_str(&day, "%Y-%m-%d") {
+                days.entry((from, to)).or_default().insert(day);
+            }
+        }
+
+        let mut edges = connection.prepare("SELECT src, dst, weight, emails, first_seen, last_seen FROM edges")?;
+        let rows = edges.query_map([], |row| {
+            let info = (row.get::<_, i64>(3)?, row.get::<_, Option<String>>(4)?, row.get::<_, Option<String>>(5)?);
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, f64>(2)?, info))
+        })?;
+        for row in rows {
+            let (from, to, weight, (emails, first_seen, last_seen)) = row?;
+            let days = days.remove(&(from.clone(), to.clone())).unwrap_or_default();
+            let info = EdgeInfo::restore(emails as usize, parse_timestamp(first_seen), parse_timestamp(last_seen), days);
+            graph.accumulate_edge(from, to, weight, &info);
+        }
+        Ok(graph)
+    }
+}
+
+/// Parses an RFC 3339 timestamp written by `persist_to_sqlite`.
+fn parse_timestamp(text: Option<String>) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(&text?).ok().map(|ts| ts.with_timezone(&Utc))
+}
+
+/// 64-bit FNV-1a hash of the subject, stored as SQLite's signed integer.
+/// Unlike `DefaultHasher`, it stays the same across Rust releases.
+fn subject_hash(subject: &str) -> i64 {
+    let hash = subject.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    hash as i64
+}
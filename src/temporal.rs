@@ -0,0 +1,330 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, HashSet};
+use std::ops::RangeInclusive;
+use crate::email::ParsedEmail;
+use crate::graph::{Graph, LpaOptions};
+
+/// A time window and the graph built from the emails sent within it.
+pub type Snapshot = (RangeInclusive<DateTime<Utc>>, Graph);
+
+/// Buckets emails into consecutive windows of length `window`, starting at the
+/// earliest timestamp, and builds one graph per window.
+///
+/// Windows without any emails yield empty graphs so the series stays regular.
+/// Emails without a timestamp are ignored. Returns no snapshots if no email
+/// has a timestamp or `window` is not positive.
+pub fn build_temporal_snapshots(emails: &[ParsedEmail], window: Duration) -> Vec<Snapshot> {
+    let Some(buckets) = Buckets::covering(emails, window) else {
+        return Vec::new();
+    };
+
+    let mut snapshots: Vec<Snapshot> = (0..buckets.count)
+        .map(|i| {
+            let start = buckets.start(i);
+            let end = start + window - Duration::nanoseconds(1);
+            (start..=end, Graph::new())
+        })
+        .collect();
+
+    for email in emails {
+        let Some(timestamp) = email.timestamp else {
+            continue;
+        };
+        let graph = &mut snapshots[buckets.index(timestamp)].1;
+        for recipient in &email.to {
+            graph.add_edge(email.from.clone(), recipient.clone());
+        }
+    }
+
+    snapshots
+}
+
+/// Activity per time bucket: the start of each bucket and the number of emails sent in it.
+pub type ActivitySeries = Vec<(DateTime<Utc>, usize)>;
+
+/// Counts the emails each sender sent per bucket of length `bucket`, starting
+/// at the earliest timestamp. Every sender's series covers the same buckets,
+/// from the first to the last email overall, with zeros where they were quiet.
+///
+/// Emails without a timestamp are ignored. Returns an empty map if no email
+/// has a timestamp or `bucket` is not positive.
+pub fn sender_activity_series(emails: &[ParsedEmail], bucket: Duration) -> HashMap<String, ActivitySeries> {
+    let Some(buckets) = Buckets::covering(emails, bucket) else {
+        return HashMap::new();
+    };
+
+    let mut counts: HashMap<String, Vec<usize>> = HashMap::new();
+    for email in emails {
+        if let Some(timestamp) = email.timestamp {
+            counts.entry(email.from.clone()).or_insert_with(|| vec![0; buckets.count])[buckets.index(timestamp)] += 1;
+        }
+    }
+
+    counts
+        .into_iter()
+        .map(|(sender, counts)| (sender, buckets.series(counts)))
+        .collect()
+}
+
+/// Counts all emails per bucket, over the same buckets as `sender_activity_series`.
+pub fn total_activity_series(emails: &[ParsedEmail], bucket: Duration) -> ActivitySeries {
+    let Some(buckets) = Buckets::covering(emails, bucket) else {
+        return Vec::new();
+    };
+
+    let mut counts = vec![0; buckets.count];
+    for timestamp in emails.iter().filter_map(|email| email.timestamp) {
+        counts[buckets.index(timestamp)] += 1;
+    }
+    buckets.series(counts)
+}
+
+/// Senders with fewer buckets than this are skipped by `detect_bursts`.
+pub const MIN_BURST_BUCKETS: usize = 3;
+
+/// A run of consecutive buckets in which one sender was far more active than usual.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BurstEvent {
+    pub sender: String,
+    pub start: DateTime<Utc>, // Start of the first bucket in the burst
+    pub end: DateTime<Utc>, // Start of the last bucket in the burst
+    pub peak: usize, // Most emails sent in a single bucket of the burst
+}
+
+/// Flags every run of consecutive buckets in which a sender sent more than
+/// `threshold_sigma` standard deviations above their own mean per-bucket
+/// count. Senders with fewer than `MIN_BURST_BUCKETS` buckets are skipped,
+/// and a perfectly even series never bursts.
+///
+/// Events are sorted by peak, highest first, then by sender and start.
+pub fn detect_bursts(series: &HashMap<String, ActivitySeries>, threshold_sigma: f64) -> Vec<BurstEvent> {
+    let mut bursts = Vec::new();
+    for (sender, buckets) in series {
+        if buckets.len() < MIN_BURST_BUCKETS {
+            continue;
+        }
+
+        let n = buckets.len() as f64;
+        let mean = buckets.iter().map(|&(_, count)| count as f64).sum::<f64>() / n;
+        let variance = buckets.iter().map(|&(_, count)| (count as f64 - mean).powi(2)).sum::<f64>() / n;
+        let threshold = mean + threshold_sigma * variance.sqrt();
+
+        let mut current: Option<BurstEvent> = None;
+        for &(start, count) in buckets {
+            if variance > 0.0 && count as f64 > threshold {
+                let burst = current.get_or_insert_with(|| BurstEvent { sender: sender.clone(), start, end: start, peak: 0 });
+                burst.end = start;
+                burst.peak = burst.peak.max(count);
+            } else if let Some(burst) = current.take() {
+                bursts.push(burst);
+            }
+        }
+        bursts.extend(current);
+    }
+
+    bursts.sort_by(|a, b| b.peak.cmp(&a.peak).then_with(|| a.sender.cmp(&b.sender)).then_with(|| a.start.cmp(&b.start)));
+    bursts
+}
+
+/// Consecutive equal-length time buckets covering every timestamped email.
+struct Buckets {
+    first: DateTime<Utc>,
+    width: Duration,
+    width_nanos: i64,
+    count: usize,
+}
+
+impl Buckets {
+    /// Buckets of length `width` starting at the earliest timestamp, or `None`
+    /// if no email has a timestamp or `width` is not positive.
+    fn covering(emails: &[ParsedEmail], width: Duration) -> Option<Buckets> {
+        if width <= Duration::zero() {
+            return None;
+        }
+
+        let timestamps = emails.iter().filter_map(|email| email.timestamp);
+        let (first, last) = (timestamps.clone().min()?, timestamps.max()?);
+
+        // Number of buckets needed to cover [first, last]
+        let span = (last - first).num_nanoseconds().unwrap_or(i64::MAX);
+        let width_nanos = width.num_nanoseconds().unwrap_or(i64::MAX);
+        let count = (span / width_nanos) as usize + 1;
+        Some(Buckets { first, width, width_nanos, count })
+    }
+
+    /// Start of bucket `i`.
+    fn start(&self, i: usize) -> DateTime<Utc> {
+        self.first + self.width * i as i32
+    }
+
+    /// The bucket `timestamp` falls into.
+    fn index(&self, timestamp: DateTime<Utc>) -> usize {
+        ((timestamp - self.first).num_nanoseconds().unwrap_or(i64::MAX) / self.width_nanos) as usize
+    }
+
+    /// Pairs per-bucket counts with their bucket starts.
+    fn series(&self, counts: Vec<usize>) -> ActivitySeries {
+        counts.into_iter().enumerate().map(|(i, count)| (self.start(i), count)).collect()
+    }
+}
+
+/// One email along a time-respecting path, as returned by `temporal_path`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemporalHop {
+    pub from: String,
+    pub to: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Everyone who could have heard something `source` learned at `start`,
+/// passing it on only by email: C is reachable via B only if A emailed B
+/// before B emailed C. The source's own emails count from `start` on; every
+/// later hop must come strictly after the email that informed its sender,
+/// and, with `max_delay`, no later than `max_delay` after it (or after
+/// `start`, for the source). The source itself is not included.
+///
+/// Emails without a timestamp, sent before `start` or to the sender
+/// themselves are ignored.
+pub fn temporal_reachable_set(
+    emails: &[ParsedEmail],
+    source: &str,
+    start: DateTime<Utc>,
+    max_delay: Option<Duration>,
+) -> HashSet<String> {
+    let spread = TemporalSpread::run(emails, source, start, max_delay);
+    spread.first_hop.into_keys().filter(|&node| node != source).map(str::to_string).collect()
+}
+
+/// Returns one time-respecting path from `source` to `target`, the one that
+/// reaches `target` earliest, with the email of every hop. Follows the same
+/// rules as `temporal_reachable_set`. Returns an empty path if `source` is
+/// `target`, and `None` if `target` can't be reached.
+pub fn temporal_path(
+    emails: &[ParsedEmail],
+    source: &str,
+    target: &str,
+    start: DateTime<Utc>,
+    max_delay: Option<Duration>,
+) -> Option<Vec<TemporalHop>> {
+    if source == target {
+        return Some(Vec::new());
+    }
+    let spread = TemporalSpread::run(emails, source, start, max_delay);
+    let mut path = Vec::new();
+    let mut hop = spread.first_hop.get(target).copied();
+    while let Some(index) = hop {
+        let (from, to, timestamp, parent) = &spread.hops[index];
+        path.push(TemporalHop { from: from.to_string(), to: to.to_string(), timestamp: *timestamp });
+        hop = *parent;
+    }
+    path.reverse();
+    (!path.is_empty()).then_some(path)
+}
+
+/// Every hop along which the information passed, in time order.
+struct TemporalSpread<'a> {
+    /// (from, to, timestamp, hop that informed `from`; `None` for the source)
+    hops: Vec<(&'a str, &'a str, DateTime<Utc>, Option<usize>)>,
+    /// The earliest hop into each informed node
+    first_hop: HashMap<&'a str, usize>,
+}
+
+impl<'a> TemporalSpread<'a> {
+    fn run(emails: &'a [ParsedEmail], source: &str, start: DateTime<Utc>, max_delay: Option<Duration>) -> Self {
+        let mut dated: Vec<(DateTime<Utc>, &ParsedEmail)> = emails
+            .iter()
+            .filter_map(|email| email.timestamp.filter(|&timestamp| timestamp >= start).map(|timestamp| (timestamp, email)))
+            .collect();
+        dated.sort_by_key(|&(timestamp, _)| timestamp);
+
+        let mut spread = TemporalSpread { hops: Vec::new(), first_hop: HashMap::new() };
+        // The latest hop into each informed node: a later arrival keeps the
+        // max_delay window open longest, and every arrival so far is earlier
+        // than the emails still to come
+        let mut latest_hop: HashMap<&str, usize> = HashMap::new();
+        for group in dated.chunk_by(|a, b| a.0 == b.0) {
+            // Emails sent at the same moment can't pass on each other's news
+            let mut arrivals = Vec::new();
+            for &(timestamp, email) in group {
+                let in_time = |informed_at| max_delay.is_none_or(|delay| timestamp - informed_at <= delay);
+                // The source passes on what it knew at `start` directly, not
+                // via someone who told it back
+                let parent = if email.from == source && in_time(start) {
+                    None
+                } else {
+                    match latest_hop.get(email.from.as_str()) {
+                        Some(&hop) if in_time(spread.hops[hop].2) => Some(hop),
+                        _ => continue,
+                    }
+                };
+                for recipient in email.to.iter().filter(|recipient| **recipient != email.from) {
+                    arrivals.push((email.from.as_str(), recipient.as_str(), timestamp, parent));
+                }
+            }
+            for arrival in arrivals {
+                let index = spread.hops.len();
+                spread.hops.push(arrival);
+                latest_hop.insert(arrival.1, index);
+                spread.first_hop.entry(arrival.1).or_insert(index);
+            }
+        }
+        spread
+    }
+}
+
+/// How the community structure changed between two adjacent windows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommunityShift {
+    pub from_window: usize,
+    pub to_window: usize,
+    pub communities_before: usize,
+    pub communities_after: usize,
+    /// Jaccard overlap between the largest community of each window
+    /// (0.0 if either window has no communities)
+    pub largest_jaccard: f64,
+}
+
+/// Runs label propagation on every snapshot and reports, for each pair of
+/// adjacent windows, how the community memberships shifted.
+pub fn community_stability(snapshots: &[Snapshot], opts: &LpaOptions) -> Vec<CommunityShift> {
+    let partitions: Vec<Vec<HashSet<&String>>> = snapshots
+        .iter()
+        .map(|(_, graph)| group_by_label(graph.label_propagation_with_options(opts), graph))
+        .collect();
+
+    partitions
+        .windows(2)
+        .enumerate()
+        .map(|(i, pair)| CommunityShift {
+            from_window: i,
+            to_window: i + 1,
+            communities_before: pair[0].len(),
+            communities_after: pair[1].len(),
+            largest_jaccard: match (pair[0].first(), pair[1].first()) {
+                (Some(before), Some(after)) => jaccard(before, after),
+                _ => 0.0,
+            },
+        })
+        .collect()
+}
+
+/// Groups nodes by label, largest community first (ties broken by smallest member).
+fn group_by_label(labels: HashMap<String, String>, graph: &Graph) -> Vec<HashSet<&String>> {
+    let mut groups: HashMap<String, HashSet<&String>> = HashMap::new();
+    for (node, _) in graph.adjacency_list.iter() {
+        groups.entry(labels[node].clone()).or_default().insert(node);
+    }
+
+    let mut groups: Vec<HashSet<&String>> = groups.into_values().collect();
+    groups.sort_by_cached_key(|members| (std::cmp::Reverse(members.len()), members.iter().min().cloned()));
+    groups
+}
+
+/// Jaccard similarity |a ∩ b| / |a ∪ b| of two node sets.
+fn jaccard(a: &HashSet<&String>, b: &HashSet<&String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeSet, HashMap};
+use crate::email::ParsedEmailFull;
+
+/// Reply and forward markers stripped from the front of subjects.
+const SUBJECT_PREFIXES: [&str; 3] = ["re:", "fwd:", "fw:"];
+
+/// A conversation: every email whose subject normalizes to the same string.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Thread {
+    pub participants: BTreeSet<String>, // Senders and recipients, sorted
+    pub message_count: usize,
+    pub first: Option<DateTime<Utc>>, // Earliest dated message
+    pub last: Option<DateTime<Utc>>, // Latest dated message
+}
+
+impl Thread {
+    /// Time between the first and last dated message, if any message is dated.
+    pub fn span(&self) -> Option<Duration> {
+        Some(self.last? - self.first?)
+    }
+}
+
+/// Normalizes a subject for threading: repeatedly strips leading "Re:",
+/// "Fwd:" and "FW:" markers, trims whitespace and lowercases.
+pub fn normalize_subject(subject: &str) -> String {
+    let mut subject = subject.trim().to_lowercase();
+    while let Some(prefix) = SUBJECT_PREFIXES.iter().find(|prefix| subject.starts_with(*prefix)) {
+        subject = subject[prefix.len()..].trim_start().to_string();
+    }
+    subject.trim_end().to_string()
+}
+
+/// Groups emails into threads keyed by normalized subject. Emails whose
+/// subject is empty after normalization are left out, since they would all
+/// collapse into one meaningless thread.
+pub fn group_into_threads(records: &[ParsedEmailFull]) -> HashMap<String, Thread> {
+    let mut threads: HashMap<String, Thread> = HashMap::new();
+    for record in records {
+        let key = normalize_subject(&record.subject);
+        if key.is_empty() {
+            continue;
+        }
+
+        let thread = threads.entry(key).or_default();
+        thread.message_count += 1;
+        thread.participants.insert(record.email.from.clone());
+        thread.participants.extend(record.email.to.iter().cloned());
+        if let Some(timestamp) = record.email.timestamp {
+            thread.first = Some(thread.first.map_or(timestamp, |first| first.min(timestamp)));
+            thread.last = Some(thread.last.map_or(timestamp, |last| last.max(timestamp)));
+        }
+    }
+    threads
+}
+
+/// Returns the `top_n` threads with the most messages, ties by subject.
+pub fn longest_threads(threads: &HashMap<String, Thread>, top_n: usize) -> Vec<(&String, &Thread)> {
+    let mut ranked: Vec<(&String, &Thread)> = threads.iter().collect();
+    ranked.sort_by(|a, b| b.1.message_count.cmp(&a.1.message_count).then_with(|| a.0.cmp(b.0)));
+    ranked.truncate(top_n);
+    ranked
+}